@@ -0,0 +1,138 @@
+// Throughput benchmarks for key setup, single-block operations and bulk
+// modes across word sizes and round counts. Run with `cargo bench`; pass a
+// substring to only run the matching benchmarks, e.g. `cargo bench -- ctr`
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rc5_test::CipherMagicConstants;
+use rc5_test::{Mode, Rc5Encryptor, StreamDecryptor, StreamEncryptor, Unsigned16To64, RC5};
+
+const MEASUREMENT_TIME: Duration = Duration::from_millis(500);
+const BULK_LEN: usize = 1 << 20;
+
+// runs `f` repeatedly for roughly MEASUREMENT_TIME and reports the mean
+// time per call, plus the throughput when `bytes` per call is known
+fn bench<F: FnMut()>(filter: &Option<String>, name: &str, bytes: usize, mut f: F) {
+    if let Some(filter) = filter {
+        if !name.contains(filter.as_str()) {
+            return;
+        }
+    }
+
+    // warm up and find an iteration count that takes a measurable time
+    let mut iterations = 1u64;
+    loop {
+        let start = Instant::now();
+        (0..iterations).for_each(|_| f());
+        if start.elapsed() > MEASUREMENT_TIME / 10 {
+            break;
+        }
+        iterations *= 2;
+    }
+
+    let mut total = 0u64;
+    let start = Instant::now();
+    while start.elapsed() < MEASUREMENT_TIME {
+        (0..iterations).for_each(|_| f());
+        total += iterations;
+    }
+
+    let per_call = start.elapsed().as_nanos() as f64 / total as f64;
+    if bytes == 0 {
+        println!("{:<40} {:>12.1} ns/iter", name, per_call);
+    } else {
+        let throughput = bytes as f64 / per_call * 1e9 / (1 << 20) as f64;
+        println!(
+            "{:<40} {:>12.1} ns/iter {:>10.1} MiB/s",
+            name, per_call, throughput
+        );
+    }
+}
+
+fn bench_parameters<T>(filter: &Option<String>, rounds: usize)
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let words = std::mem::size_of::<T>();
+    let block_len = 2 * words;
+    let label = format!("rc5-{}/{}/16", 8 * words, rounds);
+    let cipher = || RC5::<T>::new((0u8..16).collect::<Vec<u8>>(), words, rounds, 16);
+
+    bench(filter, &format!("{} key setup", label), 0, || {
+        black_box(StreamEncryptor::new(&cipher(), Mode::Ecb).unwrap());
+    });
+
+    // `encode` allocates its output on every call
+    let rc_5 = cipher();
+    let block = vec![0x5Au8; block_len];
+    bench(filter, &format!("{} encode", label), block_len, || {
+        black_box(rc_5.encode(black_box(&block)).unwrap());
+    });
+
+    let mut encryptor = StreamEncryptor::new(&rc_5, Mode::Ecb).unwrap();
+    bench(
+        filter,
+        &format!("{} encrypt block", label),
+        block_len,
+        || {
+            black_box(encryptor.update(black_box(&block)));
+        },
+    );
+
+    let mut decryptor = StreamDecryptor::new(
+        &rc_5,
+        Mode::Ctr {
+            nonce: vec![0; block_len],
+        },
+    )
+    .unwrap();
+    bench(
+        filter,
+        &format!("{} decrypt block", label),
+        block_len,
+        || {
+            black_box(decryptor.update(black_box(&block)));
+        },
+    );
+
+    let data = vec![0xA5u8; BULK_LEN];
+    let modes = vec![
+        ("ecb", Mode::Ecb),
+        (
+            "cbc",
+            Mode::Cbc {
+                iv: vec![0; block_len],
+            },
+        ),
+        (
+            "ctr",
+            Mode::Ctr {
+                nonce: vec![0; block_len],
+            },
+        ),
+    ];
+    for (name, mode) in modes {
+        let mut encryptor = StreamEncryptor::new(&rc_5, mode).unwrap();
+        bench(
+            filter,
+            &format!("{} bulk {}", label, name),
+            BULK_LEN,
+            || {
+                black_box(encryptor.update(black_box(&data)));
+            },
+        );
+    }
+}
+
+fn main() {
+    // cargo passes `--bench` to harness-less targets, everything else that
+    // isn't a flag is treated as a name filter
+    let filter = std::env::args().skip(1).find(|arg| !arg.starts_with("--"));
+
+    bench_parameters::<u16>(&filter, 12);
+    bench_parameters::<u16>(&filter, 16);
+    bench_parameters::<u32>(&filter, 12);
+    bench_parameters::<u32>(&filter, 20);
+    bench_parameters::<u64>(&filter, 16);
+    bench_parameters::<u64>(&filter, 24);
+}
@@ -0,0 +1,40 @@
+// the first three bytes of every input pick the word size, the number of
+// rounds and the key length, and the key follows them, so the fuzzer
+// explores cipher parameters along with the data the target gets. Not
+// every target uses all of it
+#![allow(dead_code)]
+
+use rc5_test::{CipherMagicConstants, Unsigned16To64, MAX_KEY_LEN, RC5};
+
+pub trait Target {
+    fn run<T>(cipher: &RC5<T>, data: &[u8])
+    where
+        T: Unsigned16To64 + CipherMagicConstants + Copy;
+}
+
+pub fn dispatch<F: Target>(data: &[u8]) {
+    let (word_bits, rounds, key, data) = match parameters(data) {
+        Some(parameters) => parameters,
+        None => return,
+    };
+    let key_len = key.len();
+    match word_bits {
+        16 => F::run(&RC5::<u16>::new(key.to_vec(), 2, rounds, key_len), data),
+        32 => F::run(&RC5::<u32>::new(key.to_vec(), 4, rounds, key_len), data),
+        _ => F::run(&RC5::<u64>::new(key.to_vec(), 8, rounds, key_len), data),
+    }
+}
+
+// the word size in bits, the rounds, the key and the rest of the input
+pub fn parameters(data: &[u8]) -> Option<(u32, usize, &[u8], &[u8])> {
+    let (word, rounds, key_len, rest) = match data {
+        [word, rounds, key_len, rest @ ..] => (*word, *rounds as usize, *key_len as usize, rest),
+        _ => return None,
+    };
+    let key_len = key_len % (MAX_KEY_LEN + 1);
+    if rest.len() < key_len {
+        return None;
+    }
+    let (key, rest) = rest.split_at(key_len);
+    Some((16 << (word % 3), rounds, key, rest))
+}
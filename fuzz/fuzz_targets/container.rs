@@ -0,0 +1,35 @@
+// arbitrary bytes read as a chunked EAX container. The length fields are
+// untrusted, so a bad header, a chunk that lies about its length,
+// truncation and forgery must all end in an error, never a panic or a
+// huge allocation
+#![no_main]
+
+mod common;
+
+use common::Target;
+use libfuzzer_sys::fuzz_target;
+use rc5_test::{CipherMagicConstants, ContainerReader, Unsigned16To64, RC5};
+
+struct Container;
+
+impl Target for Container {
+    fn run<T>(cipher: &RC5<T>, data: &[u8])
+    where
+        T: Unsigned16To64 + CipherMagicConstants + Copy,
+    {
+        let mut reader = match ContainerReader::new(data, cipher) {
+            Ok(reader) => reader,
+            Err(_) => return,
+        };
+        let mut total = 0;
+        while let Ok(Some(chunk)) = reader.next_chunk() {
+            assert!(chunk.len() <= reader.chunk_size() as usize);
+            total += chunk.len();
+        }
+        assert!(total <= data.len());
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    common::dispatch::<Container>(data);
+});
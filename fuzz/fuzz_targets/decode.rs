@@ -0,0 +1,47 @@
+// single blocks of any length through `encode`/`decode` and their `_into`
+// forms, which must reject a wrong length or a short output buffer with an
+// error rather than slicing out of bounds
+#![no_main]
+
+mod common;
+
+use common::Target;
+use libfuzzer_sys::fuzz_target;
+use rc5_test::{CipherMagicConstants, Rc5Decryptor, Rc5Encryptor, Unsigned16To64, RC5};
+
+struct Decode;
+
+impl Target for Decode {
+    fn run<T>(cipher: &RC5<T>, data: &[u8])
+    where
+        T: Unsigned16To64 + CipherMagicConstants + Copy,
+    {
+        let block_len = 2 * std::mem::size_of::<T>();
+        let (out_len, block) = match data.split_first() {
+            Some((out_len, block)) => (*out_len as usize % (2 * block_len + 1), block),
+            None => return,
+        };
+
+        match cipher.decode(block) {
+            Ok(plaintext) => {
+                assert_eq!(block.len(), block_len);
+                assert_eq!(cipher.encode(&plaintext).as_deref(), Ok(block));
+            }
+            Err(_) => assert!(cipher.encode(block).is_err()),
+        }
+
+        let mut out = vec![0u8; out_len];
+        if let Ok(written) = cipher.decode_into(block, &mut out) {
+            assert_eq!(written, block_len);
+            assert_eq!(cipher.decode(block).as_deref(), Ok(&out[..written]));
+        }
+        if let Ok(written) = cipher.encode_into(block, &mut out) {
+            assert_eq!(written, block_len);
+            assert_eq!(cipher.encode(block).as_deref(), Ok(&out[..written]));
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    common::dispatch::<Decode>(data);
+});
@@ -0,0 +1,68 @@
+// arbitrary ciphertext through the streaming ECB/CBC decryptor with every
+// padding scheme. Malformed padding and truncated input are errors from
+// `finalize`, and whatever is accepted must encrypt back to the same bytes
+#![no_main]
+
+mod common;
+
+use common::Target;
+use libfuzzer_sys::fuzz_target;
+use rc5_test::{
+    CipherMagicConstants, Mode, Padding, StreamDecryptor, StreamEncryptor, Unsigned16To64, RC5,
+};
+
+struct PaddedDecrypt;
+
+impl Target for PaddedDecrypt {
+    fn run<T>(cipher: &RC5<T>, data: &[u8])
+    where
+        T: Unsigned16To64 + CipherMagicConstants + Copy,
+    {
+        let block_len = 2 * std::mem::size_of::<T>();
+        let (selector, rest) = match data.split_first() {
+            Some((selector, rest)) => (*selector, rest),
+            None => return,
+        };
+        let padding = [
+            Padding::Pkcs7,
+            Padding::Iso7816,
+            Padding::AnsiX923,
+            Padding::Zero,
+        ][selector as usize & 3];
+        let (mode, ciphertext) = if selector & 4 == 0 || rest.len() < block_len {
+            (Mode::Ecb, rest)
+        } else {
+            let (iv, ciphertext) = rest.split_at(block_len);
+            (Mode::Cbc { iv: iv.to_vec() }, ciphertext)
+        };
+
+        let mut decryptor = match StreamDecryptor::new(cipher, mode.clone()) {
+            Ok(decryptor) => decryptor.with_padding(padding),
+            Err(_) => return,
+        };
+        // split in two so the buffering between calls gets exercised too
+        let middle = (selector as usize >> 3) % (ciphertext.len() + 1);
+        let mut plaintext = decryptor.update(&ciphertext[..middle]);
+        plaintext.extend(decryptor.update(&ciphertext[middle..]));
+        let rest = match decryptor.finalize() {
+            Ok(rest) => rest,
+            Err(_) => return,
+        };
+        plaintext.extend(rest);
+
+        // zero padding is the only scheme that isn't checked, it strips
+        // whatever zeros end the message
+        if padding != Padding::Zero {
+            let mut encryptor = StreamEncryptor::new(cipher, mode)
+                .unwrap()
+                .with_padding(padding);
+            let mut encrypted = encryptor.update(&plaintext);
+            encrypted.extend(encryptor.finalize());
+            assert_eq!(encrypted, ciphertext);
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    common::dispatch::<PaddedDecrypt>(data);
+});
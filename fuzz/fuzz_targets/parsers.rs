@@ -0,0 +1,52 @@
+// the parsers that take bytes from outside: the DER encoded RC5-CBC
+// parameters, alone and inside an AlgorithmIdentifier, and the stream
+// snapshots `resume` accepts. Anything parsed must encode back to
+// something that parses the same
+#![no_main]
+
+mod common;
+
+use common::Target;
+use libfuzzer_sys::fuzz_target;
+use rc5_test::{
+    CipherMagicConstants, Rc5CbcParameters, StreamDecryptor, StreamEncryptor, Unsigned16To64, RC5,
+};
+
+struct Snapshots;
+
+impl Target for Snapshots {
+    fn run<T>(cipher: &RC5<T>, data: &[u8])
+    where
+        T: Unsigned16To64 + CipherMagicConstants + Copy,
+    {
+        if let Ok(mut encryptor) = StreamEncryptor::resume(cipher, data) {
+            let snapshot = encryptor.snapshot();
+            assert!(StreamEncryptor::resume(cipher, &snapshot).is_ok());
+            encryptor.update(data);
+            encryptor.finalize();
+        }
+        if let Ok(mut decryptor) = StreamDecryptor::resume(cipher, data) {
+            let snapshot = decryptor.snapshot();
+            assert!(StreamDecryptor::resume(cipher, &snapshot).is_ok());
+            decryptor.update(data);
+            let _ = decryptor.finalize();
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(parameters) = Rc5CbcParameters::from_der(data) {
+        assert_eq!(
+            Rc5CbcParameters::from_der(&parameters.to_der()),
+            Ok(parameters)
+        );
+    }
+    if let Ok(parameters) = Rc5CbcParameters::from_algorithm_identifier_der(data) {
+        let der = parameters.to_algorithm_identifier_der();
+        assert_eq!(
+            Rc5CbcParameters::from_algorithm_identifier_der(&der),
+            Ok(parameters)
+        );
+    }
+    common::dispatch::<Snapshots>(data);
+});
@@ -0,0 +1,119 @@
+/*
+ * C interface of the rc5_test crate, exported with the `ffi` feature.
+ * Build the library with
+ *
+ *   cargo rustc --release --features ffi --crate-type staticlib
+ *
+ * (or `cdylib`) and link against target/release/librc5_test.a. Keep this
+ * file in step with src/ffi.rs.
+ *
+ * Every fallible function returns an rc5_status, and on failure leaves a
+ * description for rc5_last_error.
+ */
+#ifndef RC5_H
+#define RC5_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * The status returned by every fallible function. The values are stable:
+ * a value never changes meaning and new failures get new values.
+ */
+typedef enum {
+    RC5_OK = 0,
+    RC5_ERR_NULL_POINTER = -1,
+    RC5_ERR_INVALID_ARGUMENT = -2,
+    RC5_ERR_EMPTY_KEY = -3,
+    RC5_ERR_INVALID_WORD_SIZE = -4,
+    RC5_ERR_INVALID_KEY_LENGTH = -5,
+    RC5_ERR_INVALID_BLOCK_LENGTH = -6,
+    RC5_ERR_INVALID_CIPHERTEXT_LENGTH = -7,
+    RC5_ERR_INVALID_IV_LENGTH = -8,
+    RC5_ERR_INVALID_NONCE_LENGTH = -9,
+    /* bad padding or truncated ciphertext */
+    RC5_ERR_DECRYPTION_FAILED = -10,
+    RC5_ERR_BUFFER_TOO_SMALL = -11,
+    RC5_ERR_INVALID_SNAPSHOT = -12,
+    RC5_ERR_INVALID_ENCODING = -13,
+    /* a bug in the library, caught before it could unwind into the caller */
+    RC5_ERR_PANIC = -14,
+    RC5_ERR_INVALID_KDF_PARAMETERS = -15,
+    RC5_ERR_RNG_UNAVAILABLE = -16,
+    RC5_ERR_UNKNOWN_KEY_ID = -17,
+    RC5_ERR_KEY_STORE_UNAVAILABLE = -18,
+    /* a header asks for parameters the reader doesn't accept */
+    RC5_ERR_PARAMETERS_NOT_ALLOWED = -19,
+    /* decrypted text isn't UTF-8 */
+    RC5_ERR_INVALID_UTF8 = -20,
+    /* more than 255 rounds, or more than a key schedule has */
+    RC5_ERR_INVALID_ROUNDS = -21,
+    /* a container's key check value isn't that of the key */
+    RC5_ERR_WRONG_KEY = -22
+} rc5_status;
+
+/* ECB and CBC add PKCS#7 padding, CTR doesn't */
+#define RC5_MODE_ECB 0
+#define RC5_MODE_CBC 1
+#define RC5_MODE_CTR 2
+
+typedef struct Rc5Cipher rc5_cipher;
+
+/*
+ * Creates a cipher for RC5-word_bits/rounds (word_bits is 16, 32 or 64,
+ * rounds at most 255) with the key_len bytes at key, and stores it in
+ * *cipher. The key is copied. Release the cipher with rc5_cipher_free.
+ * More rounds fail with RC5_ERR_INVALID_ROUNDS.
+ */
+rc5_status rc5_cipher_new(const uint8_t *key, size_t key_len,
+                          uint32_t word_bits, uint32_t rounds,
+                          rc5_cipher **cipher);
+
+/* Wipes and releases a cipher. NULL is ignored. */
+void rc5_cipher_free(rc5_cipher *cipher);
+
+/* The block length in bytes, which is also the IV/nonce length. */
+size_t rc5_block_len(const rc5_cipher *cipher);
+
+/*
+ * Encrypts input_len bytes at input in mode (RC5_MODE_*), with the
+ * iv_len byte IV or nonce at iv for CBC and CTR (ignored for ECB), into
+ * the output_cap bytes at output. *output_len receives the ciphertext
+ * length even when it returns RC5_ERR_BUFFER_TOO_SMALL, so the call can
+ * be repeated with a large enough buffer; input_len plus one block is
+ * always enough.
+ */
+rc5_status rc5_encrypt(const rc5_cipher *cipher, int mode,
+                       const uint8_t *iv, size_t iv_len,
+                       const uint8_t *input, size_t input_len,
+                       uint8_t *output, size_t output_cap,
+                       size_t *output_len);
+
+/*
+ * The inverse of rc5_encrypt. input_len bytes are always enough for the
+ * plaintext.
+ */
+rc5_status rc5_decrypt(const rc5_cipher *cipher, int mode,
+                       const uint8_t *iv, size_t iv_len,
+                       const uint8_t *input, size_t input_len,
+                       uint8_t *output, size_t output_cap,
+                       size_t *output_len);
+
+/*
+ * Describes the last failure on the calling thread, starting with the
+ * name of the function that failed, or returns NULL if the last fallible
+ * call succeeded or nothing has been called yet. The string is owned by
+ * the library and stays valid until the next fallible call on the same
+ * thread.
+ */
+const char *rc5_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RC5_H */
@@ -0,0 +1,610 @@
+use crate::traits::{Block, CipherMagicConstants, Rc5Decryptor, Rc5Encryptor, Unsigned16To64};
+use crate::vectors::SplitMix64;
+use crate::{heapless, secure, Rc5Error, RC5};
+
+// how far one flipped plaintext bit has spread after a number of rounds,
+// as measured by `RC5::avalanche`. Round 0 is the state after the initial
+// key addition, which flips exactly the one bit. Fractions are of the
+// block's bits, and full diffusion puts every one of them near 1/2
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoundAvalanche {
+    pub round: usize,
+    // mean fraction of the state's bits that flip
+    pub mean: f64,
+    // standard deviation of that fraction over all samples and input bits
+    pub std_dev: f64,
+    // fewest and most bits flipped by any single input bit flip
+    pub min_flipped: u32,
+    pub max_flipped: u32,
+    // the strict avalanche criterion: how far from 1/2, at worst, the
+    // probability is that flipping a given input bit flips a given state
+    // bit. Each probability is estimated from the samples, so even ideal
+    // diffusion leaves a few times 1 / (2 * sqrt(samples)) here
+    pub sac_deviation: f64,
+}
+
+// how often each data-dependent rotation amount came up in one round, as
+// counted by `RC5::rotation_histograms`. `a[r]` counts the blocks whose A
+// half round rotated by r, that is by B mod w, and `b[r]` those whose B
+// half round did, by the new A mod w. Small amounts, 0 above all, barely
+// mix the words and are what the weak-rotation attacks on RC5 look for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RotationHistogram {
+    pub round: usize,
+    pub a: Vec<u64>,
+    pub b: Vec<u64>,
+}
+
+impl RotationHistogram {
+    // the number of blocks counted, in either half
+    pub fn samples(&self) -> u64 {
+        self.a.iter().sum()
+    }
+
+    // Pearson's chi-squared statistic of both halves against uniform
+    // amounts, with 2 * (w - 1) degrees of freedom. 0 for no samples
+    pub fn chi_squared(&self) -> f64 {
+        if self.samples() == 0 {
+            return 0.0;
+        }
+        let expected = self.samples() as f64 / self.a.len() as f64;
+        self.a
+            .iter()
+            .chain(&self.b)
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+}
+
+// every step of a key expansion, as recorded by
+// `RC5::trace_key_expansion`. It holds the key and everything derived from
+// it, and is wiped when dropped
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyExpansionTrace<T: Unsigned16To64 + Copy> {
+    // the key as c little-endian words, before any mixing
+    pub key_words: Vec<T>,
+    // the S table filled from P_w and Q_w, before any mixing
+    pub initial_table: Vec<T>,
+    // one entry per mixing iteration, 3 * max(t, c) in all
+    pub steps: Vec<MixingStep<T>>,
+    // the expanded S table the cipher encrypts with
+    pub s_table: Vec<T>,
+}
+
+// the state after one mixing iteration, which has just set S[i] = A and
+// L[j] = B
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MixingStep<T> {
+    pub i: usize,
+    pub j: usize,
+    pub a: T,
+    pub b: T,
+}
+
+impl<T: Unsigned16To64 + Copy> Drop for KeyExpansionTrace<T> {
+    fn drop(&mut self) {
+        secure::release(&mut self.key_words);
+        secure::release(&mut self.initial_table);
+        for step in self.steps.iter_mut() {
+            secure::release(std::slice::from_mut(&mut step.a));
+            secure::release(std::slice::from_mut(&mut step.b));
+        }
+        secure::release(&mut self.s_table);
+    }
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> RC5<T> {
+    // expands `key` for `rounds` rounds like `RC5::new`, recording every
+    // mixing iteration along the way. A key expansion that disagrees with
+    // another implementation can be pinned to its first differing step
+    // this way, where the ciphertext only shows that something differs
+    pub fn trace_key_expansion(
+        key: &[u8],
+        rounds: usize,
+    ) -> Result<KeyExpansionTrace<T>, Rc5Error> {
+        if key.is_empty() {
+            return Err(Rc5Error::EmptyKey);
+        }
+
+        let mut l = vec![T::zero(); key.len().div_ceil(std::mem::size_of::<T>())];
+        heapless::load_key(key, &mut l);
+        let mut s_table = vec![T::zero(); heapless::table_len(rounds)];
+        heapless::fill_table(&mut s_table);
+
+        let mut trace = KeyExpansionTrace {
+            key_words: l.clone(),
+            initial_table: s_table.clone(),
+            steps: Vec::with_capacity(3 * l.len().max(s_table.len())),
+            s_table: Vec::new(),
+        };
+        heapless::mix_into_table(&mut l, &mut s_table, |i, j, a, b| {
+            trace.steps.push(MixingStep { i, j, a, b })
+        });
+        secure::release(&mut l);
+
+        trace.s_table = s_table;
+        Ok(trace)
+    }
+
+    // encrypts every block of `corpus` and counts the rotation amounts of
+    // each round, giving one histogram per round from 1 to `rounds`. Every
+    // item has to be exactly one block
+    pub fn rotation_histograms<I, B>(&self, corpus: I) -> Result<Vec<RotationHistogram>, Rc5Error>
+    where
+        I: IntoIterator<Item = B>,
+        B: AsRef<[u8]>,
+    {
+        let schedule = self.schedule()?;
+        let bits = 8 * std::mem::size_of::<T>();
+        let amount = |word: T| word.to_le_bytes().as_ref()[0] as usize % bits;
+        let mut histograms = (1..=self.rounds)
+            .map(|round| RotationHistogram {
+                round,
+                a: vec![0; bits],
+                b: vec![0; bits],
+            })
+            .collect::<Vec<RotationHistogram>>();
+
+        for block in corpus {
+            let block = block.as_ref();
+            if block.len() != schedule.block_len() {
+                return Err(Rc5Error::InvalidBlockLength);
+            }
+            let registers = round_registers(schedule.s_table(), self.rounds, block);
+            for (histogram, pair) in histograms.iter_mut().zip(registers.windows(2)) {
+                histogram.a[amount(pair[0].1)] += 1;
+                histogram.b[amount(pair[1].0)] += 1;
+            }
+        }
+        Ok(histograms)
+    }
+
+    // a JSON dump of the encryption of `block` for visualizations: the
+    // parameters, the expanded S table, and the registers after the key
+    // addition and after each round together with the round's two
+    // rotation amounts. Words are big-endian hex strings, since 64-bit
+    // words don't fit the numbers of most JSON readers. The S table is
+    // derived from the key, so a dump of a real key is as secret as it
+    pub fn trace_to_json(&self, block: &[u8]) -> Result<String, Rc5Error> {
+        let schedule = self.schedule()?;
+        let registers = self.encode_traced(block)?;
+        let bits = 8 * self.words;
+        let amount = |word: T| word.to_le_bytes().as_ref()[0] as usize % bits;
+
+        let s_table = schedule
+            .s_table()
+            .iter()
+            .map(|&word| format!("\"{}\"", word_hex(word)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let rounds = registers
+            .iter()
+            .enumerate()
+            .map(|(round, &(a, b))| {
+                let rotations = match round {
+                    0 => String::new(),
+                    _ => format!(
+                        " \"rotationA\": {}, \"rotationB\": {},",
+                        amount(registers[round - 1].1),
+                        amount(a)
+                    ),
+                };
+                format!(
+                    "    {{ \"round\": {},{} \"a\": \"{}\", \"b\": \"{}\" }}",
+                    round,
+                    rotations,
+                    word_hex(a),
+                    word_hex(b)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",\n");
+        let (a, b) = registers[self.rounds];
+
+        Ok(format!(
+            "{{\n  \"algorithm\": \"RC5-{}/{}/{}\",\n  \"plaintext\": \"{}\",\n  \"ciphertext\": \"{}{}\",\n  \"schedule\": [{}],\n  \"rounds\": [\n{}\n  ]\n}}\n",
+            bits,
+            self.rounds,
+            self.bytes,
+            hex(block),
+            hex(a.to_le_bytes().as_ref()),
+            hex(b.to_le_bytes().as_ref()),
+            s_table,
+            rounds
+        ))
+    }
+
+    // encrypts one block like `encode`, but returns the (A, B) registers
+    // after the key addition and after every round instead: `rounds + 1`
+    // pairs, the last of which is the ciphertext. Implementations in other
+    // languages can be checked against these step by step
+    pub fn encode_traced(&self, block: &[u8]) -> Result<Vec<(T, T)>, Rc5Error> {
+        let schedule = self.schedule()?;
+        if block.len() != schedule.block_len() {
+            return Err(Rc5Error::InvalidBlockLength);
+        }
+        Ok(round_registers(schedule.s_table(), self.rounds, block))
+    }
+
+    // flips each plaintext bit of `samples` random blocks in turn and
+    // compares the two states after every round of this cipher's key
+    // schedule, giving one entry per round from 0 to `rounds`. The blocks
+    // come from SplitMix64 started at `seed`, so a measurement can be
+    // repeated exactly. Inputs are independent, so the variation between
+    // seeds shrinks with more samples
+    pub fn avalanche(&self, samples: usize, seed: u64) -> Result<Vec<RoundAvalanche>, Rc5Error> {
+        let schedule = self.schedule()?;
+        let s = schedule.s_table();
+        let block_len = schedule.block_len();
+        let bits = 8 * block_len;
+        let mut random = SplitMix64(seed);
+
+        // per round: the number of flipped bits for each trial, and how
+        // often each (input bit, state bit) pair flipped
+        let trials = samples * bits;
+        let mut flipped = vec![Vec::with_capacity(trials); self.rounds + 1];
+        let mut pairs = vec![vec![0u32; bits * bits]; self.rounds + 1];
+
+        for _ in 0..samples {
+            let block = random.bytes(block_len);
+            let states = round_states(s, self.rounds, &block);
+
+            for input_bit in 0..bits {
+                let mut changed = block.clone();
+                changed[input_bit / 8] ^= 1 << (input_bit % 8);
+                let changed_states = round_states(s, self.rounds, &changed);
+
+                for (round, (a, b)) in states.iter().zip(&changed_states).enumerate() {
+                    let mut count = 0;
+                    for (byte, (x, y)) in a.iter().zip(b).enumerate() {
+                        let difference = x ^ y;
+                        count += difference.count_ones();
+                        for bit in 0..8 {
+                            if difference & (1 << bit) != 0 {
+                                pairs[round][input_bit * bits + 8 * byte + bit] += 1;
+                            }
+                        }
+                    }
+                    flipped[round].push(count);
+                }
+            }
+        }
+
+        Ok(flipped
+            .iter()
+            .zip(&pairs)
+            .enumerate()
+            .map(|(round, (flipped, pairs))| {
+                let fractions = flipped.iter().map(|&count| count as f64 / bits as f64);
+                let mean = fractions.clone().sum::<f64>() / trials as f64;
+                let variance =
+                    fractions.map(|x| (x - mean) * (x - mean)).sum::<f64>() / trials as f64;
+                let sac_deviation = pairs
+                    .iter()
+                    .map(|&count| (count as f64 / samples as f64 - 0.5).abs())
+                    .fold(0.0, f64::max);
+
+                RoundAvalanche {
+                    round,
+                    mean,
+                    std_dev: variance.sqrt(),
+                    min_flipped: flipped.iter().copied().min().unwrap_or(0),
+                    max_flipped: flipped.iter().copied().max().unwrap_or(0),
+                    sac_deviation,
+                }
+            })
+            .collect())
+    }
+}
+
+// an RC5 cut short after its first `stop_after_round` rounds, for
+// reproducing attacks on reduced-round RC5 with this implementation as
+// the oracle. It keeps the key schedule of the full cipher, so its
+// rounds are exactly the first rounds of the full cipher, and round 0 is
+// just the key addition. Decryption undoes the same rounds
+#[derive(Clone, Debug)]
+pub struct ReducedRc5<T: Unsigned16To64> {
+    cipher: RC5<T>,
+    stop_after_round: usize,
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> ReducedRc5<T> {
+    // fails with `InvalidRounds` past the rounds of `cipher`, which has
+    // no key words for them
+    pub fn new(cipher: &RC5<T>, stop_after_round: usize) -> Result<Self, Rc5Error> {
+        if stop_after_round > cipher.rounds {
+            return Err(Rc5Error::InvalidRounds);
+        }
+        Ok(ReducedRc5 {
+            cipher: cipher.clone(),
+            stop_after_round,
+        })
+    }
+
+    pub fn stop_after_round(&self) -> usize {
+        self.stop_after_round
+    }
+
+    // the block after the key addition and after each round up to the
+    // last one, in the byte order of a ciphertext block
+    pub fn states(&self, block: &[u8]) -> Result<Vec<Vec<u8>>, Rc5Error> {
+        let schedule = self.cipher.schedule()?;
+        if block.len() != schedule.block_len() {
+            return Err(Rc5Error::InvalidBlockLength);
+        }
+        Ok(round_states(
+            schedule.s_table(),
+            self.stop_after_round,
+            block,
+        ))
+    }
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> Rc5Encryptor<T> for ReducedRc5<T> {
+    fn encrypt_block_in_place(&self, block: &mut Block<T>) -> Result<(), Rc5Error> {
+        let s_table = self.cipher.schedule()?.s_table();
+        heapless::encrypt_block(s_table, self.stop_after_round, block.as_mut());
+        Ok(())
+    }
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> Rc5Decryptor<T> for ReducedRc5<T> {
+    fn decrypt_block_in_place(&self, block: &mut Block<T>) -> Result<(), Rc5Error> {
+        let s_table = self.cipher.schedule()?.s_table();
+        heapless::decrypt_block(s_table, self.stop_after_round, block.as_mut());
+        Ok(())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// the value of a word, most significant digit first
+fn word_hex<T: Unsigned16To64>(word: T) -> String {
+    let mut bytes = word.to_le_bytes().as_ref().to_vec();
+    bytes.reverse();
+    hex(&bytes)
+}
+
+// the (A, B) registers after the key addition and after each round
+fn round_registers<T>(s: &[T], rounds: usize, block: &[u8]) -> Vec<(T, T)>
+where
+    T: Unsigned16To64 + Copy,
+{
+    let words = block.len() / 2;
+    let mut a = T::from_le_bytes(T::copy_from_slice(block, 0, words)).wrapping_add(s[0]);
+    let mut b = T::from_le_bytes(T::copy_from_slice(block, words, 2 * words)).wrapping_add(s[1]);
+    let mut registers = vec![(a, b)];
+    for i in 1..=rounds {
+        a = a.xor(b).rotate_left(b).wrapping_add(s[2 * i]);
+        b = b.xor(a).rotate_left(a).wrapping_add(s[2 * i + 1]);
+        registers.push((a, b));
+    }
+    registers
+}
+
+// the block after the key addition and after each round, in the byte
+// order of a ciphertext block
+fn round_states<T>(s: &[T], rounds: usize, block: &[u8]) -> Vec<Vec<u8>>
+where
+    T: Unsigned16To64 + Copy,
+{
+    round_registers(s, rounds, block)
+        .into_iter()
+        .map(|(a, b)| {
+            let mut bytes = a.to_le_bytes().as_ref().to_vec();
+            bytes.extend_from_slice(b.to_le_bytes().as_ref());
+            bytes
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vectors::Json;
+
+    fn cipher(rounds: usize) -> RC5<u32> {
+        RC5::new((0u8..16).collect::<Vec<u8>>(), 4, rounds, 16)
+    }
+
+    #[test]
+    fn the_last_state_is_the_ciphertext() {
+        let cipher = cipher(12);
+        let schedule = cipher.schedule().unwrap();
+        let block = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let states = round_states(schedule.s_table(), 12, &block);
+        assert_eq!(states.len(), 13);
+        assert_eq!(states[12], cipher.encode(&block).unwrap());
+    }
+
+    #[test]
+    fn traces_start_from_the_key_addition() {
+        let cipher = cipher(12);
+        let s = cipher.schedule().unwrap().s_table().to_vec();
+        let block = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let trace = cipher.encode_traced(&block).unwrap();
+
+        assert_eq!(trace.len(), 13);
+        assert_eq!(
+            trace[0],
+            (
+                0x3322_1100u32.wrapping_add(s[0]),
+                0x7766_5544u32.wrapping_add(s[1])
+            )
+        );
+        let (a, b) = trace[0];
+        let a = (a ^ b).rotate_left(b).wrapping_add(s[2]);
+        assert_eq!(trace[1], (a, (b ^ a).rotate_left(a).wrapping_add(s[3])));
+
+        let (a, b) = trace[12];
+        let mut ciphertext = a.to_le_bytes().to_vec();
+        ciphertext.extend_from_slice(&b.to_le_bytes());
+        assert_eq!(ciphertext, cipher.encode(&block).unwrap());
+
+        assert_eq!(
+            cipher.encode_traced(&block[..4]),
+            Err(Rc5Error::InvalidBlockLength)
+        );
+        assert_eq!(
+            RC5::<u32>::new(vec![], 4, 12, 0).encode_traced(&block),
+            Err(Rc5Error::EmptyKey)
+        );
+    }
+
+    #[test]
+    fn key_expansion_traces_end_in_the_schedule() {
+        let key = (0u8..10).collect::<Vec<u8>>();
+        let trace = RC5::<u32>::trace_key_expansion(&key, 12).unwrap();
+
+        // 26 table words outnumber the 3 key words
+        assert_eq!(trace.key_words, [0x0302_0100, 0x0706_0504, 0x0908]);
+        assert_eq!(trace.initial_table[..2], [0xb7e1_5163, 0x5618_cb1c]);
+        assert_eq!(trace.steps.len(), 3 * 26);
+        assert_eq!((trace.steps[0].i, trace.steps[0].j), (0, 0));
+        assert_eq!((trace.steps[27].i, trace.steps[27].j), (1, 0));
+        assert_eq!(trace.steps[0].a, 0xb7e1_5163u32.rotate_left(3));
+
+        // the last write to each S[i] is what the cipher encrypts with
+        let cipher = RC5::<u32>::new(&key, 4, 12, 10);
+        assert_eq!(trace.s_table, cipher.schedule().unwrap().s_table());
+        for step in &trace.steps[52..] {
+            assert_eq!(trace.s_table[step.i], step.a);
+        }
+
+        assert_eq!(
+            RC5::<u32>::trace_key_expansion(&[], 12),
+            Err(Rc5Error::EmptyKey)
+        );
+    }
+
+    #[test]
+    fn reduced_ciphers_are_the_first_rounds_of_the_full_one() {
+        let full = cipher(12);
+        let block = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let states = full.encode_traced(&block).unwrap();
+
+        for (stop, &(a, b)) in states.iter().enumerate() {
+            let reduced = ReducedRc5::new(&full, stop).unwrap();
+            let mut expected = a.to_le_bytes().to_vec();
+            expected.extend_from_slice(&b.to_le_bytes());
+
+            let ct = reduced.encode(&block).unwrap();
+            assert_eq!(ct, expected, "round {}", stop);
+            assert_eq!(reduced.decode(&ct).unwrap(), block);
+            assert_eq!(reduced.states(&block).unwrap().len(), stop + 1);
+            assert_eq!(reduced.states(&block).unwrap()[stop], expected);
+        }
+        assert_eq!(
+            ReducedRc5::new(&full, 12).unwrap().encode(&block),
+            full.encode(&block)
+        );
+
+        assert_eq!(
+            ReducedRc5::new(&full, 13).err(),
+            Some(Rc5Error::InvalidRounds)
+        );
+        let reduced = ReducedRc5::new(&full, 4).unwrap();
+        assert_eq!(
+            reduced.states(&block[..4]),
+            Err(Rc5Error::InvalidBlockLength)
+        );
+        assert_eq!(
+            reduced.encode(&block[..4]),
+            Err(Rc5Error::InvalidBlockLength)
+        );
+    }
+
+    #[test]
+    fn rotation_amounts_come_from_the_registers() {
+        let cipher = cipher(12);
+        let mut random = SplitMix64(5);
+        let corpus = (0..4096).map(|_| random.bytes(8)).collect::<Vec<Vec<u8>>>();
+        let histograms = cipher.rotation_histograms(&corpus).unwrap();
+
+        assert_eq!(histograms.len(), 12);
+        assert_eq!(histograms[0].round, 1);
+        assert!(histograms
+            .iter()
+            .all(|histogram| histogram.samples() == 4096));
+
+        // the first A rotation is by B + S[1], the first B one by the new A
+        let trace = cipher.encode_traced(&corpus[0]).unwrap();
+        let single = cipher.rotation_histograms(&corpus[..1]).unwrap();
+        assert_eq!(single[0].a[trace[0].1 as usize % 32], 1);
+        assert_eq!(single[0].b[trace[1].0 as usize % 32], 1);
+        assert_eq!(single[11].b[trace[12].0 as usize % 32], 1);
+
+        // random blocks rotate by uniform amounts, for which the statistic
+        // is about its 62 degrees of freedom
+        for histogram in &histograms {
+            assert!(histogram.chi_squared() < 120.0, "{:?}", histogram);
+        }
+        // repeating one block puts every count into one amount
+        let repeated = cipher.rotation_histograms(vec![[0u8; 8]; 64]).unwrap();
+        assert!(repeated[0].chi_squared() > 1000.0);
+
+        assert_eq!(
+            cipher.rotation_histograms(vec![[0u8; 4]]),
+            Err(Rc5Error::InvalidBlockLength)
+        );
+    }
+
+    #[test]
+    fn json_traces_parse_back() {
+        let cipher = cipher(12);
+        let block = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let json = Json::parse(&cipher.trace_to_json(&block).unwrap()).unwrap();
+        let trace = cipher.encode_traced(&block).unwrap();
+
+        assert_eq!(json.str("algorithm"), Ok("RC5-32/12/16"));
+        assert_eq!(json.str("plaintext"), Ok("0011223344556677"));
+        assert_eq!(json.hex("ciphertext"), Ok(cipher.encode(&block).unwrap()));
+        let schedule = json.array("schedule").unwrap();
+        assert_eq!(schedule.len(), 26);
+        assert_eq!(
+            schedule[0],
+            Json::String(format!("{:08x}", cipher.schedule().unwrap().s_table()[0]))
+        );
+
+        let rounds = json.array("rounds").unwrap();
+        assert_eq!(rounds.len(), 13);
+        assert_eq!(rounds[0].int("round"), Ok(0));
+        assert!(rounds[0].get("rotationA").is_none());
+        assert_eq!(rounds[12].str("b"), Ok(&*format!("{:08x}", trace[12].1)));
+        assert_eq!(rounds[1].int("rotationA"), Ok(i64::from(trace[0].1 % 32)));
+        assert_eq!(rounds[1].int("rotationB"), Ok(i64::from(trace[1].0 % 32)));
+
+        assert_eq!(
+            cipher.trace_to_json(&block[..4]),
+            Err(Rc5Error::InvalidBlockLength)
+        );
+    }
+
+    #[test]
+    fn diffusion_grows_with_the_rounds() {
+        let report = cipher(12).avalanche(256, 7).unwrap();
+        assert_eq!(report.len(), 13);
+        assert_eq!(report, cipher(12).avalanche(256, 7).unwrap());
+
+        // the key addition only moves the flipped bit, carries aside
+        assert!(report[0].min_flipped >= 1);
+        assert!(report[0].mean < 0.1);
+        assert!(report[0].sac_deviation > 0.4);
+
+        // RC5-32 is fully diffused a few rounds in and stays that way, with
+        // flips as spread out as for a random permutation: binomial, with
+        // a standard deviation of sqrt(64 / 4) of the 64 bits
+        for round in &report[6..] {
+            assert!((round.mean - 0.5).abs() < 0.01, "{:?}", round);
+            assert!((round.std_dev - 0.0625).abs() < 0.005, "{:?}", round);
+            assert!(round.sac_deviation < 0.15, "{:?}", round);
+        }
+
+        assert_eq!(
+            RC5::<u32>::new(vec![], 4, 12, 0).avalanche(1, 0),
+            Err(Rc5Error::EmptyKey)
+        );
+    }
+}
@@ -0,0 +1,512 @@
+use std::convert::{TryFrom, TryInto};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::metadata::is_file_name;
+use crate::rng::CryptoRng;
+use crate::{
+    ContainerReader, ContainerWriter, FileMetadata, Rc5SecretKey, DEFAULT_CHUNK_SIZE, RC5,
+};
+
+// Archive format, many files in one authenticated file:
+//
+//   header:  magic "RC5A" | version u8 | word bytes u8 | rounds u8 |
+//            key bytes u8 | salt (16 bytes)
+//   entries: one `ContainerWriter` output per file
+//   index:   `ContainerWriter` output of the encoded index
+//   trailer: index offset u64
+//
+// with little-endian integers and offsets from the start of the header.
+// The index lists every entry as
+//
+//   path length u16 | path | size u64 | offset u64 | length u64 |
+//   metadata length u16 | `FileMetadata` encoding
+//
+// after an entry count u32, so names and sizes are encrypted too, and any
+// entry can be read without the others. Each entry and the index have a
+// key of their own, derived from the archive key for the header bytes and
+// their position, which makes entries fail to open when they are moved,
+// swapped or come from another archive. With a key per container, the
+// nonces don't have to differ
+const MAGIC: &[u8; 4] = b"RC5A";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = 8 + SALT_LEN;
+const TRAILER_LEN: u64 = 8;
+// archives are RC5-32/12 with 16 byte subkeys, like sealed files
+const WORDS: usize = 4;
+const ROUNDS: usize = 12;
+const KEY_LEN: usize = 16;
+const SUBKEY_INFO: &[u8] = b"rc5 archive";
+// the index takes the position after any entry could
+const INDEX: u64 = u64::MAX;
+const MAX_PATH_LEN: usize = 1 << 12;
+// what an index of millions of files takes, and more than enough to
+// refuse before reading one into memory
+const MAX_INDEX_LEN: u64 = 1 << 30;
+
+// a file stored in an archive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    // relative, with `/` between the components
+    pub path: String,
+    // the length of the file
+    pub size: u64,
+    // the time and permissions, the name being `path`
+    pub metadata: FileMetadata,
+    offset: u64,
+    length: u64,
+}
+
+// writes an archive one file at a time. `finish` writes the index, and
+// without it the archive doesn't open. After an `add` fails partway, so
+// does everything else, as the output holds a broken entry whose key the
+// next one would reuse
+pub struct ArchiveWriter<W: Write> {
+    writer: Counted<W>,
+    header: [u8; HEADER_LEN],
+    key: Rc5SecretKey,
+    entries: Vec<ArchiveEntry>,
+    failed: bool,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    // writes the header right away, with a salt from `rng`
+    pub fn new<G: CryptoRng>(mut rng: G, writer: W, key: &Rc5SecretKey) -> io::Result<Self> {
+        let mut header = [0u8; HEADER_LEN];
+        header[..4].copy_from_slice(MAGIC);
+        header[4] = VERSION;
+        header[5] = WORDS as u8;
+        header[6] = ROUNDS as u8;
+        header[7] = KEY_LEN as u8;
+        rng.fill_bytes(&mut header[8..]);
+
+        let mut writer = Counted {
+            inner: writer,
+            count: 0,
+        };
+        writer.write_all(&header)?;
+        Ok(ArchiveWriter {
+            writer,
+            key: archive_key(key, &header)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?,
+            header,
+            entries: Vec::new(),
+            failed: false,
+        })
+    }
+
+    // adds everything `reader` yields as the file at `path`, which must be
+    // relative without `.` or `..` components and not be in the archive
+    // yet. Returns the size of the file
+    pub fn add<R: Read>(
+        &mut self,
+        path: &str,
+        metadata: &FileMetadata,
+        mut reader: R,
+    ) -> io::Result<u64> {
+        if !is_archive_path(path) {
+            return Err(invalid_input("invalid archive path"));
+        }
+        if self.entries.iter().any(|entry| entry.path == path) {
+            return Err(invalid_input("the path is in the archive already"));
+        }
+        let metadata = FileMetadata {
+            name: None,
+            ..metadata.clone()
+        };
+        metadata.to_bytes().map_err(invalid_input)?;
+        self.check()?;
+
+        self.failed = true;
+        let cipher = subkey_cipher(&self.key, &self.header, self.entries.len() as u64)?;
+        let offset = self.writer.count;
+        let mut container = ContainerWriter::new(
+            &mut self.writer,
+            &cipher,
+            DEFAULT_CHUNK_SIZE,
+            &[0; 2 * WORDS],
+        )?;
+        let size = io::copy(&mut reader, &mut container)?;
+        container.finish()?;
+
+        self.entries.push(ArchiveEntry {
+            path: path.to_string(),
+            size,
+            metadata,
+            offset,
+            length: self.writer.count - offset,
+        });
+        self.failed = false;
+        Ok(size)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.check()?;
+        let index = encode_index(&self.entries)?;
+        let cipher = subkey_cipher(&self.key, &self.header, INDEX)?;
+        let offset = self.writer.count;
+        let mut container = ContainerWriter::new(
+            &mut self.writer,
+            &cipher,
+            DEFAULT_CHUNK_SIZE,
+            &[0; 2 * WORDS],
+        )?;
+        container.write_all(&index)?;
+        container.finish()?;
+        self.writer.write_all(&offset.to_le_bytes())?;
+        self.writer.flush()?;
+        Ok(self.writer.inner)
+    }
+
+    fn check(&self) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::other("an earlier entry of the archive failed"));
+        }
+        Ok(())
+    }
+}
+
+// reads the index of an archive, and any of its files on request. The
+// archive starts where the reader is positioned when it is opened, so it
+// can follow other data
+pub struct ArchiveReader<R: Read + Seek> {
+    reader: R,
+    start: u64,
+    header: [u8; HEADER_LEN],
+    key: Rc5SecretKey,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    // reads and verifies the index. A wrong key or a modified index gives
+    // `InvalidData`, like anything else malformed
+    pub fn new(mut reader: R, key: &Rc5SecretKey) -> io::Result<Self> {
+        let start = reader.stream_position()?;
+        let mut header = [0u8; HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(invalid_data("not an RC5 archive"));
+        }
+        if header[4] != VERSION {
+            return Err(invalid_data("unsupported archive version"));
+        }
+        if header[5..8] != [WORDS as u8, ROUNDS as u8, KEY_LEN as u8] {
+            return Err(invalid_data("unsupported archive parameters"));
+        }
+        let key = archive_key(key, &header).map_err(invalid_data)?;
+
+        let end = reader.seek(SeekFrom::End(0))? - start;
+        if end < HEADER_LEN as u64 + TRAILER_LEN {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        reader.seek(SeekFrom::Start(start + end - TRAILER_LEN))?;
+        let mut offset = [0u8; 8];
+        reader.read_exact(&mut offset)?;
+        let offset = u64::from_le_bytes(offset);
+        let index_end = end - TRAILER_LEN;
+        if offset < HEADER_LEN as u64 || offset > index_end || index_end - offset > MAX_INDEX_LEN {
+            return Err(invalid_data("malformed archive trailer"));
+        }
+
+        reader.seek(SeekFrom::Start(start + offset))?;
+        let cipher = subkey_cipher(&key, &header, INDEX)?;
+        let mut index = Vec::new();
+        ContainerReader::new((&mut reader).take(index_end - offset), &cipher)?
+            .read_to_end(&mut index)?;
+        let entries =
+            decode_index(&index, offset).ok_or_else(|| invalid_data("malformed archive index"))?;
+
+        Ok(ArchiveReader {
+            reader,
+            start,
+            header,
+            key,
+            entries,
+        })
+    }
+
+    // in the order they were added
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    // writes the file at position `entry` of `entries` to `writer` and
+    // returns its size. The file is verified a chunk at a time as it is
+    // written, so after an error whatever reached `writer` must be
+    // discarded
+    pub fn extract<W: Write>(&mut self, entry: usize, mut writer: W) -> io::Result<u64> {
+        let ArchiveEntry {
+            size,
+            offset,
+            length,
+            ..
+        } = *self
+            .entries
+            .get(entry)
+            .ok_or_else(|| invalid_input("no such archive entry"))?;
+
+        let cipher = subkey_cipher(&self.key, &self.header, entry as u64)?;
+        self.reader.seek(SeekFrom::Start(self.start + offset))?;
+        let mut container = ContainerReader::new((&mut self.reader).take(length), &cipher)?;
+        let copied = io::copy(&mut container, &mut writer)?;
+        if copied != size {
+            return Err(invalid_data("the entry doesn't match its size"));
+        }
+        Ok(copied)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+fn is_archive_path(path: &str) -> bool {
+    path.len() <= MAX_PATH_LEN && path.split('/').all(is_file_name)
+}
+
+fn archive_key(
+    key: &Rc5SecretKey,
+    header: &[u8; HEADER_LEN],
+) -> Result<Rc5SecretKey, crate::Rc5Error> {
+    key.derive_subkey(&header[8..], SUBKEY_INFO, KEY_LEN)
+}
+
+fn subkey_cipher(key: &Rc5SecretKey, header: &[u8], position: u64) -> io::Result<RC5<u32>> {
+    let mut info = SUBKEY_INFO.to_vec();
+    info.extend_from_slice(header);
+    info.extend_from_slice(&position.to_le_bytes());
+    let subkey = key
+        .derive_subkey(&[], &info, KEY_LEN)
+        .map_err(invalid_input)?;
+    Ok(RC5::from_secret_key(subkey, WORDS, ROUNDS, KEY_LEN))
+}
+
+fn encode_index(entries: &[ArchiveEntry]) -> io::Result<Vec<u8>> {
+    let count =
+        u32::try_from(entries.len()).map_err(|_| invalid_input("too many archive entries"))?;
+    let mut index = count.to_le_bytes().to_vec();
+    for entry in entries {
+        let metadata = entry.metadata.to_bytes().map_err(invalid_input)?;
+        index.extend_from_slice(&(entry.path.len() as u16).to_le_bytes());
+        index.extend_from_slice(entry.path.as_bytes());
+        index.extend_from_slice(&entry.size.to_le_bytes());
+        index.extend_from_slice(&entry.offset.to_le_bytes());
+        index.extend_from_slice(&entry.length.to_le_bytes());
+        index.extend_from_slice(&(metadata.len() as u16).to_le_bytes());
+        index.extend_from_slice(&metadata);
+    }
+    Ok(index)
+}
+
+// `None` for anything malformed, including entries that reach past
+// `index_offset`, where the index begins
+fn decode_index(mut index: &[u8], index_offset: u64) -> Option<Vec<ArchiveEntry>> {
+    fn take<'a>(index: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+        let taken = index.get(..len)?;
+        *index = &index[len..];
+        Some(taken)
+    }
+    fn number<const N: usize>(index: &mut &[u8]) -> Option<[u8; N]> {
+        take(index, N)?.try_into().ok()
+    }
+
+    let count = u32::from_le_bytes(number(&mut index)?);
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let path_len = u16::from_le_bytes(number(&mut index)?) as usize;
+        let path = std::str::from_utf8(take(&mut index, path_len)?).ok()?;
+        let size = u64::from_le_bytes(number(&mut index)?);
+        let offset = u64::from_le_bytes(number(&mut index)?);
+        let length = u64::from_le_bytes(number(&mut index)?);
+        let metadata_len = u16::from_le_bytes(number(&mut index)?) as usize;
+        let metadata = FileMetadata::from_bytes(take(&mut index, metadata_len)?).ok()?;
+
+        let within = offset >= HEADER_LEN as u64 && offset.checked_add(length)? <= index_offset;
+        if !is_archive_path(path)
+            || !within
+            || entries
+                .iter()
+                .any(|entry: &ArchiveEntry| entry.path == path)
+        {
+            return None;
+        }
+        entries.push(ArchiveEntry {
+            path: path.to_string(),
+            size,
+            metadata,
+            offset,
+            length,
+        });
+    }
+    if !index.is_empty() {
+        return None;
+    }
+    Some(entries)
+}
+
+// counts what is written, for the offsets of the index
+struct Counted<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn invalid_input<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::tests::CountingRng;
+    use std::io::Cursor;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn key() -> Rc5SecretKey {
+        Rc5SecretKey::new((0u8..16).collect())
+    }
+
+    fn files() -> Vec<(&'static str, Vec<u8>)> {
+        vec![
+            ("a.txt", b"alpha".to_vec()),
+            ("empty", Vec::new()),
+            ("dir/sub/b.bin", (0..200_000).map(|x| x as u8).collect()),
+        ]
+    }
+
+    fn archive() -> Vec<u8> {
+        let mut archive = ArchiveWriter::new(CountingRng(1), Vec::new(), &key()).unwrap();
+        let metadata = FileMetadata {
+            modified: Some(UNIX_EPOCH + Duration::from_secs(1_000)),
+            permissions: Some(0o644),
+            ..FileMetadata::default()
+        };
+        for (path, data) in files() {
+            assert_eq!(
+                archive.add(path, &metadata, &data[..]).unwrap(),
+                data.len() as u64
+            );
+        }
+        archive.finish().unwrap()
+    }
+
+    #[test]
+    fn entries_are_listed_and_extracted_alone() {
+        let archive = archive();
+        // nothing about the files shows in the archive
+        assert!(!archive
+            .windows(5)
+            .any(|window| window == b"alpha" || window == b"a.txt"));
+
+        // the archive may follow other data
+        let mut prefixed = b"prefix".to_vec();
+        prefixed.extend_from_slice(&archive);
+        let mut reader = Cursor::new(prefixed);
+        reader.set_position(6);
+        let mut reader = ArchiveReader::new(reader, &key()).unwrap();
+
+        let listed = reader
+            .entries()
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.size))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            listed,
+            [("a.txt", 5), ("empty", 0), ("dir/sub/b.bin", 200_000)]
+        );
+        assert_eq!(reader.entries()[0].metadata.permissions, Some(0o644));
+
+        // in any order
+        for (entry, (_, data)) in files().iter().enumerate().rev() {
+            let mut extracted = Vec::new();
+            reader.extract(entry, &mut extracted).unwrap();
+            assert_eq!(&extracted, data);
+        }
+        assert!(reader.extract(3, io::sink()).is_err());
+    }
+
+    #[test]
+    fn bad_paths_and_duplicates_are_refused() {
+        let mut archive = ArchiveWriter::new(CountingRng(1), Vec::new(), &key()).unwrap();
+        let metadata = FileMetadata::default();
+        for path in ["", "/abs", "a//b", "a/../b", "./a", "a/", "a\\b"].iter() {
+            assert_eq!(
+                archive.add(path, &metadata, &b""[..]).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput,
+                "{}",
+                path
+            );
+        }
+        archive.add("a", &metadata, &b""[..]).unwrap();
+        assert!(archive.add("a", &metadata, &b""[..]).is_err());
+
+        // a failed read leaves the archive unusable
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+        }
+        assert!(archive.add("b", &metadata, Failing).is_err());
+        assert!(archive.add("c", &metadata, &b""[..]).is_err());
+        assert!(archive.finish().is_err());
+    }
+
+    #[test]
+    fn tampering_and_wrong_keys_are_detected() {
+        let archive = archive();
+        let open = |archive: &[u8], key: &Rc5SecretKey| {
+            ArchiveReader::new(Cursor::new(archive.to_vec()), key).map(drop)
+        };
+        assert!(open(&archive, &key()).is_ok());
+        assert_eq!(
+            open(&archive, &Rc5SecretKey::new(vec![1; 16]))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+        // the salt, the index and the trailer
+        for at in [10, archive.len() - 20, archive.len() - 1].iter() {
+            let mut modified = archive.clone();
+            modified[*at] ^= 1;
+            assert!(open(&modified, &key()).is_err(), "{}", at);
+        }
+        for len in 0..30 {
+            assert!(open(&archive[..len], &key()).is_err());
+        }
+
+        // an entry's data only opens at its own position
+        let mut modified = archive.clone();
+        modified[HEADER_LEN + 30] ^= 1;
+        let mut reader = ArchiveReader::new(Cursor::new(modified), &key()).unwrap();
+        assert_eq!(
+            reader.extract(0, io::sink()).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        let mut reader = ArchiveReader::new(Cursor::new(archive), &key()).unwrap();
+        reader.entries[1].offset = reader.entries[0].offset;
+        reader.entries[1].length = reader.entries[0].length;
+        assert!(reader.extract(1, io::sink()).is_err());
+    }
+}
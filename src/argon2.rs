@@ -0,0 +1,466 @@
+use std::convert::TryInto;
+
+use crate::digest::{blake2b, BLAKE2B_MAX_LEN};
+use crate::{secure, Rc5Error, Rc5SecretKey, MAX_KEY_LEN};
+
+// Argon2id (RFC 9106, version 0x13), for turning a passphrase into an RC5
+// key. It is deliberately slow and memory-hard, so guessing passphrases
+// costs an attacker as much memory per guess as it costs us
+const VERSION: u32 = 0x13;
+const ARGON2ID: u32 = 2;
+const BLOCK_WORDS: usize = 128;
+const SYNC_POINTS: usize = 4;
+
+pub const ARGON2_MIN_SALT_LEN: usize = 8;
+
+type Block = [u64; BLOCK_WORDS];
+
+// `memory_kib` KiB of memory (at least 8 per lane) filled `iterations`
+// times by `parallelism` lanes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Argon2Params {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+// the second recommended option of RFC 9106: 64 MiB, 3 passes, 4 lanes
+impl Default for Argon2Params {
+    fn default() -> Self {
+        Argon2Params {
+            memory_kib: 1 << 16,
+            iterations: 3,
+            parallelism: 4,
+        }
+    }
+}
+
+impl Argon2Params {
+    // a `len` byte RC5 key for `passphrase`. The salt must be random, at
+    // least `ARGON2_MIN_SALT_LEN` bytes, and stored with the ciphertext
+    pub fn derive_key(
+        &self,
+        passphrase: &[u8],
+        salt: &[u8],
+        len: usize,
+    ) -> Result<Rc5SecretKey, Rc5Error> {
+        // Argon2 outputs at least 4 bytes
+        if !(4..=MAX_KEY_LEN).contains(&len) {
+            return Err(Rc5Error::InvalidKeyLength);
+        }
+        let mut key = vec![0u8; len];
+        argon2id(self, passphrase, salt, &[], &[], &mut key)?;
+        Ok(Rc5SecretKey::new(key))
+    }
+
+    fn check(&self, salt: &[u8], output_len: usize) -> Result<(), Rc5Error> {
+        let lanes_ok = (1..1 << 24).contains(&self.parallelism);
+        let memory_ok = self.memory_kib as u64 >= 8 * self.parallelism as u64;
+        if !lanes_ok
+            || !memory_ok
+            || self.iterations == 0
+            || salt.len() < ARGON2_MIN_SALT_LEN
+            || output_len < 4
+        {
+            return Err(Rc5Error::InvalidKdfParameters);
+        }
+        Ok(())
+    }
+}
+
+// Argon2id with the optional secret and associated data of the RFC,
+// filling `output`
+pub(crate) fn argon2id(
+    params: &Argon2Params,
+    password: &[u8],
+    salt: &[u8],
+    secret: &[u8],
+    associated: &[u8],
+    output: &mut [u8],
+) -> Result<(), Rc5Error> {
+    params.check(salt, output.len())?;
+
+    let lanes = params.parallelism as usize;
+    let segment_len = params.memory_kib as usize / (SYNC_POINTS * lanes);
+    let lane_len = segment_len * SYNC_POINTS;
+    let blocks = lane_len * lanes;
+
+    let mut input = Vec::new();
+    for value in [
+        params.parallelism,
+        output.len() as u32,
+        params.memory_kib,
+        params.iterations,
+        VERSION,
+        ARGON2ID,
+    ]
+    .iter()
+    {
+        input.extend_from_slice(&value.to_le_bytes());
+    }
+    for field in [password, salt, secret, associated].iter() {
+        input.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        input.extend_from_slice(field);
+    }
+    let mut h0 = [0u8; BLAKE2B_MAX_LEN + 8];
+    blake2b(&input, &mut h0[..BLAKE2B_MAX_LEN]);
+    secure::wipe(&mut input);
+
+    let mut memory = vec![[0u64; BLOCK_WORDS]; blocks];
+    let mut bytes = [0u8; 8 * BLOCK_WORDS];
+    for lane in 0..lanes {
+        for column in 0..2 {
+            h0[BLAKE2B_MAX_LEN..BLAKE2B_MAX_LEN + 4]
+                .copy_from_slice(&(column as u32).to_le_bytes());
+            h0[BLAKE2B_MAX_LEN + 4..].copy_from_slice(&(lane as u32).to_le_bytes());
+            variable_hash(&h0, &mut bytes);
+            memory[lane * lane_len + column] = block_from_bytes(&bytes);
+        }
+    }
+    secure::wipe(&mut h0);
+
+    for pass in 0..params.iterations as usize {
+        for slice in 0..SYNC_POINTS {
+            for lane in 0..lanes {
+                let position = Position {
+                    pass,
+                    lane,
+                    slice,
+                    lanes,
+                    segment_len,
+                    lane_len,
+                };
+                fill_segment(&mut memory, &position, params);
+            }
+        }
+    }
+
+    let mut last = memory[lane_len - 1];
+    for lane in 1..lanes {
+        xor_into(&mut last, &memory[lane * lane_len + lane_len - 1]);
+    }
+    for (chunk, word) in bytes.chunks_exact_mut(8).zip(last.iter()) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    variable_hash(&bytes, output);
+
+    secure::wipe(&mut bytes);
+    secure::release(&mut last);
+    secure::release(&mut memory);
+    Ok(())
+}
+
+struct Position {
+    pass: usize,
+    lane: usize,
+    slice: usize,
+    lanes: usize,
+    segment_len: usize,
+    lane_len: usize,
+}
+
+fn fill_segment(memory: &mut [Block], position: &Position, params: &Argon2Params) {
+    let Position {
+        pass,
+        lane,
+        slice,
+        lanes,
+        segment_len,
+        lane_len,
+    } = *position;
+
+    // Argon2id addresses independently of the data in the first half of
+    // the first pass, and like Argon2d after that
+    let independent = pass == 0 && slice < SYNC_POINTS / 2;
+    let mut address_input = [0u64; BLOCK_WORDS];
+    let mut addresses = [0u64; BLOCK_WORDS];
+    if independent {
+        address_input[..6].copy_from_slice(&[
+            pass as u64,
+            lane as u64,
+            slice as u64,
+            memory.len() as u64,
+            params.iterations as u64,
+            ARGON2ID as u64,
+        ]);
+    }
+
+    // the first two blocks of each lane come from the initial hash
+    let start = if pass == 0 && slice == 0 { 2 } else { 0 };
+    if independent && start != 0 {
+        next_addresses(&mut address_input, &mut addresses);
+    }
+
+    for index in start..segment_len {
+        let column = slice * segment_len + index;
+        let current = lane * lane_len + column;
+        let previous = if column == 0 {
+            current + lane_len - 1
+        } else {
+            current - 1
+        };
+
+        let random = if independent {
+            if index % BLOCK_WORDS == 0 {
+                next_addresses(&mut address_input, &mut addresses);
+            }
+            addresses[index % BLOCK_WORDS]
+        } else {
+            memory[previous][0]
+        };
+
+        let reference_lane = if pass == 0 && slice == 0 {
+            lane
+        } else {
+            ((random >> 32) % lanes as u64) as usize
+        };
+        let same_lane = reference_lane == lane;
+
+        // the blocks that may be referenced: everything already finished,
+        // minus the current segment of other lanes and the previous block
+        let area = if pass == 0 {
+            if slice == 0 || same_lane {
+                slice * segment_len + index - 1
+            } else {
+                slice * segment_len - usize::from(index == 0)
+            }
+        } else if same_lane {
+            lane_len - segment_len + index - 1
+        } else {
+            lane_len - segment_len - usize::from(index == 0)
+        } as u64;
+
+        let x = ((random & 0xFFFF_FFFF) * (random & 0xFFFF_FFFF)) >> 32;
+        let relative = area - 1 - ((area * x) >> 32);
+        let start_column = if pass == 0 || slice == SYNC_POINTS - 1 {
+            0
+        } else {
+            (slice + 1) * segment_len
+        };
+        let reference_column = (start_column + relative as usize) % lane_len;
+        let reference = reference_lane * lane_len + reference_column;
+
+        let (previous_block, reference_block) = (memory[previous], memory[reference]);
+        fill_block(
+            &previous_block,
+            &reference_block,
+            &mut memory[current],
+            pass > 0,
+        );
+    }
+
+    secure::release(&mut address_input);
+    secure::release(&mut addresses);
+}
+
+fn next_addresses(input: &mut Block, addresses: &mut Block) {
+    input[6] += 1;
+    let zero = [0u64; BLOCK_WORDS];
+    fill_block(&zero, input, addresses, false);
+    let first = *addresses;
+    fill_block(&zero, &first, addresses, false);
+}
+
+// the compression function G of the RFC, xoring into what `next` held
+// before on passes after the first
+fn fill_block(previous: &Block, reference: &Block, next: &mut Block, with_xor: bool) {
+    let mut r = *reference;
+    xor_into(&mut r, previous);
+    let mut z = r;
+    if with_xor {
+        xor_into(&mut z, next);
+    }
+
+    for row in 0..8 {
+        let mut indices = [0; 16];
+        for (i, index) in indices.iter_mut().enumerate() {
+            *index = 16 * row + i;
+        }
+        permute(&mut r, &indices);
+    }
+    for column in 0..8 {
+        let mut indices = [0; 16];
+        for (i, index) in indices.iter_mut().enumerate() {
+            *index = 2 * column + 16 * (i / 2) + i % 2;
+        }
+        permute(&mut r, &indices);
+    }
+
+    xor_into(&mut z, &r);
+    *next = z;
+}
+
+// the BLAKE2b round on the 16 words at `indices`, with the
+// multiplication-hardened mixing of Argon2
+fn permute(block: &mut Block, indices: &[usize; 16]) {
+    let mut v = [0u64; 16];
+    for (word, &index) in v.iter_mut().zip(indices.iter()) {
+        *word = block[index];
+    }
+
+    let mut mix = |a: usize, b: usize, c: usize, d: usize| {
+        let multiply = |x: u64, y: u64| {
+            2u64.wrapping_mul(x & 0xFFFF_FFFF)
+                .wrapping_mul(y & 0xFFFF_FFFF)
+        };
+        v[a] = v[a].wrapping_add(v[b]).wrapping_add(multiply(v[a], v[b]));
+        v[d] = (v[d] ^ v[a]).rotate_right(32);
+        v[c] = v[c].wrapping_add(v[d]).wrapping_add(multiply(v[c], v[d]));
+        v[b] = (v[b] ^ v[c]).rotate_right(24);
+        v[a] = v[a].wrapping_add(v[b]).wrapping_add(multiply(v[a], v[b]));
+        v[d] = (v[d] ^ v[a]).rotate_right(16);
+        v[c] = v[c].wrapping_add(v[d]).wrapping_add(multiply(v[c], v[d]));
+        v[b] = (v[b] ^ v[c]).rotate_right(63);
+    };
+    mix(0, 4, 8, 12);
+    mix(1, 5, 9, 13);
+    mix(2, 6, 10, 14);
+    mix(3, 7, 11, 15);
+    mix(0, 5, 10, 15);
+    mix(1, 6, 11, 12);
+    mix(2, 7, 8, 13);
+    mix(3, 4, 9, 14);
+
+    for (word, &index) in v.iter().zip(indices.iter()) {
+        block[index] = *word;
+    }
+}
+
+fn xor_into(block: &mut Block, other: &Block) {
+    block
+        .iter_mut()
+        .zip(other.iter())
+        .for_each(|(x, y)| *x ^= y);
+}
+
+fn block_from_bytes(bytes: &[u8]) -> Block {
+    let mut block = [0u64; BLOCK_WORDS];
+    for (word, chunk) in block.iter_mut().zip(bytes.chunks_exact(8)) {
+        *word = u64::from_le_bytes(chunk.try_into().unwrap());
+    }
+    block
+}
+
+// H' of the RFC: BLAKE2b stretched to any output length
+fn variable_hash(data: &[u8], output: &mut [u8]) {
+    let mut input = (output.len() as u32).to_le_bytes().to_vec();
+    input.extend_from_slice(data);
+
+    if output.len() <= BLAKE2B_MAX_LEN {
+        blake2b(&input, output);
+        secure::wipe(&mut input);
+        return;
+    }
+
+    let mut v = [0u8; BLAKE2B_MAX_LEN];
+    blake2b(&input, &mut v);
+    secure::wipe(&mut input);
+
+    // the first half of each intermediate hash, then all of the last one
+    let mut written = 0;
+    loop {
+        output[written..written + 32].copy_from_slice(&v[..32]);
+        written += 32;
+        if output.len() - written <= BLAKE2B_MAX_LEN {
+            break;
+        }
+        let previous = v;
+        blake2b(&previous, &mut v);
+    }
+    let rest = output.len() - written;
+    let previous = v;
+    blake2b(&previous, &mut v[..rest]);
+    output[written..].copy_from_slice(&v[..rest]);
+    secure::wipe(&mut v);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    // section 5.3 of RFC 9106
+    #[test]
+    fn matches_rfc_9106() {
+        let params = Argon2Params {
+            memory_kib: 32,
+            iterations: 3,
+            parallelism: 4,
+        };
+        let mut tag = [0u8; 32];
+        argon2id(&params, &[1; 32], &[2; 16], &[3; 8], &[4; 12], &mut tag).unwrap();
+        assert_eq!(
+            hex(&tag),
+            "0d640df58d78766c08c037a34a8b53c9d01ef0452d75b65eb52520e96b01e659"
+        );
+    }
+
+    // checked against the Argon2id of Python's `cryptography`, with more
+    // than one address block per segment and an output longer than 64
+    // bytes
+    #[test]
+    fn matches_reference_outputs() {
+        let params = Argon2Params {
+            memory_kib: 1024,
+            iterations: 2,
+            parallelism: 1,
+        };
+        let mut tag = [0u8; 16];
+        argon2id(
+            &params,
+            b"password",
+            b"saltsaltsaltsalt",
+            &[],
+            &[],
+            &mut tag,
+        )
+        .unwrap();
+        assert_eq!(hex(&tag), "a5d61afa623c7003e9c57b93dc0df5fc");
+
+        let params = Argon2Params {
+            memory_kib: 16,
+            iterations: 1,
+            parallelism: 2,
+        };
+        let mut tag = [0u8; 80];
+        argon2id(&params, b"hunter2", b"0123456789abcdef", &[], &[], &mut tag).unwrap();
+        assert_eq!(
+            hex(&tag),
+            concat!(
+                "ca93f3dc3fc546fc3ece924b34a1e323cf21c7936b847e24e73014579b2b86e6",
+                "a87af2e23a8901fbd73f25056837d65b3d437dcdf783c83d227f07709506ed51",
+                "3c85d344c40ab93321aa6444e9fc1184"
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        let params = Argon2Params {
+            memory_kib: 15,
+            iterations: 1,
+            parallelism: 2,
+        };
+        assert_eq!(
+            params.derive_key(b"pass", b"saltsalt", 16).err(),
+            Some(Rc5Error::InvalidKdfParameters)
+        );
+
+        let params = Argon2Params {
+            memory_kib: 16,
+            ..params
+        };
+        assert!(params.derive_key(b"pass", b"salt", 16).is_err());
+        assert_eq!(
+            params.derive_key(b"pass", b"saltsalt", 3).err(),
+            Some(Rc5Error::InvalidKeyLength)
+        );
+        assert_eq!(
+            params.derive_key(b"pass", b"saltsalt", 16).unwrap().len(),
+            16
+        );
+    }
+}
@@ -0,0 +1,355 @@
+// ASCII armor, for ciphertext that has to survive being pasted into mail
+// or a ticket:
+//
+//   -----BEGIN RC5 MESSAGE-----
+//   base64 lines of 64 characters
+//   =checksum
+//   -----END RC5 MESSAGE-----
+//
+// with the checksum the base64 of the CRC-24 of OpenPGP (RFC 4880
+// section 6.1) over the data. Readers skip leading whitespace, blank lines
+// and CRLF line endings, and accept armor without the checksum line,
+// which older writers left out, but reject one that doesn't match
+use std::io::{self, BufRead, Read, Write};
+
+use crate::encoding::{decode_base64, encode_base64};
+use crate::Rc5Error;
+
+pub const BEGIN: &str = "-----BEGIN RC5 MESSAGE-----";
+pub const END: &str = "-----END RC5 MESSAGE-----";
+
+// bytes per line, which encode to 64 characters
+const LINE_BYTES: usize = 48;
+// longer lines are not armor, and aren't buffered whole
+const MAX_LINE: u64 = 1 << 12;
+// how much leading whitespace may come before the header
+const MAX_INDENT: usize = 1 << 10;
+
+const CRC24_INIT: u32 = 0xB7_04CE;
+const CRC24_POLY: u32 = 0x186_4CFB;
+
+// `data` armored
+pub fn write(data: &[u8]) -> String {
+    let mut writer = ArmorWriter::new(Vec::new()).expect("writing to a vector can't fail");
+    writer
+        .write_all(data)
+        .expect("writing to a vector can't fail");
+    let text = writer.finish().expect("writing to a vector can't fail");
+    String::from_utf8(text).expect("armor is ASCII")
+}
+
+// the data armored in `text`, which must hold nothing but the armor and
+// surrounding whitespace. Anything malformed, a bad checksum or a missing
+// footer gives `InvalidEncoding`
+pub fn read(text: &str) -> Result<Vec<u8>, Rc5Error> {
+    let mut reader = match dearmor(text.as_bytes()) {
+        Ok(Dearmored(Inner::Armored(reader))) => reader,
+        _ => return Err(Rc5Error::InvalidEncoding),
+    };
+    let mut data = Vec::new();
+    reader
+        .read_to_end(&mut data)
+        .map_err(|_| Rc5Error::InvalidEncoding)?;
+    if !reader
+        .inner
+        .fill_buf()
+        .is_ok_and(|rest| rest.trim_ascii().is_empty())
+    {
+        return Err(Rc5Error::InvalidEncoding);
+    }
+    Ok(data)
+}
+
+fn crc24(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= (byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x100_0000 != 0 {
+                crc ^= CRC24_POLY;
+            }
+        }
+    }
+    crc & 0xFF_FFFF
+}
+
+fn checksum_line(crc: u32) -> String {
+    format!("={}", encode_base64(&crc.to_be_bytes()[1..]))
+}
+
+// armors everything written to it into `inner`. The checksum and the
+// footer are only written by `finish`
+pub struct ArmorWriter<W: Write> {
+    inner: W,
+    pending: Vec<u8>,
+    crc: u32,
+}
+
+impl<W: Write> ArmorWriter<W> {
+    // writes the header right away
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        writeln!(inner, "{}", BEGIN)?;
+        Ok(ArmorWriter {
+            inner,
+            pending: Vec::with_capacity(LINE_BYTES),
+            crc: CRC24_INIT,
+        })
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.pending.is_empty() {
+            writeln!(self.inner, "{}", encode_base64(&self.pending))?;
+        }
+        writeln!(self.inner, "{}", checksum_line(self.crc))?;
+        writeln!(self.inner, "{}", END)?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ArmorWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let taken = buf.len().min(LINE_BYTES - self.pending.len());
+        self.pending.extend_from_slice(&buf[..taken]);
+        self.crc = crc24(self.crc, &buf[..taken]);
+        if self.pending.len() == LINE_BYTES {
+            writeln!(self.inner, "{}", encode_base64(&self.pending))?;
+            self.pending.clear();
+        }
+        Ok(taken)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// decodes armored input as it is read, or passes anything else through
+// unchanged, see `dearmor`
+pub struct Dearmored<R: BufRead>(Inner<R>);
+
+enum Inner<R: BufRead> {
+    Armored(ArmorReader<R>),
+    Plain(io::Chain<io::Cursor<Vec<u8>>, R>),
+}
+
+impl<R: BufRead> Dearmored<R> {
+    pub fn is_armored(&self) -> bool {
+        matches!(self.0, Inner::Armored(_))
+    }
+}
+
+impl<R: BufRead> Read for Dearmored<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            Inner::Armored(reader) => reader.read(buf),
+            Inner::Plain(reader) => reader.read(buf),
+        }
+    }
+}
+
+// looks for the armor header at the start of `input`, after at most 1 KiB
+// of whitespace. Armored input is decoded and checked as it is read, with
+// `InvalidData` errors for malformed armor, so decryption can take either
+// form without being told which
+pub fn dearmor<R: BufRead>(mut input: R) -> io::Result<Dearmored<R>> {
+    let mut prefix = Vec::new();
+    let mut byte = [0u8; 1];
+    while prefix.len() < MAX_INDENT + BEGIN.len() {
+        if input.read(&mut byte)? == 0 {
+            break;
+        }
+        prefix.push(byte[0]);
+        let start = prefix
+            .iter()
+            .position(|byte| !byte.is_ascii_whitespace())
+            .unwrap_or(prefix.len());
+        let text = &prefix[start..];
+        if text == BEGIN.as_bytes() {
+            return Ok(Dearmored(Inner::Armored(ArmorReader {
+                inner: input,
+                line: Vec::new(),
+                decoded: Vec::new(),
+                position: 0,
+                crc: CRC24_INIT,
+                state: State::Header,
+            })));
+        }
+        if !BEGIN.as_bytes().starts_with(text) || start > MAX_INDENT {
+            break;
+        }
+    }
+    Ok(Dearmored(Inner::Plain(
+        io::Cursor::new(prefix).chain(input),
+    )))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    // the rest of the header line is still to come
+    Header,
+    Body,
+    // the checksum matched, only the footer may follow
+    Checked,
+    Ended,
+}
+
+struct ArmorReader<R: BufRead> {
+    inner: R,
+    line: Vec<u8>,
+    decoded: Vec<u8>,
+    position: usize,
+    crc: u32,
+    state: State,
+}
+
+impl<R: BufRead> ArmorReader<R> {
+    // the next line without its ending, or `None` at the end of the input
+    fn next_line(&mut self) -> io::Result<Option<&str>> {
+        self.line.clear();
+        let read = (&mut self.inner)
+            .take(MAX_LINE)
+            .read_until(b'\n', &mut self.line)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if !self.line.ends_with(b"\n") && read as u64 == MAX_LINE {
+            return Err(invalid_armor("overlong line"));
+        }
+        std::str::from_utf8(&self.line)
+            .map(|line| Some(line.trim()))
+            .map_err(|_| invalid_armor("not text"))
+    }
+
+    // the next line that isn't blank
+    fn next_text(&mut self) -> io::Result<String> {
+        loop {
+            match self.next_line()? {
+                Some("") => continue,
+                Some(line) => return Ok(line.to_string()),
+                None => return Err(invalid_armor("missing footer")),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for ArmorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.decoded.len() {
+            match self.state {
+                State::Ended => return Ok(0),
+                State::Header => {
+                    if self.next_line()?.is_some_and(|rest| !rest.is_empty()) {
+                        return Err(invalid_armor("text after the header"));
+                    }
+                    self.state = State::Body;
+                }
+                State::Body => {
+                    let line = self.next_text()?;
+                    if line == END {
+                        self.state = State::Ended;
+                    } else if let Some(checksum) = line.strip_prefix('=') {
+                        if checksum != &checksum_line(self.crc)[1..] {
+                            return Err(invalid_armor("checksum mismatch"));
+                        }
+                        self.state = State::Checked;
+                    } else {
+                        self.decoded =
+                            decode_base64(&line).map_err(|_| invalid_armor("bad base64"))?;
+                        self.crc = crc24(self.crc, &self.decoded);
+                        self.position = 0;
+                    }
+                }
+                State::Checked => {
+                    if self.next_text()? != END {
+                        return Err(invalid_armor("text after the checksum"));
+                    }
+                    self.state = State::Ended;
+                }
+            }
+        }
+
+        let count = buf.len().min(self.decoded.len() - self.position);
+        buf[..count].copy_from_slice(&self.decoded[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+fn invalid_armor(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("malformed armor: {}", reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dearmored(input: Vec<u8>) -> io::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        dearmor(io::BufReader::with_capacity(7, &input[..]))?.read_to_end(&mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn crc24_matches_openpgp() {
+        // the empty armor checksum, and the catalogued check value of
+        // CRC-24/OPENPGP
+        assert_eq!(checksum_line(crc24(CRC24_INIT, b"")), "=twTO");
+        assert_eq!(crc24(CRC24_INIT, b"123456789"), 0x21CF02);
+    }
+
+    #[test]
+    fn armor_round_trips_and_is_detected() {
+        for len in [0, 1, 47, 48, 49, 1000].iter() {
+            let data = (0..*len).map(|x| (x * 7) as u8).collect::<Vec<u8>>();
+            let text = write(&data);
+
+            assert!(text.starts_with(BEGIN) && text.ends_with("-----END RC5 MESSAGE-----\n"));
+            assert!(text.lines().all(|line| line.len() <= 64));
+            assert_eq!(read(&text).unwrap(), data);
+            assert_eq!(dearmored(text.clone().into_bytes()).unwrap(), data);
+
+            // pasting tends to add indentation and CRLF line endings
+            let pasted = format!("\n  {}", text.replace('\n', "\r\n"));
+            assert_eq!(read(&pasted).unwrap(), data);
+            assert_eq!(dearmored(pasted.into_bytes()).unwrap(), data);
+            // and binary input passes through untouched
+            assert_eq!(dearmored(data.clone()).unwrap(), data);
+            assert!(!dearmor(&data[..]).unwrap().is_armored());
+        }
+
+        let prefix = format!("  {}", &BEGIN[..10]).into_bytes();
+        assert_eq!(dearmored(prefix.clone()).unwrap(), prefix);
+    }
+
+    #[test]
+    fn malformed_armor_is_refused() {
+        let text = write(b"foobar");
+        assert_eq!(text, format!("{}\nZm9vYmFy\n=czTe\n{}\n", BEGIN, END));
+        // armor from before the checksum was added
+        let unchecked = format!("{}\nZm9vYmFy\n{}\n", BEGIN, END);
+        assert_eq!(read(&unchecked).unwrap(), b"foobar");
+
+        let malformed = [
+            format!("{}\nZm9v\n", BEGIN),
+            format!("{}\nZm9*\n{}\n", BEGIN, END),
+            format!("{} x\nZm9v\n{}\n", BEGIN, END),
+            text.replace("Zm9vYmFy", "Zm9vYmFz"),
+            text.replace("=czTe", "=czTf"),
+            text.replace("=czTe\n", "=czTe\nZm9v\n"),
+            format!("{}trailing\n", text),
+            "Zm9vYmFy".to_string(),
+        ];
+        for text in malformed.iter() {
+            assert_eq!(read(text), Err(Rc5Error::InvalidEncoding), "{}", text);
+        }
+        for text in malformed[..6].iter() {
+            assert_eq!(
+                dearmored(text.clone().into_bytes()).unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use crate::mode::Mode;
+use crate::{Rc5Error, Rc5Params};
+
+// the RC5-CBC-Pad algorithm identifier of RFC 2040 and PKCS #5, as used
+// by CMS and PKCS #7:
+//
+//   rc5-CBC-PAD OBJECT IDENTIFIER ::= { 1 2 840 113549 3 9 }
+//
+//   RC5-CBC-Parameters ::= SEQUENCE {
+//       version          INTEGER { v1-0(16) },
+//       rounds           INTEGER (8..127),
+//       blockSizeInBits  INTEGER (64 | 128),
+//       iv               OCTET STRING OPTIONAL }
+//
+// The padding is PKCS#7 and the key length is carried by the key itself
+pub const RC5_CBC_PAD_OID: &str = "1.2.840.113549.3.9";
+
+const OID_DER: [u8; 8] = [0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x03, 0x09];
+const VERSION: u32 = 16;
+
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const SEQUENCE: u8 = 0x30;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rc5CbcParameters {
+    pub rounds: usize,
+    pub block_size_bits: usize,
+    // absent when the IV is conveyed some other way
+    pub iv: Option<Vec<u8>>,
+}
+
+impl Rc5CbcParameters {
+    // fails for parameters the structure can't express: RC5-16, or a round
+    // count outside 8..=127, or an IV that isn't one block long
+    pub fn new(params: &Rc5Params, iv: Option<Vec<u8>>) -> Result<Self, Rc5Error> {
+        let parameters = Rc5CbcParameters {
+            rounds: params.rounds,
+            block_size_bits: 16 * params.words,
+            iv,
+        };
+        parameters.check()?;
+        Ok(parameters)
+    }
+
+    // the RC5 parameters for a key of `key_len` bytes
+    pub fn params(&self, key_len: usize) -> Rc5Params {
+        Rc5Params {
+            words: self.block_size_bits / 16,
+            rounds: self.rounds,
+            bytes: key_len,
+        }
+    }
+
+    pub fn mode(&self) -> Option<Mode> {
+        self.iv.clone().map(|iv| Mode::Cbc { iv })
+    }
+
+    // DER encoding of the RC5-CBC-Parameters alone
+    pub fn to_der(&self) -> Vec<u8> {
+        let mut content = Vec::new();
+        write_integer(&mut content, VERSION);
+        write_integer(&mut content, self.rounds as u32);
+        write_integer(&mut content, self.block_size_bits as u32);
+        if let Some(iv) = &self.iv {
+            write_tlv(&mut content, OCTET_STRING, iv);
+        }
+
+        let mut der = Vec::new();
+        write_tlv(&mut der, SEQUENCE, &content);
+        der
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Rc5Error> {
+        let (content, rest) = read_tlv(der, SEQUENCE)?;
+        if !rest.is_empty() {
+            return Err(Rc5Error::InvalidEncoding);
+        }
+
+        let (version, content) = read_integer(content)?;
+        let (rounds, content) = read_integer(content)?;
+        let (block_size_bits, content) = read_integer(content)?;
+        let iv = if content.is_empty() {
+            None
+        } else {
+            let (iv, rest) = read_tlv(content, OCTET_STRING)?;
+            if !rest.is_empty() {
+                return Err(Rc5Error::InvalidEncoding);
+            }
+            Some(iv.to_vec())
+        };
+
+        if version != VERSION {
+            return Err(Rc5Error::InvalidEncoding);
+        }
+        let parameters = Rc5CbcParameters {
+            rounds: rounds as usize,
+            block_size_bits: block_size_bits as usize,
+            iv,
+        };
+        parameters.check()?;
+        Ok(parameters)
+    }
+
+    // DER encoding of the whole AlgorithmIdentifier, OID and parameters
+    pub fn to_algorithm_identifier_der(&self) -> Vec<u8> {
+        let mut content = Vec::new();
+        write_tlv(&mut content, OBJECT_IDENTIFIER, &OID_DER);
+        content.extend(self.to_der());
+
+        let mut der = Vec::new();
+        write_tlv(&mut der, SEQUENCE, &content);
+        der
+    }
+
+    pub fn from_algorithm_identifier_der(der: &[u8]) -> Result<Self, Rc5Error> {
+        let (content, rest) = read_tlv(der, SEQUENCE)?;
+        let (oid, parameters) = read_tlv(content, OBJECT_IDENTIFIER)?;
+        if !rest.is_empty() || oid != OID_DER {
+            return Err(Rc5Error::InvalidEncoding);
+        }
+        Rc5CbcParameters::from_der(parameters)
+    }
+
+    fn check(&self) -> Result<(), Rc5Error> {
+        let block_ok = self.block_size_bits == 64 || self.block_size_bits == 128;
+        let iv_ok = self
+            .iv
+            .as_ref()
+            .is_none_or(|iv| 8 * iv.len() == self.block_size_bits);
+        if !block_ok || !iv_ok || !(8..=127).contains(&self.rounds) {
+            return Err(Rc5Error::InvalidEncoding);
+        }
+        Ok(())
+    }
+}
+
+fn write_tlv(out: &mut Vec<u8>, tag: u8, content: &[u8]) {
+    out.push(tag);
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&byte| byte == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(content);
+}
+
+// non-negative integers in the fewest bytes, with a leading zero when the
+// top bit would otherwise make them negative
+fn write_integer(out: &mut Vec<u8>, value: u32) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes[..3].iter().take_while(|&&byte| byte == 0).count();
+    let mut content = bytes[skip..].to_vec();
+    if content[0] & 0x80 != 0 {
+        content.insert(0, 0);
+    }
+    write_tlv(out, INTEGER, &content);
+}
+
+// splits off one element with tag `tag`, returning its content and the
+// bytes after it. Only the minimal (DER) length forms are accepted
+fn read_tlv(der: &[u8], tag: u8) -> Result<(&[u8], &[u8]), Rc5Error> {
+    let (&found, rest) = der.split_first().ok_or(Rc5Error::InvalidEncoding)?;
+    let (&first, rest) = rest.split_first().ok_or(Rc5Error::InvalidEncoding)?;
+    if found != tag {
+        return Err(Rc5Error::InvalidEncoding);
+    }
+
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7F) as usize;
+        if count == 0 || count > 4 || rest.len() < count || rest[0] == 0 {
+            return Err(Rc5Error::InvalidEncoding);
+        }
+        let len = rest[..count]
+            .iter()
+            .fold(0usize, |len, &byte| len << 8 | byte as usize);
+        if len < 0x80 {
+            return Err(Rc5Error::InvalidEncoding);
+        }
+        (len, &rest[count..])
+    };
+
+    if rest.len() < len {
+        return Err(Rc5Error::InvalidEncoding);
+    }
+    Ok(rest.split_at(len))
+}
+
+fn read_integer(der: &[u8]) -> Result<(u32, &[u8]), Rc5Error> {
+    let (content, rest) = read_tlv(der, INTEGER)?;
+    let minimal = match content {
+        [] => false,
+        [0, next, ..] => next & 0x80 != 0,
+        [first, ..] => first & 0x80 == 0,
+    };
+    if !minimal || content.len() > 5 || (content.len() == 5 && content[0] != 0) {
+        return Err(Rc5Error::InvalidEncoding);
+    }
+
+    let value = content
+        .iter()
+        .fold(0u64, |value, &byte| value << 8 | byte as u64);
+    Ok((value as u32, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IV: [u8; 8] = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+
+    #[test]
+    fn algorithm_identifier_encoding() {
+        let params = Rc5Params {
+            words: 4,
+            rounds: 12,
+            bytes: 16,
+        };
+        let parameters = Rc5CbcParameters::new(&params, Some(IV.to_vec())).unwrap();
+
+        let mut expected = vec![
+            0x30, 0x1F, 0x06, 0x08, 0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x03, 0x09, 0x30, 0x13,
+            0x02, 0x01, 0x10, 0x02, 0x01, 0x0C, 0x02, 0x01, 0x40, 0x04, 0x08,
+        ];
+        expected.extend_from_slice(&IV);
+        let der = parameters.to_algorithm_identifier_der();
+        assert_eq!(der, expected);
+
+        let decoded = Rc5CbcParameters::from_algorithm_identifier_der(&der).unwrap();
+        assert_eq!(decoded, parameters);
+        assert_eq!(decoded.params(16), params);
+        assert_eq!(decoded.mode(), Some(Mode::Cbc { iv: IV.to_vec() }));
+    }
+
+    #[test]
+    fn iv_is_optional_and_rounds_above_127_are_rejected() {
+        let parameters = Rc5CbcParameters {
+            rounds: 16,
+            block_size_bits: 128,
+            iv: None,
+        };
+        let der = parameters.to_der();
+        assert_eq!(
+            der,
+            [0x30, 0x0A, 0x02, 0x01, 0x10, 0x02, 0x01, 0x10, 0x02, 0x02, 0x00, 0x80]
+        );
+        assert_eq!(Rc5CbcParameters::from_der(&der).unwrap(), parameters);
+
+        // rounds = 128
+        let der = [
+            0x30, 0x0A, 0x02, 0x01, 0x10, 0x02, 0x02, 0x00, 0x80, 0x02, 0x01, 0x40,
+        ];
+        assert_eq!(
+            Rc5CbcParameters::from_der(&der),
+            Err(Rc5Error::InvalidEncoding)
+        );
+    }
+
+    #[test]
+    fn malformed_encodings_are_rejected() {
+        let der = Rc5CbcParameters::new(
+            &Rc5Params {
+                words: 4,
+                rounds: 12,
+                bytes: 16,
+            },
+            Some(IV.to_vec()),
+        )
+        .unwrap()
+        .to_der();
+
+        assert!(Rc5CbcParameters::from_der(&der[..der.len() - 1]).is_err());
+
+        let mut wrong_version = der.clone();
+        wrong_version[4] = 0x11;
+        assert!(Rc5CbcParameters::from_der(&wrong_version).is_err());
+
+        // non-minimal integer
+        let padded = [
+            0x30, 0x0A, 0x02, 0x02, 0x00, 0x10, 0x02, 0x01, 0x0C, 0x02, 0x01, 0x40,
+        ];
+        assert!(Rc5CbcParameters::from_der(&padded).is_err());
+
+        let rc5_16 = Rc5Params {
+            words: 2,
+            rounds: 16,
+            bytes: 8,
+        };
+        assert!(Rc5CbcParameters::new(&rc5_16, None).is_err());
+    }
+}
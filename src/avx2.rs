@@ -0,0 +1,162 @@
+use std::arch::x86_64::*;
+
+// number of RC5-32 blocks per 256-bit register
+const LANES: usize = 8;
+const CHUNK_LEN: usize = 8 * LANES;
+
+// encrypts as many whole groups of eight blocks as `data` holds and
+// returns the number of bytes processed. Does nothing (and returns zero)
+// when the CPU lacks AVX2
+pub(crate) fn encrypt_blocks(s_table: &[u32], rounds: usize, data: &mut [u8]) -> usize {
+    if !is_x86_feature_detected!("avx2") {
+        return 0;
+    }
+    // SAFETY: AVX2 support was just detected
+    unsafe { encrypt_blocks_avx2(s_table, rounds, data) }
+}
+
+// decrypting counterpart of `encrypt_blocks`
+pub(crate) fn decrypt_blocks(s_table: &[u32], rounds: usize, data: &mut [u8]) -> usize {
+    if !is_x86_feature_detected!("avx2") {
+        return 0;
+    }
+    // SAFETY: AVX2 support was just detected
+    unsafe { decrypt_blocks_avx2(s_table, rounds, data) }
+}
+
+// `sllv`/`srlv` shift each lane by its own amount and yield zero for
+// amounts of 32 or more, so a rotation by zero needs no special casing
+#[target_feature(enable = "avx2")]
+unsafe fn rotate_left(x: __m256i, amount: __m256i) -> __m256i {
+    let amount = _mm256_and_si256(amount, _mm256_set1_epi32(31));
+    let inverse = _mm256_sub_epi32(_mm256_set1_epi32(32), amount);
+    _mm256_or_si256(_mm256_sllv_epi32(x, amount), _mm256_srlv_epi32(x, inverse))
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn rotate_right(x: __m256i, amount: __m256i) -> __m256i {
+    let amount = _mm256_and_si256(amount, _mm256_set1_epi32(31));
+    let inverse = _mm256_sub_epi32(_mm256_set1_epi32(32), amount);
+    _mm256_or_si256(_mm256_srlv_epi32(x, amount), _mm256_sllv_epi32(x, inverse))
+}
+
+// loads eight interleaved blocks (a0 b0 a1 b1 ...) as one register of A
+// words and one of B words
+#[target_feature(enable = "avx2")]
+unsafe fn load(chunk: &[u8]) -> (__m256i, __m256i) {
+    let low = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+    let high = _mm256_loadu_si256(chunk.as_ptr().add(32) as *const __m256i);
+
+    let split = _mm256_setr_epi32(0, 2, 4, 6, 1, 3, 5, 7);
+    let low = _mm256_permutevar8x32_epi32(low, split);
+    let high = _mm256_permutevar8x32_epi32(high, split);
+
+    (
+        _mm256_permute2x128_si256(low, high, 0x20),
+        _mm256_permute2x128_si256(low, high, 0x31),
+    )
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn store(chunk: &mut [u8], a: __m256i, b: __m256i) {
+    let low = _mm256_permute2x128_si256(a, b, 0x20);
+    let high = _mm256_permute2x128_si256(a, b, 0x31);
+
+    let interleave = _mm256_setr_epi32(0, 4, 1, 5, 2, 6, 3, 7);
+    let low = _mm256_permutevar8x32_epi32(low, interleave);
+    let high = _mm256_permutevar8x32_epi32(high, interleave);
+
+    _mm256_storeu_si256(chunk.as_mut_ptr() as *mut __m256i, low);
+    _mm256_storeu_si256(chunk.as_mut_ptr().add(32) as *mut __m256i, high);
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn encrypt_blocks_avx2(s_table: &[u32], rounds: usize, data: &mut [u8]) -> usize {
+    let mut processed = 0;
+
+    for chunk in data.chunks_exact_mut(CHUNK_LEN) {
+        let (mut a, mut b) = load(chunk);
+
+        a = _mm256_add_epi32(a, _mm256_set1_epi32(s_table[0] as i32));
+        b = _mm256_add_epi32(b, _mm256_set1_epi32(s_table[1] as i32));
+        for i in 1..(rounds + 1) {
+            a = _mm256_add_epi32(
+                rotate_left(_mm256_xor_si256(a, b), b),
+                _mm256_set1_epi32(s_table[2 * i] as i32),
+            );
+            b = _mm256_add_epi32(
+                rotate_left(_mm256_xor_si256(b, a), a),
+                _mm256_set1_epi32(s_table[2 * i + 1] as i32),
+            );
+        }
+
+        store(chunk, a, b);
+        processed += CHUNK_LEN;
+    }
+
+    processed
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn decrypt_blocks_avx2(s_table: &[u32], rounds: usize, data: &mut [u8]) -> usize {
+    let mut processed = 0;
+
+    for chunk in data.chunks_exact_mut(CHUNK_LEN) {
+        let (mut a, mut b) = load(chunk);
+
+        for i in (1..(rounds + 1)).rev() {
+            b = _mm256_xor_si256(
+                rotate_right(
+                    _mm256_sub_epi32(b, _mm256_set1_epi32(s_table[2 * i + 1] as i32)),
+                    a,
+                ),
+                a,
+            );
+            a = _mm256_xor_si256(
+                rotate_right(
+                    _mm256_sub_epi32(a, _mm256_set1_epi32(s_table[2 * i] as i32)),
+                    b,
+                ),
+                b,
+            );
+        }
+        a = _mm256_sub_epi32(a, _mm256_set1_epi32(s_table[0] as i32));
+        b = _mm256_sub_epi32(b, _mm256_set1_epi32(s_table[1] as i32));
+
+        store(chunk, a, b);
+        processed += CHUNK_LEN;
+    }
+
+    processed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RC5;
+
+    #[test]
+    fn matches_scalar_backend() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let schedule = RC5::<u32>::new(key, 4, 12, 16).key_schedule().unwrap();
+        let s_table = schedule.u32_table().unwrap();
+
+        let plaintext = (0..CHUNK_LEN * 3 + 8).map(|x| x as u8).collect::<Vec<u8>>();
+
+        let mut expected = plaintext.clone();
+        expected
+            .chunks_exact_mut(8)
+            .for_each(|block| schedule.encrypt_block(block));
+
+        let mut data = plaintext.clone();
+        assert_eq!(encrypt_blocks(s_table, 12, &mut data), CHUNK_LEN * 3);
+        assert_eq!(data[..CHUNK_LEN * 3], expected[..CHUNK_LEN * 3]);
+
+        decrypt_blocks(s_table, 12, &mut data);
+        assert_eq!(data, plaintext);
+    }
+}
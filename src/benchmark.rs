@@ -0,0 +1,106 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{KeySchedule, Rc5Error, RC5};
+
+// bytes per call of the bulk measurement
+const BULK_LEN: usize = 1 << 16;
+
+// throughput of this machine for one set of RC5 parameters, as measured
+// by `RC5::benchmark`. MB are 10^6 bytes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThroughputReport {
+    pub key_setups_per_sec: f64,
+    pub encrypt_block_mb_per_sec: f64,
+    pub decrypt_block_mb_per_sec: f64,
+    // many blocks per call, which lets the vectorized backends kick in
+    pub bulk_encrypt_mb_per_sec: f64,
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> RC5<T> {
+    // measures key setup and block operations with this cipher's word
+    // size, rounds and key length for about `duration` in total, so that
+    // applications can pick parameters meeting their latency budget
+    pub fn benchmark(&self, duration: Duration) -> Result<ThroughputReport, Rc5Error> {
+        let schedule = self.schedule()?;
+        let block_len = schedule.block_len();
+        let slice = duration / 4;
+
+        // key setup time doesn't depend on the key bytes
+        let key = vec![0u8; self.bytes];
+        let key_setups = calls_per_sec(slice, || {
+            black_box(KeySchedule::<T>::new(
+                black_box(&key),
+                self.words,
+                self.rounds,
+                self.bytes,
+            ))
+            .is_ok()
+        });
+
+        let mut block = vec![0u8; block_len];
+        let encrypt = calls_per_sec(slice, || {
+            schedule.encrypt_block(black_box(&mut block));
+        });
+        let decrypt = calls_per_sec(slice, || {
+            schedule.decrypt_block(black_box(&mut block));
+        });
+
+        let mut data = vec![0u8; BULK_LEN];
+        let bulk = calls_per_sec(slice, || {
+            schedule.encrypt_blocks(black_box(&mut data));
+        });
+
+        Ok(ThroughputReport {
+            key_setups_per_sec: key_setups,
+            encrypt_block_mb_per_sec: encrypt * block_len as f64 / 1e6,
+            decrypt_block_mb_per_sec: decrypt * block_len as f64 / 1e6,
+            bulk_encrypt_mb_per_sec: bulk * BULK_LEN as f64 / 1e6,
+        })
+    }
+}
+
+// calls `f` in growing batches until `duration` has passed, at least once
+fn calls_per_sec<F: FnMut() -> R, R>(duration: Duration, mut f: F) -> f64 {
+    let start = Instant::now();
+    let mut calls = 0u64;
+    let mut batch = 1u64;
+
+    loop {
+        (0..batch).for_each(|_| {
+            black_box(f());
+        });
+        calls += batch;
+
+        if start.elapsed() >= duration {
+            break;
+        }
+        batch = (batch * 2).min(1 << 16);
+    }
+
+    calls as f64 / start.elapsed().as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_positive_throughput() {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let rc_5 = RC5::<u32>::new(key, 4, 12, 16);
+
+        let report = rc_5.benchmark(Duration::from_millis(20)).unwrap();
+        assert!(report.key_setups_per_sec > 0.0);
+        assert!(report.encrypt_block_mb_per_sec > 0.0);
+        assert!(report.decrypt_block_mb_per_sec > 0.0);
+        assert!(report.bulk_encrypt_mb_per_sec > 0.0);
+    }
+
+    #[test]
+    fn invalid_keys_are_reported() {
+        let rc_5 = RC5::<u32>::new(vec![1, 2, 3], 4, 12, 16);
+        assert!(rc_5.benchmark(Duration::from_millis(1)).is_err());
+    }
+}
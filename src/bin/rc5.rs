@@ -0,0 +1,2703 @@
+// command line front end to the crate, built with `--features cli`:
+//
+//   rc5 keygen --bytes 16 --out k
+//   rc5 keygen --store backup
+//   rc5 encrypt --key-name backup --in a --out a.rc5
+//   rc5 encrypt --key-file k --in a --out a.rc5
+//   rc5 decrypt --key-file k --in a.rc5 --out a
+//   rc5 encrypt --passphrase env:RC5_PASSPHRASE --in a --out a.rc5
+//   tar c dir | rc5 encrypt --key-file k > backup.rc5
+//   rc5 encrypt --key-file k --recursive photos --out photos.rc5 --exclude '*.tmp'
+//   rc5 encrypt --key-file k --armor --in notes.txt
+//   rc5 encrypt --key-file k --header --word 64 --rounds 16 --mode cbc --in a --out a.rc5
+//   rc5 encrypt --key-file k --metadata --in a --out x.rc5
+//   rc5 decrypt --key-file k --in x.rc5 --out restored/
+//   rc5 encrypt --key-file k --recursive docs --out docs.rc5 --archive
+//   rc5 decrypt --key-file k --recursive docs.rc5 --out docs --archive --include 'a/**'
+//   rc5 vectors --word 32 --rounds 12 --count 100 --format json
+//   rc5 bench --mode ctr --rounds 20
+//   rc5 verify backup.rc5 --key-file k
+//   rc5 tag backup.rc5 --key-file k
+//   rc5 verify backup.rc5 --key-file k --tag
+//
+// The default mode writes the authenticated chunked container (see
+// `ContainerWriter`), which records the word size and rounds and rejects
+// any tampering. ECB, CBC and CTR write plain ciphertext for interop with
+// other RC5 implementations, prefixed with the random IV or nonce unless
+// one is given with `--iv`.
+//
+// With `--passphrase` the output is a sealed file (see `seal`) instead,
+// whose `Rc5Header` records the Argon2id salt and costs the key was
+// derived with. A passphrase archive starts with such a header too, which
+// only says how its key was derived
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, IsTerminal, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use rc5_test::armor::{self, ArmorWriter};
+use rc5_test::vectors;
+use rc5_test::{
+    decode_base64, decode_hex, decrypt_with_header, encode_base64, encode_hex, encrypt_with_header,
+    generate_iv, generate_key, generate_nonce, open, verify_tag_file, wipe, write_tag_file,
+    ArchiveReader, ArchiveWriter, Argon2Params, CipherMagicConstants, CipherMode, Compression,
+    ContainerReader, ContainerWriter, FileMetadata, HeaderPolicy, Kdf, Mode, OsRng, Padding,
+    Rc5Header, Rc5Params, Rc5SecretKey, Sealer, Secret, StreamDecryptor, StreamEncryptor,
+    Unsigned16To64, DEFAULT_CHUNK_SIZE, HEADER_MAGIC, MAX_KEY_LEN, RC5,
+};
+use rc5_test::{OsKeyStore, Rc5Error};
+
+const USAGE: &str = "\
+usage: rc5 keygen (--out <file> | --store <name>) [--bytes <n>] [--format <raw|hex|base64>]
+       rc5 vectors [--word <16|32|64>] [--rounds <n>] [--count <n>] [--key-bytes <n>]
+                   [--seed <n>] [--format <json|text>] [--out <file>]
+       rc5 bench [--rounds <n>] [--key-bytes <n>] [--mode <mode>] [--seconds <n>]
+       rc5 verify [<file>] <key> [--word <n>] [--rounds <n>]
+       rc5 verify <file> <key> --tag [--word <n>] [--rounds <n>]
+       rc5 tag <file> <key> [--word <n>] [--rounds <n>]
+       rc5 encrypt <key> [--in <file>] [--out <file>] [options]
+       rc5 decrypt <key> [--in <file>] [--out <file>] [options]
+
+where <key> is one of --key-file <file>, --key-name <name> or
+--passphrase <source>.
+
+encrypt and decrypt read standard input and write standard output unless
+`--in` and `--out` name files, and `-` names them explicitly. Data is
+streamed, so memory use doesn't grow with the input. A failed decryption
+removes its output file, but on standard output anything written before
+the failure is already gone, so check the exit status.
+
+With `--recursive <dir>`, every file under the directory is encrypted on
+its own into the same relative path under the `--out` directory, with
+`.rc5` appended. Decrypting a tree removes the suffix again. `--include`
+and `--exclude` take globs and may be repeated: a file is processed if it
+matches an include (or none are given) and no exclude. `*` and `?` stop at
+`/`, `**` doesn't, and a glob without `/` is matched against the file
+name only. Symbolic links are not followed.
+
+`--metadata` stores the name, modification time and permissions of the
+input file in the encrypted container, so the ciphertext can be named
+anything. Decrypting such a container gives the output file the recorded
+time and permissions, and decrypting it with `--out` naming a directory
+writes the file into it under the recorded name, never replacing an
+existing file.
+
+`--archive` with `--recursive` packs the tree into the single file
+`--out` instead: an archive of eax containers, one per file, and an
+encrypted index of their paths, sizes, times and permissions, so nothing
+about the files shows. Decrypting with `--archive` takes the archive as
+`--recursive` and extracts into the `--out` directory, only the entries
+matching `--include` and `--exclude` if given, without reading the others.
+Archives always use 32 bit words and 12 rounds.
+
+`--armor` writes the output as base64 lines between
+`-----BEGIN RC5 MESSAGE-----` and `-----END RC5 MESSAGE-----`, which
+survive being pasted into mail or a ticket. decrypt recognizes armored
+input by itself.
+
+`--header` starts the output with a header recording the word size,
+rounds, key length, mode, padding and IV, and decrypt recognizes it and
+takes all of them from the input instead of the options. So that a file
+can't ask for a weak cipher, decrypt only follows a header to RC5-32 with
+12, 16 or 20 rounds or RC5-64 with 16, 20 or 24, in cbc, ctr or eax mode,
+or to the `--word`, `--rounds` and `--mode` it is given.
+
+verify checks every tag of an eax container (standard input without a
+file) and writes no plaintext. It exits with 0 if the container is
+intact, 3 if it was modified or truncated and 4 if the key or passphrase
+is wrong, which the key check value in the container's header shows
+before any chunk is opened. Containers written before it was added have
+none, so a wrong key is reported as a modification there. Other failures
+exit with 1 and usage errors with 2.
+
+tag writes a detached tag of any file to `<file>.tag`, so that a large
+file can be checked without decrypting it, or its tag be handed out
+separately. `verify --tag` checks the file against that tag and exits
+with 3 if they don't match, which a wrong key, word size or number of
+rounds also causes. Tags need a key file or a named key.
+
+When standard output and standard error are terminals, runs that take
+more than a moment draw a progress bar with the throughput and, for files,
+the estimated time left on standard error.
+
+keygen writes a random key of 16 bytes unless `--bytes` says otherwise
+(1 to 255), readable only by the owner on unix. It never overwrites an
+existing file.
+
+`keygen --store <name>` keeps the key in the Linux kernel keyring instead
+of a file, where `--key-name <name>` finds it again. The kernel holds it
+in memory for the user's processes only, until the user's last session
+ends, and never writes it to disk. It is not available on other systems.
+
+vectors prints reproducible single-block test vectors for other
+implementations to check against: `count` keys of `key-bytes` bytes
+(default 16) and plaintext blocks drawn from SplitMix64 seeded with
+`seed` (default 0), with their ciphertexts. json output is laid out like
+the files in the crate's vectors/ directory, text output is one
+`key plaintext ciphertext` line of hex per vector.
+
+bench measures this machine with each word size for about `seconds`
+(default 1) apiece: key setups, single blocks, ECB on large buffers and
+the whole encrypt path of `--mode` (default eax), and prints a table.
+
+The passphrase source is one of pass:<text>, env:<variable> or
+file:<path>, where a file may end in a newline. The key is derived from it
+with Argon2id and a random salt, both recorded in a header at the start
+of the output. encrypt then seals the input, always as RC5-32/12 in eax
+mode, so a passphrase can't be used with other modes, `--header` or
+`--metadata`.
+
+options:
+  --key-format <raw|hex|base64>
+                             how the key file is encoded (default raw)
+  --word <16|32|64>          word size in bits (default 32)
+  --rounds <n>               number of rounds, 0 to 255 (default 12)
+  --mode <eax|ecb|cbc|ctr>   eax writes an authenticated container, the
+                             others plain ciphertext (default eax)
+  --iv <hex>                 CBC IV or CTR nonce. Without it, encrypt
+                             picks a random one and writes it before the
+                             ciphertext, and decrypt reads it from there
+  --padding <pkcs7|iso7816|x923|zero>
+                             how ecb and cbc pad the last block (default
+                             pkcs7)
+  --armor                    encrypt to base64 text with header and footer
+  --header                   record the parameters before the ciphertext
+  --metadata                 encrypt the input file's name, time and
+                             permissions along with it (eax only)
+  --recursive <dir>          process a directory tree instead of `--in`
+  --archive                  pack the tree into one file, or extract one
+  --include <glob>           only process matching files of the tree
+  --exclude <glob>           skip matching files of the tree";
+
+// the salt of a passphrase archive's key
+const SALT_LEN: usize = 16;
+
+// appended to encrypted files of a tree, and required when decrypting one
+const TREE_SUFFIX: &str = ".rc5";
+
+// flags that take no value
+const SWITCHES: &[&str] = &["--archive", "--armor", "--header", "--metadata", "--tag"];
+
+// the progress bar appears once a run takes longer than this, and is
+// redrawn at most this often
+const PROGRESS_DELAY: Duration = Duration::from_millis(500);
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+const PROGRESS_WIDTH: usize = 30;
+
+// plaintext and ciphertext are streamed in pieces of this size
+const BUFFER_LEN: usize = 1 << 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Command {
+    Encrypt,
+    Decrypt,
+}
+
+// how a key file stores the key. Text forms may end in a newline
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KeyFormat {
+    Raw,
+    Hex,
+    Base64,
+}
+
+impl KeyFormat {
+    fn parse(text: &str) -> Result<Self, String> {
+        match text {
+            "raw" => Ok(KeyFormat::Raw),
+            "hex" => Ok(KeyFormat::Hex),
+            "base64" => Ok(KeyFormat::Base64),
+            other => Err(format!("unknown key format `{}`", other)),
+        }
+    }
+
+    fn encode(self, key: &[u8]) -> Vec<u8> {
+        match self {
+            KeyFormat::Raw => key.to_vec(),
+            KeyFormat::Hex => format!("{}\n", encode_hex(key)).into_bytes(),
+            KeyFormat::Base64 => format!("{}\n", encode_base64(key)).into_bytes(),
+        }
+    }
+
+    fn decode(self, contents: &[u8]) -> Option<Vec<u8>> {
+        if self == KeyFormat::Raw {
+            return Some(contents.to_vec());
+        }
+        let text = std::str::from_utf8(contents).ok()?;
+        match self {
+            KeyFormat::Hex => decode_hex(text).ok(),
+            _ => decode_base64(text).ok(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Passphrase {
+    Text(String),
+    Env(OsString),
+    File(PathBuf),
+}
+
+impl Passphrase {
+    fn parse(value: &OsString) -> Result<Self, String> {
+        let value = value.to_str().ok_or("invalid value for `--passphrase`")?;
+        if let Some(text) = value.strip_prefix("pass:") {
+            Ok(Passphrase::Text(text.to_string()))
+        } else if let Some(variable) = value.strip_prefix("env:") {
+            Ok(Passphrase::Env(OsString::from(variable)))
+        } else if let Some(path) = value.strip_prefix("file:") {
+            Ok(Passphrase::File(PathBuf::from(path)))
+        } else {
+            Err("`--passphrase` must start with pass:, env: or file:".to_string())
+        }
+    }
+
+    fn read(&self) -> Result<Vec<u8>, String> {
+        let mut passphrase = match self {
+            Passphrase::Text(text) => text.as_bytes().to_vec(),
+            Passphrase::Env(variable) => std::env::var(variable)
+                .map_err(|_| {
+                    format!(
+                        "`{}` is not set to a passphrase",
+                        variable.to_string_lossy()
+                    )
+                })?
+                .into_bytes(),
+            Passphrase::File(path) => {
+                let mut contents =
+                    fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+                for ending in [&b"\n"[..], b"\r"].iter() {
+                    if contents.ends_with(ending) {
+                        let len = contents.len() - 1;
+                        contents[len] = 0;
+                        contents.truncate(len);
+                    }
+                }
+                contents
+            }
+        };
+        if passphrase.is_empty() {
+            wipe(&mut passphrase);
+            return Err("the passphrase is empty".to_string());
+        }
+        Ok(passphrase)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum KeySource {
+    File(PathBuf, KeyFormat),
+    // a key stored with `keygen --store`
+    Named(String),
+    Passphrase(Passphrase),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CliMode {
+    Eax,
+    Ecb,
+    Cbc,
+    Ctr,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Tree {
+    root: PathBuf,
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Options {
+    command: Command,
+    key: KeySource,
+    // `None` for standard input and output
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    word: u32,
+    rounds: usize,
+    mode: CliMode,
+    iv: Option<Vec<u8>>,
+    padding: Padding,
+    // the Argon2id cost when encrypting with a passphrase; decryption
+    // takes it from the header
+    kdf: Argon2Params,
+    recursive: Option<Tree>,
+    armor: bool,
+    // store the metadata of the input file when encrypting
+    metadata: bool,
+    // the tree goes into, or comes out of, one archive
+    archive: bool,
+    // write an `Rc5Header` before the ciphertext when encrypting
+    header: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct KeygenOptions {
+    bytes: usize,
+    output: KeyOutput,
+    format: KeyFormat,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum KeyOutput {
+    File(PathBuf),
+    Store(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum VectorFormat {
+    Json,
+    Text,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct VectorOptions {
+    word: u32,
+    rounds: usize,
+    count: usize,
+    key_bytes: usize,
+    seed: u64,
+    format: VectorFormat,
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq)]
+struct BenchOptions {
+    rounds: usize,
+    key_bytes: usize,
+    mode: CliMode,
+    duration: Duration,
+}
+
+// exit codes of `rc5 verify`
+const EXIT_TAMPERED: u8 = 3;
+const EXIT_WRONG_KEY: u8 = 4;
+
+#[derive(Debug, PartialEq)]
+enum Invocation {
+    Cipher(Options),
+    Keygen(KeygenOptions),
+    Vectors(VectorOptions),
+    Bench(BenchOptions),
+    // the options of a decryption that checks the input and discards it
+    Verify(Options),
+    // the file to tag or to check against its tag, and the key
+    Tag(Options),
+    VerifyTag(Options),
+}
+
+fn parse<I: IntoIterator<Item = OsString>>(args: I) -> Result<Invocation, String> {
+    let mut args = args.into_iter();
+    let command = match args.next().as_ref().and_then(|arg| arg.to_str()) {
+        Some("encrypt") => Command::Encrypt,
+        Some("decrypt") => Command::Decrypt,
+        Some("keygen") => return parse_keygen(flags(args)?).map(Invocation::Keygen),
+        Some("vectors") => return parse_vectors(flags(args)?).map(Invocation::Vectors),
+        Some("bench") => return parse_bench(flags(args)?).map(Invocation::Bench),
+        Some("verify") => return parse_verify(args),
+        Some("tag") => return parse_tag(args).map(Invocation::Tag),
+        Some(other) => return Err(format!("unknown command `{}`", other)),
+        None => return Err("missing command".to_string()),
+    };
+    parse_cipher(command, flags(args)?).map(Invocation::Cipher)
+}
+
+// splits the arguments into `--flag value` pairs, with an empty value for
+// the `SWITCHES`
+fn flags<I: Iterator<Item = OsString>>(mut args: I) -> Result<Vec<(String, OsString)>, String> {
+    let mut flags = Vec::new();
+    while let Some(flag) = args.next() {
+        let flag = flag.to_string_lossy().into_owned();
+        if SWITCHES.contains(&flag.as_str()) {
+            flags.push((flag, OsString::new()));
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("`{}` needs a value", flag))?;
+        flags.push((flag, value));
+    }
+    Ok(flags)
+}
+
+fn text<'a>(flag: &str, value: &'a OsString) -> Result<&'a str, String> {
+    value
+        .to_str()
+        .ok_or_else(|| format!("invalid value for `{}`", flag))
+}
+
+fn parse_cipher(command: Command, flags: Vec<(String, OsString)>) -> Result<Options, String> {
+    let mut key_file = None;
+    let mut passphrase = None;
+    let mut input = None;
+    let mut output = None;
+    let mut word = 32;
+    let mut rounds = 12;
+    let mut mode = CliMode::Eax;
+    let mut iv = None;
+    let mut key_format = KeyFormat::Raw;
+    let mut key_name = None;
+    let mut root = None;
+    let mut include = Vec::new();
+    let mut exclude = Vec::new();
+    let mut armor = false;
+    let mut metadata = false;
+    let mut archive = false;
+    let mut header = false;
+    let mut padding = Padding::Pkcs7;
+
+    for (flag, value) in flags {
+        let text = || text(&flag, &value);
+        match flag.as_str() {
+            "--key-file" => key_file = Some(PathBuf::from(&value)),
+            "--key-name" => key_name = Some(text()?.to_string()),
+            "--key-format" => key_format = KeyFormat::parse(text()?)?,
+            "--passphrase" => passphrase = Some(Passphrase::parse(&value)?),
+            "--in" => input = stdio_or_path(&value),
+            "--out" => output = stdio_or_path(&value),
+            "--word" => word = parse_word(text()?)?,
+            "--rounds" => rounds = parse_rounds(text()?)?,
+            "--mode" => mode = parse_mode(text()?)?,
+            "--iv" => iv = Some(decode_hex(text()?).map_err(|_| "`--iv` must be hex")?),
+            "--recursive" => root = Some(PathBuf::from(&value)),
+            "--include" => include.push(text()?.to_string()),
+            "--exclude" => exclude.push(text()?.to_string()),
+            "--armor" => armor = true,
+            "--metadata" => metadata = true,
+            "--archive" => archive = true,
+            "--header" => header = true,
+            "--padding" => padding = parse_padding(text()?)?,
+            other => return Err(format!("unknown option `{}`", other)),
+        }
+    }
+
+    if iv.is_some() && !matches!(mode, CliMode::Cbc | CliMode::Ctr) {
+        return Err("`--iv` only applies to cbc and ctr".to_string());
+    }
+    if padding != Padding::Pkcs7 && !matches!(mode, CliMode::Ecb | CliMode::Cbc) {
+        return Err("`--padding` only applies to ecb and cbc".to_string());
+    }
+    if header && (command == Command::Decrypt || metadata) {
+        return Err(
+            "`--header` only applies to encrypt without `--metadata`, decrypt detects it"
+                .to_string(),
+        );
+    }
+    if armor && command == Command::Decrypt {
+        return Err("`--armor` only applies to encrypt, decrypt detects armor".to_string());
+    }
+    if metadata {
+        if command == Command::Decrypt {
+            return Err("`--metadata` only applies to encrypt, decrypt restores it".to_string());
+        }
+        if mode != CliMode::Eax {
+            return Err("`--metadata` needs an eax container to store it in".to_string());
+        }
+        if input.is_none() && root.is_none() {
+            return Err("`--metadata` needs `--in` to name a file".to_string());
+        }
+    }
+    if archive {
+        if root.is_none() {
+            return Err("`--archive` needs `--recursive`".to_string());
+        }
+        if mode != CliMode::Eax || armor || header {
+            return Err(
+                "`--archive` can't be used with other modes, `--armor` or `--header`".to_string(),
+            );
+        }
+        if metadata {
+            return Err("`--archive` always stores the metadata".to_string());
+        }
+        if word != 32 || rounds != 12 {
+            return Err("archives always use 32 bit words and 12 rounds".to_string());
+        }
+    }
+    let recursive = match root {
+        Some(root) => {
+            if input.is_some() || output.is_none() {
+                return Err("`--recursive` needs `--out` and no `--in`".to_string());
+            }
+            // the files of a tree must not share an IV or nonce
+            if iv.is_some() {
+                return Err("`--iv` can't be used with `--recursive`".to_string());
+            }
+            Some(Tree {
+                root,
+                include,
+                exclude,
+            })
+        }
+        None if !include.is_empty() || !exclude.is_empty() => {
+            return Err("`--include` and `--exclude` need `--recursive`".to_string())
+        }
+        None => None,
+    };
+    let key = match (key_file, key_name, passphrase) {
+        (Some(path), None, None) => KeySource::File(path, key_format),
+        (None, Some(name), None) => KeySource::Named(name),
+        (None, None, Some(passphrase)) => KeySource::Passphrase(passphrase),
+        (None, None, None) => {
+            return Err("missing `--key-file`, `--key-name` or `--passphrase`".to_string())
+        }
+        _ => {
+            return Err(
+                "`--key-file`, `--key-name` and `--passphrase` are mutually exclusive".to_string(),
+            )
+        }
+    };
+    if let KeySource::Passphrase(_) = key {
+        if mode != CliMode::Eax || header || metadata {
+            return Err(
+                "`--passphrase` seals the input in eax mode, without `--header` or `--metadata`"
+                    .to_string(),
+            );
+        }
+        if word != 32 || rounds != 12 {
+            return Err("`--passphrase` always seals with 32 bit words and 12 rounds".to_string());
+        }
+    }
+    Ok(Options {
+        command,
+        key,
+        input,
+        output,
+        word,
+        rounds,
+        mode,
+        iv,
+        padding,
+        kdf: Argon2Params::default(),
+        recursive,
+        armor,
+        metadata,
+        archive,
+        header,
+    })
+}
+
+fn stdio_or_path(value: &OsString) -> Option<PathBuf> {
+    if value == "-" {
+        None
+    } else {
+        Some(PathBuf::from(value))
+    }
+}
+
+fn parse_word(text: &str) -> Result<u32, String> {
+    match text {
+        "16" => Ok(16),
+        "32" => Ok(32),
+        "64" => Ok(64),
+        other => Err(format!("unsupported word size `{}`", other)),
+    }
+}
+
+fn parse_rounds(text: &str) -> Result<usize, String> {
+    text.parse::<u8>()
+        .map(usize::from)
+        .map_err(|_| "rounds must be between 0 and 255".to_string())
+}
+
+fn parse_mode(text: &str) -> Result<CliMode, String> {
+    match text {
+        "eax" => Ok(CliMode::Eax),
+        "ecb" => Ok(CliMode::Ecb),
+        "cbc" => Ok(CliMode::Cbc),
+        "ctr" => Ok(CliMode::Ctr),
+        other => Err(format!("unknown mode `{}`", other)),
+    }
+}
+
+fn parse_padding(text: &str) -> Result<Padding, String> {
+    text.parse()
+        .map_err(|_| format!("unknown padding `{}`", text))
+}
+
+fn parse_key_bytes(text: &str) -> Result<usize, String> {
+    match text.parse::<usize>() {
+        Ok(bytes) if (1..=MAX_KEY_LEN).contains(&bytes) => Ok(bytes),
+        _ => Err(format!("key length must be between 1 and {}", MAX_KEY_LEN)),
+    }
+}
+
+// at most this many vectors are generated in one go
+const MAX_VECTORS: usize = 1_000_000;
+
+fn parse_vectors(flags: Vec<(String, OsString)>) -> Result<VectorOptions, String> {
+    let mut options = VectorOptions {
+        word: 32,
+        rounds: 12,
+        count: 100,
+        key_bytes: 16,
+        seed: 0,
+        format: VectorFormat::Json,
+        output: None,
+    };
+
+    for (flag, value) in flags {
+        let text = || text(&flag, &value);
+        match flag.as_str() {
+            "--word" => options.word = parse_word(text()?)?,
+            "--rounds" => options.rounds = parse_rounds(text()?)?,
+            "--count" => {
+                options.count = match text()?.parse::<usize>() {
+                    Ok(count) if (1..=MAX_VECTORS).contains(&count) => count,
+                    _ => return Err(format!("count must be between 1 and {}", MAX_VECTORS)),
+                }
+            }
+            "--key-bytes" => options.key_bytes = parse_key_bytes(text()?)?,
+            "--seed" => options.seed = text()?.parse().map_err(|_| "invalid seed".to_string())?,
+            "--format" => {
+                options.format = match text()? {
+                    "json" => VectorFormat::Json,
+                    "text" => VectorFormat::Text,
+                    other => return Err(format!("unknown vector format `{}`", other)),
+                }
+            }
+            "--out" => options.output = stdio_or_path(&value),
+            other => return Err(format!("unknown option `{}`", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_verify<I: Iterator<Item = OsString>>(args: I) -> Result<Invocation, String> {
+    let (options, tag) = parse_checked_file(args)?;
+    if tag {
+        return detached_options(options).map(Invocation::VerifyTag);
+    }
+    if options.mode != CliMode::Eax {
+        return Err("only eax containers carry tags to verify".to_string());
+    }
+    Ok(Invocation::Verify(options))
+}
+
+fn parse_tag<I: Iterator<Item = OsString>>(args: I) -> Result<Options, String> {
+    let (options, tag) = parse_checked_file(args)?;
+    if tag {
+        return Err("unknown option `--tag`".to_string());
+    }
+    detached_options(options)
+}
+
+// the options of `verify` and `tag`: an optional file first, then the
+// flags, of which `--tag` is reported separately
+fn parse_checked_file<I: Iterator<Item = OsString>>(args: I) -> Result<(Options, bool), String> {
+    let mut args = args.peekable();
+    let file = match args.peek() {
+        Some(arg) if !arg.to_string_lossy().starts_with("--") => args.next(),
+        _ => None,
+    };
+    let mut flags = flags(args)?;
+    let tag = flags.iter().any(|(flag, _)| flag == "--tag");
+    flags.retain(|(flag, _)| flag != "--tag");
+    flags.extend(file.map(|file| ("--in".to_string(), file)));
+
+    let options = parse_cipher(Command::Decrypt, flags)?;
+    if options.output.is_some() || options.recursive.is_some() {
+        return Err("verify and tag write no output and take a single file".to_string());
+    }
+    Ok((options, tag))
+}
+
+// a detached tag sits next to its file, and there is no header to hold
+// the salt of a passphrase
+fn detached_options(options: Options) -> Result<Options, String> {
+    if options.input.is_none() {
+        return Err("detached tags need a file".to_string());
+    }
+    if let KeySource::Passphrase(_) = options.key {
+        return Err("detached tags need `--key-file` or `--key-name`".to_string());
+    }
+    Ok(options)
+}
+
+fn parse_bench(flags: Vec<(String, OsString)>) -> Result<BenchOptions, String> {
+    let mut options = BenchOptions {
+        rounds: 12,
+        key_bytes: 16,
+        mode: CliMode::Eax,
+        duration: Duration::from_secs(1),
+    };
+
+    for (flag, value) in flags {
+        let text = || text(&flag, &value);
+        match flag.as_str() {
+            "--rounds" => options.rounds = parse_rounds(text()?)?,
+            "--key-bytes" => options.key_bytes = parse_key_bytes(text()?)?,
+            "--mode" => options.mode = parse_mode(text()?)?,
+            "--seconds" => {
+                options.duration = match text()?.parse::<f64>() {
+                    Ok(seconds) if seconds > 0.0 && seconds <= 60.0 => {
+                        Duration::from_secs_f64(seconds)
+                    }
+                    _ => return Err("seconds must be above 0 and at most 60".to_string()),
+                }
+            }
+            other => return Err(format!("unknown option `{}`", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_keygen(flags: Vec<(String, OsString)>) -> Result<KeygenOptions, String> {
+    let mut bytes = 16;
+    let mut output = None;
+    let mut store = None;
+    let mut format = None;
+
+    for (flag, value) in flags {
+        match flag.as_str() {
+            "--bytes" => bytes = parse_key_bytes(text(&flag, &value)?)?,
+            "--out" => output = Some(PathBuf::from(&value)),
+            "--store" => store = Some(text(&flag, &value)?.to_string()),
+            "--format" => format = Some(KeyFormat::parse(text(&flag, &value)?)?),
+            other => return Err(format!("unknown option `{}`", other)),
+        }
+    }
+
+    let output = match (output, store) {
+        (Some(path), None) => KeyOutput::File(path),
+        (None, Some(_)) if format.is_some() => {
+            return Err("`--format` only applies to key files".to_string())
+        }
+        (None, Some(name)) => KeyOutput::Store(name),
+        (None, None) => return Err("missing `--out` or `--store`".to_string()),
+        (Some(_), Some(_)) => {
+            return Err("`--out` and `--store` are mutually exclusive".to_string())
+        }
+    };
+    Ok(KeygenOptions {
+        bytes,
+        output,
+        format: format.unwrap_or(KeyFormat::Raw),
+    })
+}
+
+fn read_key(path: &Path, format: KeyFormat) -> Result<Rc5SecretKey, String> {
+    let mut contents = fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let key = format.decode(&contents);
+    wipe(&mut contents);
+
+    key.map(Rc5SecretKey::new)
+        .ok_or_else(|| format!("{}: not a valid key file", path.display()))
+}
+
+fn keygen(options: &KeygenOptions) -> Result<(), String> {
+    let path = match &options.output {
+        KeyOutput::File(path) => path,
+        KeyOutput::Store(name) => {
+            let key = generate_key(options.bytes).map_err(message)?;
+            return store_named_key(name, &key);
+        }
+    };
+    let mut key = vec![0u8; options.bytes];
+    OsRng.try_fill_bytes(&mut key).map_err(message)?;
+    let mut contents = options.format.encode(&key);
+    wipe(&mut key);
+
+    let mut file = fs::OpenOptions::new();
+    file.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut file, 0o600);
+
+    let written = file
+        .open(path)
+        .and_then(|mut file| file.write_all(&contents))
+        .map_err(|err| format!("{}: {}", path.display(), err));
+    wipe(&mut contents);
+    written
+}
+
+// keys of `keygen --store` and `--key-name` live under this service
+const KEY_STORE_SERVICE: &str = "rc5";
+
+// like a key file, a stored key is never replaced
+fn store_named_key(name: &str, key: &Rc5SecretKey) -> Result<(), String> {
+    let store = OsKeyStore::new(KEY_STORE_SERVICE);
+    match store.load(name) {
+        Ok(_) => Err(format!("a key named `{}` is already stored", name)),
+        Err(Rc5Error::UnknownKeyId) => store
+            .store(name, key)
+            .map_err(|err| format!("key `{}`: {}", name, err)),
+        Err(err) => Err(format!("key `{}`: {}", name, err)),
+    }
+}
+
+fn load_named_key(name: &str) -> Result<Rc5SecretKey, String> {
+    OsKeyStore::new(KEY_STORE_SERVICE)
+        .load(name)
+        .map_err(|err| format!("key `{}`: {}", name, err))
+}
+
+fn vectors(options: &VectorOptions) -> Result<(), String> {
+    let text = vector_text(options)?;
+    match &options.output {
+        Some(path) => fs::write(path, text).map_err(|err| format!("{}: {}", path.display(), err)),
+        None => io::stdout()
+            .lock()
+            .write_all(text.as_bytes())
+            .map_err(message),
+    }
+}
+
+// the vectors come from `vectors::generate`, which documents how they
+// are drawn from the seed
+fn vector_text(options: &VectorOptions) -> Result<String, String> {
+    let file = vectors::generate(
+        options.word,
+        options.rounds,
+        options.key_bytes,
+        options.count,
+        options.seed,
+    )
+    .map_err(message)?;
+
+    Ok(match options.format {
+        VectorFormat::Json => file.to_json(),
+        VectorFormat::Text => file.groups[0]
+            .tests
+            .iter()
+            .map(|test| {
+                format!(
+                    "{} {} {}\n",
+                    encode_hex(&test.key),
+                    encode_hex(&test.msg),
+                    encode_hex(&test.ct)
+                )
+            })
+            .collect(),
+    })
+}
+
+// how much plaintext one pass of the mode measurement encrypts
+const BENCH_LEN: usize = 1 << 20;
+
+fn bench_table(options: &BenchOptions) -> Result<String, String> {
+    let mode = format!("{:?}", options.mode).to_lowercase();
+    let mut table = format!(
+        "word  rounds  key bytes  key setups/s  block enc MB/s  block dec MB/s  ecb bulk MB/s  {:>8} MB/s\n",
+        mode
+    );
+    for word in [16, 32, 64].iter() {
+        let row = match word {
+            16 => bench_row::<u16>(options),
+            32 => bench_row::<u32>(options),
+            _ => bench_row::<u64>(options),
+        }?;
+        table.push_str(&format!(
+            "{:>4}  {:>6}  {:>9}  {:>12.0}  {:>14.1}  {:>14.1}  {:>13.1}  {:>13.1}\n",
+            word, options.rounds, options.key_bytes, row.0, row.1, row.2, row.3, row.4
+        ));
+    }
+    Ok(table)
+}
+
+// key setups per second, then the MB/s of single block encryption and
+// decryption, bulk ECB and the configured mode
+fn bench_row<T>(options: &BenchOptions) -> Result<(f64, f64, f64, f64, f64), String>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let key = || Rc5SecretKey::new(vec![0x5A; options.key_bytes]);
+    let cipher = RC5::<T>::from_secret_key(
+        key(),
+        std::mem::size_of::<T>(),
+        options.rounds,
+        options.key_bytes,
+    );
+    let report = cipher.benchmark(options.duration / 2).map_err(message)?;
+
+    // the same work as `rc5 encrypt`, minus the files
+    let encrypt = Options {
+        command: Command::Encrypt,
+        key: KeySource::File(PathBuf::new(), KeyFormat::Raw),
+        input: None,
+        output: None,
+        word: 8 * std::mem::size_of::<T>() as u32,
+        rounds: options.rounds,
+        mode: options.mode,
+        iv: None,
+        padding: Padding::Pkcs7,
+        kdf: Argon2Params::default(),
+        recursive: None,
+        armor: false,
+        metadata: false,
+        archive: false,
+        header: false,
+    };
+    let plaintext = vec![0u8; BENCH_LEN];
+    let slice = options.duration / 2;
+    let started = Instant::now();
+    let mut bytes = 0u64;
+    while bytes == 0 || started.elapsed() < slice {
+        process::<T>(&encrypt, key(), &mut &plaintext[..], &mut io::sink())?;
+        bytes += BENCH_LEN as u64;
+    }
+    let mode = bytes as f64 / started.elapsed().as_secs_f64() / 1e6;
+
+    Ok((
+        report.key_setups_per_sec,
+        report.encrypt_block_mb_per_sec,
+        report.decrypt_block_mb_per_sec,
+        report.bulk_encrypt_mb_per_sec,
+        mode,
+    ))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum VerifyFailure {
+    Tampered(String),
+    WrongKey,
+    Error(String),
+}
+
+impl VerifyFailure {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            VerifyFailure::Tampered(_) => ExitCode::from(EXIT_TAMPERED),
+            VerifyFailure::WrongKey => ExitCode::from(EXIT_WRONG_KEY),
+            VerifyFailure::Error(_) => ExitCode::FAILURE,
+        }
+    }
+}
+
+impl std::fmt::Display for VerifyFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyFailure::Tampered(reason) => write!(f, "the input was modified: {}", reason),
+            VerifyFailure::WrongKey => f.write_str("the key or passphrase is wrong"),
+            VerifyFailure::Error(err) => f.write_str(err),
+        }
+    }
+}
+
+fn verify(options: &Options) -> Result<(), VerifyFailure> {
+    let (input, _) = open_input(&options.input).map_err(VerifyFailure::Error)?;
+    let input = BufReader::with_capacity(BUFFER_LEN, input);
+    let mut input =
+        armor::dearmor(input).map_err(|err| VerifyFailure::Tampered(err.to_string()))?;
+    let key = match &options.key {
+        KeySource::File(path, format) => read_key(path, *format),
+        KeySource::Named(name) => load_named_key(name),
+        KeySource::Passphrase(passphrase) => return verify_sealed(passphrase, &mut input),
+    }
+    .map_err(VerifyFailure::Error)?;
+
+    match options.word {
+        16 => verify_container::<u16>(options, key, &mut input),
+        32 => verify_container::<u32>(options, key, &mut input),
+        _ => verify_container::<u64>(options, key, &mut input),
+    }
+}
+
+fn verify_container<T>(
+    options: &Options,
+    key: Rc5SecretKey,
+    input: &mut dyn Read,
+) -> Result<(), VerifyFailure>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let bytes = key.len();
+    let cipher = RC5::<T>::from_secret_key(key, std::mem::size_of::<T>(), options.rounds, bytes);
+
+    // the reader has checked the key against the header's check value, so
+    // any chunk failing to authenticate was forged
+    let mut reader = ContainerReader::new(input, &cipher).map_err(verify_failure)?;
+    loop {
+        match reader.next_chunk() {
+            Ok(Some(mut chunk)) => wipe(&mut chunk),
+            Ok(None) => return Ok(()),
+            Err(err) => return Err(verify_failure(err)),
+        }
+    }
+}
+
+// opens a sealed file into nothing. The container's key check value
+// covers a wrong passphrase, and since the container's key is derived
+// for the exact header, a modified header as well
+fn verify_sealed(passphrase: &Passphrase, input: &mut dyn Read) -> Result<(), VerifyFailure> {
+    let mut secret = passphrase.read().map_err(VerifyFailure::Error)?;
+    let result = open(input, io::sink(), Secret::Passphrase(&secret));
+    wipe(&mut secret);
+    result.map(drop).map_err(verify_failure)
+}
+
+fn verify_failure(err: io::Error) -> VerifyFailure {
+    match err.kind() {
+        _ if rc5_error(&err) == Some(Rc5Error::WrongKey) => VerifyFailure::WrongKey,
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+            VerifyFailure::Tampered(err.to_string())
+        }
+        _ => VerifyFailure::Error(err.to_string()),
+    }
+}
+
+fn rc5_error(err: &io::Error) -> Option<Rc5Error> {
+    err.get_ref()
+        .and_then(|inner| inner.downcast_ref::<Rc5Error>())
+        .copied()
+}
+
+fn is_forged(err: &io::Error) -> bool {
+    rc5_error(err) == Some(Rc5Error::DecryptionFailed)
+}
+
+// writes the tag of the file of `options`, or with `verify` checks the
+// file against it
+fn detached(options: &Options, verify: bool) -> Result<(), VerifyFailure> {
+    let key = match &options.key {
+        KeySource::File(path, format) => read_key(path, *format),
+        KeySource::Named(name) => load_named_key(name),
+        KeySource::Passphrase(_) => unreachable!("refused by `detached_options`"),
+    }
+    .map_err(VerifyFailure::Error)?;
+    let path = options
+        .input
+        .as_deref()
+        .expect("checked by `detached_options`");
+
+    let result = match options.word {
+        16 => detached_file::<u16>(options, key, path, verify),
+        32 => detached_file::<u32>(options, key, path, verify),
+        _ => detached_file::<u64>(options, key, path, verify),
+    };
+    result.map_err(|err| {
+        if is_forged(&err) {
+            VerifyFailure::Tampered("the file doesn't match its tag".to_string())
+        } else {
+            VerifyFailure::Error(format!("{}: {}", path.display(), err))
+        }
+    })
+}
+
+fn detached_file<T>(
+    options: &Options,
+    key: Rc5SecretKey,
+    path: &Path,
+    verify: bool,
+) -> io::Result<()>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let bytes = key.len();
+    let cipher = RC5::<T>::from_secret_key(key, std::mem::size_of::<T>(), options.rounds, bytes);
+    if verify {
+        verify_tag_file(&cipher, path)
+    } else {
+        write_tag_file(&cipher, path).map(drop)
+    }
+}
+
+fn open_input(path: &Option<PathBuf>) -> Result<(Box<dyn Read>, Option<u64>), String> {
+    match path {
+        Some(path) => {
+            let file = File::open(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+            let total = file.metadata().ok().map(|metadata| metadata.len());
+            Ok((Box::new(file), total))
+        }
+        None => Ok((Box::new(io::stdin().lock()), None)),
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let key = match &options.key {
+        KeySource::File(path, format) => Some(read_key(path, *format)?),
+        KeySource::Named(name) => Some(load_named_key(name)?),
+        KeySource::Passphrase(_) => None,
+    };
+    let (input, total) = open_input(&options.input)?;
+    let input: Box<dyn Read> = if io::stdout().is_terminal() && io::stderr().is_terminal() {
+        let mut bar = ProgressBar::new(total);
+        Box::new(Counted::new(input, move |done| bar.update(done)))
+    } else {
+        input
+    };
+    // decrypting into a directory writes to a temporary file there, which
+    // is renamed once the container has said what it was called
+    let into_dir = options.command == Command::Decrypt
+        && options.output.as_ref().is_some_and(|path| path.is_dir());
+    let output_path = match &options.output {
+        Some(dir) if into_dir => Some(dir.join(format!(".rc5-{}.partial", std::process::id()))),
+        path => path.clone(),
+    };
+    let output: Box<dyn Write> = match &output_path {
+        Some(path) => {
+            Box::new(File::create(path).map_err(|err| format!("{}: {}", path.display(), err))?)
+        }
+        None => Box::new(io::stdout().lock()),
+    };
+
+    let input = BufReader::with_capacity(BUFFER_LEN, input);
+    let mut input: Box<dyn Read> = match options.command {
+        Command::Encrypt => Box::new(input),
+        Command::Decrypt => Box::new(armor::dearmor(input).map_err(message)?),
+    };
+    let mut output = BufWriter::with_capacity(BUFFER_LEN, output);
+
+    let result = if options.armor {
+        ArmorWriter::new(&mut output)
+            .map_err(message)
+            .and_then(|mut armored| {
+                let metadata = transform(options, key, &mut input, &mut armored)?;
+                armored.finish().map_err(message)?;
+                Ok(metadata)
+            })
+    } else {
+        transform(options, key, &mut input, &mut output)
+    }
+    .and_then(|metadata| output.flush().map(|()| metadata).map_err(message));
+    drop(output);
+
+    let result = result.and_then(|metadata| match &output_path {
+        Some(path) => restore(path, into_dir, &metadata.unwrap_or_default()),
+        None => Ok(()),
+    });
+    // a failed decryption must not leave partial plaintext behind
+    if let (Err(_), Some(path)) = (&result, &output_path) {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+// gives the decrypted file at `path` its recorded time and permissions,
+// and moves it to its recorded name when decrypting into a directory
+fn restore(path: &Path, into_dir: bool, metadata: &FileMetadata) -> Result<(), String> {
+    let target = match (&metadata.name, path.parent()) {
+        (Some(name), Some(dir)) if into_dir => Some(dir.join(name)),
+        _ if into_dir => {
+            return Err("the input records no file name to decrypt into a directory".to_string())
+        }
+        _ => None,
+    };
+    if let Some(target) = &target {
+        if target.exists() {
+            return Err(format!("{}: refusing to replace it", target.display()));
+        }
+    }
+    metadata
+        .apply(path)
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+    match target {
+        Some(target) => {
+            fs::rename(path, &target).map_err(|err| format!("{}: {}", target.display(), err))
+        }
+        None => Ok(()),
+    }
+}
+
+// encrypts or decrypts `input` into `output` with `key`, or seals or
+// opens it with the passphrase of `options`. Returns the metadata a
+// decrypted container recorded
+fn transform(
+    options: &Options,
+    key: Option<Rc5SecretKey>,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<Option<FileMetadata>, String> {
+    let key = match (key, &options.key) {
+        (Some(key), _) => key,
+        (None, KeySource::Passphrase(passphrase)) => {
+            let mut secret = passphrase.read()?;
+            let result = match options.command {
+                Command::Encrypt => Sealer::new().argon2(options.kdf).seal(
+                    OsRng,
+                    input,
+                    output,
+                    Secret::Passphrase(&secret),
+                ),
+                Command::Decrypt => open(input, output, Secret::Passphrase(&secret)),
+            };
+            wipe(&mut secret);
+            return result.map(|_| None).map_err(message);
+        }
+        (None, KeySource::File(..)) | (None, KeySource::Named(_)) => unreachable!(),
+    };
+    if options.command == Command::Encrypt && options.header {
+        return encrypt_headed(options, &key, input, output).map(|()| None);
+    }
+    // input starting with a header is decrypted the way it says
+    let mut prefix = Vec::new();
+    if options.command == Command::Decrypt {
+        (&mut *input)
+            .take(HEADER_MAGIC.len() as u64)
+            .read_to_end(&mut prefix)
+            .map_err(message)?;
+        if prefix == HEADER_MAGIC {
+            let policy = HeaderPolicy::new()
+                .allow_cipher(options.word as usize / 8, options.rounds)
+                .allow_mode(cipher_mode(options.mode));
+            return match decrypt_with_header((&prefix[..]).chain(input), output, &key, &policy) {
+                Ok(_) => Ok(None),
+                Err(err) if is_refused(&err) => Err(format!(
+                    "{}, allow them with `--word`, `--rounds` and `--mode`",
+                    err
+                )),
+                Err(err) => Err(message(err)),
+            };
+        }
+    }
+    let input = &mut (&prefix[..]).chain(input);
+    match options.word {
+        16 => process::<u16>(options, key, input, output),
+        32 => process::<u32>(options, key, input, output),
+        _ => process::<u64>(options, key, input, output),
+    }
+}
+
+// writes an `Rc5Header` with a fresh IV or the one of `options`, and the
+// ciphertext after it
+fn encrypt_headed(
+    options: &Options,
+    key: &Rc5SecretKey,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), String> {
+    let words = options.word as usize / 8;
+    let mode = cipher_mode(options.mode);
+    let iv = match (mode, &options.iv) {
+        (CipherMode::Ecb, _) => Vec::new(),
+        (_, Some(iv)) => iv.clone(),
+        (_, None) => {
+            let mut iv = vec![0u8; 2 * words];
+            OsRng.try_fill_bytes(&mut iv).map_err(message)?;
+            iv
+        }
+    };
+    let header = Rc5Header {
+        params: Rc5Params {
+            words,
+            rounds: options.rounds,
+            bytes: key.len(),
+        },
+        mode,
+        kdf: Kdf::None,
+        iv,
+        key_id: None,
+        compression: Compression::None,
+        padding: options.padding,
+    };
+    encrypt_with_header(&header, key, input, output)
+        .map(drop)
+        .map_err(message)
+}
+
+fn cipher_mode(mode: CliMode) -> CipherMode {
+    match mode {
+        CliMode::Eax => CipherMode::Eax,
+        CliMode::Ecb => CipherMode::Ecb,
+        CliMode::Cbc => CipherMode::Cbc,
+        CliMode::Ctr => CipherMode::Ctr,
+    }
+}
+
+// whether a header was refused by the policy
+fn is_refused(err: &io::Error) -> bool {
+    rc5_error(err) == Some(Rc5Error::ParametersNotAllowed)
+}
+
+// runs `options` on every selected file of `tree`, carrying on past
+// failures and reporting them at the end
+fn run_tree(options: &Options, tree: &Tree) -> Result<(), String> {
+    let out_dir = options.output.as_ref().ok_or("missing `--out`")?;
+    fs::create_dir_all(out_dir).map_err(|err| format!("{}: {}", out_dir.display(), err))?;
+    // the output may live inside the tree, and is not part of it
+    let skip = fs::canonicalize(out_dir).ok();
+
+    let mut files = Vec::new();
+    walk(&tree.root, Path::new(""), skip.as_deref(), &mut files)?;
+
+    let (mut processed, mut failed) = (0, 0);
+    for relative in files.iter().filter(|relative| tree.selects(relative)) {
+        processed += 1;
+        let result = tree_target(options.command, relative).and_then(|target| {
+            let output = out_dir.join(target);
+            if let Some(parent) = output.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|err| format!("{}: {}", parent.display(), err))?;
+            }
+            run(&Options {
+                input: Some(tree.root.join(relative)),
+                output: Some(output),
+                recursive: None,
+                ..options.clone()
+            })
+        });
+        if let Err(err) = result {
+            eprintln!("rc5: {}: {}", relative.display(), err);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{} of {} files failed", failed, processed));
+    }
+    Ok(())
+}
+
+// packs the selected files of `tree` into the archive `--out`, or
+// extracts the selected entries of the archive at the root of `tree` into
+// the directory `--out`, carrying on past files that fail
+fn run_archive(options: &Options, tree: &Tree) -> Result<(), String> {
+    let (failed, processed) = match options.command {
+        Command::Encrypt => pack(options, tree)?,
+        Command::Decrypt => unpack(options, tree)?,
+    };
+    if failed > 0 {
+        return Err(format!("{} of {} files failed", failed, processed));
+    }
+    Ok(())
+}
+
+// the key of `options`. A passphrase archive starts with an `Rc5Header`
+// that only records how its key was derived, which packing writes to
+// `output` and unpacking reads from `input`
+fn archive_key(
+    options: &Options,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<Rc5SecretKey, String> {
+    let passphrase = match &options.key {
+        KeySource::File(path, format) => return read_key(path, *format),
+        KeySource::Named(name) => return load_named_key(name),
+        KeySource::Passphrase(passphrase) => passphrase,
+    };
+    let kdf = match options.command {
+        Command::Encrypt => {
+            let mut salt = vec![0u8; SALT_LEN];
+            OsRng.try_fill_bytes(&mut salt).map_err(message)?;
+            let kdf = Kdf::Argon2id {
+                memory_kib: options.kdf.memory_kib,
+                iterations: options.kdf.iterations,
+                parallelism: options.kdf.parallelism,
+                salt,
+            };
+            // archives are RC5-32/12 eax with 16 byte keys
+            let mut iv = vec![0u8; 8];
+            OsRng.try_fill_bytes(&mut iv).map_err(message)?;
+            let header = Rc5Header {
+                params: Rc5Params {
+                    words: 4,
+                    rounds: 12,
+                    bytes: 16,
+                },
+                mode: CipherMode::Eax,
+                kdf: kdf.clone(),
+                iv,
+                key_id: None,
+                compression: Compression::None,
+                padding: Padding::Pkcs7,
+            };
+            header.write_to(output).map_err(message)?;
+            kdf
+        }
+        Command::Decrypt => {
+            let header = Rc5Header::read_from(input).map_err(message)?;
+            if header.kdf == Kdf::None {
+                return Err("the archive was not packed with a passphrase".to_string());
+            }
+            header.kdf
+        }
+    };
+
+    let mut secret = passphrase.read()?;
+    let key = kdf.derive_key(&secret);
+    wipe(&mut secret);
+    key.map_err(|err| format!("cannot derive the key: {}", err))
+}
+
+// returns the number of failed and of selected files. Only failures to
+// open a file leave the archive usable, anything else removes it
+fn pack(options: &Options, tree: &Tree) -> Result<(usize, usize), String> {
+    let path = options.output.as_ref().ok_or("missing `--out`")?;
+    let file = File::create(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    // created first, so the walk can leave it out when it is in the tree
+    let skip = fs::canonicalize(path).ok();
+
+    let result = pack_into(options, tree, skip.as_deref(), BufWriter::new(file));
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+fn pack_into(
+    options: &Options,
+    tree: &Tree,
+    skip: Option<&Path>,
+    mut output: BufWriter<File>,
+) -> Result<(usize, usize), String> {
+    let mut files = Vec::new();
+    walk(&tree.root, Path::new(""), skip, &mut files)?;
+
+    let key = archive_key(options, &mut io::empty(), &mut output)?;
+    let mut archive = ArchiveWriter::new(OsRng, output, &key).map_err(message)?;
+    let (mut processed, mut failed) = (0, 0);
+    for relative in files.iter().filter(|relative| tree.selects(relative)) {
+        processed += 1;
+        let source = tree.root.join(relative);
+        let opened = archive_path(relative).and_then(|name| {
+            let file =
+                File::open(&source).map_err(|err| format!("{}: {}", source.display(), err))?;
+            let metadata = FileMetadata::from_file(&source)
+                .map_err(|err| format!("{}: {}", source.display(), err))?;
+            Ok((name, file, metadata))
+        });
+        match opened {
+            Ok((name, file, metadata)) => {
+                archive
+                    .add(&name, &metadata, BufReader::with_capacity(BUFFER_LEN, file))
+                    .map_err(|err| format!("{}: {}", relative.display(), err))?;
+            }
+            Err(err) => {
+                eprintln!("rc5: {}: {}", relative.display(), err);
+                failed += 1;
+            }
+        }
+    }
+
+    let mut output = archive.finish().map_err(message)?;
+    output.flush().map_err(message)?;
+    Ok((failed, processed))
+}
+
+// `relative` with `/` between its components, as archives store it
+fn archive_path(relative: &Path) -> Result<String, String> {
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()
+        .map(|components| components.join("/"))
+        .ok_or_else(|| "archives only hold UTF-8 paths".to_string())
+}
+
+// returns the number of failed and of selected entries
+fn unpack(options: &Options, tree: &Tree) -> Result<(usize, usize), String> {
+    let out_dir = options.output.as_ref().ok_or("missing `--out`")?;
+    fs::create_dir_all(out_dir).map_err(|err| format!("{}: {}", out_dir.display(), err))?;
+    let path = &tree.root;
+    let mut input = BufReader::with_capacity(
+        BUFFER_LEN,
+        File::open(path).map_err(|err| format!("{}: {}", path.display(), err))?,
+    );
+
+    let key = archive_key(options, &mut input, &mut io::sink())?;
+    let mut archive =
+        ArchiveReader::new(input, &key).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let selected = (0..archive.entries().len())
+        .filter(|&entry| tree.selects(Path::new(&archive.entries()[entry].path)))
+        .collect::<Vec<_>>();
+
+    let mut failed = 0;
+    for &entry in &selected {
+        let name = archive.entries()[entry].path.clone();
+        if let Err(err) = extract(&mut archive, entry, &out_dir.join(&name)) {
+            eprintln!("rc5: {}: {}", name, err);
+            failed += 1;
+        }
+    }
+    Ok((failed, selected.len()))
+}
+
+// writes an entry of `archive` to `target` with its time and
+// permissions, removing it again if that fails
+fn extract<R: Read + Seek>(
+    archive: &mut ArchiveReader<R>,
+    entry: usize,
+    target: &Path,
+) -> Result<(), String> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|err| format!("{}: {}", parent.display(), err))?;
+    }
+    let file = File::create(target).map_err(|err| format!("{}: {}", target.display(), err))?;
+    let mut output = BufWriter::with_capacity(BUFFER_LEN, file);
+    let result = archive
+        .extract(entry, &mut output)
+        .and_then(|_| output.flush())
+        .map_err(message);
+    drop(output);
+
+    let result = result.and_then(|()| {
+        archive.entries()[entry]
+            .metadata
+            .apply(target)
+            .map_err(|err| format!("{}: {}", target.display(), err))
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(target);
+    }
+    result
+}
+
+// collects the regular files below `root.join(dir)` as paths relative to
+// `root`, in a stable order
+fn walk(
+    root: &Path,
+    dir: &Path,
+    skip: Option<&Path>,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), String> {
+    let path = root.join(dir);
+    let mut entries = fs::read_dir(&path)
+        .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let relative = dir.join(entry.file_name());
+        let file_type = entry
+            .file_type()
+            .map_err(|err| format!("{}: {}", entry.path().display(), err))?;
+        if skip.is_some() && fs::canonicalize(entry.path()).ok().as_deref() == skip {
+            continue;
+        }
+        if file_type.is_dir() {
+            walk(root, &relative, skip, files)?;
+        } else if file_type.is_file() {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+impl Tree {
+    fn selects(&self, relative: &Path) -> bool {
+        let path = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let name = path.rsplit('/').next().unwrap_or("");
+        let matches = |pattern: &String| {
+            let text = if pattern.contains('/') {
+                &path[..]
+            } else {
+                name
+            };
+            glob(pattern.as_bytes(), text.as_bytes())
+        };
+
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+// where the result for the file at `relative` goes in the output tree
+fn tree_target(command: Command, relative: &Path) -> Result<PathBuf, String> {
+    let mut name = relative.file_name().unwrap_or_default().to_os_string();
+    match command {
+        Command::Encrypt => name.push(TREE_SUFFIX),
+        Command::Decrypt => {
+            let stripped = name
+                .to_str()
+                .and_then(|name| name.strip_suffix(TREE_SUFFIX))
+                .filter(|stripped| !stripped.is_empty())
+                .ok_or_else(|| format!("the name doesn't end in `{}`", TREE_SUFFIX))?;
+            name = OsString::from(stripped);
+        }
+    }
+    Ok(relative.with_file_name(name))
+}
+
+// `*` and `?` match within one path component, `**` across them
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            // `**/` may also stand for no directory at all
+            if let [b'/', after @ ..] = rest {
+                if glob(after, text) {
+                    return true;
+                }
+            }
+            (0..=text.len()).any(|i| glob(rest, &text[i..]))
+        }
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| glob(rest, &text[i..])),
+        [b'?', rest @ ..] => matches!(text, [c, tail @ ..] if *c != b'/' && glob(rest, tail)),
+        [p, rest @ ..] => matches!(text, [c, tail @ ..] if c == p && glob(rest, tail)),
+    }
+}
+
+// passes reads through, reporting the running total like the
+// `*_with_progress` functions of the library
+struct Counted<R, F> {
+    inner: R,
+    done: u64,
+    progress: F,
+}
+
+impl<R: Read, F: FnMut(u64)> Counted<R, F> {
+    fn new(inner: R, progress: F) -> Self {
+        Counted {
+            inner,
+            done: 0,
+            progress,
+        }
+    }
+}
+
+impl<R: Read, F: FnMut(u64)> Read for Counted<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.done += read as u64;
+        (self.progress)(self.done);
+        Ok(read)
+    }
+}
+
+// an indicatif-style bar on standard error, finished with a newline when
+// it is dropped
+struct ProgressBar {
+    total: Option<u64>,
+    started: Instant,
+    drawn: Option<Instant>,
+    done: u64,
+}
+
+impl ProgressBar {
+    fn new(total: Option<u64>) -> Self {
+        ProgressBar {
+            total,
+            started: Instant::now(),
+            drawn: None,
+            done: 0,
+        }
+    }
+
+    fn update(&mut self, done: u64) {
+        self.done = done;
+        let elapsed = self.started.elapsed();
+        let due = match self.drawn {
+            Some(drawn) => drawn.elapsed() >= PROGRESS_INTERVAL,
+            None => elapsed >= PROGRESS_DELAY,
+        };
+        if due {
+            self.draw(elapsed);
+        }
+    }
+
+    fn draw(&mut self, elapsed: Duration) {
+        let mut stderr = io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\r{}\x1b[K",
+            render_progress(self.done, self.total, elapsed)
+        );
+        let _ = stderr.flush();
+        self.drawn = Some(Instant::now());
+    }
+}
+
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        if self.drawn.is_some() {
+            self.draw(self.started.elapsed());
+            eprintln!();
+        }
+    }
+}
+
+fn render_progress(done: u64, total: Option<u64>, elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs_f64();
+    let rate = if seconds > 0.0 {
+        done as f64 / seconds
+    } else {
+        0.0
+    };
+    let megabytes = |bytes: u64| bytes as f64 / 1e6;
+
+    match total {
+        Some(total) if total > 0 => {
+            let fraction = (done as f64 / total as f64).min(1.0);
+            let filled = (fraction * PROGRESS_WIDTH as f64) as usize;
+            let eta = if rate > 0.0 {
+                let left = total.saturating_sub(done) as f64 / rate;
+                let left = left.round() as u64;
+                format!("{}:{:02}", left / 60, left % 60)
+            } else {
+                "-:--".to_string()
+            };
+            format!(
+                "[{}{}] {:3.0}%  {:.1}/{:.1} MB  {:.1} MB/s  ETA {}",
+                "#".repeat(filled),
+                "-".repeat(PROGRESS_WIDTH - filled),
+                fraction * 100.0,
+                megabytes(done),
+                megabytes(total),
+                megabytes(rate as u64),
+                eta
+            )
+        }
+        _ => format!(
+            "{:.1} MB  {:.1} MB/s",
+            megabytes(done),
+            megabytes(rate as u64)
+        ),
+    }
+}
+
+fn process<T>(
+    options: &Options,
+    key: Rc5SecretKey,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<Option<FileMetadata>, String>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let bytes = key.len();
+    let cipher = RC5::<T>::from_secret_key(key, std::mem::size_of::<T>(), options.rounds, bytes);
+
+    match options.command {
+        Command::Encrypt => encrypt(options, &cipher, input, output).map(|()| None),
+        Command::Decrypt => decrypt(options, &cipher, input, output),
+    }
+}
+
+fn encrypt<T>(
+    options: &Options,
+    cipher: &RC5<T>,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), String>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let mode = match options.mode {
+        CliMode::Eax => {
+            let nonce = generate_nonce::<T>().map_err(message)?;
+            let mut writer = match &options.input {
+                Some(path) if options.metadata => {
+                    let metadata = FileMetadata::from_file(path)
+                        .map_err(|err| format!("{}: {}", path.display(), err))?;
+                    ContainerWriter::with_metadata(
+                        output,
+                        cipher,
+                        DEFAULT_CHUNK_SIZE,
+                        nonce.as_ref(),
+                        &metadata,
+                    )
+                }
+                _ => ContainerWriter::new(output, cipher, DEFAULT_CHUNK_SIZE, nonce.as_ref()),
+            }
+            .map_err(message)?;
+            io::copy(input, &mut writer).map_err(message)?;
+            writer.finish().map_err(message)?;
+            return Ok(());
+        }
+        CliMode::Ecb => Mode::Ecb,
+        CliMode::Cbc | CliMode::Ctr => {
+            let iv = match &options.iv {
+                Some(iv) => iv.clone(),
+                None => {
+                    let iv = generate_iv::<T>().map_err(message)?.to_vec();
+                    output.write_all(&iv).map_err(message)?;
+                    iv
+                }
+            };
+            chained(options.mode, iv)
+        }
+    };
+
+    let mut encryptor = StreamEncryptor::new(cipher, mode)
+        .map_err(message)?
+        .with_padding(options.padding);
+    let mut buffer = vec![0u8; BUFFER_LEN];
+    loop {
+        let read = input.read(&mut buffer).map_err(message)?;
+        if read == 0 {
+            break;
+        }
+        output
+            .write_all(&encryptor.update(&buffer[..read]))
+            .map_err(message)?;
+    }
+    output.write_all(&encryptor.finalize()).map_err(message)
+}
+
+fn decrypt<T>(
+    options: &Options,
+    cipher: &RC5<T>,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<Option<FileMetadata>, String>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let mode = match options.mode {
+        CliMode::Eax => {
+            let mut reader = ContainerReader::new(input, cipher).map_err(message)?;
+            let metadata = reader.metadata().map_err(message)?.cloned();
+            io::copy(&mut reader, output).map_err(message)?;
+            return Ok(metadata);
+        }
+        CliMode::Ecb => Mode::Ecb,
+        CliMode::Cbc | CliMode::Ctr => {
+            let iv = match &options.iv {
+                Some(iv) => iv.clone(),
+                None => {
+                    let mut iv = vec![0u8; 2 * std::mem::size_of::<T>()];
+                    input
+                        .read_exact(&mut iv)
+                        .map_err(|_| "input is too short to hold an IV".to_string())?;
+                    iv
+                }
+            };
+            chained(options.mode, iv)
+        }
+    };
+
+    let mut decryptor = StreamDecryptor::new(cipher, mode)
+        .map_err(message)?
+        .with_padding(options.padding);
+    let mut buffer = vec![0u8; BUFFER_LEN];
+    loop {
+        let read = input.read(&mut buffer).map_err(message)?;
+        if read == 0 {
+            break;
+        }
+        output
+            .write_all(&decryptor.update(&buffer[..read]))
+            .map_err(message)?;
+    }
+    let plaintext = decryptor.finalize().map_err(message)?;
+    output.write_all(&plaintext).map_err(message)?;
+    Ok(None)
+}
+
+fn message<E: std::fmt::Display>(err: E) -> String {
+    err.to_string()
+}
+
+fn chained(mode: CliMode, iv: Vec<u8>) -> Mode {
+    if mode == CliMode::Cbc {
+        Mode::Cbc { iv }
+    } else {
+        Mode::Ctr { nonce: iv }
+    }
+}
+
+fn verified(result: Result<(), VerifyFailure>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            eprintln!("rc5: {}", failure);
+            failure.exit_code()
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let options = match parse(std::env::args_os().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("rc5: {}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let result = match &options {
+        Invocation::Verify(options) => return verified(verify(options)),
+        Invocation::VerifyTag(options) => return verified(detached(options, true)),
+        Invocation::Tag(options) => detached(options, false).map_err(|failure| failure.to_string()),
+        Invocation::Cipher(options) => match &options.recursive {
+            Some(tree) if options.archive => run_archive(options, tree),
+            Some(tree) => run_tree(options, tree),
+            None => run(options),
+        },
+        Invocation::Keygen(options) => keygen(options),
+        Invocation::Vectors(options) => vectors(options),
+        Invocation::Bench(options) => bench_table(options).map(|table| print!("{}", table)),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("rc5: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rc5_test::Rc5Decryptor;
+
+    fn args(line: &str) -> Vec<OsString> {
+        line.split_whitespace().map(OsString::from).collect()
+    }
+
+    fn cipher_options(line: &str) -> Options {
+        match parse(args(line)) {
+            Ok(Invocation::Cipher(options)) => options,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_flags() {
+        let options = cipher_options(
+            "decrypt --in a --out b --key-file k --word 64 --rounds 20 --mode ctr --iv 00ff",
+        );
+        assert_eq!(
+            options,
+            Options {
+                command: Command::Decrypt,
+                key: KeySource::File(PathBuf::from("k"), KeyFormat::Raw),
+                input: Some(PathBuf::from("a")),
+                output: Some(PathBuf::from("b")),
+                word: 64,
+                rounds: 20,
+                mode: CliMode::Ctr,
+                iv: Some(vec![0x00, 0xFF]),
+                padding: Padding::Pkcs7,
+                kdf: Argon2Params::default(),
+                recursive: None,
+                armor: false,
+                metadata: false,
+                archive: false,
+                header: false,
+            }
+        );
+        assert_eq!(
+            cipher_options("encrypt --in a --out b --passphrase env:PASS").key,
+            KeySource::Passphrase(Passphrase::Env(OsString::from("PASS")))
+        );
+        assert_eq!(
+            parse(args("keygen --out k --bytes 32 --format hex")),
+            Ok(Invocation::Keygen(KeygenOptions {
+                bytes: 32,
+                output: KeyOutput::File(PathBuf::from("k")),
+                format: KeyFormat::Hex,
+            }))
+        );
+
+        let options = cipher_options("encrypt --key-file k");
+        assert_eq!((options.input, options.output), (None, None));
+        let options = cipher_options("encrypt --key-file k --in - --out -");
+        assert_eq!((options.input, options.output), (None, None));
+
+        assert!(parse(args("encrypt --in a --out b")).is_err());
+        assert!(parse(args("encrypt --in a --out b --key-file k --rounds 256")).is_err());
+        assert!(parse(args("encrypt --in a --out b --key-file k --word 24")).is_err());
+        assert!(parse(args("encrypt --in a --out b --key-file k --iv 00")).is_err());
+        assert!(parse(args(
+            "encrypt --in a --out b --key-file k --passphrase pass:x"
+        ))
+        .is_err());
+        assert!(parse(args("encrypt --in a --out b --passphrase x")).is_err());
+        assert!(cipher_options("encrypt --key-file k --armor --out b").armor);
+        assert!(parse(args("decrypt --key-file k --armor")).is_err());
+        assert!(parse(args("sign --in a")).is_err());
+        assert!(parse(args("keygen --out k --bytes 0")).is_err());
+        assert!(parse(args("keygen --out k --bytes 256")).is_err());
+
+        assert_eq!(
+            cipher_options("encrypt --key-name backup").key,
+            KeySource::Named("backup".to_string())
+        );
+        assert!(parse(args("encrypt --key-name backup --key-file k")).is_err());
+        assert_eq!(
+            parse(args("keygen --store backup")),
+            Ok(Invocation::Keygen(KeygenOptions {
+                bytes: 16,
+                output: KeyOutput::Store("backup".to_string()),
+                format: KeyFormat::Raw,
+            }))
+        );
+        assert!(parse(args("keygen --store backup --out k")).is_err());
+        assert!(parse(args("keygen --store backup --format hex")).is_err());
+        assert!(parse(args("keygen --bytes 16")).is_err());
+    }
+
+    #[test]
+    fn round_trips_in_every_mode() {
+        let plaintext = (0..100_000u32).map(|x| x as u8).collect::<Vec<u8>>();
+
+        for mode in ["eax", "ecb", "cbc", "ctr"].iter() {
+            for word in ["16", "32", "64"].iter() {
+                let line = format!(
+                    "encrypt --in a --out b --key-file k --mode {} --word {}",
+                    mode, word
+                );
+                let mut options = cipher_options(&line);
+                let key = || Rc5SecretKey::new((0u8..16).collect());
+
+                let mut ciphertext = Vec::new();
+                match options.word {
+                    16 => process::<u16>(&options, key(), &mut &plaintext[..], &mut ciphertext),
+                    32 => process::<u32>(&options, key(), &mut &plaintext[..], &mut ciphertext),
+                    _ => process::<u64>(&options, key(), &mut &plaintext[..], &mut ciphertext),
+                }
+                .unwrap();
+                assert_ne!(ciphertext[..64], plaintext[..64]);
+
+                options.command = Command::Decrypt;
+                let mut decrypted = Vec::new();
+                match options.word {
+                    16 => process::<u16>(&options, key(), &mut &ciphertext[..], &mut decrypted),
+                    32 => process::<u32>(&options, key(), &mut &ciphertext[..], &mut decrypted),
+                    _ => process::<u64>(&options, key(), &mut &ciphertext[..], &mut decrypted),
+                }
+                .unwrap();
+                assert_eq!(decrypted, plaintext, "{} {}", mode, word);
+            }
+        }
+    }
+
+    #[test]
+    fn headers_configure_decryption() {
+        assert!(parse(args("decrypt --key-file k --header")).is_err());
+        assert!(parse(args("encrypt --key-file k --padding zero")).is_err());
+        assert!(parse(args("encrypt --key-file k --header --metadata --in a")).is_err());
+
+        let key = || Some(Rc5SecretKey::new((0u8..16).collect()));
+        let plaintext = (0..1000u32).map(|x| x as u8).collect::<Vec<u8>>();
+        let run = |line: &str, input: &[u8]| {
+            let mut output = Vec::new();
+            transform(&cipher_options(line), key(), &mut &input[..], &mut output).map(|_| output)
+        };
+        let decrypt = |flags: &str, ciphertext: &[u8]| {
+            run(&format!("decrypt --key-file k {}", flags), ciphertext)
+        };
+
+        for flags in [
+            "--word 64 --rounds 16 --mode cbc --padding iso7816",
+            "--mode ecb --padding x923",
+            "--word 64 --rounds 20 --mode ctr",
+            "--rounds 16",
+        ]
+        .iter()
+        {
+            let ciphertext = run(
+                &format!("encrypt --key-file k --header {}", flags),
+                &plaintext,
+            )
+            .unwrap();
+            assert_eq!(&ciphertext[..4], HEADER_MAGIC);
+            // the header is followed whatever the decrypt options say, but
+            // ecb has to be allowed
+            let allow = if flags.contains("ecb") {
+                "--mode ecb"
+            } else {
+                "--mode ctr"
+            };
+            assert_eq!(decrypt(allow, &ciphertext).unwrap(), plaintext, "{}", flags);
+        }
+
+        // weak parameters only when they are asked for
+        let weak = run(
+            "encrypt --key-file k --header --word 16 --rounds 8",
+            &plaintext,
+        )
+        .unwrap();
+        assert!(decrypt("", &weak).unwrap_err().contains("--rounds"));
+        assert_eq!(decrypt("--word 16 --rounds 8", &weak).unwrap(), plaintext);
+
+        // input without a header, even shorter than one, still decrypts
+        let line = "encrypt --key-file k --mode ctr --iv 0011223344556677";
+        let short = run(line, b"ab").unwrap();
+        assert_eq!(
+            decrypt("--mode ctr --iv 0011223344556677", &short).unwrap(),
+            b"ab"
+        );
+    }
+
+    #[test]
+    fn tampered_container_is_rejected() {
+        let options = cipher_options("encrypt --in a --out b --key-file k");
+        let key = || Rc5SecretKey::new(vec![7; 16]);
+
+        let mut ciphertext = Vec::new();
+        process::<u32>(
+            &options,
+            key(),
+            &mut &b"attack at dawn"[..],
+            &mut ciphertext,
+        )
+        .unwrap();
+        let last = ciphertext.len() - 1;
+        ciphertext[last] ^= 1;
+
+        let options = Options {
+            command: Command::Decrypt,
+            ..options
+        };
+        let mut decrypted = Vec::new();
+        assert!(process::<u32>(&options, key(), &mut &ciphertext[..], &mut decrypted).is_err());
+    }
+
+    // hands out a few bytes per read, like a pipe
+    struct Trickle<R>(R);
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(5);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn standard_streams_round_trip() {
+        let key = || Some(Rc5SecretKey::new((0u8..16).collect()));
+        for mode in ["eax", "ecb", "cbc", "ctr"].iter() {
+            // none of them a multiple of the block length but the first
+            for &len in [0usize, 1, 7, 9, 1001, BUFFER_LEN + 3].iter() {
+                let plaintext = (0..len).map(|x| (x * 31) as u8).collect::<Vec<u8>>();
+                let line = format!("encrypt --key-file k --in - --out - --mode {}", mode);
+                let mut options = cipher_options(&line);
+
+                let mut input = io::Cursor::new(plaintext.clone());
+                let mut ciphertext = Vec::new();
+                transform(&options, key(), &mut input, &mut ciphertext).unwrap();
+                assert_eq!(input.position(), len as u64);
+                if *mode != "eax" {
+                    // the IV, then the padded or unpadded blocks
+                    let body = if *mode == "ctr" { len } else { len / 8 * 8 + 8 };
+                    let iv = if *mode == "ecb" { 0 } else { 8 };
+                    assert_eq!(ciphertext.len(), iv + body, "{} {}", mode, len);
+                }
+
+                options.command = Command::Decrypt;
+                let mut decrypted = Vec::new();
+                let mut input = Trickle(io::Cursor::new(ciphertext.clone()));
+                transform(&options, key(), &mut input, &mut decrypted).unwrap();
+                assert_eq!(decrypted, plaintext, "{} {}", mode, len);
+
+                // cut short, padded input is refused
+                if *mode != "eax" && *mode != "ctr" {
+                    let cut = &ciphertext[..ciphertext.len() - 1];
+                    let mut input = io::Cursor::new(cut);
+                    assert!(transform(&options, key(), &mut input, &mut Vec::new()).is_err());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn passphrases_seal_the_input() {
+        let mut options = cipher_options("encrypt --in a --out b --passphrase pass:hunter2");
+        options.kdf = Argon2Params {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 2,
+        };
+        let plaintext = b"attack at dawn".to_vec();
+
+        // a sealed file, which the library opens too
+        let mut sealed = Vec::new();
+        transform(&options, None, &mut &plaintext[..], &mut sealed).unwrap();
+        let (header, _) = Rc5Header::from_bytes(&sealed).unwrap();
+        assert!(matches!(
+            header.kdf,
+            Kdf::Argon2id {
+                memory_kib: 64,
+                parallelism: 2,
+                ..
+            }
+        ));
+        let mut opened = Vec::new();
+        open(&sealed[..], &mut opened, Secret::Passphrase(b"hunter2")).unwrap();
+        assert_eq!(opened, plaintext);
+
+        options.command = Command::Decrypt;
+        let passphrase = |text: &str| Passphrase::Text(text.to_string());
+        let decrypt = |text: &str| {
+            let options = Options {
+                key: KeySource::Passphrase(passphrase(text)),
+                ..options.clone()
+            };
+            let mut decrypted = Vec::new();
+            transform(&options, None, &mut &sealed[..], &mut decrypted).map(|_| decrypted)
+        };
+        assert_eq!(decrypt("hunter2"), Ok(plaintext));
+        assert!(decrypt("hunter3").is_err());
+        assert!(decrypt("").is_err());
+
+        let verify = |text: &str, sealed: &[u8]| verify_sealed(&passphrase(text), &mut &sealed[..]);
+        assert_eq!(verify("hunter2", &sealed), Ok(()));
+        assert_eq!(verify("hunter3", &sealed), Err(VerifyFailure::WrongKey));
+        let mut modified = sealed.clone();
+        let last = modified.len() - 1;
+        modified[last] ^= 1;
+        assert!(matches!(
+            verify("hunter2", &modified),
+            Err(VerifyFailure::Tampered(_))
+        ));
+    }
+
+    #[test]
+    fn passphrases_only_seal() {
+        for line in [
+            "encrypt --passphrase pass:x --mode cbc",
+            "encrypt --passphrase pass:x --word 64",
+            "encrypt --passphrase pass:x --rounds 16",
+            "encrypt --passphrase pass:x --header",
+            "encrypt --passphrase pass:x --metadata --in a",
+            "decrypt --passphrase pass:x --mode ctr",
+        ]
+        .iter()
+        {
+            assert!(parse(args(line)).is_err(), "{}", line);
+        }
+
+        // a passphrase archive's header has to name a key derivation
+        let options = cipher_options("decrypt --passphrase pass:x --recursive a --out b --archive");
+        let mut header = Vec::new();
+        Rc5Header {
+            params: Rc5Params {
+                words: 4,
+                rounds: 12,
+                bytes: 16,
+            },
+            mode: CipherMode::Eax,
+            kdf: Kdf::None,
+            iv: vec![0; 8],
+            key_id: None,
+            compression: Compression::None,
+            padding: Padding::Pkcs7,
+        }
+        .write_to(&mut header)
+        .unwrap();
+        assert_eq!(
+            archive_key(&options, &mut &header[..], &mut io::sink()).err(),
+            Some("the archive was not packed with a passphrase".to_string())
+        );
+    }
+
+    #[test]
+    fn globs_match_paths() {
+        assert!(glob(b"*.txt", b"notes.txt"));
+        assert!(!glob(b"*.txt", b"notes.txt.bak"));
+        assert!(!glob(b"*.txt", b"a/notes.txt"));
+        assert!(glob(b"a/?/c", b"a/b/c"));
+        assert!(!glob(b"a/?/c", b"a//c"));
+        assert!(glob(b"**/c", b"c"));
+        assert!(glob(b"**/c", b"a/b/c"));
+        assert!(glob(b"a/**", b"a/b/c"));
+        assert!(!glob(b"a/**", b"ab/c"));
+
+        let tree = Tree {
+            root: PathBuf::new(),
+            include: vec!["*.txt".to_string()],
+            exclude: vec!["cache/**".to_string()],
+        };
+        assert!(tree.selects(Path::new("docs/a.txt")));
+        assert!(!tree.selects(Path::new("docs/a.log")));
+        assert!(!tree.selects(Path::new("cache/b.txt")));
+    }
+
+    #[test]
+    fn trees_round_trip() {
+        let base = std::env::temp_dir().join(format!("rc5-cli-tree-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let (plain, encrypted, decrypted) = (base.join("in"), base.join("enc"), base.join("dec"));
+        for (path, contents) in [
+            ("a.txt", "a"),
+            ("sub/b.txt", "bb"),
+            ("sub/c.log", "c"),
+            ("skip/d.txt", "d"),
+        ]
+        .iter()
+        {
+            let path = plain.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        fs::write(base.join("k"), [5u8; 16]).unwrap();
+        let options = cipher_options(&format!(
+            "encrypt --key-file {} --recursive {} --out {} --include *.txt --exclude skip/**",
+            base.join("k").display(),
+            plain.display(),
+            encrypted.display()
+        ));
+        run_tree(&options, options.recursive.as_ref().unwrap()).unwrap();
+        assert!(encrypted.join("sub/b.txt.rc5").is_file());
+        assert!(!encrypted.join("sub/c.log.rc5").exists());
+        assert!(!encrypted.join("skip").exists());
+
+        let options = cipher_options(&format!(
+            "decrypt --key-file {} --recursive {} --out {}",
+            base.join("k").display(),
+            encrypted.display(),
+            decrypted.display()
+        ));
+        run_tree(&options, options.recursive.as_ref().unwrap()).unwrap();
+        assert_eq!(fs::read(decrypted.join("a.txt")).unwrap(), b"a");
+        assert_eq!(fs::read(decrypted.join("sub/b.txt")).unwrap(), b"bb");
+
+        // a name without the suffix fails, and the other files still go through
+        fs::write(encrypted.join("stray"), b"x").unwrap();
+        assert!(run_tree(&options, options.recursive.as_ref().unwrap()).is_err());
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn archives_pack_trees_and_extract_selectively() {
+        assert!(parse(args("encrypt --key-file k --archive --out x")).is_err());
+        assert!(parse(args(
+            "encrypt --key-file k --recursive d --out x --archive --word 64"
+        ))
+        .is_err());
+        assert!(parse(args(
+            "encrypt --key-file k --recursive d --out x --archive --mode ctr"
+        ))
+        .is_err());
+
+        let base = std::env::temp_dir().join(format!("rc5-cli-archive-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let (plain, extracted) = (base.join("in"), base.join("out"));
+        for path in ["a.txt", "sub/b.txt", "sub/c.log"].iter() {
+            let path = plain.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, path.file_name().unwrap().to_str().unwrap()).unwrap();
+        }
+        // the archive is written into the tree it packs, and left out of it
+        let archive = plain.join("tree.rc5");
+        let run = |line: String| {
+            let mut options = cipher_options(&line);
+            options.kdf = Argon2Params {
+                memory_kib: 64,
+                iterations: 1,
+                parallelism: 1,
+            };
+            run_archive(&options, options.recursive.as_ref().unwrap())
+        };
+        run(format!(
+            "encrypt --passphrase pass:secret --recursive {} --out {} --archive",
+            plain.display(),
+            archive.display()
+        ))
+        .unwrap();
+        let packed = fs::read(&archive).unwrap();
+        assert!(!packed.windows(5).any(|window| window == b"a.txt"));
+
+        let decrypt = |passphrase: &str, include: &str| {
+            run(format!(
+                "decrypt --passphrase pass:{} --recursive {} --out {} --archive {}",
+                passphrase,
+                archive.display(),
+                extracted.display(),
+                include
+            ))
+        };
+        assert!(decrypt("wrong", "").is_err());
+        decrypt("secret", "--include sub/*.txt").unwrap();
+        assert_eq!(fs::read(extracted.join("sub/b.txt")).unwrap(), b"b.txt");
+        assert!(!extracted.join("a.txt").exists() && !extracted.join("sub/c.log").exists());
+        decrypt("secret", "").unwrap();
+        assert_eq!(fs::read(extracted.join("sub/c.log")).unwrap(), b"c.log");
+        assert!(!extracted.join("tree.rc5").exists());
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn metadata_names_the_decrypted_file() {
+        assert!(parse(args("encrypt --key-file k --metadata")).is_err());
+        assert!(parse(args("encrypt --key-file k --metadata --in a --mode cbc")).is_err());
+        assert!(parse(args("decrypt --key-file k --metadata --in a")).is_err());
+
+        let base = std::env::temp_dir().join(format!("rc5-cli-metadata-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let restored = base.join("restored");
+        fs::create_dir_all(&restored).unwrap();
+        fs::write(base.join("k"), [6u8; 16]).unwrap();
+        fs::write(base.join("plans.txt"), b"plans").unwrap();
+        let modified = std::time::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        FileMetadata {
+            modified: Some(modified),
+            ..FileMetadata::default()
+        }
+        .apply(&base.join("plans.txt"))
+        .unwrap();
+
+        let key = base.join("k").display().to_string();
+        let encrypt = |extra: &str, output: &str| {
+            run(&cipher_options(&format!(
+                "encrypt --key-file {} --in {} --out {} {}",
+                key,
+                base.join("plans.txt").display(),
+                base.join(output).display(),
+                extra
+            )))
+        };
+        let decrypt = |input: &str, output: &Path| {
+            run(&cipher_options(&format!(
+                "decrypt --key-file {} --in {} --out {}",
+                key,
+                base.join(input).display(),
+                output.display()
+            )))
+        };
+        encrypt("--metadata", "x.rc5").unwrap();
+        encrypt("", "y.rc5").unwrap();
+
+        decrypt("x.rc5", &restored).unwrap();
+        let file = restored.join("plans.txt");
+        assert_eq!(fs::read(&file).unwrap(), b"plans");
+        assert_eq!(fs::metadata(&file).unwrap().modified().unwrap(), modified);
+        // nothing is replaced, and nothing is left behind
+        assert!(decrypt("x.rc5", &restored).is_err());
+        assert!(decrypt("y.rc5", &restored).is_err());
+        assert_eq!(fs::read_dir(&restored).unwrap().count(), 1);
+
+        // decrypting into a named file ignores the recorded name
+        decrypt("x.rc5", &base.join("copy")).unwrap();
+        assert_eq!(fs::read(base.join("copy")).unwrap(), b"plans");
+        assert_eq!(
+            fs::metadata(base.join("copy")).unwrap().modified().unwrap(),
+            modified
+        );
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn progress_is_counted_and_rendered() {
+        let mut reports = Vec::new();
+        {
+            let mut input = Counted::new(&[0u8; 10][..], |done| reports.push(done));
+            let mut buffer = [0u8; 4];
+            while input.read(&mut buffer).unwrap() > 0 {}
+        }
+        assert_eq!(reports, [4, 8, 10, 10]);
+
+        let second = Duration::from_secs(1);
+        assert_eq!(
+            render_progress(2_500_000, Some(10_000_000), 3 * second),
+            format!(
+                "[{}{}]  25%  2.5/10.0 MB  0.8 MB/s  ETA 0:09",
+                "#".repeat(7),
+                "-".repeat(23)
+            )
+        );
+        assert_eq!(
+            render_progress(3_000_000, None, 2 * second),
+            "3.0 MB  1.5 MB/s"
+        );
+        assert!(render_progress(0, Some(1), Duration::from_secs(0)).ends_with("ETA -:--"));
+    }
+
+    #[test]
+    fn vectors_are_reproducible() {
+        let options = match parse(args(
+            "vectors --word 16 --rounds 16 --count 3 --key-bytes 8 --format text",
+        )) {
+            Ok(Invocation::Vectors(options)) => options,
+            other => panic!("{:?}", other),
+        };
+        let text = vector_text(&options).unwrap();
+        assert_eq!(text, vector_text(&options).unwrap());
+        assert_eq!(text.lines().count(), 3);
+
+        // the first key and block are the first two SplitMix64(0) outputs
+        let first = text.lines().next().unwrap().split(' ').collect::<Vec<_>>();
+        assert_eq!(
+            first[0],
+            encode_hex(&0xE220_A839_7B1D_CDAFu64.to_le_bytes())
+        );
+        assert_eq!(
+            first[1],
+            encode_hex(&0x6E78_9E6A_A1B9_65F4u64.to_le_bytes()[..4])
+        );
+        let cipher = RC5::<u16>::new(decode_hex(first[0]).unwrap(), 2, 16, 8);
+        assert_eq!(
+            cipher.decode(&decode_hex(first[2]).unwrap()),
+            Ok(decode_hex(first[1]).unwrap())
+        );
+
+        let json = vector_text(&VectorOptions {
+            format: VectorFormat::Json,
+            seed: 1,
+            ..options
+        })
+        .unwrap();
+        assert!(json.contains("\"wordBits\": 16,"));
+        assert!(json.contains("{ \"tcId\": 3, \"comment\": \"RC5-16/16/8\", \"key\": \""));
+        assert!(!json.contains(first[0]));
+
+        assert!(parse(args("vectors --count 0")).is_err());
+        assert!(parse(args("vectors --format xml")).is_err());
+    }
+
+    #[test]
+    fn bench_prints_a_row_per_word_size() {
+        let options = match parse(args(
+            "bench --rounds 8 --key-bytes 4 --mode ctr --seconds 0.01",
+        )) {
+            Ok(Invocation::Bench(options)) => options,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(options.mode, CliMode::Ctr);
+        let table = bench_table(&options).unwrap();
+        let rows = table.lines().collect::<Vec<_>>();
+        assert_eq!(rows.len(), 4);
+        assert!(rows[0].ends_with("ctr MB/s"));
+        for (row, word) in rows[1..].iter().zip(["16", "32", "64"].iter()) {
+            let columns = row.split_whitespace().collect::<Vec<_>>();
+            assert_eq!(columns[..3], [*word, "8", "4"]);
+            assert!(columns[3..].iter().all(|x| x.parse::<f64>().unwrap() > 0.0));
+        }
+
+        assert!(parse(args("bench --seconds 0")).is_err());
+        assert!(parse(args("bench --mode ofb")).is_err());
+    }
+
+    #[test]
+    fn verify_tells_tampering_from_a_wrong_key() {
+        let options = match parse(args("verify backup.rc5 --key-file k --word 16")) {
+            Ok(Invocation::Verify(options)) => options,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(options.input, Some(PathBuf::from("backup.rc5")));
+        assert!(parse(args("verify a --key-file k --out b")).is_err());
+        assert!(parse(args("verify a --key-file k --mode ctr")).is_err());
+
+        let key = || Rc5SecretKey::new(vec![3; 16]);
+        let encrypt = Options {
+            command: Command::Encrypt,
+            ..cipher_options("encrypt --key-file k --word 16")
+        };
+        let plaintext = vec![0x42; 3 * DEFAULT_CHUNK_SIZE as usize / 2];
+        let mut container = Vec::new();
+        process::<u16>(&encrypt, key(), &mut &plaintext[..], &mut container).unwrap();
+
+        let check = |container: &[u8], key: Rc5SecretKey| {
+            verify_container::<u16>(&options, key, &mut &container[..])
+        };
+        assert_eq!(check(&container, key()), Ok(()));
+        assert_eq!(
+            check(&container, Rc5SecretKey::new(vec![4; 16])),
+            Err(VerifyFailure::WrongKey)
+        );
+
+        // a modified first chunk no longer looks like a wrong key
+        for &position in [30, container.len() - 1].iter() {
+            let mut modified = container.clone();
+            modified[position] ^= 1;
+            assert!(matches!(
+                check(&modified, key()),
+                Err(VerifyFailure::Tampered(_))
+            ));
+        }
+        let truncated = &container[..container.len() - 20];
+        assert!(matches!(
+            check(truncated, key()),
+            Err(VerifyFailure::Tampered(_))
+        ));
+        assert!(matches!(
+            check(b"not a container", key()),
+            Err(VerifyFailure::Tampered(_))
+        ));
+    }
+
+    #[test]
+    fn detached_tags_are_written_and_checked() {
+        assert!(parse(args("tag --key-file k")).is_err());
+        assert!(parse(args("tag a --passphrase pass:x")).is_err());
+        assert!(parse(args("tag a --key-file k --tag")).is_err());
+        assert!(parse(args("verify --key-file k --tag")).is_err());
+        assert!(matches!(
+            parse(args("verify a --key-file k --tag --mode ctr")),
+            Ok(Invocation::VerifyTag(_))
+        ));
+
+        let dir = std::env::temp_dir().join(format!("rc5_cli_tag_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let key = dir.join("k");
+        let file = dir.join("backup.rc5");
+        fs::write(&key, [3; 16]).unwrap();
+        fs::write(&file, vec![0x42; 1000]).unwrap();
+
+        let line = format!("{} --key-file {} --word 16", file.display(), key.display());
+        let tag = match parse(args(&format!("tag {}", line))) {
+            Ok(Invocation::Tag(options)) => options,
+            other => panic!("{:?}", other),
+        };
+        let check = match parse(args(&format!("verify {} --tag", line))) {
+            Ok(Invocation::VerifyTag(options)) => options,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(detached(&tag, false), Ok(()));
+        assert!(dir.join("backup.rc5.tag").exists());
+        assert_eq!(detached(&check, true), Ok(()));
+
+        let other_rounds = Options {
+            rounds: 16,
+            ..check.clone()
+        };
+        assert!(matches!(
+            detached(&other_rounds, true),
+            Err(VerifyFailure::Tampered(_))
+        ));
+        fs::write(&file, vec![0x43; 1000]).unwrap();
+        assert!(matches!(
+            detached(&check, true),
+            Err(VerifyFailure::Tampered(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn key_formats_round_trip() {
+        let key = (0u8..=255).step_by(7).collect::<Vec<u8>>();
+        for format in [KeyFormat::Raw, KeyFormat::Hex, KeyFormat::Base64].iter() {
+            let contents = format.encode(&key);
+            assert_eq!(format.decode(&contents), Some(key.clone()), "{:?}", format);
+        }
+        assert_eq!(KeyFormat::Hex.decode(b"00fg\n"), None);
+    }
+
+    #[test]
+    fn keygen_writes_a_private_key_once() {
+        let path = std::env::temp_dir().join(format!("rc5-cli-keygen-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let options = KeygenOptions {
+            bytes: 20,
+            output: KeyOutput::File(path.clone()),
+            format: KeyFormat::Hex,
+        };
+
+        keygen(&options).unwrap();
+        let contents = fs::read(&path).unwrap();
+        assert_eq!(KeyFormat::Hex.decode(&contents).unwrap().len(), 20);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // an existing key is never replaced
+        assert!(keygen(&options).is_err());
+        assert_eq!(fs::read(&path).unwrap(), contents);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    #[test]
+    fn keygen_stores_a_named_key_once() {
+        let name = format!("cli-test-{}", std::process::id());
+        let options = KeygenOptions {
+            bytes: 24,
+            output: KeyOutput::Store(name.clone()),
+            format: KeyFormat::Raw,
+        };
+
+        keygen(&options).unwrap();
+        assert_eq!(load_named_key(&name).unwrap().len(), 24);
+        assert!(keygen(&options).is_err());
+
+        OsKeyStore::new(KEY_STORE_SERVICE).delete(&name).unwrap();
+        assert!(load_named_key(&name).is_err());
+    }
+}
@@ -0,0 +1,707 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+
+#[cfg(target_arch = "x86_64")]
+use crate::avx2;
+#[cfg(feature = "simd")]
+use crate::simd;
+#[cfg(feature = "tracing")]
+use crate::trace;
+use crate::traits::{Block, CipherMagicConstants, Rc5Decryptor, Rc5Encryptor, Unsigned16To64};
+use crate::{heapless, secure, unrolled, Rc5Error, Rc5SecretKey};
+
+pub struct RC5<T: Unsigned16To64> {
+    pub(crate) words: usize,
+    pub(crate) rounds: usize,
+    pub(crate) bytes: usize,
+    // expanded once on construction, or the reason why it couldn't be.
+    // Clones share it, so any number of threads can encrypt with one key
+    // expansion, and with one locked copy of the tables
+    schedule: Result<Arc<KeySchedule<T>>, Rc5Error>,
+}
+
+// cheap, the clone shares the schedule instead of copying it
+impl<T: Unsigned16To64> Clone for RC5<T> {
+    fn clone(&self) -> Self {
+        RC5 {
+            words: self.words,
+            rounds: self.rounds,
+            bytes: self.bytes,
+            schedule: self.schedule.clone(),
+        }
+    }
+}
+
+// shows the parameters, while the key only shows up through its length
+impl<T: Unsigned16To64> fmt::Debug for RC5<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("RC5");
+        debug
+            .field("words", &self.words)
+            .field("rounds", &self.rounds)
+            .field("key", &Redacted(self.bytes));
+        if let Err(err) = &self.schedule {
+            debug.field("error", err);
+        }
+        debug.finish()
+    }
+}
+
+// placeholder for secret bytes in `Debug` output
+pub(crate) struct Redacted(pub(crate) usize);
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[REDACTED; {} bytes]", self.0)
+    }
+}
+
+fn generate_block_cipher<T>(key: &[u8], words: usize, rounds: usize) -> Result<Vec<T>, Rc5Error>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    if key.is_empty() {
+        return Err(Rc5Error::EmptyKey);
+    }
+
+    let mut l = vec![T::zero(); key.len().div_ceil(words)];
+    heapless::load_key(key, &mut l);
+
+    let mut s_table = vec![T::zero(); heapless::table_len(rounds)];
+    heapless::mix_key(&mut l, &mut s_table);
+
+    // the key words are as secret as the key itself
+    secure::release(&mut l);
+    Ok(s_table)
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> RC5<T> {
+    // the key is expanded right away, so that encrypting many blocks
+    // doesn't pay for the key expansion on every block. The cipher never
+    // keeps the key: an owned `Vec` is wiped afterwards, while borrowed
+    // bytes, e.g. in a caller's locked buffer, are only read
+    pub fn new<'a, K>(key: K, words: usize, rounds: usize, bytes: usize) -> Self
+    where
+        K: Into<Cow<'a, [u8]>>,
+    {
+        match key.into() {
+            Cow::Owned(key) => Self::from_secret_key(Rc5SecretKey::new(key), words, rounds, bytes),
+            Cow::Borrowed(key) => Self::from_bytes(key, words, rounds, bytes),
+        }
+    }
+
+    pub fn from_secret_key(key: Rc5SecretKey, words: usize, rounds: usize, bytes: usize) -> Self {
+        Self::with_key(&key, words, rounds, bytes)
+    }
+
+    // `from_secret_key` for a key that is still needed afterwards
+    pub(crate) fn with_key(key: &Rc5SecretKey, words: usize, rounds: usize, bytes: usize) -> Self {
+        Self::from_bytes(key.expose(), words, rounds, bytes)
+    }
+
+    fn from_bytes(key: &[u8], words: usize, rounds: usize, bytes: usize) -> Self {
+        let schedule = KeySchedule::new(key, words, rounds, bytes).map(Arc::new);
+
+        RC5 {
+            words,
+            rounds,
+            bytes,
+            schedule,
+        }
+    }
+
+    // expands `key` in place of the current key, for services that rotate
+    // keys without rebuilding what holds the cipher. The parameters stay,
+    // so `key` has to be as long as the key before. The old schedule is
+    // wiped once no clone shares it any more, clones made earlier keep the
+    // old key. On an error nothing changes
+    pub fn rekey<'a, K>(&mut self, key: K) -> Result<(), Rc5Error>
+    where
+        K: Into<Cow<'a, [u8]>>,
+    {
+        let rekeyed = Self::new(key, self.words, self.rounds, self.bytes);
+        self.schedule = Ok(rekeyed.schedule?);
+        Ok(())
+    }
+
+    // `rekey` with a key that is kept in an `Rc5SecretKey`
+    pub fn rekey_from_secret_key(&mut self, key: &Rc5SecretKey) -> Result<(), Rc5Error> {
+        self.rekey(key.expose())
+    }
+
+    pub(crate) fn schedule(&self) -> Result<&KeySchedule<T>, Rc5Error> {
+        self.schedule.as_deref().map_err(|&err| err)
+    }
+
+    pub(crate) fn key_schedule(&self) -> Result<KeySchedule<T>, Rc5Error> {
+        self.schedule().cloned()
+    }
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> KeySchedule<T> {
+    pub(crate) fn new(
+        key: &[u8],
+        words: usize,
+        rounds: usize,
+        bytes: usize,
+    ) -> Result<Self, Rc5Error> {
+        // blocks are split into words of `T`, any other size can't be used
+        if words != std::mem::size_of::<T>() {
+            return Err(Rc5Error::InvalidWordSize);
+        }
+        if key.len() != bytes {
+            return Err(Rc5Error::InvalidKeyLength);
+        }
+        // the parameter encodings give rounds one byte, and a larger count
+        // would only ask for a huge table
+        if rounds > 255 {
+            return Err(Rc5Error::InvalidRounds);
+        }
+
+        #[cfg(feature = "tracing")]
+        let started = std::time::Instant::now();
+        let s_table = generate_block_cipher(key, words, rounds)?;
+
+        // the standard RC5-32/12 parameterization gets its own fast path
+        let unrolled = if words == 4 && rounds == unrolled::ROUNDS {
+            let mut table = Box::new([0u32; unrolled::TABLE_LEN]);
+            table
+                .iter_mut()
+                .zip(&s_table)
+                .for_each(|(x, &word)| *x = word_to_u32(word));
+            Some(table)
+        } else {
+            None
+        };
+
+        #[cfg(feature = "tracing")]
+        trace::emit(trace::TraceEvent::KeyExpansion {
+            word_bits: 8 * words,
+            rounds,
+            key_len: bytes,
+            elapsed: started.elapsed(),
+        });
+
+        Ok(KeySchedule {
+            s_table,
+            unrolled,
+            words,
+            rounds,
+        }
+        .lock())
+    }
+}
+
+// reinterprets a 32-bit word of an RC5-32 schedule as a plain u32
+fn word_to_u32<T: Unsigned16To64>(word: T) -> u32 {
+    let bytes = word.to_le_bytes();
+    let bytes = bytes.as_ref();
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+// expanded key table together with the parameters needed to apply it.
+// Both tables are secret, they live in locked memory with the
+// `secure-memory` feature and are wiped on drop
+pub(crate) struct KeySchedule<T: Unsigned16To64> {
+    s_table: Vec<T>,
+    pub(crate) unrolled: Option<Box<unrolled::Table>>,
+    words: usize,
+    rounds: usize,
+}
+
+impl<T: Unsigned16To64> KeySchedule<T> {
+    fn lock(self) -> Self {
+        secure::lock(&self.s_table);
+        if let Some(table) = &self.unrolled {
+            secure::lock(&table[..]);
+        }
+        self
+    }
+}
+
+impl<T: Unsigned16To64> Clone for KeySchedule<T> {
+    fn clone(&self) -> Self {
+        KeySchedule {
+            s_table: self.s_table.clone(),
+            unrolled: self.unrolled.clone(),
+            words: self.words,
+            rounds: self.rounds,
+        }
+        .lock()
+    }
+}
+
+// the tables are never printed, only their size
+impl<T: Unsigned16To64> fmt::Debug for KeySchedule<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeySchedule")
+            .field("words", &self.words)
+            .field("rounds", &self.rounds)
+            .field(
+                "s_table",
+                &Redacted(std::mem::size_of_val(&self.s_table[..])),
+            )
+            .finish()
+    }
+}
+
+impl<T: Unsigned16To64> Drop for KeySchedule<T> {
+    fn drop(&mut self) {
+        secure::release(&mut self.s_table);
+        if let Some(table) = &mut self.unrolled {
+            secure::release(&mut table[..]);
+        }
+    }
+}
+
+impl<T: Unsigned16To64 + Copy> KeySchedule<T> {
+    pub(crate) fn block_len(&self) -> usize {
+        2 * self.words
+    }
+
+    pub(crate) fn s_table(&self) -> &[T] {
+        &self.s_table
+    }
+
+    // encrypts every whole block of `data` in place, using the vectorized
+    // backends for RC5-32 when they are available
+    pub(crate) fn encrypt_blocks(&self, data: &mut [u8]) {
+        #[cfg(any(feature = "simd", target_arch = "x86_64"))]
+        let data = match self.u32_table() {
+            Some(s_table) if data.len() >= 8 * self.block_len() => {
+                let processed = encrypt_blocks_u32(s_table, self.rounds, data);
+                &mut data[processed..]
+            }
+            _ => data,
+        };
+
+        for block in data.chunks_exact_mut(self.block_len()) {
+            self.encrypt_block(block);
+        }
+    }
+
+    // decrypts every whole block of `data` in place, see `encrypt_blocks`
+    pub(crate) fn decrypt_blocks(&self, data: &mut [u8]) {
+        #[cfg(any(feature = "simd", target_arch = "x86_64"))]
+        let data = match self.u32_table() {
+            Some(s_table) if data.len() >= 8 * self.block_len() => {
+                let processed = decrypt_blocks_u32(s_table, self.rounds, data);
+                &mut data[processed..]
+            }
+            _ => data,
+        };
+
+        for block in data.chunks_exact_mut(self.block_len()) {
+            self.decrypt_block(block);
+        }
+    }
+
+    // the S table as plain 32-bit words, if this is an RC5-32 schedule
+    #[cfg(any(feature = "simd", target_arch = "x86_64"))]
+    pub(crate) fn u32_table(&self) -> Option<&[u32]> {
+        if self.words != 4 || std::mem::size_of::<T>() != 4 {
+            return None;
+        }
+
+        // SAFETY: the word traits are sealed and u32 is the only 4-byte
+        // type implementing them, so `T` is u32 here. The table is
+        // borrowed rather than copied, so no unlocked copy of it is ever
+        // made
+        let table = self.s_table.as_ptr() as *const u32;
+        Some(unsafe { std::slice::from_raw_parts(table, self.s_table.len()) })
+    }
+
+    // encrypts a single block of `2 * words` bytes in place
+    pub(crate) fn encrypt_block(&self, block: &mut [u8]) {
+        match &self.unrolled {
+            Some(s_table) => unrolled::encrypt_block(s_table, block),
+            None => heapless::encrypt_block(&self.s_table, self.rounds, block),
+        }
+    }
+
+    // decrypts a single block of `2 * words` bytes in place
+    pub(crate) fn decrypt_block(&self, block: &mut [u8]) {
+        match &self.unrolled {
+            Some(s_table) => unrolled::decrypt_block(s_table, block),
+            None => heapless::decrypt_block(&self.s_table, self.rounds, block),
+        }
+    }
+}
+
+// runs the fastest available RC5-32 multi-block backends over `data`,
+// returning the number of bytes they processed
+#[cfg(any(feature = "simd", target_arch = "x86_64"))]
+fn encrypt_blocks_u32(s_table: &[u32], rounds: usize, data: &mut [u8]) -> usize {
+    let mut processed = 0;
+    #[cfg(target_arch = "x86_64")]
+    {
+        processed += avx2::encrypt_blocks(s_table, rounds, data);
+    }
+    #[cfg(feature = "simd")]
+    {
+        processed += simd::encrypt_blocks(s_table, rounds, &mut data[processed..]);
+    }
+    processed
+}
+
+#[cfg(any(feature = "simd", target_arch = "x86_64"))]
+fn decrypt_blocks_u32(s_table: &[u32], rounds: usize, data: &mut [u8]) -> usize {
+    let mut processed = 0;
+    #[cfg(target_arch = "x86_64")]
+    {
+        processed += avx2::decrypt_blocks(s_table, rounds, data);
+    }
+    #[cfg(feature = "simd")]
+    {
+        processed += simd::decrypt_blocks(s_table, rounds, &mut data[processed..]);
+    }
+    processed
+}
+
+// the slice variants check the parameters before the length, so they
+// are kept instead of the provided ones
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> Rc5Encryptor<T> for RC5<T> {
+    // the schedule only exists for `words == size_of::<T>()`, so the
+    // block length always matches
+    fn encrypt_block_in_place(&self, block: &mut Block<T>) -> Result<(), Rc5Error> {
+        self.schedule()?.encrypt_block(block.as_mut());
+        Ok(())
+    }
+
+    fn encode_into(&self, input: &[u8], out: &mut [u8]) -> Result<usize, Rc5Error> {
+        let schedule = self.schedule()?;
+        let block = block_into(schedule.block_len(), input, out)?;
+
+        schedule.encrypt_block(block);
+        Ok(block.len())
+    }
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> Rc5Decryptor<T> for RC5<T> {
+    fn decrypt_block_in_place(&self, block: &mut Block<T>) -> Result<(), Rc5Error> {
+        self.schedule()?.decrypt_block(block.as_mut());
+        Ok(())
+    }
+
+    fn decode_into(&self, input: &[u8], out: &mut [u8]) -> Result<usize, Rc5Error> {
+        let schedule = self.schedule()?;
+        let block = block_into(schedule.block_len(), input, out)?;
+
+        schedule.decrypt_block(block);
+        Ok(block.len())
+    }
+}
+
+// copies the single input block to the front of `out` and returns that
+// part of `out`, to be processed in place
+fn block_into<'a>(
+    block_len: usize,
+    input: &[u8],
+    out: &'a mut [u8],
+) -> Result<&'a mut [u8], Rc5Error> {
+    if input.len() != block_len {
+        return Err(Rc5Error::InvalidBlockLength);
+    }
+    if out.len() < block_len {
+        return Err(Rc5Error::BufferTooSmall);
+    }
+
+    let block = &mut out[..block_len];
+    block.copy_from_slice(input);
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const WORDS: usize = 4; // 4-bytes long, or 32-bit long
+    const ROUNDS: usize = 12; // 1 round total
+    const BYTES: usize = 16; // Key generation of length 10-bytes
+
+    #[test]
+    fn encode_a() {
+        let key = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D,
+            0x0E, 0x0F,
+        ];
+
+        let rc_5 = RC5::<u32>::new(key.to_vec(), WORDS, ROUNDS, BYTES);
+
+        let pt = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let ct = [0x2D, 0xDC, 0x14, 0x9B, 0xCF, 0x08, 0x8B, 0x9E];
+        let res = rc_5.encode(&pt).unwrap();
+        assert_eq!(ct[..], res[..]);
+    }
+
+    #[test]
+    fn encode_b() {
+        let key = [
+            0x2B, 0xD6, 0x45, 0x9F, 0x82, 0xC5, 0xB3, 0x00, 0x95, 0x2C, 0x49, 0x10, 0x48, 0x81,
+            0xFF, 0x48,
+        ];
+
+        let rc_5 = RC5::<u32>::new(key.to_vec(), WORDS, ROUNDS, BYTES);
+
+        let pt = [0xEA, 0x02, 0x47, 0x14, 0xAD, 0x5C, 0x4D, 0x84];
+        let ct = [0x11, 0xE4, 0x3B, 0x86, 0xD2, 0x31, 0xEA, 0x64];
+        let res = rc_5.encode(&pt).unwrap();
+        assert_eq!(ct[..], res[..]);
+    }
+
+    #[test]
+    fn decode_a() {
+        let key = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D,
+            0x0E, 0x0F,
+        ];
+
+        let rc_5 = RC5::<u32>::new(key.to_vec(), WORDS, ROUNDS, BYTES);
+
+        let pt = [0x96, 0x95, 0x0D, 0xDA, 0x65, 0x4A, 0x3D, 0x62];
+        let ct = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let res = rc_5.decode(&ct).unwrap();
+        assert!(pt[..] == res[..]);
+    }
+
+    #[test]
+    fn decode_b() {
+        let key = [
+            0x2B, 0xD6, 0x45, 0x9F, 0x82, 0xC5, 0xB3, 0x00, 0x95, 0x2C, 0x49, 0x10, 0x48, 0x81,
+            0xFF, 0x48,
+        ];
+
+        let rc_5 = RC5::<u32>::new(key.to_vec(), WORDS, ROUNDS, BYTES);
+
+        let pt = [0x63, 0x8B, 0x3A, 0x5E, 0xF7, 0x2B, 0x66, 0x3F];
+        let ct = [0xEA, 0x02, 0x47, 0x14, 0xAD, 0x5C, 0x4D, 0x84];
+        let res = rc_5.decode(&ct).unwrap();
+        assert!(pt[..] == res[..]);
+    }
+
+    #[test]
+    fn fixed_size_blocks_match_slice_api() {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let rc_5 = RC5::<u32>::new(key, WORDS, ROUNDS, BYTES);
+
+        let pt: Block<u32> = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let ct = rc_5.encrypt_block(&pt).unwrap();
+        assert_eq!(ct, [0x2D, 0xDC, 0x14, 0x9B, 0xCF, 0x08, 0x8B, 0x9E]);
+        assert_eq!(rc_5.decrypt_block(&ct).unwrap(), pt);
+        let mut block = pt;
+        rc_5.encrypt_block_in_place(&mut block).unwrap();
+        assert_eq!(block, ct);
+        rc_5.decrypt_block_in_place(&mut block).unwrap();
+        assert_eq!(block, pt);
+
+        #[cfg(feature = "rc5-16")]
+        {
+            let rc_5 = RC5::<u16>::new(vec![0x00, 0x01, 0x02, 0x03], 2, 16, 4);
+            let ct: Block<u16> = rc_5.encrypt_block(&[0x00, 0x01, 0x02, 0x03]).unwrap();
+            assert_eq!(ct[..], rc_5.encode(&[0x00, 0x01, 0x02, 0x03]).unwrap()[..]);
+        }
+    }
+
+    #[test]
+    fn either_half_can_be_handed_out_alone() {
+        fn round_trip<C: crate::Rc5CipherStream<u32>>(cipher: &C, block: &[u8]) -> Vec<u8> {
+            cipher.decode(&cipher.encode(block).unwrap()).unwrap()
+        }
+
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let rc_5 = RC5::<u32>::new(key, WORDS, ROUNDS, BYTES);
+        let encryptor: &dyn Rc5Encryptor<u32> = &rc_5;
+        let decryptor: &dyn Rc5Decryptor<u32> = &rc_5;
+
+        let pt = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let ct = encryptor.encode(&pt).unwrap();
+        assert_eq!(decryptor.decode(&ct).unwrap(), pt);
+        assert_eq!(round_trip(&rc_5, &pt), pt);
+    }
+
+    #[test]
+    fn borrowed_keys_are_left_to_the_caller() {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let pt = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let owned = RC5::<u32>::new(key.clone(), WORDS, ROUNDS, BYTES);
+        let borrowed = RC5::<u32>::new(&key[..], WORDS, ROUNDS, BYTES);
+
+        assert_eq!(borrowed.encode(&pt), owned.encode(&pt));
+        assert_eq!(
+            RC5::<u32>::new(&key, WORDS, ROUNDS, BYTES).encode(&pt),
+            owned.encode(&pt)
+        );
+        assert_eq!(key, (0u8..16).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn round_counts_are_capped() {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        assert!(RC5::<u32>::new(&key, WORDS, 255, BYTES).schedule().is_ok());
+        assert_eq!(
+            RC5::<u32>::new(&key, WORDS, usize::MAX, BYTES).encode(&[0; 8]),
+            Err(Rc5Error::InvalidRounds)
+        );
+    }
+
+    #[test]
+    fn ciphers_are_shared_across_threads() {
+        fn shareable<C: Send + Sync + Clone>() {}
+        #[cfg(feature = "rc5-16")]
+        shareable::<RC5<u16>>();
+        shareable::<RC5<u32>>();
+        #[cfg(feature = "rc5-64")]
+        shareable::<RC5<u64>>();
+
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let rc_5 = RC5::<u32>::new(key, WORDS, ROUNDS, BYTES);
+        let clone = rc_5.clone();
+        assert!(std::ptr::eq(
+            rc_5.schedule().unwrap(),
+            clone.schedule().unwrap()
+        ));
+
+        let rc_5 = Arc::new(rc_5);
+        let expected = (0u8..8)
+            .map(|i| rc_5.encode(&[i; 8]).unwrap())
+            .collect::<Vec<_>>();
+        let threads = (0u8..8)
+            .map(|i| {
+                let (shared, cloned) = (Arc::clone(&rc_5), clone.clone());
+                std::thread::spawn(move || {
+                    let ct = shared.encode(&[i; 8]).unwrap();
+                    assert_eq!(cloned.decode(&ct).unwrap(), [i; 8]);
+                    ct
+                })
+            })
+            .collect::<Vec<_>>();
+        for (thread, expected) in threads.into_iter().zip(expected) {
+            assert_eq!(thread.join().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn rekeying_keeps_the_parameters() {
+        let pt = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let first = (0u8..16).collect::<Vec<u8>>();
+        let second = (16u8..32).collect::<Vec<u8>>();
+        let mut rc_5 = RC5::<u32>::new(first.clone(), WORDS, ROUNDS, BYTES);
+        let before = rc_5.clone();
+
+        rc_5.rekey(&second[..]).unwrap();
+        let expected = RC5::<u32>::new(second.clone(), WORDS, ROUNDS, BYTES);
+        assert_eq!(rc_5.encode(&pt), expected.encode(&pt));
+        assert_eq!(
+            before.encode(&pt),
+            RC5::<u32>::new(first.clone(), WORDS, ROUNDS, BYTES).encode(&pt)
+        );
+
+        // the key length is a parameter, a failed rekey changes nothing
+        assert_eq!(rc_5.rekey(vec![1; 8]), Err(Rc5Error::InvalidKeyLength));
+        assert_eq!(rc_5.encode(&pt), expected.encode(&pt));
+
+        rc_5.rekey_from_secret_key(&Rc5SecretKey::new(first))
+            .unwrap();
+        assert_eq!(rc_5.encode(&pt), before.encode(&pt));
+
+        // a cipher that never had a valid key gets one
+        let mut rc_5 = RC5::<u32>::new(Vec::new(), WORDS, ROUNDS, BYTES);
+        rc_5.rekey(second).unwrap();
+        assert_eq!(rc_5.encode(&pt), expected.encode(&pt));
+    }
+
+    #[test]
+    fn key_length_need_not_be_a_multiple_of_the_word_size() {
+        let key = [0x2B, 0xD6, 0x45, 0x9F, 0x82, 0xC5, 0xB3, 0x00, 0x95, 0x2C];
+
+        let rc_5 = RC5::<u32>::new(key.to_vec(), WORDS, ROUNDS, key.len());
+        let pt = [0xEA, 0x02, 0x47, 0x14, 0xAD, 0x5C, 0x4D, 0x84];
+        let ct = rc_5.encode(&pt).unwrap();
+        assert_eq!(rc_5.decode(&ct).unwrap()[..], pt[..]);
+    }
+
+    #[cfg(all(feature = "rc5-16", feature = "rc5-64"))]
+    #[test]
+    fn encode_round_trips_for_16_and_64_bit_words() {
+        let key = (0u8..16).collect::<Vec<u8>>();
+
+        let rc_5 = RC5::<u16>::new(key.clone(), 2, ROUNDS, BYTES);
+        let pt = [0x00, 0x11, 0x22, 0x33];
+        assert_eq!(rc_5.decode(&rc_5.encode(&pt).unwrap()).unwrap()[..], pt[..]);
+
+        let rc_5 = RC5::<u64>::new(key, 8, ROUNDS, BYTES);
+        let pt = (0u8..16).collect::<Vec<u8>>();
+        assert_eq!(rc_5.decode(&rc_5.encode(&pt).unwrap()).unwrap(), pt);
+    }
+
+    #[test]
+    fn encode_into_caller_buffer() {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let rc_5 = RC5::<u32>::new(key, WORDS, ROUNDS, BYTES);
+
+        let pt = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let mut out = [0u8; 12];
+        assert_eq!(rc_5.encode_into(&pt, &mut out).unwrap(), 8);
+        assert_eq!(out[..8], [0x2D, 0xDC, 0x14, 0x9B, 0xCF, 0x08, 0x8B, 0x9E]);
+        assert_eq!(out[8..], [0u8; 4]);
+
+        let mut decrypted = [0u8; 8];
+        assert_eq!(rc_5.decode_into(&out[..8], &mut decrypted).unwrap(), 8);
+        assert_eq!(decrypted, pt);
+
+        assert_eq!(
+            rc_5.encode_into(&pt, &mut [0u8; 7]),
+            Err(Rc5Error::BufferTooSmall)
+        );
+        assert_eq!(
+            rc_5.encode_into(&pt[..7], &mut out),
+            Err(Rc5Error::InvalidBlockLength)
+        );
+    }
+
+    #[test]
+    fn invalid_keys_are_reported_on_use() {
+        let rc_5 = RC5::<u32>::new(vec![1, 2, 3], WORDS, ROUNDS, BYTES);
+        assert_eq!(rc_5.encode(&[0u8; 8]), Err(Rc5Error::InvalidKeyLength));
+
+        let rc_5 = RC5::<u32>::new(vec![], WORDS, ROUNDS, 0);
+        assert_eq!(rc_5.encode(&[0u8; 8]), Err(Rc5Error::EmptyKey));
+    }
+
+    #[test]
+    fn debug_output_hides_the_key() {
+        let key = vec![0xABu8; 16];
+        let rc_5 = RC5::<u32>::new(key, WORDS, ROUNDS, BYTES);
+
+        let printed = format!("{:?}", rc_5);
+        assert_eq!(
+            printed,
+            "RC5 { words: 4, rounds: 12, key: [REDACTED; 16 bytes] }"
+        );
+
+        let printed = format!("{:?}", rc_5.key_schedule().unwrap());
+        assert_eq!(
+            printed,
+            "KeySchedule { words: 4, rounds: 12, s_table: [REDACTED; 104 bytes] }"
+        );
+    }
+
+    #[test]
+    fn wrong_size_blocks_are_rejected() {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let rc_5 = RC5::<u32>::new(key, WORDS, ROUNDS, BYTES);
+
+        for len in [0usize, 1, 4, 7, 9, 16].iter() {
+            let input = vec![0u8; *len];
+            assert_eq!(rc_5.encode(&input), Err(Rc5Error::InvalidBlockLength));
+            assert_eq!(rc_5.decode(&input), Err(Rc5Error::InvalidBlockLength));
+        }
+    }
+
+    #[test]
+    fn word_size_must_match_the_word_type() {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let rc_5 = RC5::<u32>::new(key, 8, ROUNDS, BYTES);
+        assert_eq!(rc_5.encode(&[0u8; 16]), Err(Rc5Error::InvalidWordSize));
+    }
+}
@@ -0,0 +1,404 @@
+use std::io::{self, Read, Write};
+
+use crate::Rc5Error;
+
+// Compression of plaintext before it is encrypted, as a stream of blocks
+//
+//   block: length u32 | data
+//
+// little-endian, each decoding to at most 64 KiB. With bit 31 of the
+// length set the data is stored as is, otherwise it is an LZ4 block
+// (the raw block format of the LZ4 reference, without frames). Blocks
+// that don't shrink are stored, so the stream grows by no more than 4
+// bytes per 64 KiB. The stream ends with the input, so it relies on the
+// encryption around it to notice truncation.
+//
+// Compressing before encrypting leaks how well the plaintext compresses
+// through the length of the ciphertext. When an attacker can mix their
+// own data into the plaintext next to a secret, that reveals the secret
+// bit by bit (CRIME, BREACH), so only compress data no attacker has a
+// hand in
+const BLOCK_LEN: usize = 1 << 16;
+const STORED: u32 = 1 << 31;
+// the most an LZ4 block of `BLOCK_LEN` bytes takes, which is more than
+// the block itself when nothing matches
+const MAX_ENCODED_LEN: usize = BLOCK_LEN + BLOCK_LEN / 255 + 16;
+
+const MIN_MATCH: usize = 4;
+// the format requires the last 5 bytes to be literals and the last match
+// to start 12 bytes before the end
+const LAST_LITERALS: usize = 5;
+const MATCH_LIMIT: usize = 12;
+const MAX_OFFSET: usize = u16::MAX as usize;
+const HASH_LOG: u32 = 12;
+
+// compresses everything written to it into `inner` a block at a time.
+// `finish` writes the last block
+pub struct CompressWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+    encoded: Vec<u8>,
+}
+
+impl<W: Write> CompressWriter<W> {
+    pub fn new(inner: W) -> Self {
+        CompressWriter {
+            inner,
+            buffer: Vec::with_capacity(BLOCK_LEN),
+            encoded: Vec::with_capacity(MAX_ENCODED_LEN),
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.buffer.is_empty() {
+            self.write_block()?;
+        }
+        Ok(self.inner)
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        self.encoded.clear();
+        compress_block(&self.buffer, &mut self.encoded);
+        if self.encoded.len() < self.buffer.len() {
+            self.inner
+                .write_all(&(self.encoded.len() as u32).to_le_bytes())?;
+            self.inner.write_all(&self.encoded)?;
+        } else {
+            self.inner
+                .write_all(&(self.buffer.len() as u32 | STORED).to_le_bytes())?;
+            self.inner.write_all(&self.buffer)?;
+        }
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for CompressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let taken = buf.len().min(BLOCK_LEN - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..taken]);
+        if self.buffer.len() == BLOCK_LEN {
+            self.write_block()?;
+        }
+        Ok(taken)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// decompresses what a `CompressWriter` wrote as it is read. Malformed
+// blocks give `InvalidData` errors and a stream that stops inside a block
+// `UnexpectedEof`
+pub struct DecompressReader<R: Read> {
+    inner: R,
+    encoded: Vec<u8>,
+    decoded: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> DecompressReader<R> {
+    pub fn new(inner: R) -> Self {
+        DecompressReader {
+            inner,
+            encoded: Vec::new(),
+            decoded: Vec::new(),
+            position: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // the next block, or `false` at the end of the stream
+    fn read_block(&mut self) -> io::Result<bool> {
+        let mut length = [0u8; 4];
+        let read = read_full(&mut self.inner, &mut length)?;
+        if read == 0 {
+            return Ok(false);
+        }
+        if read < length.len() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let length = u32::from_le_bytes(length);
+        let (stored, len) = (length & STORED != 0, (length & !STORED) as usize);
+        if len == 0 || len > if stored { BLOCK_LEN } else { MAX_ENCODED_LEN } {
+            return Err(invalid_data(Rc5Error::InvalidEncoding));
+        }
+
+        self.encoded.clear();
+        (&mut self.inner)
+            .take(len as u64)
+            .read_to_end(&mut self.encoded)?;
+        if self.encoded.len() != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.decoded.clear();
+        if stored {
+            self.decoded.extend_from_slice(&self.encoded);
+        } else {
+            decompress_block(&self.encoded, BLOCK_LEN, &mut self.decoded).map_err(invalid_data)?;
+        }
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for DecompressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.decoded.len() {
+            if !self.read_block()? {
+                return Ok(0);
+            }
+        }
+        let count = buf.len().min(self.decoded.len() - self.position);
+        buf[..count].copy_from_slice(&self.decoded[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+// like `read_exact`, but reports how much arrived before the end
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+// appends `input` as an LZ4 block to `output`, greedily taking the match
+// a hash of the next four bytes finds
+fn compress_block(input: &[u8], output: &mut Vec<u8>) {
+    let mut table = vec![usize::MAX; 1 << HASH_LOG];
+    let mut anchor = 0;
+    let mut position = 0;
+
+    while position + MATCH_LIMIT < input.len() {
+        let word = read_word(input, position);
+        let slot = &mut table[hash(word)];
+        let candidate = std::mem::replace(slot, position);
+        if candidate == usize::MAX
+            || position - candidate > MAX_OFFSET
+            || read_word(input, candidate) != word
+        {
+            position += 1;
+            continue;
+        }
+
+        let limit = input.len() - LAST_LITERALS - position;
+        let mut len = MIN_MATCH;
+        while len < limit && input[candidate + len] == input[position + len] {
+            len += 1;
+        }
+        write_sequence(
+            output,
+            &input[anchor..position],
+            Some((position - candidate, len)),
+        );
+        position += len;
+        anchor = position;
+    }
+    write_sequence(output, &input[anchor..], None);
+}
+
+// token | [literal length] | literals | [offset u16 | [match length]]
+fn write_sequence(output: &mut Vec<u8>, literals: &[u8], found: Option<(usize, usize)>) {
+    let match_len = found.map_or(0, |(_, len)| len - MIN_MATCH);
+    output.push((literals.len().min(15) << 4 | match_len.min(15)) as u8);
+    if literals.len() >= 15 {
+        write_length(output, literals.len() - 15);
+    }
+    output.extend_from_slice(literals);
+    if let Some((offset, _)) = found {
+        output.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_len >= 15 {
+            write_length(output, match_len - 15);
+        }
+    }
+}
+
+fn write_length(output: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        output.push(255);
+        len -= 255;
+    }
+    output.push(len as u8);
+}
+
+// appends the LZ4 block `input` decoded to `output`, refusing blocks that
+// decode to more than `max_len` bytes
+fn decompress_block(input: &[u8], max_len: usize, output: &mut Vec<u8>) -> Result<(), Rc5Error> {
+    let start = output.len();
+    let mut at = 0;
+    loop {
+        let token = *input.get(at).ok_or(Rc5Error::InvalidEncoding)?;
+        at += 1;
+
+        let literals = read_length(input, &mut at, (token >> 4) as usize)?;
+        let literals = input
+            .get(at..at + literals)
+            .filter(|literals| output.len() - start + literals.len() <= max_len)
+            .ok_or(Rc5Error::InvalidEncoding)?;
+        output.extend_from_slice(literals);
+        at += literals.len();
+        if at == input.len() {
+            return Ok(());
+        }
+
+        let offset = input.get(at..at + 2).ok_or(Rc5Error::InvalidEncoding)?;
+        let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+        at += 2;
+        let len = read_length(input, &mut at, (token & 15) as usize)? + MIN_MATCH;
+        if offset == 0 || offset > output.len() - start || output.len() - start + len > max_len {
+            return Err(Rc5Error::InvalidEncoding);
+        }
+        // byte by byte, since a match may overlap what it produces
+        let from = output.len() - offset;
+        for i in 0..len {
+            let byte = output[from + i];
+            output.push(byte);
+        }
+    }
+}
+
+fn read_length(input: &[u8], at: &mut usize, nibble: usize) -> Result<usize, Rc5Error> {
+    let mut len = nibble;
+    if nibble == 15 {
+        loop {
+            let byte = *input.get(*at).ok_or(Rc5Error::InvalidEncoding)?;
+            *at += 1;
+            len += byte as usize;
+            if byte != 255 {
+                break;
+            }
+        }
+    }
+    Ok(len)
+}
+
+fn read_word(input: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([input[at], input[at + 1], input[at + 2], input[at + 3]])
+}
+
+fn hash(word: u32) -> usize {
+    (word.wrapping_mul(2_654_435_761) >> (32 - HASH_LOG)) as usize
+}
+
+fn invalid_data(err: Rc5Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compressed(data: &[u8]) -> Vec<u8> {
+        let mut writer = CompressWriter::new(Vec::new());
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap()
+    }
+
+    fn decompressed(stream: &[u8]) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        DecompressReader::new(stream).read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    #[test]
+    fn blocks_round_trip() {
+        let text = b"the quick brown fox jumps over the lazy dog. ".repeat(2000);
+        let mut noise = Vec::new();
+        let mut state = 1u32;
+        for _ in 0..100_000 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            noise.push(state as u8);
+        }
+        let inputs = [
+            &b""[..],
+            b"a",
+            b"aaaaaaaaaaaaaaaaaaaa",
+            &text,
+            &noise,
+            &[0u8; 300_000],
+        ];
+        for input in inputs.iter() {
+            let mut block = Vec::new();
+            let chunk = &input[..input.len().min(BLOCK_LEN)];
+            compress_block(chunk, &mut block);
+            assert!(block.len() <= MAX_ENCODED_LEN);
+            let mut decoded = Vec::new();
+            decompress_block(&block, BLOCK_LEN, &mut decoded).unwrap();
+            assert_eq!(decoded, chunk);
+
+            let stream = compressed(input);
+            assert_eq!(decompressed(&stream).unwrap(), *input);
+        }
+        assert!(compressed(&text).len() < text.len() / 10);
+        // noise is stored, at 4 bytes a block
+        assert_eq!(compressed(&noise).len(), noise.len() + 8);
+    }
+
+    #[test]
+    fn reference_blocks_decode() {
+        // hand-assembled per the LZ4 block format: 4 literals, then a
+        // match 4 back of length 8 and the final 5 literals
+        let block = [
+            0x44, b'a', b'b', b'c', b'd', 0x04, 0x00, 0x50, b'v', b'w', b'x', b'y', b'z',
+        ];
+        let mut decoded = Vec::new();
+        decompress_block(&block, BLOCK_LEN, &mut decoded).unwrap();
+        assert_eq!(decoded, b"abcdabcdabcdvwxyz");
+    }
+
+    #[test]
+    fn malformed_streams_are_refused() {
+        let stream = compressed(&b"abcabcabcabcabcabcabcabc".repeat(100));
+        for len in 1..stream.len() {
+            assert!(decompressed(&stream[..len]).is_err(), "{}", len);
+        }
+
+        let malformed: [&[u8]; 5] = [
+            // an offset before the start
+            &[0x10, b'a', 0x02, 0x00],
+            // an offset of zero
+            &[0x10, b'a', 0x00, 0x00],
+            // literals past the end
+            &[0x50, b'a'],
+            // a length that never ends
+            &[0xF0, 255],
+            &[],
+        ];
+        for block in malformed.iter() {
+            let mut decoded = Vec::new();
+            assert_eq!(
+                decompress_block(block, BLOCK_LEN, &mut decoded),
+                Err(Rc5Error::InvalidEncoding)
+            );
+        }
+
+        // blocks may not decode to more than 64 KiB
+        let mut bomb = Vec::new();
+        write_sequence(&mut bomb, b"a", Some((1, BLOCK_LEN)));
+        write_sequence(&mut bomb, b"", None);
+        let mut stream = (bomb.len() as u32).to_le_bytes().to_vec();
+        stream.extend_from_slice(&bomb);
+        assert_eq!(
+            decompressed(&stream).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        // and empty or oversized blocks aren't written
+        for length in [0, STORED, STORED | (BLOCK_LEN as u32 + 1)].iter() {
+            assert!(decompressed(&length.to_le_bytes()).is_err());
+        }
+    }
+}
@@ -0,0 +1,549 @@
+use std::io::{self, Read, Write};
+
+use crate::eax::Eax;
+use crate::mode::increment_counter;
+#[cfg(feature = "tracing")]
+use crate::trace::{self, TraceEvent, TraceOperation};
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{FileMetadata, Rc5Error, KCV_LEN, RC5};
+
+// Chunked container format, all integers little-endian:
+//
+//   header: magic "RC5F" | version u8 | word bytes u8 | rounds u8 |
+//           key bytes u8 | chunk size u32 | key check value (3 bytes)
+//   chunk:  length u32 | flags u8 | nonce (one block) |
+//           ciphertext (length bytes) | tag (one block)
+//
+// every chunk is sealed with EAX under its own nonce, with the header,
+// the chunk index and the flags as associated data, so chunks can't be
+// reordered, dropped or moved between containers. Only the last chunk
+// carries the FINAL flag, which makes truncation detectable.
+//
+// The first chunk may instead carry the METADATA flag and hold an encoded
+// `FileMetadata` rather than data, of any length up to 64 KiB. Containers
+// without it are laid out as before.
+//
+// The key check value, `RC5::check_value` of the cipher, tells a reader
+// with the wrong key apart from one whose input was tampered with before
+// any chunk is opened. Version 1 headers end before it and are still read
+const MAGIC: &[u8; 4] = b"RC5F";
+const VERSION: u8 = 2;
+const V1_HEADER_LEN: usize = 12;
+const HEADER_LEN: usize = V1_HEADER_LEN + KCV_LEN;
+const FINAL: u8 = 1;
+const METADATA: u8 = 2;
+const MAX_METADATA_LEN: usize = 1 << 16;
+
+pub const DEFAULT_CHUNK_SIZE: u32 = 1 << 16;
+
+fn header<T>(cipher: &RC5<T>, chunk_size: u32) -> io::Result<[u8; HEADER_LEN]>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let mut header = [0u8; HEADER_LEN];
+    header[..4].copy_from_slice(MAGIC);
+    header[4] = VERSION;
+    header[5] = cipher.words as u8;
+    header[6] = cipher.rounds as u8;
+    header[7] = cipher.bytes as u8;
+    header[8..V1_HEADER_LEN].copy_from_slice(&chunk_size.to_le_bytes());
+    header[V1_HEADER_LEN..].copy_from_slice(&cipher.check_value().map_err(invalid_data)?);
+    Ok(header)
+}
+
+fn associated_data(header: &[u8], index: u64, flags: u8) -> Vec<u8> {
+    let mut data = Vec::with_capacity(header.len() + 9);
+    data.extend_from_slice(header);
+    data.extend_from_slice(&index.to_le_bytes());
+    data.push(flags);
+    data
+}
+
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+// writes a chunked container, buffering at most one chunk of plaintext.
+// `finish` must be called to emit the final chunk
+pub struct ContainerWriter<T: Unsigned16To64, W: Write> {
+    eax: Eax<T>,
+    writer: W,
+    header: [u8; HEADER_LEN],
+    chunk_size: usize,
+    nonce: Vec<u8>,
+    index: u64,
+    buffer: Vec<u8>,
+}
+
+impl<T, W> ContainerWriter<T, W>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    W: Write,
+{
+    // `nonce` is the nonce of the first chunk and must be one block long,
+    // the following chunks use it incremented by their index. A nonce must
+    // never be reused with the same key
+    pub fn new(mut writer: W, cipher: &RC5<T>, chunk_size: u32, nonce: &[u8]) -> io::Result<Self> {
+        let eax = Eax::new(cipher).map_err(invalid_data)?;
+
+        if chunk_size == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "chunk size must not be zero",
+            ));
+        }
+        if nonce.len() != eax.tag_len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid nonce length",
+            ));
+        }
+
+        let header = header(cipher, chunk_size)?;
+        writer.write_all(&header)?;
+
+        Ok(ContainerWriter {
+            eax,
+            writer,
+            header,
+            chunk_size: chunk_size as usize,
+            nonce: nonce.to_vec(),
+            index: 0,
+            buffer: Vec::new(),
+        })
+    }
+
+    // like `new`, and stores `metadata` encrypted ahead of the data, for
+    // `ContainerReader::metadata` to restore
+    pub fn with_metadata(
+        writer: W,
+        cipher: &RC5<T>,
+        chunk_size: u32,
+        nonce: &[u8],
+        metadata: &FileMetadata,
+    ) -> io::Result<Self> {
+        let mut encoded = metadata
+            .to_bytes()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        if encoded.len() > MAX_METADATA_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "metadata is too long",
+            ));
+        }
+        let mut container = Self::new(writer, cipher, chunk_size, nonce)?;
+        container.write_chunk(&mut encoded, METADATA)?;
+        Ok(container)
+    }
+
+    // seals the buffered plaintext as the final chunk and returns the
+    // underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut chunk = std::mem::take(&mut self.buffer);
+        self.write_chunk(&mut chunk, FINAL)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_chunk(&mut self, chunk: &mut [u8], flags: u8) -> io::Result<()> {
+        let data = associated_data(&self.header, self.index, flags);
+        let tag = self
+            .eax
+            .encrypt_in_place_detached(&self.nonce, &data, chunk);
+
+        self.writer.write_all(&(chunk.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&[flags])?;
+        self.writer.write_all(&self.nonce)?;
+        self.writer.write_all(chunk)?;
+        self.writer.write_all(&tag)?;
+
+        #[cfg(feature = "tracing")]
+        trace::emit(TraceEvent::Chunk {
+            operation: TraceOperation::Encrypt,
+            index: self.index,
+            len: chunk.len(),
+            last: flags & FINAL != 0,
+        });
+        increment_counter(&mut self.nonce);
+        self.index += 1;
+        Ok(())
+    }
+}
+
+impl<T, W> Write for ContainerWriter<T, W>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+
+        // a full chunk is only sealed once more data follows it, since the
+        // last chunk has to be flagged as final
+        while self.buffer.len() > self.chunk_size {
+            let rest = self.buffer.split_off(self.chunk_size);
+            let mut chunk = std::mem::replace(&mut self.buffer, rest);
+            self.write_chunk(&mut chunk, 0)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// reads and verifies a chunked container one chunk at a time
+pub struct ContainerReader<T: Unsigned16To64, R: Read> {
+    eax: Eax<T>,
+    reader: R,
+    // as read, so a version 1 header is `V1_HEADER_LEN` bytes long
+    header: Vec<u8>,
+    chunk_size: usize,
+    index: u64,
+    done: bool,
+    metadata: Option<FileMetadata>,
+    // a chunk `metadata` had to read ahead of `next_chunk`
+    peeked: Option<Vec<u8>>,
+    pending: Vec<u8>,
+    position: usize,
+}
+
+impl<T, R> ContainerReader<T, R>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    R: Read,
+{
+    // a version 2 container whose key check value isn't that of `cipher`
+    // is refused with an `InvalidData` error wrapping `Rc5Error::WrongKey`,
+    // before any chunk is read
+    pub fn new(mut reader: R, cipher: &RC5<T>) -> io::Result<Self> {
+        let eax = Eax::new(cipher).map_err(invalid_data)?;
+
+        let mut header = vec![0u8; V1_HEADER_LEN];
+        reader.read_exact(&mut header)?;
+
+        if &header[..4] != MAGIC {
+            return Err(invalid_data("not an RC5 container"));
+        }
+        match header[4] {
+            1 => {}
+            VERSION => {
+                header.resize(HEADER_LEN, 0);
+                reader.read_exact(&mut header[V1_HEADER_LEN..])?;
+            }
+            _ => return Err(invalid_data("unsupported container version")),
+        }
+
+        let mut chunk_size = [0u8; 4];
+        chunk_size.copy_from_slice(&header[8..V1_HEADER_LEN]);
+        let chunk_size = u32::from_le_bytes(chunk_size);
+        let expected = self::header(cipher, chunk_size)?;
+        if header[5..V1_HEADER_LEN] != expected[5..V1_HEADER_LEN] {
+            return Err(invalid_data("container parameters do not match the cipher"));
+        }
+        if header.len() == HEADER_LEN && header[V1_HEADER_LEN..] != expected[V1_HEADER_LEN..] {
+            return Err(invalid_data(Rc5Error::WrongKey));
+        }
+
+        Ok(ContainerReader {
+            eax,
+            reader,
+            header,
+            chunk_size: chunk_size as usize,
+            index: 0,
+            done: false,
+            metadata: None,
+            peeked: None,
+            pending: Vec::new(),
+            position: 0,
+        })
+    }
+
+    pub fn chunk_size(&self) -> u32 {
+        self.chunk_size as u32
+    }
+
+    // the metadata the container was written with, if any. It comes
+    // before the data, so this may read and verify the first chunk early
+    pub fn metadata(&mut self) -> io::Result<Option<&FileMetadata>> {
+        if self.index == 0 {
+            self.peeked = self.next_chunk()?;
+        }
+        Ok(self.metadata.as_ref())
+    }
+
+    // returns the next verified chunk of plaintext, or `None` once the
+    // final chunk has been read
+    pub fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if let Some(chunk) = self.peeked.take() {
+            return Ok(Some(chunk));
+        }
+        if self.done {
+            return Ok(None);
+        }
+
+        let (chunk, flags) = self.read_chunk()?;
+        if flags & METADATA != 0 {
+            self.metadata = Some(FileMetadata::from_bytes(&chunk).map_err(invalid_data)?);
+            return self.next_chunk();
+        }
+        Ok(Some(chunk))
+    }
+
+    fn read_chunk(&mut self) -> io::Result<(Vec<u8>, u8)> {
+        let block_len = self.eax.tag_len();
+
+        let mut prefix = [0u8; 5];
+        self.reader.read_exact(&mut prefix).map_err(truncated)?;
+        let length = u32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize;
+        let flags = prefix[4];
+
+        let malformed = if flags == METADATA {
+            self.index != 0 || length > MAX_METADATA_LEN
+        } else {
+            length > self.chunk_size
+                || flags & !FINAL != 0
+                || (flags & FINAL == 0 && length != self.chunk_size)
+        };
+        if malformed {
+            return Err(invalid_data("malformed container chunk"));
+        }
+
+        let mut nonce = vec![0u8; block_len];
+        self.reader.read_exact(&mut nonce).map_err(truncated)?;
+
+        // the length comes from untrusted input, so the buffer only grows
+        // as data actually arrives instead of being allocated up front
+        let mut chunk = Vec::new();
+        (&mut self.reader)
+            .take(length as u64)
+            .read_to_end(&mut chunk)?;
+        if chunk.len() != length {
+            return Err(truncated(io::ErrorKind::UnexpectedEof.into()));
+        }
+        let mut tag = vec![0u8; block_len];
+        self.reader.read_exact(&mut tag).map_err(truncated)?;
+
+        let data = associated_data(&self.header, self.index, flags);
+        self.eax
+            .decrypt_in_place_detached(&nonce, &data, &mut chunk, &tag)
+            .map_err(invalid_data)?;
+        #[cfg(feature = "tracing")]
+        trace::emit(TraceEvent::Chunk {
+            operation: TraceOperation::Decrypt,
+            index: self.index,
+            len: chunk.len(),
+            last: flags & FINAL != 0,
+        });
+        self.index += 1;
+
+        if flags & FINAL != 0 {
+            self.done = true;
+            if self.reader.read(&mut [0u8; 1])? != 0 {
+                return Err(invalid_data("trailing data after the final chunk"));
+            }
+        }
+
+        Ok((chunk, flags))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+fn truncated(err: io::Error) -> io::Error {
+    if err.kind() == io::ErrorKind::UnexpectedEof {
+        io::Error::new(io::ErrorKind::UnexpectedEof, "container is truncated")
+    } else {
+        err
+    }
+}
+
+impl<T, R> Read for ContainerReader<T, R>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.pending.len() {
+            match self.next_chunk()? {
+                Some(chunk) => {
+                    self.pending = chunk;
+                    self.position = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+
+        let available = &self.pending[self.position..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher() -> RC5<u32> {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        RC5::<u32>::new(key, 4, 12, 16)
+    }
+
+    fn seal(plaintext: &[u8], chunk_size: u32) -> Vec<u8> {
+        let mut writer = ContainerWriter::new(Vec::new(), &cipher(), chunk_size, &[9; 8]).unwrap();
+        writer.write_all(plaintext).unwrap();
+        writer.finish().unwrap()
+    }
+
+    fn open(container: &[u8]) -> io::Result<Vec<u8>> {
+        let mut reader = ContainerReader::new(container, &cipher())?;
+        let mut plaintext = Vec::new();
+        reader.read_to_end(&mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn round_trip() {
+        for len in [0usize, 1, 31, 32, 33, 100].iter() {
+            let plaintext = (0..*len).map(|x| x as u8).collect::<Vec<u8>>();
+            let container = seal(&plaintext, 32);
+            assert_eq!(open(&container).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn chunks_are_verified_independently() {
+        let plaintext = vec![0x33; 100];
+        let container = seal(&plaintext, 32);
+
+        let mut reader = ContainerReader::new(&container[..], &cipher()).unwrap();
+        let sizes = std::iter::from_fn(|| reader.next_chunk().unwrap())
+            .map(|chunk| chunk.len())
+            .collect::<Vec<usize>>();
+        assert_eq!(sizes, vec![32, 32, 32, 4]);
+    }
+
+    #[test]
+    fn tampering_and_truncation_are_detected() {
+        let container = seal(&[0x33; 100], 32);
+
+        let mut flipped = container.clone();
+        flipped[HEADER_LEN + 20] ^= 1;
+        assert!(open(&flipped).is_err());
+
+        // drop the final chunk entirely
+        let chunk_len = 5 + 8 + 32 + 8;
+        assert!(open(&container[..HEADER_LEN + 3 * chunk_len]).is_err());
+
+        // swap the first two chunks
+        let mut swapped = container[..HEADER_LEN].to_vec();
+        swapped.extend_from_slice(&container[HEADER_LEN + chunk_len..HEADER_LEN + 2 * chunk_len]);
+        swapped.extend_from_slice(&container[HEADER_LEN..HEADER_LEN + chunk_len]);
+        swapped.extend_from_slice(&container[HEADER_LEN + 2 * chunk_len..]);
+        assert!(open(&swapped).is_err());
+
+        let mut trailing = container;
+        trailing.push(0);
+        assert!(open(&trailing).is_err());
+    }
+
+    #[test]
+    fn metadata_is_restored_and_bound_to_the_data() {
+        let metadata = FileMetadata {
+            name: Some("secret plans.txt".to_string()),
+            permissions: Some(0o600),
+            ..FileMetadata::default()
+        };
+        for len in [0usize, 5, 64].iter() {
+            let plaintext = vec![0x44; *len];
+            let mut writer =
+                ContainerWriter::with_metadata(Vec::new(), &cipher(), 32, &[9; 8], &metadata)
+                    .unwrap();
+            writer.write_all(&plaintext).unwrap();
+            let container = writer.finish().unwrap();
+            // the name is encrypted with everything else
+            assert!(!container.windows(6).any(|window| window == b"secret"));
+
+            let mut reader = ContainerReader::new(&container[..], &cipher()).unwrap();
+            assert_eq!(reader.metadata().unwrap(), Some(&metadata));
+            assert_eq!(reader.metadata().unwrap(), Some(&metadata));
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).unwrap();
+            assert_eq!(read, plaintext);
+            // readers that don't ask still get the data alone
+            assert_eq!(open(&container).unwrap(), plaintext);
+
+            // dropping the metadata chunk shifts every index after it
+            let metadata_len = 5 + 8 + metadata.to_bytes().unwrap().len() + 8;
+            let mut stripped = container[..HEADER_LEN].to_vec();
+            stripped.extend_from_slice(&container[HEADER_LEN + metadata_len..]);
+            assert!(open(&stripped).is_err());
+        }
+
+        let container = seal(b"hello", 32);
+        let mut reader = ContainerReader::new(&container[..], &cipher()).unwrap();
+        assert_eq!(reader.metadata().unwrap(), None);
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, b"hello");
+    }
+
+    #[test]
+    fn mismatched_parameters_are_rejected() {
+        let container = seal(b"hello", 32);
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let other = RC5::<u32>::new(key, 4, 16, 16);
+        let err = ContainerReader::new(&container[..], &other).err().unwrap();
+        assert!(err.get_ref().unwrap().downcast_ref::<Rc5Error>().is_none());
+
+        // the same parameters with another key fail on the check value
+        let other = RC5::<u32>::new(vec![7; 16], 4, 12, 16);
+        let err = ContainerReader::new(&container[..], &other).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.get_ref().unwrap().downcast_ref::<Rc5Error>(),
+            Some(&Rc5Error::WrongKey)
+        );
+    }
+
+    #[test]
+    fn version_1_containers_are_still_read() {
+        let cipher = cipher();
+        let mut container = header(&cipher, 32).unwrap()[..V1_HEADER_LEN].to_vec();
+        container[4] = 1;
+        let mut chunk = b"hello".to_vec();
+        let data = associated_data(&container, 0, FINAL);
+        let tag = Eax::new(&cipher)
+            .unwrap()
+            .encrypt_in_place_detached(&[9; 8], &data, &mut chunk);
+        container.extend_from_slice(&5u32.to_le_bytes());
+        container.push(FINAL);
+        container.extend_from_slice(&[9; 8]);
+        container.extend_from_slice(&chunk);
+        container.extend_from_slice(&tag);
+        assert_eq!(open(&container).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn oversized_chunk_lengths_do_not_allocate() {
+        // a forged header announcing 4 GiB chunks, followed by a final
+        // chunk claiming to be that long
+        let mut forged = header(&cipher(), u32::MAX).unwrap().to_vec();
+        forged.extend_from_slice(&u32::MAX.to_le_bytes());
+        forged.push(FINAL);
+        forged.extend_from_slice(&[0u8; 8 + 16]);
+
+        let mut reader = ContainerReader::new(&forged[..], &cipher()).unwrap();
+        let err = reader.next_chunk().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,48 @@
+use std::hint::black_box;
+
+// comparisons whose running time doesn't depend on secret data, for tags
+// and padding. Lengths are treated as public
+
+// `a == b`, looking at every byte even after the first difference
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let difference = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    black_box(difference) == 0
+}
+
+// 0xFF if `x < y`, else 0
+pub(crate) fn ct_lt(x: u8, y: u8) -> u8 {
+    ((x as u16).wrapping_sub(y as u16) >> 8) as u8
+}
+
+// 0xFF if `x == 0`, else 0
+pub(crate) fn ct_is_zero(x: u8) -> u8 {
+    ct_lt(x, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equality() {
+        assert!(ct_eq(b"", b""));
+        assert!(ct_eq(b"tag", b"tag"));
+        assert!(!ct_eq(b"tag", b"tah"));
+        assert!(!ct_eq(b"tag", b"ta"));
+    }
+
+    #[test]
+    fn masks() {
+        for x in 0..=255u8 {
+            for y in [0u8, 1, 8, 16, 255].iter() {
+                assert_eq!(ct_lt(x, *y) == 0xFF, x < *y);
+                assert!(ct_lt(x, *y) == 0 || ct_lt(x, *y) == 0xFF);
+            }
+            assert_eq!(ct_is_zero(x) == 0xFF, x == 0);
+        }
+    }
+}
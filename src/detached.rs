@@ -0,0 +1,194 @@
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::ct::ct_eq;
+#[cfg(feature = "tracing")]
+use crate::trace::{self, TraceEvent};
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{Cmac, MacKey, Rc5Error, RC5};
+
+// Detached tags authenticate a file that is kept or sent apart from them,
+// so a large payload can be checked out of band:
+//
+//   magic "RC5T" | version u8 | word bytes u8 | rounds u8 | key bytes u8 |
+//   tag
+//
+// where the tag is the one block CMAC, under the MAC key derived from the
+// cipher, over the bytes before it followed by the whole file. Tagging
+// the parameters too means a tag only verifies under the exact cipher it
+// was made with. `tag_path` names the tag `<file>.tag`, so the tag of
+// `file.rc5` is `file.rc5.tag`
+const MAGIC: &[u8; 4] = b"RC5T";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 8;
+
+pub const TAG_SUFFIX: &str = ".tag";
+
+// the tag file contents for everything `data` yields
+pub fn detached_tag<T, R>(cipher: &RC5<T>, data: R) -> io::Result<Vec<u8>>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    R: Read,
+{
+    let header = header(cipher)?;
+    let cmac = Cmac::new(&MacKey::derive(cipher).map_err(invalid_input)?).map_err(invalid_input)?;
+    let tag = cmac.tag_reader((&header[..]).chain(data))?;
+
+    let mut contents = header.to_vec();
+    contents.extend_from_slice(&tag);
+    Ok(contents)
+}
+
+// checks the tag file contents `tag` against everything `data` yields.
+// Anything that isn't a tag file gives `InvalidEncoding`, and a tag that
+// doesn't match `DecryptionFailed`, including one made with another key
+// or other parameters, both as `InvalidData` errors
+pub fn verify_detached<T, R>(cipher: &RC5<T>, data: R, tag: &[u8]) -> io::Result<()>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    R: Read,
+{
+    if tag.len() != HEADER_LEN + 2 * cipher.words || &tag[..4] != MAGIC || tag[4] != VERSION {
+        return Err(invalid_data(Rc5Error::InvalidEncoding));
+    }
+    let verified = ct_eq(&detached_tag(cipher, data)?, tag);
+    #[cfg(feature = "tracing")]
+    trace::emit(TraceEvent::Verification {
+        kind: "detached",
+        verified,
+    });
+    if !verified {
+        return Err(invalid_data(Rc5Error::DecryptionFailed));
+    }
+    Ok(())
+}
+
+// where the tag of `path` is kept
+pub fn tag_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(TAG_SUFFIX);
+    PathBuf::from(name)
+}
+
+// tags the file at `path` and writes the tag next to it, replacing an
+// older tag. Returns where the tag went
+pub fn write_tag_file<T>(cipher: &RC5<T>, path: &Path) -> io::Result<PathBuf>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let tag = detached_tag(cipher, BufReader::new(File::open(path)?))?;
+    let tag_path = tag_path(path);
+    fs::write(&tag_path, tag)?;
+    Ok(tag_path)
+}
+
+// checks the file at `path` against the tag next to it
+pub fn verify_tag_file<T>(cipher: &RC5<T>, path: &Path) -> io::Result<()>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let tag = fs::read(tag_path(path))?;
+    verify_detached(cipher, BufReader::new(File::open(path)?), &tag)
+}
+
+fn header<T: Unsigned16To64>(cipher: &RC5<T>) -> io::Result<[u8; HEADER_LEN]> {
+    let byte =
+        |value: usize| u8::try_from(value).map_err(|_| invalid_input(Rc5Error::InvalidEncoding));
+    let mut header = [0u8; HEADER_LEN];
+    header[..4].copy_from_slice(MAGIC);
+    header[4] = VERSION;
+    header[5] = byte(cipher.words)?;
+    header[6] = byte(cipher.rounds)?;
+    header[7] = byte(cipher.bytes)?;
+    Ok(header)
+}
+
+fn invalid_data(err: Rc5Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn invalid_input(err: Rc5Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher(rounds: usize) -> RC5<u32> {
+        RC5::new((0u8..16).collect::<Vec<u8>>(), 4, rounds, 16)
+    }
+
+    fn error(result: io::Result<()>) -> Option<Rc5Error> {
+        result
+            .unwrap_err()
+            .into_inner()
+            .and_then(|inner| inner.downcast::<Rc5Error>().ok())
+            .map(|err| *err)
+    }
+
+    #[test]
+    fn tags_verify_only_the_data_they_were_made_for() {
+        let rc5 = cipher(12);
+        let data = (0..1000).map(|x| x as u8).collect::<Vec<u8>>();
+        let tag = detached_tag(&rc5, &data[..]).unwrap();
+        assert_eq!(tag.len(), HEADER_LEN + 8);
+        assert!(verify_detached(&rc5, &data[..], &tag).is_ok());
+
+        let mut modified = data.clone();
+        modified[500] ^= 1;
+        assert_eq!(
+            error(verify_detached(&rc5, &modified[..], &tag)),
+            Some(Rc5Error::DecryptionFailed)
+        );
+        assert_eq!(
+            error(verify_detached(&rc5, &data[..999], &tag)),
+            Some(Rc5Error::DecryptionFailed)
+        );
+        // the parameters are part of what is tagged
+        assert_eq!(
+            error(verify_detached(&cipher(16), &data[..], &tag)),
+            Some(Rc5Error::DecryptionFailed)
+        );
+
+        let mut forged = tag.clone();
+        forged[6] = 16;
+        assert_eq!(
+            error(verify_detached(&rc5, &data[..], &forged)),
+            Some(Rc5Error::DecryptionFailed)
+        );
+        for malformed in [&tag[..tag.len() - 1], &tag[1..], b"RC5H"].iter() {
+            assert_eq!(
+                error(verify_detached(&rc5, &data[..], malformed)),
+                Some(Rc5Error::InvalidEncoding)
+            );
+        }
+    }
+
+    #[test]
+    fn tag_files_sit_next_to_the_file() {
+        let dir = std::env::temp_dir().join(format!("rc5_detached_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.rc5");
+        fs::write(&path, b"ciphertext").unwrap();
+
+        let cipher = cipher(12);
+        let written = write_tag_file(&cipher, &path).unwrap();
+        assert_eq!(written, dir.join("file.rc5.tag"));
+        assert!(verify_tag_file(&cipher, &path).is_ok());
+
+        fs::write(&path, b"ciphertexT").unwrap();
+        assert_eq!(
+            error(verify_tag_file(&cipher, &path)),
+            Some(Rc5Error::DecryptionFailed)
+        );
+        fs::remove_file(&written).unwrap();
+        assert_eq!(
+            verify_tag_file(&cipher, &path).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,334 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::traits::{CipherMagicConstants, Rc5Encryptor, Unsigned16To64};
+use crate::vectors::SplitMix64;
+use crate::{Rc5Error, Rc5Params, ReducedRc5};
+
+// Search for XOR differential characteristics of reduced-round RC5, the
+// starting point of differential attacks on it. A difference goes through
+// a half round A = ((A ^ B) <<< B) + S like this: the XOR keeps it, the
+// rotation moves it by the unknown amount B mod w, and adding the round
+// key keeps it with probability 1/2 for every difference bit below the
+// top one, whose carry falls off the word. The initial key addition is
+// counted the same way.
+//
+// Only the rotation cases where both texts of a pair rotate by the same
+// amount are followed, that is where the difference of the rotating word
+// is zero in its low lg w bits, and each of the w amounts then has
+// probability 1/w. These are the usual approximations: carries aren't
+// quite independent, and several characteristics can add up to one
+// differential, so `Characteristic::measure` checks an estimate against
+// the real reduced-round cipher
+const DEFAULT_BREADTH: usize = 256;
+
+// a bounded breadth-first search: from every input difference with at
+// most one bit set per word, each half round is expanded over all its
+// rotation cases, and only the `breadth` most probable partial
+// characteristics are kept for the next one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DifferentialSearch {
+    word_bits: usize,
+    rounds: usize,
+    breadth: usize,
+}
+
+// a path of differences through the rounds, with its estimated
+// probability. Differences are (ΔA, ΔB) in the low `word_bits` bits
+#[derive(Clone, Debug, PartialEq)]
+pub struct Characteristic {
+    pub word_bits: usize,
+    // after the key addition and after each round, one more entry than
+    // there are rounds. The first is also the plaintext difference and the
+    // last the ciphertext difference
+    pub differences: Vec<(u64, u64)>,
+    // -log2 of the estimated probability
+    pub weight: f64,
+}
+
+impl DifferentialSearch {
+    // the key length of `params` doesn't matter to the search
+    pub fn new(params: Rc5Params) -> Result<Self, Rc5Error> {
+        if ![2, 4, 8].contains(&params.words) {
+            return Err(Rc5Error::InvalidWordSize);
+        }
+        Ok(DifferentialSearch {
+            word_bits: 8 * params.words,
+            rounds: params.rounds,
+            breadth: DEFAULT_BREADTH,
+        })
+    }
+
+    // keeps `breadth` partial characteristics per half round instead of
+    // 256. Wider searches are slower but miss fewer characteristics
+    pub fn with_breadth(self, breadth: usize) -> Self {
+        DifferentialSearch {
+            breadth: breadth.max(1),
+            ..self
+        }
+    }
+
+    // the characteristics over all the rounds that survived the search,
+    // the most probable first
+    pub fn run(&self) -> Vec<Characteristic> {
+        let bits = self.word_bits;
+        let mut paths = Vec::new();
+        for a in single_bits(bits) {
+            for b in single_bits(bits) {
+                if (a, b) != (0, 0) {
+                    paths.push(Path {
+                        a,
+                        b,
+                        weight: self.key_addition_weight(a) + self.key_addition_weight(b),
+                        differences: vec![(a, b)],
+                    });
+                }
+            }
+        }
+        paths = self.prune(paths);
+
+        for _ in 0..self.rounds {
+            let mut next = Vec::new();
+            for path in &paths {
+                for (a, weight) in self.half_round(path.a ^ path.b, path.b) {
+                    next.push(Path {
+                        a,
+                        weight: path.weight + weight,
+                        ..path.clone()
+                    });
+                }
+            }
+            paths = self.prune(next);
+
+            let mut next = Vec::new();
+            for path in &paths {
+                for (b, weight) in self.half_round(path.b ^ path.a, path.a) {
+                    let mut differences = path.differences.clone();
+                    differences.push((path.a, b));
+                    next.push(Path {
+                        a: path.a,
+                        b,
+                        weight: path.weight + weight,
+                        differences,
+                    });
+                }
+            }
+            paths = self.prune(next);
+        }
+
+        paths
+            .into_iter()
+            .map(|path| Characteristic {
+                word_bits: bits,
+                differences: path.differences,
+                weight: path.weight,
+            })
+            .collect()
+    }
+
+    // the differences `mixed` can turn into when rotated by a word with
+    // difference `rotating` and added to a round key, with their weights
+    fn half_round(&self, mixed: u64, rotating: u64) -> Vec<(u64, f64)> {
+        let bits = self.word_bits;
+        if rotating & (bits as u64 - 1) != 0 {
+            return Vec::new();
+        }
+
+        let mut cases = HashMap::new();
+        for amount in 0..bits {
+            *cases.entry(rotate_left(mixed, amount, bits)).or_insert(0) += 1;
+        }
+        cases
+            .into_iter()
+            .map(|(difference, count)| {
+                let rotation = (bits as f64 / count as f64).log2();
+                (difference, rotation + self.key_addition_weight(difference))
+            })
+            .collect()
+    }
+
+    // the top bit's carry leaves the word, every other bit has a 1/2
+    // chance to pass
+    fn key_addition_weight(&self, difference: u64) -> f64 {
+        let below_top = difference & !(1 << (self.word_bits - 1));
+        below_top.count_ones() as f64
+    }
+
+    // keeps the most probable way to each pair of differences, and the
+    // `breadth` most probable of those, ordered so that runs repeat
+    fn prune(&self, paths: Vec<Path>) -> Vec<Path> {
+        let mut best: HashMap<(u64, u64), Path> = HashMap::new();
+        for path in paths {
+            match best.get(&(path.a, path.b)) {
+                Some(kept) if kept.order(&path).is_le() => {}
+                _ => {
+                    best.insert((path.a, path.b), path);
+                }
+            }
+        }
+
+        let mut paths = best.into_values().collect::<Vec<Path>>();
+        paths.sort_by(Path::order);
+        paths.truncate(self.breadth);
+        paths
+    }
+}
+
+impl Characteristic {
+    pub fn rounds(&self) -> usize {
+        self.differences.len() - 1
+    }
+
+    pub fn input(&self) -> (u64, u64) {
+        self.differences[0]
+    }
+
+    pub fn output(&self) -> (u64, u64) {
+        self.differences[self.rounds()]
+    }
+
+    pub fn probability(&self) -> f64 {
+        (-self.weight).exp2()
+    }
+
+    // the fraction of `samples` random plaintext pairs with the input
+    // difference whose encryptions under `cipher` have the output
+    // difference. The plaintexts come from SplitMix64 started at `seed`.
+    // `cipher` has to stop after as many rounds as the characteristic has
+    pub fn measure<T>(
+        &self,
+        cipher: &ReducedRc5<T>,
+        samples: usize,
+        seed: u64,
+    ) -> Result<f64, Rc5Error>
+    where
+        T: Unsigned16To64 + CipherMagicConstants + Copy,
+    {
+        let words = std::mem::size_of::<T>();
+        if 8 * words != self.word_bits {
+            return Err(Rc5Error::InvalidWordSize);
+        }
+        if cipher.stop_after_round() != self.rounds() {
+            return Err(Rc5Error::InvalidRounds);
+        }
+
+        let block_difference = |(a, b): (u64, u64)| {
+            let mut bytes = a.to_le_bytes()[..words].to_vec();
+            bytes.extend_from_slice(&b.to_le_bytes()[..words]);
+            bytes
+        };
+        let input = block_difference(self.input());
+        let output = block_difference(self.output());
+
+        let mut random = SplitMix64(seed);
+        let mut hits = 0;
+        for _ in 0..samples {
+            let block = random.bytes(2 * words);
+            let other = xor(&block, &input);
+            let difference = xor(&cipher.encode(&block)?, &cipher.encode(&other)?);
+            if difference == output {
+                hits += 1;
+            }
+        }
+        Ok(hits as f64 / samples.max(1) as f64)
+    }
+}
+
+#[derive(Clone)]
+struct Path {
+    a: u64,
+    b: u64,
+    weight: f64,
+    differences: Vec<(u64, u64)>,
+}
+
+impl Path {
+    // the more probable first, ties broken by the differences so far
+    fn order(&self, other: &Path) -> Ordering {
+        self.weight
+            .total_cmp(&other.weight)
+            .then_with(|| (self.a, self.b).cmp(&(other.a, other.b)))
+            .then_with(|| self.differences.cmp(&other.differences))
+    }
+}
+
+// zero, then every word with a single bit set
+fn single_bits(bits: usize) -> impl Iterator<Item = u64> {
+    std::iter::once(0).chain((0..bits).map(|bit| 1 << bit))
+}
+
+fn rotate_left(word: u64, amount: usize, bits: usize) -> u64 {
+    let mask = u64::MAX >> (64 - bits);
+    let amount = amount % bits;
+    if amount == 0 {
+        word
+    } else {
+        ((word << amount) | (word >> (bits - amount))) & mask
+    }
+}
+
+fn xor(x: &[u8], y: &[u8]) -> Vec<u8> {
+    x.iter().zip(y).map(|(x, y)| x ^ y).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RC5;
+
+    fn search(words: usize, rounds: usize) -> DifferentialSearch {
+        DifferentialSearch::new(Rc5Params {
+            words,
+            rounds,
+            bytes: 16,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn top_bit_differences_survive_the_key_addition() {
+        let found = search(4, 0).run();
+        assert_eq!(found[0].weight, 0.0);
+        assert!(found[..3].iter().all(|found| found.weight == 0.0));
+        assert_eq!(found[0].rounds(), 0);
+        assert_eq!(found[0].input(), found[0].output());
+        assert_eq!(found[3].weight, 1.0);
+
+        assert_eq!(
+            DifferentialSearch::new(Rc5Params {
+                words: 3,
+                rounds: 1,
+                bytes: 16,
+            }),
+            Err(Rc5Error::InvalidWordSize)
+        );
+    }
+
+    #[test]
+    fn estimates_hold_on_the_reduced_cipher() {
+        // one free half round and one that has to hit one rotation of 32
+        let found = search(4, 1).with_breadth(64).run();
+        let best = &found[0];
+        assert_eq!(best.weight, 5.0);
+        assert_eq!(best.rounds(), 1);
+        assert_eq!(found, search(4, 1).with_breadth(64).run());
+
+        let cipher = RC5::<u32>::new((0u8..16).collect::<Vec<u8>>(), 4, 12, 16);
+        let reduced = ReducedRc5::new(&cipher, 1).unwrap();
+        let measured = best.measure(&reduced, 1 << 14, 3).unwrap();
+        assert!(measured >= best.probability() / 2.0, "{}", measured);
+        assert!(measured < 0.25, "{}", measured);
+
+        // the estimates fall with the rounds
+        let three = search(4, 3).with_breadth(64).run();
+        assert!(three[0].weight > best.weight);
+        assert_eq!(
+            three[0].measure(&reduced, 1, 0),
+            Err(Rc5Error::InvalidRounds)
+        );
+        assert_eq!(
+            search(2, 1).run()[0].measure(&reduced, 1, 0),
+            Err(Rc5Error::InvalidWordSize)
+        );
+    }
+}
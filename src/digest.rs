@@ -0,0 +1,390 @@
+use std::convert::TryInto;
+
+use crate::secure;
+
+// the hash functions behind other tools' password-based key derivation,
+// needed for interop with them
+
+pub(crate) const MD5_LEN: usize = 16;
+pub(crate) const SHA256_LEN: usize = 32;
+#[cfg(feature = "argon2")]
+pub(crate) const BLAKE2B_MAX_LEN: usize = 64;
+
+// Merkle-Damgård padding shared by MD5 and SHA-256: a 1 bit, zeros, and
+// the bit length in the last 8 bytes of a 64 byte block
+fn padded(data: &[u8], big_endian: bool) -> Vec<u8> {
+    let bit_len = (data.len() as u64).wrapping_mul(8);
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    if big_endian {
+        message.extend_from_slice(&bit_len.to_be_bytes());
+    } else {
+        message.extend_from_slice(&bit_len.to_le_bytes());
+    }
+    message
+}
+
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+// RFC 1321
+pub(crate) fn md5(data: &[u8]) -> [u8; MD5_LEN] {
+    let constants = (0..64)
+        .map(|i| ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32)
+        .collect::<Vec<u32>>();
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+    for block in padded(data, false).chunks_exact(64) {
+        let m = block
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect::<Vec<u32>>();
+        let [mut a, mut b, mut c, mut d] = state;
+
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let shift = MD5_SHIFTS[(i / 16) * 4 + i % 4];
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(constants[i])
+                .wrapping_add(m[g])
+                .rotate_left(shift);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+
+        for (word, value) in state.iter_mut().zip([a, b, c, d].iter()) {
+            *word = word.wrapping_add(*value);
+        }
+    }
+
+    let mut digest = [0u8; MD5_LEN];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+// FIPS 180-4
+pub(crate) fn sha256(data: &[u8]) -> [u8; SHA256_LEN] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    for block in padded(data, true).chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h].iter()) {
+            *word = word.wrapping_add(*value);
+        }
+    }
+
+    let mut digest = [0u8; SHA256_LEN];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+// RFC 2104 with SHA-256
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; SHA256_LEN] {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..SHA256_LEN].copy_from_slice(&sha256(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = block.iter().map(|byte| byte ^ 0x36).collect::<Vec<u8>>();
+    inner.extend_from_slice(data);
+    let mut outer = block.iter().map(|byte| byte ^ 0x5c).collect::<Vec<u8>>();
+    outer.extend_from_slice(&sha256(&inner));
+
+    secure::wipe(&mut block);
+    secure::wipe(&mut inner);
+    sha256(&outer)
+}
+
+// RFC 8018 PBKDF2 with HMAC-SHA-256, filling `output`
+pub(crate) fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32, output: &mut [u8]) {
+    for (i, chunk) in output.chunks_mut(SHA256_LEN).enumerate() {
+        let mut input = salt.to_vec();
+        input.extend_from_slice(&(i as u32 + 1).to_be_bytes());
+
+        let mut u = hmac_sha256(password, &input);
+        let mut block = u;
+        for _ in 1..iterations {
+            u = hmac_sha256(password, &u);
+            block.iter_mut().zip(u.iter()).for_each(|(x, y)| *x ^= y);
+        }
+
+        chunk.copy_from_slice(&block[..chunk.len()]);
+        secure::wipe(&mut block);
+        secure::wipe(&mut u);
+    }
+}
+
+// RFC 5869 HKDF with HMAC-SHA-256, filling `output` (at most 255 hashes
+// long) with keying material for the context `info`
+pub(crate) fn hkdf_sha256(salt: &[u8], ikm: &[u8], info: &[u8], output: &mut [u8]) {
+    debug_assert!(output.len() <= 255 * SHA256_LEN);
+    let mut prk = hmac_sha256(salt, ikm);
+
+    let mut t = Vec::with_capacity(SHA256_LEN + info.len() + 1);
+    for (i, chunk) in output.chunks_mut(SHA256_LEN).enumerate() {
+        t.extend_from_slice(info);
+        t.push(i as u8 + 1);
+        let mut block = hmac_sha256(&prk, &t);
+        chunk.copy_from_slice(&block[..chunk.len()]);
+
+        secure::wipe(&mut t);
+        t.clear();
+        t.extend_from_slice(&block);
+        secure::wipe(&mut block);
+    }
+
+    secure::wipe(&mut t);
+    secure::wipe(&mut prk);
+}
+
+#[cfg(feature = "argon2")]
+const BLAKE2B_IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+#[cfg(feature = "argon2")]
+const BLAKE2B_SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+#[cfg(feature = "argon2")]
+fn blake2b_compress(state: &mut [u64; 8], block: &[u8], counter: u128, last: bool) {
+    let mut m = [0u64; 16];
+    for (word, bytes) in m.iter_mut().zip(block.chunks_exact(8)) {
+        *word = u64::from_le_bytes(bytes.try_into().unwrap());
+    }
+
+    let mut v = [0u64; 16];
+    v[..8].copy_from_slice(state);
+    v[8..].copy_from_slice(&BLAKE2B_IV);
+    v[12] ^= counter as u64;
+    v[13] ^= (counter >> 64) as u64;
+    if last {
+        v[14] = !v[14];
+    }
+
+    let mut mix = |a: usize, b: usize, c: usize, d: usize, x: u64, y: u64| {
+        v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+        v[d] = (v[d] ^ v[a]).rotate_right(32);
+        v[c] = v[c].wrapping_add(v[d]);
+        v[b] = (v[b] ^ v[c]).rotate_right(24);
+        v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+        v[d] = (v[d] ^ v[a]).rotate_right(16);
+        v[c] = v[c].wrapping_add(v[d]);
+        v[b] = (v[b] ^ v[c]).rotate_right(63);
+    };
+    for round in 0..12 {
+        let s = &BLAKE2B_SIGMA[round % 10];
+        mix(0, 4, 8, 12, m[s[0]], m[s[1]]);
+        mix(1, 5, 9, 13, m[s[2]], m[s[3]]);
+        mix(2, 6, 10, 14, m[s[4]], m[s[5]]);
+        mix(3, 7, 11, 15, m[s[6]], m[s[7]]);
+        mix(0, 5, 10, 15, m[s[8]], m[s[9]]);
+        mix(1, 6, 11, 12, m[s[10]], m[s[11]]);
+        mix(2, 7, 8, 13, m[s[12]], m[s[13]]);
+        mix(3, 4, 9, 14, m[s[14]], m[s[15]]);
+    }
+
+    for (i, word) in state.iter_mut().enumerate() {
+        *word ^= v[i] ^ v[i + 8];
+    }
+}
+
+// RFC 7693 BLAKE2b without a key, with an output of `output.len()` bytes
+// (1 to 64)
+#[cfg(feature = "argon2")]
+pub(crate) fn blake2b(data: &[u8], output: &mut [u8]) {
+    debug_assert!((1..=BLAKE2B_MAX_LEN).contains(&output.len()));
+    let mut state = BLAKE2B_IV;
+    state[0] ^= 0x0101_0000 ^ output.len() as u64;
+
+    let mut counter = 0u128;
+    let mut blocks = data.chunks(128).peekable();
+    if blocks.peek().is_none() {
+        blake2b_compress(&mut state, &[0u8; 128], 0, true);
+    }
+    while let Some(block) = blocks.next() {
+        counter += block.len() as u128;
+        let mut padded = [0u8; 128];
+        padded[..block.len()].copy_from_slice(block);
+        blake2b_compress(&mut state, &padded, counter, blocks.peek().is_none());
+    }
+
+    let mut digest = [0u8; BLAKE2B_MAX_LEN];
+    for (bytes, word) in digest.chunks_exact_mut(8).zip(state.iter()) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    output.copy_from_slice(&digest[..output.len()]);
+    secure::wipe(&mut digest);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn md5_known_answers() {
+        assert_eq!(hex(&md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(
+            hex(&md5(b"The quick brown fox jumps over the lazy dog")),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+        assert_eq!(hex(&md5(&[0x61; 1000])), "cabe45dcc9ae5b66ba86600cca6b8ba8");
+    }
+
+    #[test]
+    fn sha256_known_answers() {
+        assert_eq!(
+            hex(&sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(&sha256(&[0x61; 1000])),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+    }
+
+    #[test]
+    fn hmac_and_pbkdf2_known_answers() {
+        assert_eq!(
+            hex(&hmac_sha256(
+                b"key",
+                b"The quick brown fox jumps over the lazy dog"
+            )),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+
+        let mut output = [0u8; 24];
+        pbkdf2_sha256(b"password", &[0, 1, 2, 3, 4, 5, 6, 7], 1000, &mut output);
+        assert_eq!(
+            hex(&output),
+            "f8b177bdec64144719f02b69ee888b2660d125f514100f88"
+        );
+    }
+
+    #[test]
+    fn hkdf_known_answer() {
+        let salt = (0u8..13).collect::<Vec<u8>>();
+        let info = (0xf0u8..0xfa).collect::<Vec<u8>>();
+        let mut output = [0u8; 42];
+        hkdf_sha256(&salt, &[0x0b; 22], &info, &mut output);
+        assert_eq!(
+            hex(&output),
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+        );
+    }
+
+    #[cfg(feature = "argon2")]
+    #[test]
+    fn blake2b_known_answers() {
+        let mut digest = [0u8; 64];
+        blake2b(b"abc", &mut digest);
+        assert_eq!(
+            hex(&digest),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+             7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+
+        let mut digest = [0u8; 32];
+        blake2b(b"", &mut digest);
+        assert_eq!(
+            hex(&digest),
+            "0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8"
+        );
+
+        // more than one block, and a truncated output
+        let data = (0u8..200).collect::<Vec<u8>>();
+        let mut digest = [0u8; 20];
+        blake2b(&data, &mut digest);
+        assert_eq!(hex(&digest), "b83a5733ce63f2dd8266ea8ec93333d7935142cf");
+    }
+}
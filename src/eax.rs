@@ -0,0 +1,262 @@
+use crate::ct::ct_eq;
+use crate::mac::omac_t;
+use crate::mode::{apply_keystream, xor_in_place};
+#[cfg(feature = "tracing")]
+use crate::trace::{self, TraceEvent};
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{KeySchedule, Rc5Error, RC5};
+
+// EAX authenticated encryption (Bellare, Rogaway, Wagner) over RC5: CTR
+// encryption keyed by OMAC(nonce), authenticated with OMAC over the
+// header and the ciphertext. The tag is one block long and appended to
+// the ciphertext
+pub struct Eax<T: Unsigned16To64> {
+    schedule: KeySchedule<T>,
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> Eax<T> {
+    pub fn new(cipher: &RC5<T>) -> Result<Self, Rc5Error> {
+        Ok(Eax {
+            schedule: cipher.key_schedule()?,
+        })
+    }
+}
+
+impl<T: Unsigned16To64 + Copy> Eax<T> {
+    pub fn tag_len(&self) -> usize {
+        self.schedule.block_len()
+    }
+
+    pub fn encrypt(&self, nonce: &[u8], header: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let mut buffer = plaintext.to_vec();
+        self.encrypt_in_place(nonce, header, &mut buffer);
+        buffer
+    }
+
+    pub fn decrypt(
+        &self,
+        nonce: &[u8],
+        header: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, Rc5Error> {
+        let mut buffer = ciphertext.to_vec();
+        self.decrypt_in_place(nonce, header, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    // the in-place methods follow the `aead::AeadInPlace` API: encrypts
+    // `buffer` and appends the tag to it
+    pub fn encrypt_in_place(&self, nonce: &[u8], header: &[u8], buffer: &mut Vec<u8>) {
+        let tag = self.encrypt_in_place_detached(nonce, header, buffer);
+        buffer.extend_from_slice(&tag);
+    }
+
+    // verifies and strips the trailing tag, then decrypts `buffer`. On
+    // failure `buffer` is left as it was
+    pub fn decrypt_in_place(
+        &self,
+        nonce: &[u8],
+        header: &[u8],
+        buffer: &mut Vec<u8>,
+    ) -> Result<(), Rc5Error> {
+        // input too short to hold a tag is still fully authenticated
+        // against a dummy one, so that it fails just like a bad tag
+        let tag_len = self.tag_len();
+        let complete = buffer.len() >= tag_len;
+        let split = if complete {
+            buffer.len() - tag_len
+        } else {
+            buffer.len()
+        };
+
+        let dummy_tag = vec![0u8; tag_len];
+        let (data, tag) = buffer.split_at_mut(split);
+        let tag = if complete { &*tag } else { &dummy_tag[..] };
+
+        let verified = self.decrypt_in_place_detached(nonce, header, data, tag);
+        if !complete || verified.is_err() {
+            return Err(Rc5Error::DecryptionFailed);
+        }
+
+        buffer.truncate(split);
+        Ok(())
+    }
+
+    // encrypts `data` in place and returns the tag
+    pub fn encrypt_in_place_detached(
+        &self,
+        nonce: &[u8],
+        header: &[u8],
+        data: &mut [u8],
+    ) -> Vec<u8> {
+        let n = omac_t(&self.schedule, 0, nonce);
+        let h = omac_t(&self.schedule, 1, header);
+
+        self.ctr(&n, data);
+
+        let mut tag = omac_t(&self.schedule, 2, data);
+        xor_in_place(&mut tag, &n);
+        xor_in_place(&mut tag, &h);
+        tag
+    }
+
+    // verifies `tag` and only then decrypts `data` in place
+    pub fn decrypt_in_place_detached(
+        &self,
+        nonce: &[u8],
+        header: &[u8],
+        data: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), Rc5Error> {
+        let n = omac_t(&self.schedule, 0, nonce);
+        let h = omac_t(&self.schedule, 1, header);
+
+        let mut expected = omac_t(&self.schedule, 2, data);
+        xor_in_place(&mut expected, &n);
+        xor_in_place(&mut expected, &h);
+
+        let verified = ct_eq(&expected, tag);
+        #[cfg(feature = "tracing")]
+        trace::emit(TraceEvent::Verification {
+            kind: "EAX",
+            verified,
+        });
+        if !verified {
+            return Err(Rc5Error::DecryptionFailed);
+        }
+
+        self.ctr(&n, data);
+        Ok(())
+    }
+
+    fn ctr(&self, initial_counter: &[u8], data: &mut [u8]) {
+        let mut counter = initial_counter.to_vec();
+        for block in data.chunks_mut(self.schedule.block_len()) {
+            apply_keystream(&self.schedule, &mut counter, block);
+        }
+    }
+}
+
+// decrypts an EAX `ciphertext` with the first of `keys` its tag verifies
+// under, returning that key's index with the plaintext. For migrations
+// where the key a message was sealed with isn't known: a wrong key fails
+// the tag check, so it can't produce a plaintext by mistake. Fails with
+// `DecryptionFailed` when no key fits, and with the key's own error when
+// one is invalid
+pub fn try_decrypt_with_any<T>(
+    keys: &[RC5<T>],
+    nonce: &[u8],
+    header: &[u8],
+    ciphertext: &[u8],
+) -> Result<(usize, Vec<u8>), Rc5Error>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    for (index, key) in keys.iter().enumerate() {
+        if let Ok(plaintext) = Eax::new(key)?.decrypt(nonce, header, ciphertext) {
+            return Ok((index, plaintext));
+        }
+    }
+    Err(Rc5Error::DecryptionFailed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eax() -> Eax<u32> {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        Eax::new(&RC5::<u32>::new(key, 4, 12, 16)).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let eax = eax();
+
+        for len in [0usize, 1, 8, 13, 64].iter() {
+            let plaintext = vec![0x42; *len];
+            let ciphertext = eax.encrypt(b"nonce", b"header", &plaintext);
+            assert_eq!(ciphertext.len(), len + 8);
+
+            let decrypted = eax.decrypt(b"nonce", b"header", &ciphertext).unwrap();
+            assert_eq!(decrypted, plaintext);
+        }
+    }
+
+    #[test]
+    fn in_place_matches_allocating_api() {
+        let eax = eax();
+        let expected = eax.encrypt(b"nonce", b"header", b"attack at dawn");
+
+        let mut buffer = b"attack at dawn".to_vec();
+        eax.encrypt_in_place(b"nonce", b"header", &mut buffer);
+        assert_eq!(buffer, expected);
+
+        let mut tampered = buffer.clone();
+        tampered[0] ^= 1;
+        let before = tampered.clone();
+        assert!(eax
+            .decrypt_in_place(b"nonce", b"header", &mut tampered)
+            .is_err());
+        assert_eq!(tampered, before);
+
+        eax.decrypt_in_place(b"nonce", b"header", &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, b"attack at dawn");
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let eax = eax();
+        let ciphertext = eax.encrypt(b"nonce", b"header", b"attack at dawn");
+
+        let mut flipped = ciphertext.clone();
+        flipped[3] ^= 1;
+        assert_eq!(
+            eax.decrypt(b"nonce", b"header", &flipped),
+            Err(Rc5Error::DecryptionFailed)
+        );
+
+        assert_eq!(
+            eax.decrypt(b"nonce", b"other", &ciphertext),
+            Err(Rc5Error::DecryptionFailed)
+        );
+        assert_eq!(
+            eax.decrypt(b"other", b"header", &ciphertext),
+            Err(Rc5Error::DecryptionFailed)
+        );
+        assert_eq!(
+            eax.decrypt(b"nonce", b"header", &ciphertext[..7]),
+            Err(Rc5Error::DecryptionFailed)
+        );
+    }
+
+    #[test]
+    fn the_fitting_key_is_found() {
+        let keys = (1u8..=3)
+            .map(|byte| RC5::<u32>::new(vec![byte; 16], 4, 12, 16))
+            .collect::<Vec<_>>();
+        let ciphertext = Eax::new(&keys[1])
+            .unwrap()
+            .encrypt(b"nonce", b"header", b"migrated");
+
+        assert_eq!(
+            try_decrypt_with_any(&keys, b"nonce", b"header", &ciphertext),
+            Ok((1, b"migrated".to_vec()))
+        );
+        assert_eq!(
+            try_decrypt_with_any(&keys[2..], b"nonce", b"header", &ciphertext),
+            Err(Rc5Error::DecryptionFailed)
+        );
+        assert_eq!(
+            try_decrypt_with_any::<u32>(&[], b"nonce", b"header", &ciphertext),
+            Err(Rc5Error::DecryptionFailed)
+        );
+
+        let invalid = vec![RC5::<u32>::new(vec![1, 2, 3], 4, 12, 16)];
+        assert_eq!(
+            try_decrypt_with_any(&invalid, b"nonce", b"header", &ciphertext),
+            Err(Rc5Error::InvalidKeyLength)
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{secure, Rc5Error};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// hex and standard base64 with padding (RFC 4648) for secrets typed or
+// pasted as text. ASCII whitespace anywhere is skipped, so keys may be
+// split across lines or grouped. The text is read in place, and the
+// decoded bytes are wiped again if the rest turns out to be malformed
+pub fn decode_hex(text: &str) -> Result<Vec<u8>, Rc5Error> {
+    // sized up front so the vector never reallocates and leaves a copy
+    let mut bytes = Vec::with_capacity(text.len() / 2);
+    let mut high = None;
+    for symbol in symbols(text) {
+        let nibble = match (symbol as char).to_digit(16) {
+            Some(nibble) => nibble as u8,
+            None => return malformed(bytes),
+        };
+        match high.take() {
+            Some(high) => bytes.push(high << 4 | nibble),
+            None => high = Some(nibble),
+        }
+    }
+    if high.is_some() {
+        return malformed(bytes);
+    }
+    Ok(bytes)
+}
+
+pub fn decode_base64(text: &str) -> Result<Vec<u8>, Rc5Error> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut group, mut count, mut padding) = (0u32, 0usize, 0usize);
+    let mut finished = false;
+    for symbol in symbols(text) {
+        if finished {
+            return malformed(bytes);
+        }
+        if symbol == b'=' {
+            padding += 1;
+        } else if padding > 0 {
+            return malformed(bytes);
+        } else {
+            match BASE64.iter().position(|&c| c == symbol) {
+                Some(value) => group = group << 6 | value as u32,
+                None => return malformed(bytes),
+            }
+        }
+
+        count += 1;
+        if count % 4 == 0 {
+            if padding > 2 {
+                return malformed(bytes);
+            }
+            group <<= 6 * padding;
+            bytes.extend_from_slice(&group.to_be_bytes()[1..4 - padding]);
+            group = 0;
+            finished = padding > 0;
+        }
+    }
+    if count % 4 != 0 {
+        return malformed(bytes);
+    }
+    Ok(bytes)
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64[(group >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn symbols(text: &str) -> impl Iterator<Item = u8> + '_ {
+    text.bytes().filter(|byte| !byte.is_ascii_whitespace())
+}
+
+fn malformed(mut bytes: Vec<u8>) -> Result<Vec<u8>, Rc5Error> {
+    secure::wipe(&mut bytes);
+    Err(Rc5Error::InvalidEncoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_ignores_whitespace_and_case() {
+        assert_eq!(decode_hex("00ff10Ab").unwrap(), [0x00, 0xff, 0x10, 0xab]);
+        assert_eq!(
+            decode_hex(" 00 ff\n\t10ab \r\n").unwrap(),
+            [0x00, 0xff, 0x10, 0xab]
+        );
+        assert_eq!(decode_hex("").unwrap(), []);
+        assert_eq!(encode_hex(&[0x00, 0xff, 0x10, 0xab]), "00ff10ab");
+
+        for text in ["0", "0g", "00 f", "0x00", "zz"].iter() {
+            assert_eq!(decode_hex(text), Err(Rc5Error::InvalidEncoding), "{}", text);
+        }
+    }
+
+    #[test]
+    fn base64_follows_rfc_4648() {
+        // the test vectors of RFC 4648 section 10
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (plain, encoded) in vectors.iter() {
+            assert_eq!(decode_base64(encoded).unwrap(), plain.as_bytes());
+            assert_eq!(encode_base64(plain.as_bytes()), *encoded);
+        }
+        assert_eq!(decode_base64("Zm9v\n YmE=\n").unwrap(), b"fooba");
+
+        for text in ["Zm9", "Zg=", "Z===", "Zg==Zg==", "Zm9v!A==", "Zg=a"].iter() {
+            assert_eq!(
+                decode_base64(text),
+                Err(Rc5Error::InvalidEncoding),
+                "{}",
+                text
+            );
+        }
+    }
+}
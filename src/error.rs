@@ -0,0 +1,67 @@
+use core::fmt;
+
+// every fallible operation of the crate reports one of these
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rc5Error {
+    EmptyKey,
+    InvalidWordSize,
+    InvalidKeyLength,
+    InvalidBlockLength,
+    InvalidCiphertextLength,
+    InvalidIvLength,
+    InvalidNonceLength,
+    // bad padding, a bad tag or truncated ciphertext, deliberately not
+    // told apart
+    DecryptionFailed,
+    BufferTooSmall,
+    InvalidSnapshot,
+    InvalidEncoding,
+    InvalidKdfParameters,
+    // the operating system's random generator couldn't be read
+    RngUnavailable,
+    // a message names a key that isn't in the `KeyRing`, there is no
+    // active key to encrypt with, no key is stored under a name in the
+    // `OsKeyStore`, or no `FieldCipher` is installed for the thread
+    UnknownKeyId,
+    // the operating system's key store refused or couldn't be reached
+    KeyStoreUnavailable,
+    // a header names parameters outside the reader's `HeaderPolicy`
+    ParametersNotAllowed,
+    // decrypted text asked for as a `String` isn't UTF-8
+    InvalidUtf8,
+    // more than 255 rounds, or a reduced-round cipher asks for more rounds
+    // than its key schedule has
+    InvalidRounds,
+    // a container's key check value isn't that of the key it is read with
+    WrongKey,
+}
+
+impl fmt::Display for Rc5Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            Rc5Error::EmptyKey => "key must not be empty",
+            Rc5Error::InvalidWordSize => "word size does not match the word type",
+            Rc5Error::InvalidKeyLength => "invalid key length",
+            Rc5Error::InvalidBlockLength => "input is not exactly one block long",
+            Rc5Error::InvalidCiphertextLength => "ciphertext is not a multiple of the block length",
+            Rc5Error::InvalidIvLength => "invalid initialization vector length",
+            Rc5Error::InvalidNonceLength => "invalid nonce length",
+            Rc5Error::DecryptionFailed => "decryption failed",
+            Rc5Error::BufferTooSmall => "output buffer is too small",
+            Rc5Error::InvalidSnapshot => "invalid stream snapshot",
+            Rc5Error::InvalidEncoding => "malformed or unsupported parameter encoding",
+            Rc5Error::InvalidKdfParameters => "invalid key derivation parameters",
+            Rc5Error::RngUnavailable => "the operating system's random generator is unavailable",
+            Rc5Error::UnknownKeyId => "no such key in the key ring",
+            Rc5Error::KeyStoreUnavailable => "the operating system's key store is unavailable",
+            Rc5Error::ParametersNotAllowed => "the header's parameters are not allowed",
+            Rc5Error::InvalidUtf8 => "decrypted text is not valid UTF-8",
+            Rc5Error::InvalidRounds => "invalid number of rounds",
+            Rc5Error::WrongKey => "the key does not match the container",
+        };
+        f.write_str(message)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Rc5Error {}
@@ -0,0 +1,690 @@
+// C interface, declared in `include/rc5.h`. Build a library to link
+// against with
+//
+//   cargo rustc --release --features ffi --crate-type staticlib
+//
+// (or `cdylib`). Every fallible function returns an `Rc5Status`, leaves a
+// message for `rc5_last_error` when it fails, and never unwinds into the
+// caller. The safety contract of
+// each function is in its comment, and repeated in the header
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::{panic, ptr, slice};
+
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{secure, Mode, Rc5Error, StreamDecryptor, StreamEncryptor, RC5};
+
+// the status every fallible function returns, `rc5_status` with its
+// `RC5_OK` and `RC5_ERR_*` names in the header. The values are part of
+// the C interface and never change meaning: new failures get new values
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rc5Status {
+    Ok = 0,
+    NullPointer = -1,
+    InvalidArgument = -2,
+    EmptyKey = -3,
+    InvalidWordSize = -4,
+    InvalidKeyLength = -5,
+    InvalidBlockLength = -6,
+    InvalidCiphertextLength = -7,
+    InvalidIvLength = -8,
+    InvalidNonceLength = -9,
+    DecryptionFailed = -10,
+    BufferTooSmall = -11,
+    InvalidSnapshot = -12,
+    InvalidEncoding = -13,
+    Panic = -14,
+    InvalidKdfParameters = -15,
+    RngUnavailable = -16,
+    UnknownKeyId = -17,
+    KeyStoreUnavailable = -18,
+    ParametersNotAllowed = -19,
+    InvalidUtf8 = -20,
+    InvalidRounds = -21,
+    WrongKey = -22,
+}
+
+impl From<Rc5Error> for Rc5Status {
+    fn from(err: Rc5Error) -> Self {
+        match err {
+            Rc5Error::EmptyKey => Rc5Status::EmptyKey,
+            Rc5Error::InvalidWordSize => Rc5Status::InvalidWordSize,
+            Rc5Error::InvalidKeyLength => Rc5Status::InvalidKeyLength,
+            Rc5Error::InvalidBlockLength => Rc5Status::InvalidBlockLength,
+            Rc5Error::InvalidCiphertextLength => Rc5Status::InvalidCiphertextLength,
+            Rc5Error::InvalidIvLength => Rc5Status::InvalidIvLength,
+            Rc5Error::InvalidNonceLength => Rc5Status::InvalidNonceLength,
+            Rc5Error::DecryptionFailed => Rc5Status::DecryptionFailed,
+            Rc5Error::BufferTooSmall => Rc5Status::BufferTooSmall,
+            Rc5Error::InvalidSnapshot => Rc5Status::InvalidSnapshot,
+            Rc5Error::InvalidEncoding => Rc5Status::InvalidEncoding,
+            Rc5Error::InvalidKdfParameters => Rc5Status::InvalidKdfParameters,
+            Rc5Error::RngUnavailable => Rc5Status::RngUnavailable,
+            Rc5Error::UnknownKeyId => Rc5Status::UnknownKeyId,
+            Rc5Error::KeyStoreUnavailable => Rc5Status::KeyStoreUnavailable,
+            Rc5Error::ParametersNotAllowed => Rc5Status::ParametersNotAllowed,
+            Rc5Error::InvalidUtf8 => Rc5Status::InvalidUtf8,
+            Rc5Error::InvalidRounds => Rc5Status::InvalidRounds,
+            Rc5Error::WrongKey => Rc5Status::WrongKey,
+        }
+    }
+}
+
+// a failed call: its status, and the message `rc5_last_error` will show
+struct Failure {
+    status: Rc5Status,
+    message: String,
+}
+
+impl Failure {
+    fn null(argument: &str) -> Self {
+        Failure {
+            status: Rc5Status::NullPointer,
+            message: format!("`{}` is null", argument),
+        }
+    }
+
+    fn invalid(message: String) -> Self {
+        Failure {
+            status: Rc5Status::InvalidArgument,
+            message,
+        }
+    }
+}
+
+impl From<Rc5Error> for Failure {
+    fn from(err: Rc5Error) -> Self {
+        Failure {
+            status: err.into(),
+            message: err.to_string(),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+pub const RC5_MODE_ECB: c_int = 0;
+pub const RC5_MODE_CBC: c_int = 1;
+pub const RC5_MODE_CTR: c_int = 2;
+
+// the opaque `rc5_cipher` of the header
+pub struct Rc5Cipher {
+    inner: Inner,
+}
+
+enum Inner {
+    #[cfg(feature = "rc5-16")]
+    W16(RC5<u16>),
+    W32(RC5<u32>),
+    #[cfg(feature = "rc5-64")]
+    W64(RC5<u64>),
+}
+
+impl Rc5Cipher {
+    fn block_len(&self) -> usize {
+        match &self.inner {
+            #[cfg(feature = "rc5-16")]
+            Inner::W16(_) => 4,
+            Inner::W32(_) => 8,
+            #[cfg(feature = "rc5-64")]
+            Inner::W64(_) => 16,
+        }
+    }
+
+    fn encrypt(&self, mode: Mode, input: &[u8]) -> Result<Vec<u8>, Rc5Error> {
+        fn run<T>(cipher: &RC5<T>, mode: Mode, input: &[u8]) -> Result<Vec<u8>, Rc5Error>
+        where
+            T: Unsigned16To64 + CipherMagicConstants + Copy,
+        {
+            let mut encryptor = StreamEncryptor::new(cipher, mode)?;
+            let mut output = encryptor.update(input);
+            output.extend(encryptor.finalize());
+            Ok(output)
+        }
+
+        match &self.inner {
+            #[cfg(feature = "rc5-16")]
+            Inner::W16(cipher) => run(cipher, mode, input),
+            Inner::W32(cipher) => run(cipher, mode, input),
+            #[cfg(feature = "rc5-64")]
+            Inner::W64(cipher) => run(cipher, mode, input),
+        }
+    }
+
+    fn decrypt(&self, mode: Mode, input: &[u8]) -> Result<Vec<u8>, Rc5Error> {
+        fn run<T>(cipher: &RC5<T>, mode: Mode, input: &[u8]) -> Result<Vec<u8>, Rc5Error>
+        where
+            T: Unsigned16To64 + CipherMagicConstants + Copy,
+        {
+            let mut decryptor = StreamDecryptor::new(cipher, mode)?;
+            let mut output = decryptor.update(input);
+            output.extend(decryptor.finalize()?);
+            Ok(output)
+        }
+
+        match &self.inner {
+            #[cfg(feature = "rc5-16")]
+            Inner::W16(cipher) => run(cipher, mode, input),
+            Inner::W32(cipher) => run(cipher, mode, input),
+            #[cfg(feature = "rc5-64")]
+            Inner::W64(cipher) => run(cipher, mode, input),
+        }
+    }
+}
+
+// a null pointer is only fine for an empty buffer
+unsafe fn bytes<'a>(data: *const u8, len: usize, argument: &str) -> Result<&'a [u8], Failure> {
+    if len == 0 {
+        Ok(&[])
+    } else if data.is_null() {
+        Err(Failure::null(argument))
+    } else {
+        // SAFETY: the caller promises `len` readable bytes at `data`
+        Ok(slice::from_raw_parts(data, len))
+    }
+}
+
+unsafe fn mode(mode: c_int, iv: *const u8, iv_len: usize) -> Result<Mode, Failure> {
+    match mode {
+        RC5_MODE_ECB => Ok(Mode::Ecb),
+        RC5_MODE_CBC => Ok(Mode::Cbc {
+            iv: bytes(iv, iv_len, "iv")?.to_vec(),
+        }),
+        RC5_MODE_CTR => Ok(Mode::Ctr {
+            nonce: bytes(iv, iv_len, "iv")?.to_vec(),
+        }),
+        _ => Err(Failure::invalid(format!("unknown mode {}", mode))),
+    }
+}
+
+// runs the body of the C function `function`, recording why it failed for
+// `rc5_last_error`, or clearing the record once it succeeds. A panic
+// becomes `Rc5Status::Panic` since unwinding across the C boundary is
+// undefined behaviour
+fn guard<F: FnOnce() -> Result<(), Failure>>(function: &str, body: F) -> Rc5Status {
+    let failure = match panic::catch_unwind(panic::AssertUnwindSafe(body)) {
+        Ok(Ok(())) => {
+            LAST_ERROR.with(|last| *last.borrow_mut() = None);
+            return Rc5Status::Ok;
+        }
+        Ok(Err(failure)) => failure,
+        Err(payload) => Failure {
+            status: Rc5Status::Panic,
+            message: payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "panicked".to_string()),
+        },
+    };
+
+    let message = format!("{}: {}", function, failure.message);
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+    failure.status
+}
+
+// copies `result` to the caller's buffer. `*output_len` always receives
+// the full length, so a caller told `RC5_ERR_BUFFER_TOO_SMALL` can retry
+// with a buffer that big
+unsafe fn write_output(
+    result: Result<Vec<u8>, Rc5Error>,
+    output: *mut u8,
+    output_cap: usize,
+    output_len: *mut usize,
+) -> Result<(), Failure> {
+    let mut data = result?;
+    // SAFETY: checked for null by the callers
+    *output_len = data.len();
+
+    let written = if data.len() > output_cap {
+        Err(Failure {
+            status: Rc5Status::BufferTooSmall,
+            message: format!(
+                "the output needs {} bytes, the buffer has {}",
+                data.len(),
+                output_cap
+            ),
+        })
+    } else if !data.is_empty() && output.is_null() {
+        Err(Failure::null("output"))
+    } else {
+        // SAFETY: the caller promises `output_cap` writable bytes at
+        // `output`, which don't overlap the freshly allocated `data`
+        ptr::copy_nonoverlapping(data.as_ptr(), output, data.len());
+        Ok(())
+    };
+    secure::wipe(&mut data);
+    written
+}
+
+// creates a cipher for RC5-`word_bits`/`rounds` with the `key_len` bytes
+// at `key` and stores it in `*cipher`, to be released with
+// `rc5_cipher_free`. The key bytes are copied and can be wiped afterwards
+//
+// `key` must point to `key_len` readable bytes and `cipher` must be a
+// valid pointer to write the handle to
+#[no_mangle]
+pub unsafe extern "C" fn rc5_cipher_new(
+    key: *const u8,
+    key_len: usize,
+    word_bits: u32,
+    rounds: u32,
+    cipher: *mut *mut Rc5Cipher,
+) -> Rc5Status {
+    guard("rc5_cipher_new", || {
+        if cipher.is_null() {
+            return Err(Failure::null("cipher"));
+        }
+        let key = bytes(key, key_len, "key")?;
+        // more than 255 rounds are refused by the key schedule, before any
+        // table is allocated
+        let rounds = rounds as usize;
+
+        let inner = match word_bits {
+            #[cfg(feature = "rc5-16")]
+            16 => Inner::W16(RC5::new(key, 2, rounds, key_len)),
+            32 => Inner::W32(RC5::new(key, 4, rounds, key_len)),
+            #[cfg(feature = "rc5-64")]
+            64 => Inner::W64(RC5::new(key, 8, rounds, key_len)),
+            _ => {
+                return Err(Failure {
+                    status: Rc5Status::InvalidWordSize,
+                    message: format!("unsupported word size of {} bits", word_bits),
+                })
+            }
+        };
+        let failed = match &inner {
+            #[cfg(feature = "rc5-16")]
+            Inner::W16(rc5) => rc5.schedule().err(),
+            Inner::W32(rc5) => rc5.schedule().err(),
+            #[cfg(feature = "rc5-64")]
+            Inner::W64(rc5) => rc5.schedule().err(),
+        };
+        if let Some(err) = failed {
+            return Err(err.into());
+        }
+
+        // SAFETY: checked for null above
+        *cipher = Box::into_raw(Box::new(Rc5Cipher { inner }));
+        Ok(())
+    })
+}
+
+// releases a cipher made by `rc5_cipher_new`, wiping its key schedule.
+// Null is ignored
+//
+// `cipher` must be null or a handle from `rc5_cipher_new` that hasn't
+// been freed yet
+#[no_mangle]
+pub unsafe extern "C" fn rc5_cipher_free(cipher: *mut Rc5Cipher) {
+    if !cipher.is_null() {
+        // SAFETY: the handle came from `Box::into_raw` in `rc5_cipher_new`
+        drop(Box::from_raw(cipher));
+    }
+}
+
+// the block length in bytes, which is also the IV and nonce length, or 0
+// for a null handle
+//
+// `cipher` must be null or a live handle from `rc5_cipher_new`
+#[no_mangle]
+pub unsafe extern "C" fn rc5_block_len(cipher: *const Rc5Cipher) -> usize {
+    cipher.as_ref().map_or(0, Rc5Cipher::block_len)
+}
+
+// encrypts `input_len` bytes in `mode` (one of the `RC5_MODE_*`), with
+// the IV or nonce at `iv` for CBC and CTR, into `output`. ECB and CBC
+// add PKCS#7 padding, so `output` needs up to one block more than the
+// input
+//
+// `cipher` must be a live handle, `iv`, `input` and `output` must point
+// to `iv_len`, `input_len` and `output_cap` valid bytes, and
+// `output_len` must be a valid pointer
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn rc5_encrypt(
+    cipher: *const Rc5Cipher,
+    mode_id: c_int,
+    iv: *const u8,
+    iv_len: usize,
+    input: *const u8,
+    input_len: usize,
+    output: *mut u8,
+    output_cap: usize,
+    output_len: *mut usize,
+) -> Rc5Status {
+    guard("rc5_encrypt", || {
+        let cipher = cipher.as_ref().ok_or_else(|| Failure::null("cipher"))?;
+        if output_len.is_null() {
+            return Err(Failure::null("output_len"));
+        }
+        let mode = mode(mode_id, iv, iv_len)?;
+        let input = bytes(input, input_len, "input")?;
+        write_output(cipher.encrypt(mode, input), output, output_cap, output_len)
+    })
+}
+
+// the inverse of `rc5_encrypt`. The plaintext is never longer than the
+// ciphertext
+//
+// as for `rc5_encrypt`
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn rc5_decrypt(
+    cipher: *const Rc5Cipher,
+    mode_id: c_int,
+    iv: *const u8,
+    iv_len: usize,
+    input: *const u8,
+    input_len: usize,
+    output: *mut u8,
+    output_cap: usize,
+    output_len: *mut usize,
+) -> Rc5Status {
+    guard("rc5_decrypt", || {
+        let cipher = cipher.as_ref().ok_or_else(|| Failure::null("cipher"))?;
+        if output_len.is_null() {
+            return Err(Failure::null("output_len"));
+        }
+        let mode = mode(mode_id, iv, iv_len)?;
+        let input = bytes(input, input_len, "input")?;
+        write_output(cipher.decrypt(mode, input), output, output_cap, output_len)
+    })
+}
+
+// a description of the last failure on the calling thread, prefixed with
+// the name of the function that failed, or null if the last fallible call
+// succeeded or nothing has been called yet. The string stays valid until
+// the next fallible call on the same thread
+#[no_mangle]
+pub extern "C" fn rc5_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    fn last_error() -> String {
+        let message = rc5_last_error();
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn new_cipher(key: &[u8], word_bits: u32, rounds: u32) -> Result<*mut Rc5Cipher, Rc5Status> {
+        let mut cipher = ptr::null_mut();
+        let status =
+            unsafe { rc5_cipher_new(key.as_ptr(), key.len(), word_bits, rounds, &mut cipher) };
+        if status == Rc5Status::Ok {
+            Ok(cipher)
+        } else {
+            Err(status)
+        }
+    }
+
+    #[test]
+    fn round_trip_through_c_interface() {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let cipher = new_cipher(&key, 32, 12).unwrap();
+        let iv = [7u8; 8];
+        assert_eq!(unsafe { rc5_block_len(cipher) }, 8);
+
+        for &mode_id in [RC5_MODE_ECB, RC5_MODE_CBC, RC5_MODE_CTR].iter() {
+            let plaintext = b"twenty bytes of data";
+            let mut ciphertext = [0u8; 32];
+            let mut len = 0;
+            let status = unsafe {
+                rc5_encrypt(
+                    cipher,
+                    mode_id,
+                    iv.as_ptr(),
+                    iv.len(),
+                    plaintext.as_ptr(),
+                    plaintext.len(),
+                    ciphertext.as_mut_ptr(),
+                    ciphertext.len(),
+                    &mut len,
+                )
+            };
+            assert_eq!(status, Rc5Status::Ok);
+            assert_eq!(len, if mode_id == RC5_MODE_CTR { 20 } else { 24 });
+
+            let mut decrypted = [0u8; 32];
+            let mut decrypted_len = 0;
+            let status = unsafe {
+                rc5_decrypt(
+                    cipher,
+                    mode_id,
+                    iv.as_ptr(),
+                    iv.len(),
+                    ciphertext.as_ptr(),
+                    len,
+                    decrypted.as_mut_ptr(),
+                    decrypted.len(),
+                    &mut decrypted_len,
+                )
+            };
+            assert_eq!(status, Rc5Status::Ok);
+            assert_eq!(&decrypted[..decrypted_len], &plaintext[..]);
+        }
+
+        unsafe { rc5_cipher_free(cipher) };
+    }
+
+    #[test]
+    fn failures_are_reported_with_a_status_and_message() {
+        assert_eq!(new_cipher(&[], 32, 12), Err(Rc5Status::EmptyKey));
+        assert_eq!(last_error(), "rc5_cipher_new: key must not be empty");
+        assert_eq!(
+            new_cipher(&[1, 2, 3], 24, 12),
+            Err(Rc5Status::InvalidWordSize)
+        );
+        assert_eq!(
+            new_cipher(&[1, 2, 3], 32, u32::MAX),
+            Err(Rc5Status::InvalidRounds)
+        );
+        assert_eq!(last_error(), "rc5_cipher_new: invalid number of rounds");
+        unsafe { rc5_cipher_free(new_cipher(&[1, 2, 3], 32, 255).unwrap()) };
+
+        #[cfg(not(feature = "rc5-16"))]
+        assert_eq!(
+            new_cipher(&[1, 2, 3], 16, 12),
+            Err(Rc5Status::InvalidWordSize)
+        );
+        #[cfg(not(feature = "rc5-64"))]
+        {
+            assert_eq!(
+                new_cipher(&[1, 2, 3], 64, 12),
+                Err(Rc5Status::InvalidWordSize)
+            );
+            assert_eq!(
+                last_error(),
+                "rc5_cipher_new: unsupported word size of 64 bits"
+            );
+        }
+        #[cfg(feature = "rc5-16")]
+        {
+            let cipher = new_cipher(&[1, 2, 3], 16, 12).unwrap();
+            let mut output = [0u8; 2];
+            let mut len = 0;
+            let status = unsafe {
+                rc5_encrypt(
+                    cipher,
+                    RC5_MODE_ECB,
+                    ptr::null(),
+                    0,
+                    b"abc".as_ptr(),
+                    3,
+                    output.as_mut_ptr(),
+                    output.len(),
+                    &mut len,
+                )
+            };
+            assert_eq!(status, Rc5Status::BufferTooSmall);
+            assert_eq!(len, 4);
+            assert_eq!(
+                last_error(),
+                "rc5_encrypt: the output needs 4 bytes, the buffer has 2"
+            );
+
+            let status = unsafe {
+                rc5_decrypt(
+                    cipher,
+                    RC5_MODE_CBC,
+                    ptr::null(),
+                    0,
+                    b"abcd".as_ptr(),
+                    4,
+                    output.as_mut_ptr(),
+                    output.len(),
+                    &mut len,
+                )
+            };
+            assert_eq!(status, Rc5Status::InvalidIvLength);
+            assert_eq!(
+                last_error(),
+                "rc5_decrypt: invalid initialization vector length"
+            );
+
+            let status = unsafe {
+                rc5_encrypt(
+                    cipher,
+                    7,
+                    ptr::null(),
+                    0,
+                    ptr::null(),
+                    0,
+                    output.as_mut_ptr(),
+                    output.len(),
+                    &mut len,
+                )
+            };
+            assert_eq!(status, Rc5Status::InvalidArgument);
+            assert_eq!(last_error(), "rc5_encrypt: unknown mode 7");
+
+            unsafe { rc5_cipher_free(cipher) };
+        }
+
+        unsafe { rc5_cipher_free(ptr::null_mut()) };
+    }
+
+    #[test]
+    fn status_values_are_fixed_by_the_header() {
+        let statuses = [
+            (Rc5Status::Ok, 0),
+            (Rc5Status::NullPointer, -1),
+            (Rc5Status::InvalidArgument, -2),
+            (Rc5Status::EmptyKey, -3),
+            (Rc5Status::InvalidWordSize, -4),
+            (Rc5Status::InvalidKeyLength, -5),
+            (Rc5Status::InvalidBlockLength, -6),
+            (Rc5Status::InvalidCiphertextLength, -7),
+            (Rc5Status::InvalidIvLength, -8),
+            (Rc5Status::InvalidNonceLength, -9),
+            (Rc5Status::DecryptionFailed, -10),
+            (Rc5Status::BufferTooSmall, -11),
+            (Rc5Status::InvalidSnapshot, -12),
+            (Rc5Status::InvalidEncoding, -13),
+            (Rc5Status::Panic, -14),
+            (Rc5Status::InvalidKdfParameters, -15),
+            (Rc5Status::RngUnavailable, -16),
+            (Rc5Status::UnknownKeyId, -17),
+            (Rc5Status::KeyStoreUnavailable, -18),
+            (Rc5Status::ParametersNotAllowed, -19),
+            (Rc5Status::InvalidUtf8, -20),
+            (Rc5Status::InvalidRounds, -21),
+            (Rc5Status::WrongKey, -22),
+        ];
+        for &(status, value) in statuses.iter() {
+            assert_eq!(status as i32, value, "{:?}", status);
+        }
+        assert_eq!(
+            std::mem::size_of::<Rc5Status>(),
+            std::mem::size_of::<c_int>()
+        );
+
+        // which has the same values in the same order
+        let declared = include_str!("../include/rc5.h")
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("RC5_OK") || line.starts_with("RC5_ERR_"))
+            .map(|line| {
+                let value = line.split('=').nth(1).unwrap();
+                value.trim().trim_end_matches(',').parse::<i32>().unwrap()
+            })
+            .collect::<Vec<i32>>();
+        let values = statuses
+            .iter()
+            .map(|&(_, value)| value)
+            .collect::<Vec<i32>>();
+        assert_eq!(declared, values);
+    }
+
+    #[test]
+    fn the_last_error_follows_the_last_call() {
+        let key = [1u8; 16];
+        assert_eq!(new_cipher(&key, 24, 12), Err(Rc5Status::InvalidWordSize));
+        assert_eq!(
+            last_error(),
+            "rc5_cipher_new: unsupported word size of 24 bits"
+        );
+
+        // a later failure replaces it, a later success clears it
+        assert_eq!(new_cipher(&key, 32, 256), Err(Rc5Status::InvalidRounds));
+        assert_eq!(last_error(), "rc5_cipher_new: invalid number of rounds");
+        let cipher = new_cipher(&key, 32, 12).unwrap();
+        assert!(rc5_last_error().is_null());
+
+        // null out-pointers are refused with their own status
+        let mut output = [0u8; 16];
+        let status = unsafe {
+            rc5_encrypt(
+                cipher,
+                RC5_MODE_ECB,
+                ptr::null(),
+                0,
+                key.as_ptr(),
+                4,
+                output.as_mut_ptr(),
+                output.len(),
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(status, Rc5Status::NullPointer);
+        assert_eq!(last_error(), "rc5_encrypt: `output_len` is null");
+        let status = unsafe { rc5_cipher_new(key.as_ptr(), key.len(), 32, 12, ptr::null_mut()) };
+        assert_eq!(status, Rc5Status::NullPointer);
+        assert_eq!(last_error(), "rc5_cipher_new: `cipher` is null");
+        let mut len = 0;
+        let status = unsafe {
+            rc5_encrypt(
+                cipher,
+                RC5_MODE_ECB,
+                ptr::null(),
+                0,
+                key.as_ptr(),
+                4,
+                ptr::null_mut(),
+                output.len(),
+                &mut len,
+            )
+        };
+        assert_eq!(status, Rc5Status::NullPointer);
+        assert_eq!(last_error(), "rc5_encrypt: `output` is null");
+        unsafe { rc5_cipher_free(cipher) };
+    }
+}
@@ -0,0 +1,420 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use crate::encoding::{decode_base64, encode_base64};
+use crate::rng::CryptoRng;
+use crate::{secure, Eax, Rc5Error, Rc5SecretKey, RC5};
+
+// Field-level encryption, for single values of a config file or a record
+// that is otherwise stored as plain text, e.g. one password among the
+// settings of a TOML file. A field is the base64 of
+//
+//   nonce (one block) | ciphertext | tag (one block)
+//
+// sealed with EAX under RC5-32 and a fresh random nonce, so the same value
+// encrypts differently every time and a modified field is refused. The
+// associated data is the magic "RC5F".
+//
+// An `EncryptedBox` is the binary counterpart for a database column,
+//
+//   version u8 | nonce (one block) | ciphertext | tag (one block)
+//
+// with the associated data "RC5B" | version, also under RC5-32 with 12
+// rounds and a key of any valid length
+const MAGIC: &[u8; 4] = b"RC5F";
+const BOX_MAGIC: &[u8; 4] = b"RC5B";
+const BOX_VERSION: u8 = 1;
+const BOX_ROUNDS: usize = 12;
+
+thread_local! {
+    static INSTALLED: RefCell<Option<FieldCipher>> = const { RefCell::new(None) };
+}
+
+// a value that can be stored in an encrypted field, turned into bytes and
+// back before encryption and after decryption
+pub trait FieldValue: Sized {
+    fn to_field_bytes(&self) -> Vec<u8>;
+    // `bytes` are the decrypted plaintext, wiped by the implementation if
+    // they don't make a value
+    fn from_field_bytes(bytes: Vec<u8>) -> Result<Self, Rc5Error>;
+}
+
+impl FieldValue for Vec<u8> {
+    fn to_field_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn from_field_bytes(bytes: Vec<u8>) -> Result<Self, Rc5Error> {
+        Ok(bytes)
+    }
+}
+
+impl FieldValue for String {
+    fn to_field_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_field_bytes(bytes: Vec<u8>) -> Result<Self, Rc5Error> {
+        String::from_utf8(bytes).map_err(|err| {
+            secure::wipe(&mut err.into_bytes());
+            Rc5Error::InvalidUtf8
+        })
+    }
+}
+
+impl FieldValue for bool {
+    fn to_field_bytes(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+
+    fn from_field_bytes(mut bytes: Vec<u8>) -> Result<Self, Rc5Error> {
+        match bytes[..] {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            _ => {
+                secure::wipe(&mut bytes);
+                Err(Rc5Error::InvalidEncoding)
+            }
+        }
+    }
+}
+
+// integers are stored little-endian at their full width
+macro_rules! impl_field_value {
+    ($($ty:ty),*) => { $(
+        impl FieldValue for $ty {
+            fn to_field_bytes(&self) -> Vec<u8> {
+                self.to_le_bytes().to_vec()
+            }
+
+            fn from_field_bytes(mut bytes: Vec<u8>) -> Result<Self, Rc5Error> {
+                let mut value = [0u8; core::mem::size_of::<$ty>()];
+                if bytes.len() != value.len() {
+                    secure::wipe(&mut bytes);
+                    return Err(Rc5Error::InvalidEncoding);
+                }
+                value.copy_from_slice(&bytes);
+                Ok(<$ty>::from_le_bytes(value))
+            }
+        }
+    )* }
+}
+impl_field_value!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+// encrypts and decrypts fields, either passed to `Encrypted::encrypt` and
+// `Encrypted::decrypt` or installed for the current thread, where the
+// `Display` and `FromStr` impls of `Encrypted` find it
+pub struct FieldCipher {
+    eax: Eax<u32>,
+    rng: Box<dyn CryptoRng>,
+}
+
+impl FieldCipher {
+    // the nonces are drawn from `rng`
+    pub fn new<G: CryptoRng + 'static>(cipher: &RC5<u32>, rng: G) -> Result<Self, Rc5Error> {
+        Ok(FieldCipher {
+            eax: Eax::new(cipher)?,
+            rng: Box::new(rng),
+        })
+    }
+
+    pub fn encrypt<V: FieldValue>(&mut self, value: &V) -> String {
+        encode_base64(&seal(&self.eax, &mut *self.rng, MAGIC, value))
+    }
+
+    // `InvalidEncoding` for text that isn't base64, `DecryptionFailed` for
+    // a field that was modified or encrypted under another key
+    pub fn decrypt<V: FieldValue>(&self, text: &str) -> Result<V, Rc5Error> {
+        open(&self.eax, MAGIC, &decode_base64(text)?)
+    }
+
+    // makes this the cipher of the current thread, returning the one that
+    // was installed before
+    pub fn install(self) -> Option<FieldCipher> {
+        INSTALLED.with(|installed| installed.borrow_mut().replace(self))
+    }
+
+    // removes the cipher of the current thread
+    pub fn uninstall() -> Option<FieldCipher> {
+        INSTALLED.with(|installed| installed.borrow_mut().take())
+    }
+}
+
+// a value that goes into text only encrypted. `Display` encrypts it with
+// the cipher installed for the thread and fails without one, `FromStr`
+// decrypts it again and gives `UnknownKeyId` without one. Both are what
+// text formats without an encryption hook of their own can be fed with
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Encrypted<V>(pub V);
+
+impl<V: FieldValue> Encrypted<V> {
+    pub fn encrypt(&self, cipher: &mut FieldCipher) -> String {
+        cipher.encrypt(&self.0)
+    }
+
+    pub fn decrypt(text: &str, cipher: &FieldCipher) -> Result<Self, Rc5Error> {
+        cipher.decrypt(text).map(Encrypted)
+    }
+}
+
+impl<V> Encrypted<V> {
+    pub fn into_inner(self) -> V {
+        self.0
+    }
+}
+
+// the plaintext never shows up in `Debug` output
+impl<V> fmt::Debug for Encrypted<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Encrypted(..)")
+    }
+}
+
+impl<V: FieldValue> fmt::Display for Encrypted<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let field = INSTALLED.with(|installed| {
+            let mut installed = installed.borrow_mut();
+            installed.as_mut().map(|cipher| self.encrypt(cipher))
+        });
+        f.write_str(&field.ok_or(fmt::Error)?)
+    }
+}
+
+impl<V: FieldValue> FromStr for Encrypted<V> {
+    type Err = Rc5Error;
+
+    fn from_str(text: &str) -> Result<Self, Rc5Error> {
+        INSTALLED.with(|installed| match installed.borrow().as_ref() {
+            Some(cipher) => Encrypted::decrypt(text, cipher),
+            None => Err(Rc5Error::UnknownKeyId),
+        })
+    }
+}
+
+// a value sealed on its own, for small secrets stored as bytes, e.g. in
+// a database. The type of the value is part of the box's type, not of
+// the bytes
+pub struct EncryptedBox<V> {
+    bytes: Vec<u8>,
+    value: PhantomData<fn() -> V>,
+}
+
+impl<V: FieldValue> EncryptedBox<V> {
+    // the nonce is drawn from `rng`
+    pub fn seal<G: CryptoRng>(mut rng: G, value: &V, key: &Rc5SecretKey) -> Result<Self, Rc5Error> {
+        let mut bytes = vec![BOX_VERSION];
+        bytes.extend_from_slice(&seal(&box_eax(key)?, &mut rng, &box_data(), value));
+        Ok(EncryptedBox {
+            bytes,
+            value: PhantomData,
+        })
+    }
+
+    // `DecryptionFailed` for a box that was modified or sealed under
+    // another key
+    pub fn open(&self, key: &Rc5SecretKey) -> Result<V, Rc5Error> {
+        open(&box_eax(key)?, &box_data(), &self.bytes[1..])
+    }
+}
+
+impl<V> EncryptedBox<V> {
+    // a box as stored by `into_bytes`, `InvalidEncoding` if it is of a
+    // version this crate doesn't know
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Rc5Error> {
+        if bytes.first() != Some(&BOX_VERSION) {
+            return Err(Rc5Error::InvalidEncoding);
+        }
+        Ok(EncryptedBox {
+            bytes,
+            value: PhantomData,
+        })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl<V> Clone for EncryptedBox<V> {
+    fn clone(&self) -> Self {
+        EncryptedBox {
+            bytes: self.bytes.clone(),
+            value: PhantomData,
+        }
+    }
+}
+
+impl<V> PartialEq for EncryptedBox<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes == other.bytes
+    }
+}
+
+impl<V> Eq for EncryptedBox<V> {}
+
+impl<V> fmt::Debug for EncryptedBox<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedBox")
+            .field("len", &self.bytes.len())
+            .finish()
+    }
+}
+
+fn box_eax(key: &Rc5SecretKey) -> Result<Eax<u32>, Rc5Error> {
+    Eax::new(&RC5::with_key(key, 4, BOX_ROUNDS, key.len()))
+}
+
+fn box_data() -> [u8; 5] {
+    let mut data = [BOX_VERSION; 5];
+    data[..4].copy_from_slice(BOX_MAGIC);
+    data
+}
+
+// nonce | ciphertext | tag of `value`
+fn seal<V, G>(eax: &Eax<u32>, rng: &mut G, associated: &[u8], value: &V) -> Vec<u8>
+where
+    V: FieldValue,
+    G: CryptoRng + ?Sized,
+{
+    let mut sealed = vec![0u8; eax.tag_len()];
+    rng.fill_bytes(&mut sealed);
+
+    let mut data = value.to_field_bytes();
+    let tag = eax.encrypt_in_place_detached(&sealed, associated, &mut data);
+    sealed.extend_from_slice(&data);
+    sealed.extend_from_slice(&tag);
+    sealed
+}
+
+fn open<V: FieldValue>(eax: &Eax<u32>, associated: &[u8], sealed: &[u8]) -> Result<V, Rc5Error> {
+    let block_len = eax.tag_len();
+    if sealed.len() < 2 * block_len {
+        return Err(Rc5Error::DecryptionFailed);
+    }
+    let (nonce, rest) = sealed.split_at(block_len);
+    let (data, tag) = rest.split_at(rest.len() - block_len);
+
+    let mut plaintext = data.to_vec();
+    eax.decrypt_in_place_detached(nonce, associated, &mut plaintext, tag)?;
+    V::from_field_bytes(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::tests::CountingRng;
+
+    fn field_cipher(byte: u8) -> FieldCipher {
+        let rc5 = RC5::<u32>::new(vec![byte; 16], 4, 12, 16);
+        FieldCipher::new(&rc5, CountingRng(0)).unwrap()
+    }
+
+    #[test]
+    fn fields_round_trip() {
+        let mut cipher = field_cipher(1);
+        let text = Encrypted("hunter2".to_string()).encrypt(&mut cipher);
+        assert_eq!(
+            Encrypted::<String>::decrypt(&text, &cipher).unwrap().0,
+            "hunter2"
+        );
+        // a fresh nonce for every field
+        assert_ne!(cipher.encrypt(&"hunter2".to_string()), text);
+
+        let text = cipher.encrypt(&-5i64);
+        assert_eq!(cipher.decrypt::<i64>(&text), Ok(-5));
+        let text = cipher.encrypt(&true);
+        assert_eq!(cipher.decrypt::<bool>(&text), Ok(true));
+        let text = cipher.encrypt(&Vec::new());
+        assert_eq!(cipher.decrypt::<Vec<u8>>(&text), Ok(Vec::new()));
+
+        // the types have to match
+        assert_eq!(cipher.decrypt::<u32>(&text), Err(Rc5Error::InvalidEncoding));
+        let text = cipher.encrypt(&vec![0xFF]);
+        assert_eq!(cipher.decrypt::<String>(&text), Err(Rc5Error::InvalidUtf8));
+    }
+
+    #[test]
+    fn modified_fields_are_refused() {
+        let mut cipher = field_cipher(1);
+        let text = cipher.encrypt(&"hunter2".to_string());
+        assert_eq!(
+            field_cipher(2).decrypt::<String>(&text),
+            Err(Rc5Error::DecryptionFailed)
+        );
+
+        let mut field = decode_base64(&text).unwrap();
+        field[9] ^= 1;
+        assert_eq!(
+            cipher.decrypt::<String>(&encode_base64(&field)),
+            Err(Rc5Error::DecryptionFailed)
+        );
+        assert_eq!(
+            cipher.decrypt::<String>(&encode_base64(&field[..15])),
+            Err(Rc5Error::DecryptionFailed)
+        );
+        assert_eq!(
+            cipher.decrypt::<String>("not base64!"),
+            Err(Rc5Error::InvalidEncoding)
+        );
+    }
+
+    #[test]
+    fn installed_ciphers_serve_display_and_from_str() {
+        let password = Encrypted("hunter2".to_string());
+        assert_eq!(format!("{:?}", password), "Encrypted(..)");
+        assert!(FieldCipher::uninstall().is_none());
+        assert!(std::fmt::write(&mut String::new(), format_args!("{}", password)).is_err());
+        assert_eq!(
+            "AAAA".parse::<Encrypted<String>>(),
+            Err(Rc5Error::UnknownKeyId)
+        );
+
+        assert!(field_cipher(1).install().is_none());
+        let line = format!("password = \"{}\"", password);
+        let text = line
+            .trim_start_matches("password = \"")
+            .trim_end_matches('"');
+        assert_eq!(text.parse::<Encrypted<String>>().unwrap(), password);
+
+        // other threads don't see the cipher
+        let moved = text.to_string();
+        let result = std::thread::spawn(move || moved.parse::<Encrypted<String>>().is_err());
+        assert!(result.join().unwrap());
+
+        assert!(field_cipher(2).install().is_some());
+        assert!(text.parse::<Encrypted<String>>().is_err());
+        assert!(FieldCipher::uninstall().is_some());
+    }
+
+    #[test]
+    fn boxes_seal_values_for_storage() {
+        let key = Rc5SecretKey::new(vec![7; 16]);
+        let sealed = EncryptedBox::seal(CountingRng(0), &"api-token".to_string(), &key).unwrap();
+        assert_eq!(sealed.as_bytes().len(), 1 + 8 + 9 + 8);
+        assert_eq!(format!("{:?}", sealed), "EncryptedBox { len: 26 }");
+
+        let stored = EncryptedBox::<String>::from_bytes(sealed.clone().into_bytes()).unwrap();
+        assert_eq!(stored, sealed);
+        assert_eq!(stored.open(&key).unwrap(), "api-token");
+
+        let other = Rc5SecretKey::new(vec![8; 10]);
+        assert_eq!(stored.open(&other), Err(Rc5Error::DecryptionFailed));
+        let mut modified = sealed.into_bytes();
+        modified[12] ^= 1;
+        let modified = EncryptedBox::<String>::from_bytes(modified).unwrap();
+        assert_eq!(modified.open(&key), Err(Rc5Error::DecryptionFailed));
+
+        assert!(EncryptedBox::<String>::from_bytes(vec![2; 26]).is_err());
+        assert!(EncryptedBox::<String>::from_bytes(Vec::new()).is_err());
+        let short = EncryptedBox::<String>::from_bytes(vec![1; 9]).unwrap();
+        assert_eq!(short.open(&key), Err(Rc5Error::DecryptionFailed));
+        let empty = Rc5SecretKey::new(Vec::new());
+        assert!(EncryptedBox::seal(CountingRng(0), &1u8, &empty).is_err());
+    }
+}
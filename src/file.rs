@@ -0,0 +1,520 @@
+// Whole-file encryption. `encrypt_file` and `decrypt_file` read the input
+// a window at a time. The `_mmap` variants map it into memory instead,
+// which saves copying it through a read buffer but is only sound while
+// nothing else changes the file: a mapped file that another process
+// truncates faults (SIGBUS) on the next access to the lost pages, and one
+// it rewrites changes under the cipher. Their callers promise that, which
+// is why they are `unsafe`
+#![allow(clippy::missing_safety_doc)]
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::mode::Mode;
+use crate::secure;
+use crate::stream::{StreamDecryptor, StreamEncryptor};
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{Rc5Error, RC5};
+
+// input is processed in windows of this many bytes, a multiple of every
+// supported block length
+const WINDOW_LEN: usize = 1 << 22;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Input {
+    Read,
+    Mapped,
+}
+
+// encrypts the file at `path_in` into `path_out`, reading the input a
+// window at a time. Returns the number of bytes written
+pub fn encrypt_file<T, P, Q>(
+    path_in: P,
+    path_out: Q,
+    cipher: &RC5<T>,
+    mode: Mode,
+) -> io::Result<u64>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    encrypt_file_with_progress(path_in, path_out, cipher, mode, |_, _| {})
+}
+
+// same as `encrypt_file`, calling `progress(bytes_done, bytes_total)` with
+// the number of input bytes consumed after every window
+pub fn encrypt_file_with_progress<T, P, Q, F>(
+    path_in: P,
+    path_out: Q,
+    cipher: &RC5<T>,
+    mode: Mode,
+    progress: F,
+) -> io::Result<u64>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnMut(u64, u64),
+{
+    encrypt(path_in, path_out, cipher, mode, progress, Input::Read)
+}
+
+// `encrypt_file` with the input mapped into memory instead of read
+//
+// nothing may truncate or modify the file at `path_in` until this returns
+pub unsafe fn encrypt_file_mmap<T, P, Q>(
+    path_in: P,
+    path_out: Q,
+    cipher: &RC5<T>,
+    mode: Mode,
+) -> io::Result<u64>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    encrypt_file_mmap_with_progress(path_in, path_out, cipher, mode, |_, _| {})
+}
+
+// `encrypt_file_with_progress` with the input mapped into memory, under
+// the contract of `encrypt_file_mmap`
+pub unsafe fn encrypt_file_mmap_with_progress<T, P, Q, F>(
+    path_in: P,
+    path_out: Q,
+    cipher: &RC5<T>,
+    mode: Mode,
+    progress: F,
+) -> io::Result<u64>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnMut(u64, u64),
+{
+    encrypt(path_in, path_out, cipher, mode, progress, Input::Mapped)
+}
+
+// decrypts the file at `path_in` into `path_out`, see `encrypt_file`
+pub fn decrypt_file<T, P, Q>(
+    path_in: P,
+    path_out: Q,
+    cipher: &RC5<T>,
+    mode: Mode,
+) -> io::Result<u64>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    decrypt_file_with_progress(path_in, path_out, cipher, mode, |_, _| {})
+}
+
+// same as `decrypt_file`, reporting progress like
+// `encrypt_file_with_progress`
+pub fn decrypt_file_with_progress<T, P, Q, F>(
+    path_in: P,
+    path_out: Q,
+    cipher: &RC5<T>,
+    mode: Mode,
+    progress: F,
+) -> io::Result<u64>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnMut(u64, u64),
+{
+    decrypt(path_in, path_out, cipher, mode, progress, Input::Read)
+}
+
+// `decrypt_file` with the input mapped into memory, under the contract of
+// `encrypt_file_mmap`
+pub unsafe fn decrypt_file_mmap<T, P, Q>(
+    path_in: P,
+    path_out: Q,
+    cipher: &RC5<T>,
+    mode: Mode,
+) -> io::Result<u64>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    decrypt_file_mmap_with_progress(path_in, path_out, cipher, mode, |_, _| {})
+}
+
+// `decrypt_file_with_progress` with the input mapped into memory, under
+// the contract of `encrypt_file_mmap`
+pub unsafe fn decrypt_file_mmap_with_progress<T, P, Q, F>(
+    path_in: P,
+    path_out: Q,
+    cipher: &RC5<T>,
+    mode: Mode,
+    progress: F,
+) -> io::Result<u64>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnMut(u64, u64),
+{
+    decrypt(path_in, path_out, cipher, mode, progress, Input::Mapped)
+}
+
+fn encrypt<T, P, Q, F>(
+    path_in: P,
+    path_out: Q,
+    cipher: &RC5<T>,
+    mode: Mode,
+    mut progress: F,
+    how: Input,
+) -> io::Result<u64>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnMut(u64, u64),
+{
+    let mut encryptor = StreamEncryptor::new(cipher, mode).map_err(invalid_input)?;
+
+    let input = File::open(path_in)?;
+    let total = input.metadata()?.len();
+    let mut output = File::create(path_out)?;
+    let mut done = 0u64;
+    let mut written = 0u64;
+
+    for_each_window(&input, how, |window| {
+        let ciphertext = encryptor.update(window);
+        written += ciphertext.len() as u64;
+        output.write_all(&ciphertext)?;
+
+        done += window.len() as u64;
+        progress(done, total);
+        Ok(())
+    })?;
+
+    let ciphertext = encryptor.finalize();
+    written += ciphertext.len() as u64;
+    output.write_all(&ciphertext)?;
+    output.flush()?;
+
+    Ok(written)
+}
+
+fn decrypt<T, P, Q, F>(
+    path_in: P,
+    path_out: Q,
+    cipher: &RC5<T>,
+    mode: Mode,
+    mut progress: F,
+    how: Input,
+) -> io::Result<u64>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnMut(u64, u64),
+{
+    let block_len = cipher.schedule().map_err(invalid_input)?.block_len();
+    mode.check_ciphertext_len(len_of(&path_in)?, block_len)
+        .map_err(invalid_data)?;
+    let mut decryptor = StreamDecryptor::new(cipher, mode).map_err(invalid_input)?;
+
+    let input = File::open(path_in)?;
+    let total = input.metadata()?.len();
+    let mut output = File::create(path_out)?;
+    let mut done = 0u64;
+    let mut written = 0u64;
+
+    for_each_window(&input, how, |window| {
+        let plaintext = decryptor.update(window);
+        written += plaintext.len() as u64;
+        output.write_all(&plaintext)?;
+
+        done += window.len() as u64;
+        progress(done, total);
+        Ok(())
+    })?;
+
+    let plaintext = decryptor.finalize().map_err(invalid_data)?;
+    written += plaintext.len() as u64;
+    output.write_all(&plaintext)?;
+    output.flush()?;
+
+    Ok(written)
+}
+
+// malformed input is rejected before the output file is even created
+fn len_of<P: AsRef<Path>>(path: P) -> io::Result<u64> {
+    Ok(std::fs::metadata(path)?.len())
+}
+
+fn invalid_input(err: Rc5Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+fn invalid_data(err: Rc5Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn for_each_window<F>(input: &File, how: Input, f: F) -> io::Result<()>
+where
+    F: FnMut(&[u8]) -> io::Result<()>,
+{
+    // platforms without a usable mmap binding read the input even when a
+    // mapping was asked for. That includes WASI, where `File` goes through
+    // the WASI fs calls and paths must lie inside a directory the host
+    // preopened (e.g. `wasmtime run --dir .`)
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    if how == Input::Mapped {
+        let map = sys::Mmap::map(input)?;
+        return map.as_slice().chunks(WINDOW_LEN).try_for_each(f);
+    }
+    #[cfg(not(all(unix, target_pointer_width = "64")))]
+    let _ = how;
+
+    read_windows(input, f)
+}
+
+// every window but the last is full, so progress is reported at the same
+// points as for a mapped file. The window is wiped afterwards, it held
+// plaintext one way or the other
+fn read_windows<F>(input: &File, mut f: F) -> io::Result<()>
+where
+    F: FnMut(&[u8]) -> io::Result<()>,
+{
+    let mut window = vec![0u8; WINDOW_LEN];
+    let result = loop {
+        match fill(input, &mut window) {
+            Ok(0) => break Ok(()),
+            Ok(len) => {
+                if let Err(err) = f(&window[..len]) {
+                    break Err(err);
+                }
+                if len < window.len() {
+                    break Ok(());
+                }
+            }
+            Err(err) => break Err(err),
+        }
+    };
+    secure::wipe(&mut window);
+    result
+}
+
+// reads until `window` is full or the input ends
+fn fill(mut input: &File, window: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < window.len() {
+        match input.read(&mut window[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(len)
+}
+
+#[cfg(all(unix, target_pointer_width = "64"))]
+mod sys {
+    use std::fs::File;
+    use std::io;
+    use std::os::raw::{c_int, c_void};
+    use std::os::unix::io::AsRawFd;
+
+    const PROT_READ: c_int = 1;
+    const MAP_PRIVATE: c_int = 2;
+
+    extern "C" {
+        fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: i64,
+        ) -> *mut c_void;
+        fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+
+    // read-only private mapping of a whole file, unmapped on drop
+    pub(super) struct Mmap {
+        ptr: *mut c_void,
+        len: usize,
+    }
+
+    impl Mmap {
+        pub(super) fn map(file: &File) -> io::Result<Self> {
+            let len = file.metadata()?.len() as usize;
+
+            // mapping zero bytes is an error, so empty files get an empty slice
+            if len == 0 {
+                return Ok(Mmap {
+                    ptr: std::ptr::null_mut(),
+                    len,
+                });
+            }
+
+            // SAFETY: the arguments describe a fresh read-only mapping of an
+            // open file descriptor, the result is checked against MAP_FAILED
+            let ptr = unsafe {
+                mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    PROT_READ,
+                    MAP_PRIVATE,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            if ptr as isize == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(Mmap { ptr, len })
+        }
+
+        pub(super) fn as_slice(&self) -> &[u8] {
+            if self.len == 0 {
+                return &[];
+            }
+            // SAFETY: `ptr` points to a live mapping of `len` readable bytes
+            unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+        }
+    }
+
+    impl Drop for Mmap {
+        fn drop(&mut self) {
+            if self.len != 0 {
+                // SAFETY: `ptr`/`len` come from a successful mmap call
+                unsafe {
+                    munmap(self.ptr, self.len);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn cipher() -> RC5<u32> {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        RC5::<u32>::new(key, 4, 12, 16)
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rc5-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let plain = temp_path("file-round-trip.txt");
+        let encrypted = temp_path("file-round-trip.rc5");
+        let decrypted = temp_path("file-round-trip.out");
+
+        let contents = (0..10_000u32).map(|x| x as u8).collect::<Vec<u8>>();
+        fs::write(&plain, &contents).unwrap();
+
+        let mode = Mode::Cbc { iv: vec![7; 8] };
+        let written = encrypt_file(&plain, &encrypted, &cipher(), mode.clone()).unwrap();
+        assert_eq!(written, 10_008);
+        let ciphertext = fs::read(&encrypted).unwrap();
+
+        decrypt_file(&encrypted, &decrypted, &cipher(), mode.clone()).unwrap();
+        assert_eq!(fs::read(&decrypted).unwrap(), contents);
+
+        // the files are only used by this test, mapping them is sound
+        let written =
+            unsafe { encrypt_file_mmap(&plain, &encrypted, &cipher(), mode.clone()) }.unwrap();
+        assert_eq!(written, 10_008);
+        assert_eq!(fs::read(&encrypted).unwrap(), ciphertext);
+        fs::remove_file(&decrypted).unwrap();
+        unsafe { decrypt_file_mmap(&encrypted, &decrypted, &cipher(), mode) }.unwrap();
+        assert_eq!(fs::read(&decrypted).unwrap(), contents);
+
+        for path in [plain, encrypted, decrypted].iter() {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn empty_file_round_trip() {
+        let plain = temp_path("file-empty.txt");
+        let encrypted = temp_path("file-empty.rc5");
+        let decrypted = temp_path("file-empty.out");
+
+        fs::write(&plain, b"").unwrap();
+
+        let written = encrypt_file(&plain, &encrypted, &cipher(), Mode::Ecb).unwrap();
+        assert_eq!(written, 8);
+        decrypt_file(&encrypted, &decrypted, &cipher(), Mode::Ecb).unwrap();
+        assert!(fs::read(&decrypted).unwrap().is_empty());
+
+        let written = unsafe { encrypt_file_mmap(&plain, &encrypted, &cipher(), Mode::Ecb) };
+        assert_eq!(written.unwrap(), 8);
+        unsafe { decrypt_file_mmap(&encrypted, &decrypted, &cipher(), Mode::Ecb) }.unwrap();
+        assert!(fs::read(&decrypted).unwrap().is_empty());
+
+        for path in [plain, encrypted, decrypted].iter() {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn progress_reaches_total() {
+        let plain = temp_path("file-progress.txt");
+        let encrypted = temp_path("file-progress.rc5");
+
+        fs::write(&plain, vec![0u8; WINDOW_LEN + 100]).unwrap();
+        let total = (WINDOW_LEN + 100) as u64;
+
+        let mut reports = Vec::new();
+        encrypt_file_with_progress(&plain, &encrypted, &cipher(), Mode::Ecb, |done, total| {
+            reports.push((done, total))
+        })
+        .unwrap();
+        assert_eq!(reports, vec![(WINDOW_LEN as u64, total), (total, total)]);
+
+        let mut reports = Vec::new();
+        unsafe {
+            encrypt_file_mmap_with_progress(
+                &plain,
+                &encrypted,
+                &cipher(),
+                Mode::Ecb,
+                |done, total| reports.push((done, total)),
+            )
+        }
+        .unwrap();
+        assert_eq!(reports, vec![(WINDOW_LEN as u64, total), (total, total)]);
+
+        for path in [plain, encrypted].iter() {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn truncated_ciphertext_is_rejected_up_front() {
+        let encrypted = temp_path("file-truncated.rc5");
+        let decrypted = temp_path("file-truncated.out");
+
+        for len in [0usize, 7, 17].iter() {
+            fs::write(&encrypted, vec![0u8; *len]).unwrap();
+
+            let err = decrypt_file(&encrypted, &decrypted, &cipher(), Mode::Ecb).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            let err = unsafe { decrypt_file_mmap(&encrypted, &decrypted, &cipher(), Mode::Ecb) };
+            assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidData);
+            assert!(!decrypted.exists());
+        }
+
+        fs::remove_file(&encrypted).unwrap();
+    }
+}
@@ -0,0 +1,927 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{
+    ContainerReader, ContainerWriter, Mode, Padding, Rc5Error, Rc5Params, Rc5SecretKey,
+    StreamDecryptor, StreamEncryptor, DEFAULT_CHUNK_SIZE, MAX_KEY_LEN, RC5,
+};
+
+// Header written before ciphertext so that a file says how to decrypt it,
+// all integers little-endian:
+//
+//   magic "RC5H" | version u8 | word bytes u8 | rounds u8 | key bytes u8 |
+//   mode u8 | flags u8 | [key id u16] | [compression u8] | [padding u8] |
+//   iv length u8 | iv | kdf u8 | kdf fields
+//
+// with the key id present if bit 0 of the flags is set, the compression
+// if bit 1 is (1 for the blocks of `CompressWriter`), the padding of ECB
+// or CBC if bit 2 is (1 ISO 7816-4, 2 ANSI X9.23, 3 zeros, and PKCS#7,
+// which is never written, without the bit), and the kdf fields
+//
+//   0 (raw key):  none
+//   1 (PBKDF2):   iterations u32 | salt length u8 | salt
+//   2 (Argon2id): memory KiB u32 | iterations u32 | lanes u32 |
+//                 salt length u8 | salt
+//
+// Only the magic and the version are fixed: a later version may lay out
+// the rest differently, and readers refuse versions they don't know
+pub const HEADER_MAGIC: &[u8; 4] = b"RC5H";
+pub const HEADER_VERSION: u8 = 1;
+const KEY_ID: u8 = 1;
+const COMPRESSED: u8 = 2;
+const PADDED: u8 = 4;
+const BUFFER_LEN: usize = 1 << 16;
+
+// the mode the ciphertext after a header was written with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CipherMode {
+    Ecb,
+    Cbc,
+    Ctr,
+    Eax,
+}
+
+// "ECB", "CBC", "CTR" or "EAX", read back in any case
+impl fmt::Display for CipherMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CipherMode::Ecb => "ECB",
+            CipherMode::Cbc => "CBC",
+            CipherMode::Ctr => "CTR",
+            CipherMode::Eax => "EAX",
+        })
+    }
+}
+
+impl FromStr for CipherMode {
+    type Err = Rc5Error;
+
+    fn from_str(text: &str) -> Result<Self, Rc5Error> {
+        match text.to_ascii_uppercase().as_str() {
+            "ECB" => Ok(CipherMode::Ecb),
+            "CBC" => Ok(CipherMode::Cbc),
+            "CTR" => Ok(CipherMode::Ctr),
+            "EAX" => Ok(CipherMode::Eax),
+            _ => Err(Rc5Error::InvalidEncoding),
+        }
+    }
+}
+
+// what the plaintext went through before it was encrypted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    // LZ4 blocks, see `CompressWriter`
+    Lz4,
+}
+
+// how the key was derived, with what the derivation needs besides the
+// password. The secret itself is never part of a header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Kdf {
+    // the key is used as is
+    None,
+    Pbkdf2 {
+        iterations: u32,
+        salt: Vec<u8>,
+    },
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+        salt: Vec<u8>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rc5Header {
+    pub params: Rc5Params,
+    pub mode: CipherMode,
+    pub kdf: Kdf,
+    // the CBC IV or the CTR or EAX nonce, one block long, and empty for ECB
+    pub iv: Vec<u8>,
+    // which key to decrypt with, e.g. of a `KeyRing`
+    pub key_id: Option<u16>,
+    pub compression: Compression,
+    // how ECB and CBC padded the last block. The other modes don't pad,
+    // and must leave it at the default
+    pub padding: Padding,
+}
+
+impl Rc5Header {
+    // the encoded header, which can also serve as associated data.
+    // Fails for headers that would not parse again: a bad word size or key
+    // length gives the error `RC5::new` would, more than 255 rounds
+    // `InvalidEncoding`, an IV of the wrong length for the mode
+    // `InvalidIvLength`, a padding for a mode that doesn't pad
+    // `InvalidEncoding`, and an empty or over-long salt or a zero KDF cost
+    // `InvalidKdfParameters`
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Rc5Error> {
+        self.check()?;
+
+        let mut bytes = Vec::with_capacity(32 + self.iv.len());
+        bytes.extend_from_slice(HEADER_MAGIC);
+        bytes.push(HEADER_VERSION);
+        bytes.push(self.params.words as u8);
+        bytes.push(self.params.rounds as u8);
+        bytes.push(self.params.bytes as u8);
+        bytes.push(mode_code(self.mode));
+        let mut flags = 0;
+        if self.key_id.is_some() {
+            flags |= KEY_ID;
+        }
+        if self.compression != Compression::None {
+            flags |= COMPRESSED;
+        }
+        if self.padding != Padding::Pkcs7 {
+            flags |= PADDED;
+        }
+        bytes.push(flags);
+        if let Some(id) = self.key_id {
+            bytes.extend_from_slice(&id.to_le_bytes());
+        }
+        if self.compression == Compression::Lz4 {
+            bytes.push(1);
+        }
+        if self.padding != Padding::Pkcs7 {
+            bytes.push(self.padding.tag());
+        }
+        bytes.push(self.iv.len() as u8);
+        bytes.extend_from_slice(&self.iv);
+
+        match &self.kdf {
+            Kdf::None => bytes.push(0),
+            Kdf::Pbkdf2 { iterations, salt } => {
+                bytes.push(1);
+                bytes.extend_from_slice(&iterations.to_le_bytes());
+                bytes.push(salt.len() as u8);
+                bytes.extend_from_slice(salt);
+            }
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+                salt,
+            } => {
+                bytes.push(2);
+                bytes.extend_from_slice(&memory_kib.to_le_bytes());
+                bytes.extend_from_slice(&iterations.to_le_bytes());
+                bytes.extend_from_slice(&parallelism.to_le_bytes());
+                bytes.push(salt.len() as u8);
+                bytes.extend_from_slice(salt);
+            }
+        }
+        Ok(bytes)
+    }
+
+    // parses the header at the start of `bytes`, and returns it with its
+    // length, where the ciphertext begins. Anything malformed, truncated or
+    // of another version gives `InvalidEncoding`
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), Rc5Error> {
+        let mut rest = bytes;
+        let header = Rc5Header::read_from(&mut rest).map_err(|_| Rc5Error::InvalidEncoding)?;
+        Ok((header, bytes.len() - rest.len()))
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let bytes = self.to_bytes().map_err(invalid_input)?;
+        writer.write_all(&bytes)
+    }
+
+    // reads exactly the header, leaving the reader at the ciphertext.
+    // Malformed headers give `InvalidData` and truncated ones
+    // `UnexpectedEof`
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut fixed = [0u8; 10];
+        reader.read_exact(&mut fixed)?;
+        if &fixed[..4] != HEADER_MAGIC {
+            return Err(invalid_data("not an RC5 header"));
+        }
+        if fixed[4] != HEADER_VERSION {
+            return Err(invalid_data("unsupported header version"));
+        }
+
+        let params = Rc5Params {
+            words: fixed[5] as usize,
+            rounds: fixed[6] as usize,
+            bytes: fixed[7] as usize,
+        };
+        let mode = match fixed[8] {
+            0 => CipherMode::Ecb,
+            1 => CipherMode::Cbc,
+            2 => CipherMode::Ctr,
+            3 => CipherMode::Eax,
+            _ => return Err(invalid_data("unknown cipher mode")),
+        };
+        let flags = fixed[9];
+        if flags & !(KEY_ID | COMPRESSED | PADDED) != 0 {
+            return Err(invalid_data("unknown header flags"));
+        }
+        let key_id = match flags & KEY_ID {
+            0 => None,
+            _ => Some(u16::from_le_bytes(read_array(&mut reader)?)),
+        };
+        let compression = match flags & COMPRESSED {
+            0 => Compression::None,
+            _ => match read_array(&mut reader)? {
+                [1] => Compression::Lz4,
+                _ => return Err(invalid_data("unknown compression")),
+            },
+        };
+        let padding = match flags & PADDED {
+            0 => Padding::Pkcs7,
+            // PKCS#7 is written without the flag, so only one encoding
+            // parses
+            _ => match read_array(&mut reader)? {
+                [tag] if tag != Padding::Pkcs7.tag() => {
+                    Padding::from_tag(tag).ok_or_else(|| invalid_data("unknown padding"))?
+                }
+                _ => return Err(invalid_data("unknown padding")),
+            },
+        };
+        let [iv_len] = read_array(&mut reader)?;
+        let mut iv = vec![0u8; iv_len as usize];
+        reader.read_exact(&mut iv)?;
+
+        let [kdf] = read_array(&mut reader)?;
+        let kdf = match kdf {
+            0 => Kdf::None,
+            1 => Kdf::Pbkdf2 {
+                iterations: u32::from_le_bytes(read_array(&mut reader)?),
+                salt: read_salt(&mut reader)?,
+            },
+            2 => Kdf::Argon2id {
+                memory_kib: u32::from_le_bytes(read_array(&mut reader)?),
+                iterations: u32::from_le_bytes(read_array(&mut reader)?),
+                parallelism: u32::from_le_bytes(read_array(&mut reader)?),
+                salt: read_salt(&mut reader)?,
+            },
+            _ => return Err(invalid_data("unknown key derivation")),
+        };
+
+        let header = Rc5Header {
+            params,
+            mode,
+            kdf,
+            iv,
+            key_id,
+            compression,
+            padding,
+        };
+        header.check().map_err(invalid_data)?;
+        Ok(header)
+    }
+
+    fn check(&self) -> Result<(), Rc5Error> {
+        let Rc5Params {
+            words,
+            rounds,
+            bytes,
+        } = self.params;
+        if ![2, 4, 8].contains(&words) {
+            return Err(Rc5Error::InvalidWordSize);
+        }
+        if rounds > 255 {
+            return Err(Rc5Error::InvalidEncoding);
+        }
+        if bytes == 0 || bytes > MAX_KEY_LEN {
+            return Err(Rc5Error::InvalidKeyLength);
+        }
+
+        let iv_len = match self.mode {
+            CipherMode::Ecb => 0,
+            _ => 2 * words,
+        };
+        if self.iv.len() != iv_len {
+            return Err(Rc5Error::InvalidIvLength);
+        }
+        let padded = matches!(self.mode, CipherMode::Ecb | CipherMode::Cbc);
+        if !padded && self.padding != Padding::Pkcs7 {
+            return Err(Rc5Error::InvalidEncoding);
+        }
+
+        let (cost, salt) = match &self.kdf {
+            Kdf::None => return Ok(()),
+            Kdf::Pbkdf2 { iterations, salt } => (*iterations, salt),
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+                salt,
+            } => ((*memory_kib).min(*iterations).min(*parallelism), salt),
+        };
+        if cost == 0 || salt.is_empty() || salt.len() > 255 {
+            return Err(Rc5Error::InvalidKdfParameters);
+        }
+        Ok(())
+    }
+}
+
+// which parameters a header may configure a reader with, so that a file
+// can't talk it into weak ones. By default RC5-32/12, /16 and /20 and
+// RC5-64/16, /20 and /24 in CBC, CTR or EAX mode, with any padding:
+// ECB, fewer rounds and 16 bit words have to be allowed explicitly
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeaderPolicy {
+    // bytes per word and rounds
+    ciphers: Vec<(usize, usize)>,
+    modes: Vec<CipherMode>,
+}
+
+impl HeaderPolicy {
+    pub fn new() -> Self {
+        HeaderPolicy {
+            ciphers: vec![(4, 12), (4, 16), (4, 20), (8, 16), (8, 20), (8, 24)],
+            modes: vec![CipherMode::Cbc, CipherMode::Ctr, CipherMode::Eax],
+        }
+    }
+
+    // also accepts `words` bytes per word with `rounds` rounds
+    pub fn allow_cipher(mut self, words: usize, rounds: usize) -> Self {
+        if !self.ciphers.contains(&(words, rounds)) {
+            self.ciphers.push((words, rounds));
+        }
+        self
+    }
+
+    pub fn allow_mode(mut self, mode: CipherMode) -> Self {
+        if !self.modes.contains(&mode) {
+            self.modes.push(mode);
+        }
+        self
+    }
+
+    // `ParametersNotAllowed` unless both the cipher and the mode of
+    // `header` are allowed
+    pub fn check(&self, header: &Rc5Header) -> Result<(), Rc5Error> {
+        let cipher = (header.params.words, header.params.rounds);
+        if !self.ciphers.contains(&cipher) || !self.modes.contains(&header.mode) {
+            return Err(Rc5Error::ParametersNotAllowed);
+        }
+        Ok(())
+    }
+}
+
+impl Default for HeaderPolicy {
+    fn default() -> Self {
+        HeaderPolicy::new()
+    }
+}
+
+// writes `header` and then everything `reader` yields encrypted with
+// `key` the way the header says, and returns the number of plaintext
+// bytes. The key is used as is, so the header must record its length and
+// neither a key derivation nor compression. EAX writes a `ContainerWriter`
+// with the header's nonce, the other modes what `StreamEncryptor` does
+pub fn encrypt_with_header<R, W>(
+    header: &Rc5Header,
+    key: &Rc5SecretKey,
+    reader: R,
+    mut writer: W,
+) -> io::Result<u64>
+where
+    R: Read,
+    W: Write,
+{
+    if header.kdf != Kdf::None || header.compression != Compression::None {
+        return Err(invalid_input(Rc5Error::InvalidEncoding));
+    }
+    if header.params.bytes != key.len() {
+        return Err(invalid_input(Rc5Error::InvalidKeyLength));
+    }
+    header.write_to(&mut writer)?;
+    match header.params.words {
+        #[cfg(feature = "rc5-16")]
+        2 => encrypt_body::<u16, _, _>(header, key, reader, writer),
+        4 => encrypt_body::<u32, _, _>(header, key, reader, writer),
+        #[cfg(feature = "rc5-64")]
+        8 => encrypt_body::<u64, _, _>(header, key, reader, writer),
+        _ => Err(invalid_input(Rc5Error::InvalidWordSize)),
+    }
+}
+
+// reads the header at the start of `reader` and decrypts what follows
+// into `writer` with `key`, configured the way the header says, so the
+// reader needs to agree on nothing but the key. Returns the header. A
+// header outside `policy` gives `InvalidData` with `ParametersNotAllowed`
+// before anything is decrypted, one naming a key derivation or
+// compression `Unsupported`, and a key of another length than the header
+// records `InvalidInput`. Only EAX detects modified ciphertext, and like
+// `ContainerReader` writes the plaintext a chunk at a time as each chunk
+// verifies, so after an error whatever reached `writer` must be discarded
+pub fn decrypt_with_header<R, W>(
+    mut reader: R,
+    writer: W,
+    key: &Rc5SecretKey,
+    policy: &HeaderPolicy,
+) -> io::Result<Rc5Header>
+where
+    R: Read,
+    W: Write,
+{
+    let header = Rc5Header::read_from(&mut reader)?;
+    policy.check(&header).map_err(invalid_data)?;
+    if header.kdf != Kdf::None || header.compression != Compression::None {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the header asks for a key derivation or compression, see `open`",
+        ));
+    }
+    if header.params.bytes != key.len() {
+        return Err(invalid_input(Rc5Error::InvalidKeyLength));
+    }
+    match header.params.words {
+        #[cfg(feature = "rc5-16")]
+        2 => decrypt_body::<u16, _, _>(&header, key, reader, writer)?,
+        4 => decrypt_body::<u32, _, _>(&header, key, reader, writer)?,
+        #[cfg(feature = "rc5-64")]
+        8 => decrypt_body::<u64, _, _>(&header, key, reader, writer)?,
+        _ => return Err(invalid_input(Rc5Error::InvalidWordSize)),
+    }
+    Ok(header)
+}
+
+fn encrypt_body<T, R, W>(
+    header: &Rc5Header,
+    key: &Rc5SecretKey,
+    mut reader: R,
+    mut writer: W,
+) -> io::Result<u64>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    R: Read,
+    W: Write,
+{
+    let cipher = header_cipher::<T>(header, key);
+    let mode = match stream_mode(header) {
+        Some(mode) => mode,
+        None => {
+            let mut container =
+                ContainerWriter::new(writer, &cipher, DEFAULT_CHUNK_SIZE, &header.iv)?;
+            let len = io::copy(&mut reader, &mut container)?;
+            container.finish()?;
+            return Ok(len);
+        }
+    };
+
+    let mut encryptor = StreamEncryptor::new(&cipher, mode)
+        .map_err(invalid_input)?
+        .with_padding(header.padding);
+    let mut buffer = vec![0u8; BUFFER_LEN];
+    let mut len = 0;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        len += read as u64;
+        writer.write_all(&encryptor.update(&buffer[..read]))?;
+    }
+    writer.write_all(&encryptor.finalize())?;
+    Ok(len)
+}
+
+fn decrypt_body<T, R, W>(
+    header: &Rc5Header,
+    key: &Rc5SecretKey,
+    mut reader: R,
+    mut writer: W,
+) -> io::Result<()>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    R: Read,
+    W: Write,
+{
+    let cipher = header_cipher::<T>(header, key);
+    let mode = match stream_mode(header) {
+        Some(mode) => mode,
+        None => {
+            io::copy(&mut ContainerReader::new(reader, &cipher)?, &mut writer)?;
+            return Ok(());
+        }
+    };
+
+    let mut decryptor = StreamDecryptor::new(&cipher, mode)
+        .map_err(invalid_input)?
+        .with_padding(header.padding);
+    let mut buffer = vec![0u8; BUFFER_LEN];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all(&decryptor.update(&buffer[..read]))?;
+    }
+    writer.write_all(&decryptor.finalize().map_err(invalid_data)?)
+}
+
+fn header_cipher<T>(header: &Rc5Header, key: &Rc5SecretKey) -> RC5<T>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let Rc5Params {
+        words,
+        rounds,
+        bytes,
+    } = header.params;
+    RC5::with_key(key, words, rounds, bytes)
+}
+
+// `None` for EAX, which is a container rather than a stream
+fn stream_mode(header: &Rc5Header) -> Option<Mode> {
+    match header.mode {
+        CipherMode::Ecb => Some(Mode::Ecb),
+        CipherMode::Cbc => Some(Mode::Cbc {
+            iv: header.iv.clone(),
+        }),
+        CipherMode::Ctr => Some(Mode::Ctr {
+            nonce: header.iv.clone(),
+        }),
+        CipherMode::Eax => None,
+    }
+}
+
+fn mode_code(mode: CipherMode) -> u8 {
+    match mode {
+        CipherMode::Ecb => 0,
+        CipherMode::Cbc => 1,
+        CipherMode::Ctr => 2,
+        CipherMode::Eax => 3,
+    }
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_salt<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let [len] = read_array(reader)?;
+    let mut salt = vec![0u8; len as usize];
+    reader.read_exact(&mut salt)?;
+    Ok(salt)
+}
+
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn invalid_input(err: Rc5Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Rc5Header {
+        Rc5Header {
+            params: Rc5Params {
+                words: 4,
+                rounds: 12,
+                bytes: 16,
+            },
+            mode: CipherMode::Cbc,
+            kdf: Kdf::None,
+            iv: vec![0xAA; 8],
+            key_id: None,
+            compression: Compression::None,
+            padding: Padding::Pkcs7,
+        }
+    }
+
+    #[test]
+    fn headers_round_trip() {
+        let argon2 = Kdf::Argon2id {
+            memory_kib: 1 << 16,
+            iterations: 3,
+            parallelism: 4,
+            salt: vec![7; 16],
+        };
+        let headers = [
+            header(),
+            Rc5Header {
+                mode: CipherMode::Ecb,
+                iv: vec![],
+                key_id: Some(0x0102),
+                ..header()
+            },
+            Rc5Header {
+                mode: CipherMode::Eax,
+                kdf: Kdf::Pbkdf2 {
+                    iterations: 600_000,
+                    salt: vec![1; 8],
+                },
+                ..header()
+            },
+            Rc5Header {
+                params: Rc5Params {
+                    words: 8,
+                    rounds: 255,
+                    bytes: 255,
+                },
+                mode: CipherMode::Ctr,
+                kdf: argon2,
+                iv: vec![3; 16],
+                key_id: Some(9),
+                compression: Compression::Lz4,
+                padding: Padding::Pkcs7,
+            },
+            Rc5Header {
+                compression: Compression::Lz4,
+                ..header()
+            },
+            Rc5Header {
+                mode: CipherMode::Ecb,
+                iv: vec![],
+                key_id: Some(1),
+                compression: Compression::Lz4,
+                padding: Padding::AnsiX923,
+                ..header()
+            },
+        ];
+
+        for header in headers.iter() {
+            let mut bytes = header.to_bytes().unwrap();
+            let len = bytes.len();
+            bytes.extend_from_slice(b"ciphertext");
+            assert_eq!(Rc5Header::from_bytes(&bytes), Ok((header.clone(), len)));
+
+            let mut reader = &bytes[..];
+            assert_eq!(&Rc5Header::read_from(&mut reader).unwrap(), header);
+            assert_eq!(reader, b"ciphertext");
+        }
+    }
+
+    #[test]
+    fn the_layout_is_fixed() {
+        let header = Rc5Header {
+            key_id: Some(0x0201),
+            ..header()
+        };
+        let mut expected = b"RC5H\x01\x04\x0c\x10\x01\x01\x01\x02\x08".to_vec();
+        expected.extend_from_slice(&[0xAA; 8]);
+        expected.push(0);
+        assert_eq!(header.to_bytes().unwrap(), expected);
+
+        let compressed = Rc5Header {
+            compression: Compression::Lz4,
+            ..self::header()
+        };
+        let mut expected = b"RC5H\x01\x04\x0c\x10\x01\x02\x01\x08".to_vec();
+        expected.extend_from_slice(&[0xAA; 8]);
+        expected.push(0);
+        assert_eq!(compressed.to_bytes().unwrap(), expected);
+
+        let padded = Rc5Header {
+            padding: Padding::Iso7816,
+            ..self::header()
+        };
+        let mut expected = b"RC5H\x01\x04\x0c\x10\x01\x04\x01\x08".to_vec();
+        expected.extend_from_slice(&[0xAA; 8]);
+        expected.push(0);
+        assert_eq!(padded.to_bytes().unwrap(), expected);
+    }
+
+    #[test]
+    fn malformed_headers_are_refused() {
+        let bytes = header().to_bytes().unwrap();
+        for len in 0..bytes.len() {
+            assert_eq!(
+                Rc5Header::from_bytes(&bytes[..len]),
+                Err(Rc5Error::InvalidEncoding)
+            );
+        }
+        assert_eq!(
+            Rc5Header::read_from(&bytes[..bytes.len() - 1])
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+
+        // the magic, a future version, and fields out of range
+        for (at, value) in [(0, b'X'), (4, 2), (5, 3), (7, 0), (8, 4), (9, 2), (10, 7)].iter() {
+            let mut changed = bytes.clone();
+            changed[*at] = *value;
+            assert_eq!(
+                Rc5Header::from_bytes(&changed),
+                Err(Rc5Error::InvalidEncoding),
+                "{}",
+                at
+            );
+        }
+        // flags announcing an unknown compression
+        let mut unknown_compression = bytes.clone();
+        unknown_compression[9] = COMPRESSED;
+        unknown_compression.insert(10, 2);
+        assert_eq!(
+            Rc5Header::from_bytes(&unknown_compression),
+            Err(Rc5Error::InvalidEncoding)
+        );
+        // an unknown padding, and PKCS#7 written out
+        for tag in [4, 0].iter() {
+            let mut padding = bytes.clone();
+            padding[9] = PADDED;
+            padding.insert(10, *tag);
+            assert_eq!(
+                Rc5Header::from_bytes(&padding),
+                Err(Rc5Error::InvalidEncoding)
+            );
+        }
+        let mut unknown_kdf = bytes.clone();
+        *unknown_kdf.last_mut().unwrap() = 3;
+        assert_eq!(
+            Rc5Header::from_bytes(&unknown_kdf),
+            Err(Rc5Error::InvalidEncoding)
+        );
+    }
+
+    #[test]
+    fn unencodable_headers_are_refused() {
+        let ecb_with_iv = Rc5Header {
+            mode: CipherMode::Ecb,
+            ..header()
+        };
+        assert_eq!(ecb_with_iv.to_bytes(), Err(Rc5Error::InvalidIvLength));
+        let padded_ctr = Rc5Header {
+            mode: CipherMode::Ctr,
+            padding: Padding::Zero,
+            ..header()
+        };
+        assert_eq!(padded_ctr.to_bytes(), Err(Rc5Error::InvalidEncoding));
+
+        let mut bad_words = header();
+        bad_words.params.words = 3;
+        assert_eq!(bad_words.to_bytes(), Err(Rc5Error::InvalidWordSize));
+
+        let no_salt = Rc5Header {
+            kdf: Kdf::Pbkdf2 {
+                iterations: 1,
+                salt: vec![],
+            },
+            ..header()
+        };
+        assert_eq!(no_salt.to_bytes(), Err(Rc5Error::InvalidKdfParameters));
+        assert_eq!(
+            no_salt.write_to(Vec::new()).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[cfg(feature = "rc5-64")]
+    #[test]
+    fn decryption_is_configured_by_the_header() {
+        let key = || Rc5SecretKey::new((0u8..16).collect());
+        let plaintext = (0..1000).map(|x| x as u8).collect::<Vec<u8>>();
+        let headers = [
+            header(),
+            Rc5Header {
+                params: Rc5Params {
+                    words: 8,
+                    rounds: 20,
+                    bytes: 16,
+                },
+                mode: CipherMode::Cbc,
+                iv: vec![1; 16],
+                padding: Padding::Iso7816,
+                ..header()
+            },
+            Rc5Header {
+                mode: CipherMode::Ctr,
+                ..header()
+            },
+            Rc5Header {
+                mode: CipherMode::Eax,
+                ..header()
+            },
+        ];
+        for header in headers.iter() {
+            let mut ciphertext = Vec::new();
+            assert_eq!(
+                encrypt_with_header(header, &key(), &plaintext[..], &mut ciphertext).unwrap(),
+                1000
+            );
+            let mut decrypted = Vec::new();
+            let read = decrypt_with_header(
+                &ciphertext[..],
+                &mut decrypted,
+                &key(),
+                &HeaderPolicy::new(),
+            )
+            .unwrap();
+            assert_eq!(read, *header);
+            assert_eq!(decrypted, plaintext, "{:?}", header.mode);
+        }
+
+        // it is the same ciphertext the modes give without a header
+        let mut ciphertext = Vec::new();
+        encrypt_with_header(&header(), &key(), &b"attack at dawn"[..], &mut ciphertext).unwrap();
+        let mut encryptor = StreamEncryptor::new(
+            &RC5::<u32>::new((0u8..16).collect::<Vec<u8>>(), 4, 12, 16),
+            Mode::Cbc { iv: vec![0xAA; 8] },
+        )
+        .unwrap();
+        let mut expected = header().to_bytes().unwrap();
+        expected.extend_from_slice(&encryptor.update(b"attack at dawn"));
+        expected.extend_from_slice(&encryptor.finalize());
+        assert_eq!(ciphertext, expected);
+
+        // a key of another length than the header records
+        let short = Rc5SecretKey::new(vec![1; 8]);
+        assert_eq!(
+            encrypt_with_header(&header(), &short, &b""[..], Vec::new())
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[cfg(not(feature = "rc5-64"))]
+    #[test]
+    fn word_sizes_left_out_of_the_build_are_refused() {
+        let key = Rc5SecretKey::new((0u8..16).collect());
+        let rc5_64 = Rc5Header {
+            params: Rc5Params {
+                words: 8,
+                rounds: 20,
+                bytes: 16,
+            },
+            iv: vec![1; 16],
+            ..header()
+        };
+        let word_size = |err: io::Error| {
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            err.get_ref()
+                .and_then(|inner| inner.downcast_ref::<Rc5Error>())
+                .copied()
+        };
+
+        let err = encrypt_with_header(&rc5_64, &key, &b"data"[..], Vec::new()).unwrap_err();
+        assert_eq!(word_size(err), Some(Rc5Error::InvalidWordSize));
+        // the header itself is still well formed
+        let mut ciphertext = rc5_64.to_bytes().unwrap();
+        ciphertext.extend_from_slice(&[0; 16]);
+        let err = decrypt_with_header(&ciphertext[..], Vec::new(), &key, &HeaderPolicy::new())
+            .unwrap_err();
+        assert_eq!(word_size(err), Some(Rc5Error::InvalidWordSize));
+    }
+
+    #[cfg(feature = "rc5-16")]
+    #[test]
+    fn headers_outside_the_policy_are_refused() {
+        let key = Rc5SecretKey::new(vec![7; 16]);
+        let weak = [
+            Rc5Header {
+                params: Rc5Params {
+                    words: 4,
+                    rounds: 8,
+                    bytes: 16,
+                },
+                ..header()
+            },
+            Rc5Header {
+                params: Rc5Params {
+                    words: 2,
+                    rounds: 12,
+                    bytes: 16,
+                },
+                iv: vec![0; 4],
+                ..header()
+            },
+            Rc5Header {
+                mode: CipherMode::Ecb,
+                iv: vec![],
+                ..header()
+            },
+        ];
+        for header in weak.iter() {
+            let mut ciphertext = Vec::new();
+            encrypt_with_header(header, &key, &b"data"[..], &mut ciphertext).unwrap();
+
+            let err = decrypt_with_header(&ciphertext[..], Vec::new(), &key, &HeaderPolicy::new())
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(
+                err.into_inner()
+                    .unwrap()
+                    .downcast::<Rc5Error>()
+                    .ok()
+                    .map(|err| *err),
+                Some(Rc5Error::ParametersNotAllowed)
+            );
+
+            let policy = HeaderPolicy::new()
+                .allow_cipher(header.params.words, header.params.rounds)
+                .allow_mode(header.mode);
+            let mut decrypted = Vec::new();
+            decrypt_with_header(&ciphertext[..], &mut decrypted, &key, &policy).unwrap();
+            assert_eq!(decrypted, b"data");
+        }
+    }
+}
@@ -0,0 +1,397 @@
+use core::fmt;
+
+use crate::traits::{Block, CipherMagicConstants, Unsigned16To64};
+use crate::{secure, Rc5Error};
+
+// the longest key RC5 defines, 255 bytes
+pub const MAX_KEY_LEN: usize = 255;
+
+// number of words in the S table for `rounds` rounds, the second
+// parameter of `HeaplessRc5`
+pub const fn table_len(rounds: usize) -> usize {
+    2 * (rounds + 1)
+}
+
+// RC5 without an allocator: the S table is an array sized by the const
+// parameter (see `table_len`), every operation works in place on slices,
+// and this module only uses `core`, so it is all that's left of the crate
+// without the `std` feature. The table is wiped on drop
+pub struct HeaplessRc5<T: Unsigned16To64, const TABLE_LEN: usize> {
+    s_table: [T; TABLE_LEN],
+}
+
+impl<T, const TABLE_LEN: usize> HeaplessRc5<T, TABLE_LEN>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    // checked at compile time for each table length used
+    const ROUNDS: usize = {
+        assert!(
+            TABLE_LEN >= 2 && TABLE_LEN.is_multiple_of(2),
+            "not a table length"
+        );
+        TABLE_LEN / 2 - 1
+    };
+
+    pub fn new(key: &[u8]) -> Result<Self, Rc5Error> {
+        if key.is_empty() {
+            return Err(Rc5Error::EmptyKey);
+        }
+        if key.len() > MAX_KEY_LEN {
+            return Err(Rc5Error::InvalidKeyLength);
+        }
+
+        let mut l = T::key_words();
+        let l = &mut l.as_mut()[..key.len().div_ceil(core::mem::size_of::<T>())];
+        load_key(key, l);
+
+        let mut s_table = [T::zero(); TABLE_LEN];
+        mix_key(l, &mut s_table);
+        secure::release(l);
+
+        Ok(HeaplessRc5 { s_table })
+    }
+
+    pub fn rounds(&self) -> usize {
+        Self::ROUNDS
+    }
+
+    pub fn encrypt_block(&self, block: &mut Block<T>) {
+        encrypt_block(&self.s_table, Self::ROUNDS, block.as_mut());
+    }
+
+    pub fn decrypt_block(&self, block: &mut Block<T>) {
+        decrypt_block(&self.s_table, Self::ROUNDS, block.as_mut());
+    }
+
+    // ECB over `data`, which must be a whole number of blocks
+    pub fn encrypt_blocks(&self, data: &mut [u8]) -> Result<(), Rc5Error> {
+        let block_len = 2 * core::mem::size_of::<T>();
+        if !data.len().is_multiple_of(block_len) {
+            return Err(Rc5Error::InvalidBlockLength);
+        }
+
+        for block in data.chunks_exact_mut(block_len) {
+            encrypt_block(&self.s_table, Self::ROUNDS, block);
+        }
+        Ok(())
+    }
+
+    pub fn decrypt_blocks(&self, data: &mut [u8]) -> Result<(), Rc5Error> {
+        let block_len = 2 * core::mem::size_of::<T>();
+        if !data.len().is_multiple_of(block_len) {
+            return Err(Rc5Error::InvalidBlockLength);
+        }
+
+        for block in data.chunks_exact_mut(block_len) {
+            decrypt_block(&self.s_table, Self::ROUNDS, block);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Unsigned16To64, const TABLE_LEN: usize> fmt::Debug for HeaplessRc5<T, TABLE_LEN> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeaplessRc5")
+            .field("table_len", &TABLE_LEN)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Unsigned16To64, const TABLE_LEN: usize> Drop for HeaplessRc5<T, TABLE_LEN> {
+    fn drop(&mut self) {
+        secure::release(&mut self.s_table);
+    }
+}
+
+// CTR mode over a `HeaplessRc5`, producing the same keystream as
+// `Mode::Ctr` with the same nonce
+pub struct HeaplessCtr<'a, T: Unsigned16To64, const TABLE_LEN: usize> {
+    cipher: &'a HeaplessRc5<T, TABLE_LEN>,
+    counter: Block<T>,
+    keystream: Block<T>,
+    // bytes of `keystream` already used
+    position: usize,
+}
+
+impl<'a, T, const TABLE_LEN: usize> HeaplessCtr<'a, T, TABLE_LEN>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    pub fn new(cipher: &'a HeaplessRc5<T, TABLE_LEN>, nonce: &Block<T>) -> Self {
+        let keystream = Block::<T>::default();
+        HeaplessCtr {
+            cipher,
+            counter: *nonce,
+            position: keystream.as_ref().len(),
+            keystream,
+        }
+    }
+
+    pub fn apply_keystream(&mut self, data: &mut [u8]) {
+        for byte in data.iter_mut() {
+            if self.position == self.keystream.as_ref().len() {
+                self.keystream = self.counter;
+                self.cipher.encrypt_block(&mut self.keystream);
+                increment_counter(self.counter.as_mut());
+                self.position = 0;
+            }
+
+            *byte ^= self.keystream.as_ref()[self.position];
+            self.position += 1;
+        }
+    }
+}
+
+impl<T: Unsigned16To64, const TABLE_LEN: usize> Drop for HeaplessCtr<'_, T, TABLE_LEN> {
+    fn drop(&mut self) {
+        secure::wipe(self.keystream.as_mut());
+    }
+}
+
+// the key is loaded into c = ceil(b / u) little-endian words, the last one
+// zero padded if the key length is not a multiple of the word size
+pub(crate) fn load_key<T: Unsigned16To64>(key: &[u8], l: &mut [T]) {
+    let words = core::mem::size_of::<T>();
+    for (word, chunk) in l.iter_mut().zip(key.chunks(words)) {
+        let mut bytes = T::zero().to_le_bytes();
+        bytes.as_mut()[..chunk.len()].copy_from_slice(chunk);
+        *word = T::from_le_bytes(bytes);
+    }
+}
+
+// fills `s_table` from the magic constants and mixes the key words `l`
+// into it, leaving `l` scrambled but still secret
+pub(crate) fn mix_key<T>(l: &mut [T], s_table: &mut [T])
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    fill_table(s_table);
+    mix_into_table(l, s_table, |_, _, _, _| {});
+}
+
+// the table before any key is mixed in: P_w, then Q_w added on each step
+pub(crate) fn fill_table<T>(s_table: &mut [T])
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let p_w = T::P_W; // first magic number
+    let q_w = T::Q_W; // second magic number
+
+    for (x, word) in s_table.iter_mut().enumerate() {
+        *word = T::from_usize(x).wrapping_mul(q_w).wrapping_add(p_w);
+    }
+}
+
+// mixes the key words `l` into a filled `s_table`, calling `step` with
+// (i, j, A, B) after each of the 3 * max(t, c) iterations
+pub(crate) fn mix_into_table<T, F>(l: &mut [T], s_table: &mut [T], mut step: F)
+where
+    T: Unsigned16To64 + Copy,
+    F: FnMut(usize, usize, T, T),
+{
+    let mut i = 0;
+    let mut j = 0;
+
+    let mut a_block = T::zero();
+    let mut b_block = T::zero();
+
+    let l_len = l.len();
+    let s_len = s_table.len();
+
+    let max_iters = core::cmp::max(s_len, l_len);
+
+    for _ in 0..(3 * max_iters) {
+        a_block = s_table[i]
+            .wrapping_add(a_block)
+            .wrapping_add(b_block)
+            .rotate_left(T::from_usize(3));
+        b_block = (l[j].wrapping_add(a_block).wrapping_add(b_block))
+            .rotate_left(a_block.wrapping_add(b_block));
+
+        s_table[i] = a_block;
+        l[j] = b_block;
+        step(i, j, a_block, b_block);
+
+        i = (i + 1) % s_len;
+        j = (j + 1) % l_len;
+    }
+}
+
+// encrypts a single block of two words in place
+pub(crate) fn encrypt_block<T>(s_table: &[T], rounds: usize, block: &mut [u8])
+where
+    T: Unsigned16To64 + Copy,
+{
+    let words = block.len() / 2;
+
+    let mut a_block = T::copy_from_slice(block, 0usize, words);
+    let mut a_from_le_bytes = T::from_le_bytes(a_block);
+
+    let mut b_block = T::copy_from_slice(block, words, block.len());
+    let mut b_from_le_bytes = T::from_le_bytes(b_block);
+
+    // initialize encryption of blocks A and B
+    a_from_le_bytes = a_from_le_bytes.wrapping_add(s_table[0]);
+    b_from_le_bytes = b_from_le_bytes.wrapping_add(s_table[1]);
+
+    // the algorithm uses ROUNDS iterations, but it starts with a zeroth evaluation first
+    for i in 1..(rounds + 1) {
+        a_from_le_bytes = (a_from_le_bytes.xor(b_from_le_bytes))
+            .rotate_left(b_from_le_bytes)
+            .wrapping_add(s_table[2 * i]);
+        b_from_le_bytes = (b_from_le_bytes.xor(a_from_le_bytes))
+            .rotate_left(a_from_le_bytes)
+            .wrapping_add(s_table[2 * i + 1]);
+    }
+
+    a_block = a_from_le_bytes.to_le_bytes();
+    b_block = b_from_le_bytes.to_le_bytes();
+
+    block[..words].copy_from_slice(a_block.as_ref());
+    block[words..].copy_from_slice(b_block.as_ref());
+}
+
+// decrypts a single block of two words in place
+pub(crate) fn decrypt_block<T>(s_table: &[T], rounds: usize, block: &mut [u8])
+where
+    T: Unsigned16To64 + Copy,
+{
+    let words = block.len() / 2;
+
+    let mut a_block = T::copy_from_slice(block, 0usize, words);
+    let mut a_from_le_bytes = T::from_le_bytes(a_block);
+
+    let mut b_block = T::copy_from_slice(block, words, block.len());
+    let mut b_from_le_bytes = T::from_le_bytes(b_block);
+
+    // the algorithm uses ROUND iterations, but it starts with a zeroth evaluation first
+    for i in (1..(rounds + 1)).rev() {
+        b_from_le_bytes = b_from_le_bytes
+            .wrapping_sub(s_table[2 * i + 1])
+            .rotate_right(a_from_le_bytes)
+            .xor(a_from_le_bytes);
+        a_from_le_bytes = a_from_le_bytes
+            .wrapping_sub(s_table[2 * i])
+            .rotate_right(b_from_le_bytes)
+            .xor(b_from_le_bytes);
+    }
+
+    // last iteration
+    a_from_le_bytes = a_from_le_bytes.wrapping_sub(s_table[0]);
+    b_from_le_bytes = b_from_le_bytes.wrapping_sub(s_table[1]);
+
+    a_block = a_from_le_bytes.to_le_bytes();
+    b_block = b_from_le_bytes.to_le_bytes();
+
+    block[..words].copy_from_slice(a_block.as_ref());
+    block[words..].copy_from_slice(b_block.as_ref());
+}
+
+// the counter block is treated as one big-endian integer
+pub(crate) fn increment_counter(counter: &mut [u8]) {
+    for byte in counter.iter_mut().rev() {
+        *byte = byte.wrapping_add(1);
+        if *byte != 0 {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(all(feature = "std", feature = "rc5-64"))]
+    use crate::{Mode, Rc5Encryptor, StreamEncryptor, RC5};
+
+    type Rc5_32_12 = HeaplessRc5<u32, { table_len(12) }>;
+
+    #[test]
+    fn matches_known_answers() {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let cipher = Rc5_32_12::new(&key).unwrap();
+        assert_eq!(cipher.rounds(), 12);
+
+        let mut block = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        cipher.encrypt_block(&mut block);
+        assert_eq!(block, [0x2D, 0xDC, 0x14, 0x9B, 0xCF, 0x08, 0x8B, 0x9E]);
+        cipher.decrypt_block(&mut block);
+        assert_eq!(block, [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77]);
+
+        #[cfg(feature = "rc5-16")]
+        {
+            let cipher = HeaplessRc5::<u16, { table_len(16) }>::new(&key[..8]).unwrap();
+            let mut block = [0x00, 0x01, 0x02, 0x03];
+            cipher.encrypt_block(&mut block);
+            assert_eq!(block, [0x23, 0xA8, 0xD7, 0x2E]);
+        }
+    }
+
+    #[cfg(all(feature = "std", feature = "rc5-64"))]
+    #[test]
+    fn matches_allocating_api() {
+        let key = (0u8..20).collect::<Vec<u8>>();
+        let heapless = HeaplessRc5::<u64, { table_len(16) }>::new(&key).unwrap();
+        let rc5 = RC5::<u64>::new(key, 8, 16, 20);
+
+        let mut data = (0..64u8).collect::<Vec<u8>>();
+        let expected = data
+            .chunks(16)
+            .flat_map(|block| rc5.encode(block).unwrap())
+            .collect::<Vec<u8>>();
+        heapless.encrypt_blocks(&mut data).unwrap();
+        assert_eq!(data, expected);
+        assert!(heapless.encrypt_blocks(&mut data[..15]).is_err());
+
+        let nonce = [0xA5; 16];
+        let plaintext = (0..45u8).collect::<Vec<u8>>();
+        let mut encryptor = StreamEncryptor::new(
+            &rc5,
+            Mode::Ctr {
+                nonce: nonce.to_vec(),
+            },
+        )
+        .unwrap();
+        let mut expected = encryptor.update(&plaintext);
+        expected.extend(encryptor.finalize());
+
+        let mut ctr = HeaplessCtr::new(&heapless, &nonce);
+        let mut data = plaintext;
+        let (first, rest) = data.split_at_mut(7);
+        ctr.apply_keystream(first);
+        ctr.apply_keystream(rest);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn key_length_is_bounded() {
+        assert_eq!(Rc5_32_12::new(&[]).unwrap_err(), Rc5Error::EmptyKey);
+        assert!(Rc5_32_12::new(&[0; MAX_KEY_LEN]).is_ok());
+        assert_eq!(
+            Rc5_32_12::new(&[0; MAX_KEY_LEN + 1]).unwrap_err(),
+            Rc5Error::InvalidKeyLength
+        );
+
+        // the longest key takes a word per byte with 8-bit words
+        let mut block = [1, 2];
+        HeaplessRc5::<u8, { table_len(12) }>::new(&[0xA5; MAX_KEY_LEN])
+            .unwrap()
+            .encrypt_block(&mut block);
+        assert_ne!(block, [1, 2]);
+
+        // and only a word per 8 bytes with 64-bit ones
+        assert_eq!(
+            core::mem::size_of::<<u8 as Unsigned16To64>::KeyWords>(),
+            255
+        );
+        assert_eq!(
+            core::mem::size_of::<<u32 as Unsigned16To64>::KeyWords>(),
+            256
+        );
+        #[cfg(feature = "rc5-64")]
+        assert_eq!(
+            core::mem::size_of::<<u64 as Unsigned16To64>::KeyWords>(),
+            256
+        );
+    }
+}
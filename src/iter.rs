@@ -0,0 +1,263 @@
+use crate::mode::{self, Mode};
+use crate::padding::Padding;
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{KeySchedule, Rc5Error, RC5};
+
+// lazily encrypts the bytes of an iterator, yielding one ciphertext block
+// at a time. The last block carries the padding for ECB/CBC, or is the
+// (possibly shorter) tail of the keystream for CTR
+pub struct Rc5EncryptIter<T: Unsigned16To64, I> {
+    bytes: I,
+    schedule: KeySchedule<T>,
+    mode: Mode,
+    padding: Padding,
+    chain: Vec<u8>,
+    done: bool,
+}
+
+// lazily decrypts the bytes of an iterator, yielding one plaintext block at
+// a time. For padded modes one block is held back until the end of the
+// input is reached, so that the padding can be stripped from it
+pub struct Rc5DecryptIter<T: Unsigned16To64, I> {
+    bytes: I,
+    schedule: KeySchedule<T>,
+    mode: Mode,
+    padding: Padding,
+    chain: Vec<u8>,
+    pending: Option<Vec<u8>>,
+    done: bool,
+}
+
+pub trait Rc5IterExt: Iterator<Item = u8> + Sized {
+    fn rc5_encrypt<T>(
+        self,
+        cipher: &RC5<T>,
+        mode: Mode,
+    ) -> Result<Rc5EncryptIter<T, Self>, Rc5Error>
+    where
+        T: Unsigned16To64 + CipherMagicConstants + Copy,
+    {
+        let schedule = cipher.key_schedule()?;
+        let chain = mode.initial_chain(schedule.block_len())?;
+
+        Ok(Rc5EncryptIter {
+            bytes: self,
+            schedule,
+            mode,
+            padding: Padding::default(),
+            chain,
+            done: false,
+        })
+    }
+
+    fn rc5_decrypt<T>(
+        self,
+        cipher: &RC5<T>,
+        mode: Mode,
+    ) -> Result<Rc5DecryptIter<T, Self>, Rc5Error>
+    where
+        T: Unsigned16To64 + CipherMagicConstants + Copy,
+    {
+        let schedule = cipher.key_schedule()?;
+        let chain = mode.initial_chain(schedule.block_len())?;
+
+        Ok(Rc5DecryptIter {
+            bytes: self,
+            schedule,
+            mode,
+            padding: Padding::default(),
+            chain,
+            pending: None,
+            done: false,
+        })
+    }
+}
+
+impl<I: Iterator<Item = u8>> Rc5IterExt for I {}
+
+impl<T: Unsigned16To64, I> Rc5EncryptIter<T, I> {
+    // pads the last block with `padding` instead of PKCS#7, has no effect
+    // in CTR mode
+    pub fn with_padding(mut self, padding: Padding) -> Self {
+        self.padding = padding;
+        self
+    }
+}
+
+impl<T: Unsigned16To64, I> Rc5DecryptIter<T, I> {
+    // see `Rc5EncryptIter::with_padding`
+    pub fn with_padding(mut self, padding: Padding) -> Self {
+        self.padding = padding;
+        self
+    }
+}
+
+// pulls up to `len` bytes out of the iterator
+fn next_block<I: Iterator<Item = u8>>(bytes: &mut I, len: usize) -> Vec<u8> {
+    bytes.by_ref().take(len).collect()
+}
+
+impl<T, I> Iterator for Rc5EncryptIter<T, I>
+where
+    T: Unsigned16To64 + Copy,
+    I: Iterator<Item = u8>,
+{
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let block_len = self.schedule.block_len();
+        let mut block = next_block(&mut self.bytes, block_len);
+
+        if block.len() == block_len {
+            mode::encrypt_blocks(&self.schedule, &self.mode, &mut self.chain, &mut block);
+            return Some(block);
+        }
+
+        self.done = true;
+        if self.mode.is_padded() {
+            self.padding.pad(&mut block, block_len);
+            mode::encrypt_blocks(&self.schedule, &self.mode, &mut self.chain, &mut block);
+        } else if block.is_empty() {
+            return None;
+        } else {
+            mode::apply_keystream(&self.schedule, &mut self.chain, &mut block);
+        }
+
+        Some(block)
+    }
+}
+
+impl<T, I> Iterator for Rc5DecryptIter<T, I>
+where
+    T: Unsigned16To64 + Copy,
+    I: Iterator<Item = u8>,
+{
+    type Item = Result<Vec<u8>, Rc5Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let block_len = self.schedule.block_len();
+
+        while !self.done {
+            let mut block = next_block(&mut self.bytes, block_len);
+
+            if !self.mode.is_padded() {
+                if block.len() < block_len {
+                    self.done = true;
+                }
+                if block.is_empty() {
+                    return None;
+                }
+                if block.len() == block_len {
+                    mode::decrypt_blocks(&self.schedule, &self.mode, &mut self.chain, &mut block);
+                } else {
+                    mode::apply_keystream(&self.schedule, &mut self.chain, &mut block);
+                }
+                return Some(Ok(block));
+            }
+
+            if block.len() == block_len {
+                mode::decrypt_blocks(&self.schedule, &self.mode, &mut self.chain, &mut block);
+                if let Some(previous) = self.pending.replace(block) {
+                    return Some(Ok(previous));
+                }
+                continue;
+            }
+
+            // truncated input still goes through unpadding, so that it
+            // fails the same way as bad padding
+            self.done = true;
+            if block.is_empty() && self.pending.is_none() && !self.padding.always_pads() {
+                return None;
+            }
+            let complete = block.is_empty() && self.pending.is_some();
+            let mut last = self.pending.take().unwrap_or_else(|| vec![0u8; block_len]);
+            let unpadded = self.padding.unpad(&mut last, block_len);
+            if !complete || unpadded.is_err() {
+                return Some(Err(Rc5Error::DecryptionFailed));
+            }
+            if !last.is_empty() {
+                return Some(Ok(last));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StreamEncryptor;
+
+    fn cipher() -> RC5<u32> {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        RC5::<u32>::new(key, 4, 12, 16)
+    }
+
+    fn modes() -> Vec<Mode> {
+        vec![
+            Mode::Ecb,
+            Mode::Cbc { iv: vec![1; 8] },
+            Mode::Ctr { nonce: vec![2; 8] },
+        ]
+    }
+
+    #[test]
+    fn matches_stream_encryptor() {
+        let plaintext = (0..45u8).collect::<Vec<u8>>();
+
+        for mode in modes() {
+            let blocks = plaintext
+                .iter()
+                .copied()
+                .rc5_encrypt(&cipher(), mode.clone())
+                .unwrap()
+                .collect::<Vec<Vec<u8>>>();
+            assert!(blocks.iter().rev().skip(1).all(|block| block.len() == 8));
+
+            let mut encryptor = StreamEncryptor::new(&cipher(), mode).unwrap();
+            let mut expected = encryptor.update(&plaintext);
+            expected.extend(encryptor.finalize());
+
+            assert_eq!(blocks.concat(), expected);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for len in [0usize, 1, 8, 15, 16, 33].iter() {
+            let plaintext = (0..*len as u8).collect::<Vec<u8>>();
+
+            for mode in modes() {
+                let decrypted = plaintext
+                    .iter()
+                    .copied()
+                    .rc5_encrypt(&cipher(), mode.clone())
+                    .unwrap()
+                    .flatten()
+                    .rc5_decrypt(&cipher(), mode)
+                    .unwrap()
+                    .collect::<Result<Vec<Vec<u8>>, _>>()
+                    .unwrap()
+                    .concat();
+
+                assert_eq!(plaintext, decrypted);
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        let mut blocks = vec![0u8; 12]
+            .into_iter()
+            .rc5_decrypt(&cipher(), Mode::Ecb)
+            .unwrap();
+
+        assert!(blocks.next().unwrap().is_err());
+        assert!(blocks.next().is_none());
+    }
+}
@@ -0,0 +1,220 @@
+// known-answer tests: the published single-block vectors run through
+// every backend that can encrypt them, the generic and unrolled scalar
+// code behind `RC5`, the const-generic `HeaplessRc5`, the multi-block
+// dispatch, each vectorised RC5-32 backend on its own, the threaded ECB
+// and both reference implementations. A backend the build or CPU lacks
+// is skipped
+use crate::reference::{ReferenceRc5, ReferenceRc5_32};
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{table_len, HeaplessRc5, Rc5Decryptor, Rc5Encryptor, RC5};
+
+struct Kat {
+    source: &'static str,
+    word_bits: u32,
+    rounds: usize,
+    key: &'static str,
+    pt: &'static str,
+    ct: &'static str,
+}
+
+const KATS: &[Kat] = &[
+    // Rivest, "The RC5 Encryption Algorithm", each ciphertext being the
+    // next plaintext
+    Kat {
+        source: "paper 1",
+        word_bits: 32,
+        rounds: 12,
+        key: "00000000000000000000000000000000",
+        pt: "0000000000000000",
+        ct: "21a5dbee154b8f6d",
+    },
+    Kat {
+        source: "paper 2",
+        word_bits: 32,
+        rounds: 12,
+        key: "915f4619be41b2516355a50110a9ce91",
+        pt: "21a5dbee154b8f6d",
+        ct: "f7c013ac5b2b8952",
+    },
+    Kat {
+        source: "paper 3",
+        word_bits: 32,
+        rounds: 12,
+        key: "783348e75aeb0f2fd7b169bb8dc16787",
+        pt: "f7c013ac5b2b8952",
+        ct: "2f42b3b70369fc92",
+    },
+    Kat {
+        source: "paper 4",
+        word_bits: 32,
+        rounds: 12,
+        key: "dc49db1375a5584f6485b413b5f12baf",
+        pt: "2f42b3b70369fc92",
+        ct: "65c178b284d197cc",
+    },
+    Kat {
+        source: "paper 5",
+        word_bits: 32,
+        rounds: 12,
+        key: "5269f149d41ba0152497574d7f153125",
+        pt: "65c178b284d197cc",
+        ct: "eb44e415da319824",
+    },
+    // Krovetz, "Test Vectors for RC6 and RC5", for the other word sizes
+    // and round counts
+    Kat {
+        source: "RC5-16/16/8",
+        word_bits: 16,
+        rounds: 16,
+        key: "0001020304050607",
+        pt: "00010203",
+        ct: "23a8d72e",
+    },
+    Kat {
+        source: "RC5-32/20/16",
+        word_bits: 32,
+        rounds: 20,
+        key: "000102030405060708090a0b0c0d0e0f",
+        pt: "0001020304050607",
+        ct: "2a0edc0e9431ff73",
+    },
+    Kat {
+        source: "RC5-64/24/24",
+        word_bits: 64,
+        rounds: 24,
+        key: "000102030405060708090a0b0c0d0e0f1011121314151617",
+        pt: "000102030405060708090a0b0c0d0e0f",
+        ct: "a46772820edbce0235abea32ae7178da",
+    },
+];
+
+fn hex(text: &str) -> Vec<u8> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+// enough copies of the block for the multi-block backends to take over
+fn repeated(block: &[u8]) -> Vec<u8> {
+    block.repeat(64)
+}
+
+fn check<T>(kat: &Kat)
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy + Send + Sync,
+{
+    let (key, pt, ct) = (hex(kat.key), hex(kat.pt), hex(kat.ct));
+    let name = kat.source;
+    let cipher = RC5::<T>::new(
+        key.clone(),
+        core::mem::size_of::<T>(),
+        kat.rounds,
+        key.len(),
+    );
+
+    assert_eq!(cipher.encode(&pt).unwrap(), ct, "{}: RC5", name);
+    assert_eq!(cipher.decode(&ct).unwrap(), pt, "{}: RC5", name);
+
+    let schedule = cipher.key_schedule().unwrap();
+    let mut data = repeated(&pt);
+    schedule.encrypt_blocks(&mut data);
+    assert_eq!(data, repeated(&ct), "{}: multi-block", name);
+    schedule.decrypt_blocks(&mut data);
+    assert_eq!(data, repeated(&pt), "{}: multi-block", name);
+
+    #[cfg(feature = "parallel")]
+    {
+        let mut data = repeated(&pt);
+        crate::par_encrypt_ecb(&cipher, &mut data).unwrap();
+        assert_eq!(data, repeated(&ct), "{}: parallel", name);
+        crate::par_decrypt_ecb(&cipher, &mut data).unwrap();
+        assert_eq!(data, repeated(&pt), "{}: parallel", name);
+    }
+
+    #[cfg(any(feature = "simd", target_arch = "x86_64"))]
+    if let Some(s_table) = schedule.u32_table() {
+        type Backend = fn(&[u32], usize, &mut [u8]) -> usize;
+        let mut backends: Vec<(&str, Backend, Backend)> = Vec::new();
+        #[cfg(target_arch = "x86_64")]
+        backends.push((
+            "AVX2",
+            crate::avx2::encrypt_blocks,
+            crate::avx2::decrypt_blocks,
+        ));
+        #[cfg(feature = "simd")]
+        backends.push((
+            "SIMD",
+            crate::simd::encrypt_blocks,
+            crate::simd::decrypt_blocks,
+        ));
+
+        for (backend, encrypt, decrypt) in backends {
+            let mut data = repeated(&pt);
+            // zero when the CPU lacks the instructions
+            if encrypt(s_table, kat.rounds, &mut data) == 0 {
+                continue;
+            }
+            assert_eq!(data, repeated(&ct), "{}: {}", name, backend);
+            assert_ne!(decrypt(s_table, kat.rounds, &mut data), 0);
+            assert_eq!(data, repeated(&pt), "{}: {}", name, backend);
+        }
+    }
+
+    let reference = ReferenceRc5::new(kat.word_bits, kat.rounds, &key);
+    assert_eq!(reference.encrypt(&pt), ct, "{}: reference", name);
+    assert_eq!(reference.decrypt(&ct), pt, "{}: reference", name);
+}
+
+fn check_heapless<T, const TABLE_LEN: usize>(kat: &Kat)
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let cipher = HeaplessRc5::<T, TABLE_LEN>::new(&hex(kat.key)).unwrap();
+    let mut data = repeated(&hex(kat.pt));
+    cipher.encrypt_blocks(&mut data).unwrap();
+    assert_eq!(data, repeated(&hex(kat.ct)), "{}: heapless", kat.source);
+    cipher.decrypt_blocks(&mut data).unwrap();
+    assert_eq!(data, repeated(&hex(kat.pt)), "{}: heapless", kat.source);
+}
+
+#[test]
+fn every_backend_matches_the_published_vectors() {
+    for kat in KATS {
+        match (kat.word_bits, kat.rounds) {
+            #[cfg(feature = "rc5-16")]
+            (16, 16) => {
+                check::<u16>(kat);
+                check_heapless::<u16, { table_len(16) }>(kat);
+            }
+            (32, 12) => {
+                check::<u32>(kat);
+                check_heapless::<u32, { table_len(12) }>(kat);
+            }
+            (32, 20) => {
+                check::<u32>(kat);
+                check_heapless::<u32, { table_len(20) }>(kat);
+            }
+            #[cfg(feature = "rc5-64")]
+            (64, 24) => {
+                check::<u64>(kat);
+                check_heapless::<u64, { table_len(24) }>(kat);
+            }
+            // a word size that isn't compiled in
+            (16, _) | (64, _) => {}
+            _ => unreachable!("no heapless table for {}", kat.source),
+        }
+    }
+}
+
+#[test]
+fn spec_literal_rc5_32_matches_the_published_vectors() {
+    for kat in KATS.iter().filter(|kat| kat.word_bits == 32) {
+        let mut block = [0u8; 8];
+        block.copy_from_slice(&hex(kat.pt));
+        let cipher = ReferenceRc5_32::new(kat.rounds, &hex(kat.key));
+        let ct = cipher.encrypt(block);
+        assert_eq!(ct[..], hex(kat.ct)[..], "{}", kat.source);
+        assert_eq!(cipher.decrypt(ct), block, "{}", kat.source);
+    }
+}
@@ -0,0 +1,515 @@
+use std::fmt;
+use std::ops::Deref;
+
+use crate::digest::{hkdf_sha256, pbkdf2_sha256};
+use crate::encoding::{decode_base64, decode_hex};
+use crate::mac::cmac;
+use crate::rng::CryptoRng;
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+#[cfg(feature = "argon2")]
+use crate::Argon2Params;
+use crate::{secure, Rc5Error, Rc5Params, Redacted, MAX_KEY_LEN, RC5};
+
+// RFC 8018 asks for at least 64 bits of salt
+pub const PBKDF2_MIN_SALT_LEN: usize = 8;
+
+// bytes of a key check value, the usual 3 of payment systems
+pub const KCV_LEN: usize = 3;
+
+const ENCRYPTION_LABEL: &[u8] = b"rc5 encryption key";
+const MAC_LABEL: &[u8] = b"rc5 mac key";
+
+// key bytes that can't be cloned or printed. They are only read by the
+// key expansion in `RC5::from_secret_key`, and overwritten with zeros when
+// the key is dropped (see `secure` for keeping them out of swap)
+pub struct Rc5SecretKey {
+    bytes: Vec<u8>,
+}
+
+impl Rc5SecretKey {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        secure::lock(&bytes);
+        Rc5SecretKey { bytes }
+    }
+
+    // a random key of `len` bytes, 16 is the usual choice. `len` must be
+    // 1 to `MAX_KEY_LEN`, or `InvalidKeyLength` is returned
+    pub fn generate<R: CryptoRng>(mut rng: R, len: usize) -> Result<Self, Rc5Error> {
+        if len == 0 || len > MAX_KEY_LEN {
+            return Err(Rc5Error::InvalidKeyLength);
+        }
+        let mut bytes = vec![0u8; len];
+        rng.fill_bytes(&mut bytes);
+        Ok(Rc5SecretKey::new(bytes))
+    }
+
+    // a `len` byte key for `password` with PBKDF2-HMAC-SHA-256. The salt
+    // must be random, at least `PBKDF2_MIN_SALT_LEN` bytes, and stored
+    // with the ciphertext. Prefer `from_password_argon2` for new data,
+    // PBKDF2 is for interop with systems that already use it
+    pub fn from_password_pbkdf2(
+        password: &[u8],
+        salt: &[u8],
+        iterations: u32,
+        len: usize,
+    ) -> Result<Self, Rc5Error> {
+        if !(1..=MAX_KEY_LEN).contains(&len) {
+            return Err(Rc5Error::InvalidKeyLength);
+        }
+        if iterations == 0 || salt.len() < PBKDF2_MIN_SALT_LEN {
+            return Err(Rc5Error::InvalidKdfParameters);
+        }
+
+        let mut bytes = vec![0u8; len];
+        pbkdf2_sha256(password, salt, iterations, &mut bytes);
+        Ok(Rc5SecretKey::new(bytes))
+    }
+
+    // a `len` byte key for `password` with Argon2id, the recommended
+    // password path. `Argon2Params::default()` is the RFC 9106 choice for
+    // when 64 MiB per derivation is affordable, and the salt follows the
+    // same rules as for PBKDF2 with `ARGON2_MIN_SALT_LEN`
+    #[cfg(feature = "argon2")]
+    pub fn from_password_argon2(
+        password: &[u8],
+        salt: &[u8],
+        params: &Argon2Params,
+        len: usize,
+    ) -> Result<Self, Rc5Error> {
+        params.derive_key(password, salt, len)
+    }
+
+    // a `len` byte subkey of this key for the context named by `info`,
+    // with HKDF-SHA-256 and this key as the input keying material. Subkeys
+    // for different infos are independent, and reveal nothing of this key
+    // or of each other. The salt is optional, but a random one stored with
+    // the data is better when this key is a secret of unknown quality,
+    // such as a Diffie-Hellman output
+    pub fn derive_subkey(&self, salt: &[u8], info: &[u8], len: usize) -> Result<Self, Rc5Error> {
+        if !(1..=MAX_KEY_LEN).contains(&len) {
+            return Err(Rc5Error::InvalidKeyLength);
+        }
+
+        let mut bytes = vec![0u8; len];
+        hkdf_sha256(salt, &self.bytes, info, &mut bytes);
+        Ok(Rc5SecretKey::new(bytes))
+    }
+
+    // a key written as hex, e.g. "000102...0f", which must decode to
+    // exactly `params.bytes` bytes. Whitespace is ignored, a malformed
+    // text gives `InvalidEncoding` and a wrong length `InvalidKeyLength`,
+    // and no decoded bytes are left behind on failure
+    pub fn from_hex(text: &str, params: &Rc5Params) -> Result<Self, Rc5Error> {
+        Self::checked(decode_hex(text)?, params)
+    }
+
+    // like `from_hex` for padded standard base64
+    pub fn from_base64(text: &str, params: &Rc5Params) -> Result<Self, Rc5Error> {
+        Self::checked(decode_base64(text)?, params)
+    }
+
+    fn checked(mut bytes: Vec<u8>, params: &Rc5Params) -> Result<Self, Rc5Error> {
+        if bytes.len() != params.bytes || bytes.is_empty() || bytes.len() > MAX_KEY_LEN {
+            secure::wipe(&mut bytes);
+            return Err(Rc5Error::InvalidKeyLength);
+        }
+        Ok(Rc5SecretKey::new(bytes))
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub(crate) fn expose(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl From<Vec<u8>> for Rc5SecretKey {
+    fn from(bytes: Vec<u8>) -> Self {
+        Rc5SecretKey::new(bytes)
+    }
+}
+
+impl fmt::Debug for Rc5SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Rc5SecretKey")
+            .field(&Redacted(self.len()))
+            .finish()
+    }
+}
+
+impl Drop for Rc5SecretKey {
+    fn drop(&mut self) {
+        secure::release(&mut self.bytes);
+    }
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> RC5<T> {
+    // the key check value: the first `KCV_LEN` bytes of the encryption of
+    // an all-zero block. Both sides of a system can compare it to confirm
+    // they loaded the same key without showing the key itself. It does
+    // give away one known plaintext, so it is no substitute for a MAC
+    pub fn check_value(&self) -> Result<[u8; KCV_LEN], Rc5Error> {
+        let schedule = self.schedule()?;
+        let mut block = vec![0u8; schedule.block_len()];
+        schedule.encrypt_block(&mut block);
+
+        let mut kcv = [0u8; KCV_LEN];
+        kcv.copy_from_slice(&block[..KCV_LEN]);
+        Ok(kcv)
+    }
+}
+
+// a key for encryption only, derived from a master key. It dereferences
+// to the cipher, so it can be passed wherever an `&RC5<T>` is accepted
+pub struct EncryptionKey<T: Unsigned16To64> {
+    cipher: RC5<T>,
+}
+
+// a key for `Cmac` only, derived from the same master key as an
+// `EncryptionKey` but independent of it. It can't be used to encrypt
+pub struct MacKey<T: Unsigned16To64> {
+    cipher: RC5<T>,
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> EncryptionKey<T> {
+    pub fn derive(master: &RC5<T>) -> Result<Self, Rc5Error> {
+        Ok(EncryptionKey {
+            cipher: derive_subkey(master, ENCRYPTION_LABEL)?,
+        })
+    }
+
+    // the encryption key for `context` under a master secret, with
+    // `Rc5SecretKey::derive_subkey`. The `MacKey` from the same arguments
+    // is independent of it, so Encrypt-then-MAC and SIV can take both from
+    // one secret, and each context (a file, a tenant) gets its own pair
+    pub fn from_master_secret(
+        master: &Rc5SecretKey,
+        salt: &[u8],
+        context: &[u8],
+        params: Rc5Params,
+    ) -> Result<Self, Rc5Error> {
+        Ok(EncryptionKey {
+            cipher: hkdf_subkey(master, salt, ENCRYPTION_LABEL, context, params)?,
+        })
+    }
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> MacKey<T> {
+    pub fn derive(master: &RC5<T>) -> Result<Self, Rc5Error> {
+        Ok(MacKey {
+            cipher: derive_subkey(master, MAC_LABEL)?,
+        })
+    }
+
+    // see `EncryptionKey::from_master_secret`
+    pub fn from_master_secret(
+        master: &Rc5SecretKey,
+        salt: &[u8],
+        context: &[u8],
+        params: Rc5Params,
+    ) -> Result<Self, Rc5Error> {
+        Ok(MacKey {
+            cipher: hkdf_subkey(master, salt, MAC_LABEL, context, params)?,
+        })
+    }
+
+    pub(crate) fn cipher(&self) -> &RC5<T> {
+        &self.cipher
+    }
+}
+
+impl<T: Unsigned16To64> Deref for EncryptionKey<T> {
+    type Target = RC5<T>;
+
+    fn deref(&self) -> &RC5<T> {
+        &self.cipher
+    }
+}
+
+impl<T: Unsigned16To64> fmt::Debug for EncryptionKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("EncryptionKey").field(&self.cipher).finish()
+    }
+}
+
+impl<T: Unsigned16To64> fmt::Debug for MacKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MacKey").field(&self.cipher).finish()
+    }
+}
+
+// KDF in counter mode with CMAC as the PRF (NIST SP 800-108): block i of
+// the subkey is CMAC(master, [i]_1 || label || 0x00 || [key bits]_2)
+fn derive_subkey<T>(master: &RC5<T>, label: &[u8]) -> Result<RC5<T>, Rc5Error>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let schedule = master.schedule()?;
+    let params = master.params();
+
+    let mut input = Vec::with_capacity(label.len() + 4);
+    let mut bytes = Vec::with_capacity(params.bytes + schedule.block_len());
+    for counter in 1u8..=255 {
+        if bytes.len() >= params.bytes {
+            break;
+        }
+
+        input.clear();
+        input.push(counter);
+        input.extend_from_slice(label);
+        input.push(0);
+        input.extend_from_slice(&((8 * params.bytes) as u16).to_be_bytes());
+        bytes.extend_from_slice(&cmac(schedule, &input));
+    }
+    secure::wipe(&mut bytes[params.bytes..]);
+    bytes.truncate(params.bytes);
+
+    Ok(RC5::new(bytes, params.words, params.rounds, params.bytes))
+}
+
+// the HKDF info is `label || 0x00 || context`: the labels contain no zero
+// byte, so no context of one key type gives the info of the other
+fn hkdf_subkey<T>(
+    master: &Rc5SecretKey,
+    salt: &[u8],
+    label: &[u8],
+    context: &[u8],
+    params: Rc5Params,
+) -> Result<RC5<T>, Rc5Error>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let mut info = Vec::with_capacity(label.len() + 1 + context.len());
+    info.extend_from_slice(label);
+    info.push(0);
+    info.extend_from_slice(context);
+
+    let key = master.derive_subkey(salt, &info, params.bytes)?;
+    Ok(RC5::from_secret_key(
+        key,
+        params.words,
+        params.rounds,
+        params.bytes,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::tests::CountingRng;
+    use crate::{Rc5Encryptor, RC5};
+
+    #[test]
+    fn debug_output_is_redacted() {
+        let key = Rc5SecretKey::new(vec![0xAB; 16]);
+        let printed = format!("{:?}", key);
+        assert_eq!(printed, "Rc5SecretKey([REDACTED; 16 bytes])");
+        assert!(!printed.contains("171"));
+    }
+
+    #[test]
+    fn generated_keys_come_from_the_rng() {
+        let key = Rc5SecretKey::generate(CountingRng(7), 16).unwrap();
+        assert_eq!(key.expose(), &(7u8..23).collect::<Vec<u8>>()[..]);
+        assert!(Rc5SecretKey::generate(CountingRng(7), MAX_KEY_LEN).is_ok());
+        for &len in [0, MAX_KEY_LEN + 1].iter() {
+            assert_eq!(
+                Rc5SecretKey::generate(CountingRng(7), len).err(),
+                Some(Rc5Error::InvalidKeyLength)
+            );
+        }
+    }
+
+    #[test]
+    fn password_keys_use_pbkdf2() {
+        let key = Rc5SecretKey::from_password_pbkdf2(
+            b"passwordPASSWORDpassword",
+            b"saltSALTsaltSALTsaltSALTsaltSALTsalt",
+            4096,
+            40,
+        )
+        .unwrap();
+        let expected = [
+            0x34, 0x8c, 0x89, 0xdb, 0xcb, 0xd3, 0x2b, 0x2f, 0x32, 0xd8, 0x14, 0xb8, 0x11, 0x6e,
+            0x84, 0xcf, 0x2b, 0x17, 0x34, 0x7e, 0xbc, 0x18, 0x00, 0x18, 0x1c, 0x4e, 0x2a, 0x1f,
+            0xb8, 0xdd, 0x53, 0xe1, 0xc6, 0x35, 0x51, 0x8c, 0x7d, 0xac, 0x47, 0xe9,
+        ];
+        assert_eq!(key.expose(), &expected[..]);
+
+        let from_password = |salt: &[u8], iterations, len| {
+            Rc5SecretKey::from_password_pbkdf2(b"password", salt, iterations, len).err()
+        };
+        assert_eq!(from_password(b"saltsalt", 1, 16), None);
+        assert_eq!(
+            from_password(b"salt", 1, 16),
+            Some(Rc5Error::InvalidKdfParameters)
+        );
+        assert_eq!(
+            from_password(b"saltsalt", 0, 16),
+            Some(Rc5Error::InvalidKdfParameters)
+        );
+        assert_eq!(
+            from_password(b"saltsalt", 1, 0),
+            Some(Rc5Error::InvalidKeyLength)
+        );
+        assert_eq!(
+            from_password(b"saltsalt", 1, 256),
+            Some(Rc5Error::InvalidKeyLength)
+        );
+    }
+
+    #[cfg(feature = "argon2")]
+    #[test]
+    fn password_keys_use_argon2id() {
+        let params = Argon2Params {
+            memory_kib: 32,
+            iterations: 2,
+            parallelism: 2,
+        };
+        let key = Rc5SecretKey::from_password_argon2(b"password", b"saltsalt", &params, 16);
+        let expected = params.derive_key(b"password", b"saltsalt", 16).unwrap();
+        assert_eq!(key.unwrap().expose(), expected.expose());
+
+        let other_salt = Rc5SecretKey::from_password_argon2(b"password", b"saltSALT", &params, 16);
+        assert_ne!(other_salt.unwrap().expose(), expected.expose());
+        assert_eq!(
+            Rc5SecretKey::from_password_argon2(b"password", b"salt", &params, 16).err(),
+            Some(Rc5Error::InvalidKdfParameters)
+        );
+    }
+
+    #[test]
+    fn derived_keys_are_independent() {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let master = RC5::<u32>::new(key, 4, 12, 16);
+        let encryption = EncryptionKey::derive(&master).unwrap();
+        let mac = MacKey::derive(&master).unwrap();
+
+        let pt = [0x42u8; 8];
+        let from_master = master.encode(&pt).unwrap();
+        let from_encryption = encryption.encode(&pt).unwrap();
+        let from_mac = mac.cipher().encode(&pt).unwrap();
+
+        assert_ne!(from_master, from_encryption);
+        assert_ne!(from_master, from_mac);
+        assert_ne!(from_encryption, from_mac);
+
+        // derivation is deterministic
+        let again = EncryptionKey::derive(&master).unwrap();
+        assert_eq!(again.encode(&pt).unwrap(), from_encryption);
+    }
+
+    #[test]
+    fn master_secrets_expand_into_separate_keys() {
+        let master = Rc5SecretKey::new(vec![0x5A; 32]);
+        let params = RC5::<u32>::new(vec![0; 16], 4, 12, 16).params();
+        let encryption = |context: &[u8]| {
+            EncryptionKey::<u32>::from_master_secret(&master, b"", context, params)
+        };
+
+        let pt = [0x42u8; 8];
+        let first = encryption(b"file 1").unwrap().encode(&pt).unwrap();
+        let second = encryption(b"file 2").unwrap().encode(&pt).unwrap();
+        let mac = MacKey::<u32>::from_master_secret(&master, b"", b"file 1", params).unwrap();
+        let mac = mac.cipher().encode(&pt).unwrap();
+
+        assert_ne!(first, second);
+        assert_ne!(first, mac);
+        assert_eq!(encryption(b"file 1").unwrap().encode(&pt).unwrap(), first);
+
+        // the encryption key is the HKDF output for its labelled info
+        let mut expected = vec![0u8; 16];
+        hkdf_sha256(
+            b"",
+            &[0x5A; 32],
+            b"rc5 encryption key\0file 1",
+            &mut expected,
+        );
+        let expected = RC5::<u32>::new(expected, 4, 12, 16).encode(&pt).unwrap();
+        assert_eq!(first, expected);
+
+        assert_eq!(
+            master.derive_subkey(b"", b"", 0).err(),
+            Some(Rc5Error::InvalidKeyLength)
+        );
+        assert_eq!(
+            master.derive_subkey(b"salt", b"info", 255).unwrap().len(),
+            255
+        );
+    }
+
+    #[test]
+    fn check_values_are_the_encrypted_zero_block() {
+        // RC5-32/12/16 with the all-zero key encrypts zeros to 21a5dbee...
+        let rc_5 = RC5::<u32>::new(vec![0; 16], 4, 12, 16);
+        assert_eq!(rc_5.check_value().unwrap(), [0x21, 0xa5, 0xdb]);
+
+        let other = RC5::<u32>::new(vec![1; 16], 4, 12, 16);
+        assert_ne!(other.check_value().unwrap(), rc_5.check_value().unwrap());
+        assert_eq!(
+            RC5::<u32>::new(vec![1, 2, 3], 4, 12, 16).check_value(),
+            Err(Rc5Error::InvalidKeyLength)
+        );
+    }
+
+    #[test]
+    fn secret_keys_encrypt_like_plain_ones() {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let rc_5 = RC5::<u32>::from_secret_key(Rc5SecretKey::from(key.clone()), 4, 12, 16);
+
+        let pt = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let expected = RC5::<u32>::new(key, 4, 12, 16).encode(&pt).unwrap();
+        assert_eq!(rc_5.encode(&pt).unwrap(), expected);
+    }
+
+    #[test]
+    fn text_keys_are_checked_against_the_parameters() {
+        let params = Rc5Params {
+            words: 4,
+            rounds: 12,
+            bytes: 16,
+        };
+        let expected = RC5::<u32>::new((0u8..16).collect::<Vec<u8>>(), 4, 12, 16)
+            .check_value()
+            .unwrap();
+        let check = |key: Rc5SecretKey| {
+            RC5::<u32>::from_secret_key(key, 4, 12, 16)
+                .check_value()
+                .unwrap()
+        };
+
+        let hex = "00010203 04050607\n08090a0b 0C0D0E0F\n";
+        assert_eq!(
+            check(Rc5SecretKey::from_hex(hex, &params).unwrap()),
+            expected
+        );
+        let base64 = "  AAECAwQFBgcICQoLDA0ODw==\n";
+        assert_eq!(
+            check(Rc5SecretKey::from_base64(base64, &params).unwrap()),
+            expected
+        );
+
+        assert_eq!(
+            Rc5SecretKey::from_hex("0001", &params).err(),
+            Some(Rc5Error::InvalidKeyLength)
+        );
+        assert_eq!(
+            Rc5SecretKey::from_base64("AAECAwQFBgcICQoLDA0ODxA=", &params).err(),
+            Some(Rc5Error::InvalidKeyLength)
+        );
+        assert_eq!(
+            Rc5SecretKey::from_hex(&hex.replace("0b", "0x"), &params).err(),
+            Some(Rc5Error::InvalidEncoding)
+        );
+        assert_eq!(
+            Rc5SecretKey::from_hex("", &Rc5Params { bytes: 0, ..params }).err(),
+            Some(Rc5Error::InvalidKeyLength)
+        );
+    }
+}
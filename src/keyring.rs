@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::rng::{CryptoRng, Nonce};
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{Eax, Rc5Error, RC5};
+
+// Message format written by `KeyRing::encrypt`:
+//
+//   key id u16 (little-endian) | nonce (one block) | ciphertext | tag (one block)
+//
+// sealed with EAX under the key the id names, with the id as associated
+// data so it can't be swapped for another
+const KEY_ID_LEN: usize = 2;
+
+// keys under small ids, one of them active. New messages are encrypted
+// under the active key and stamped with its id, and messages are
+// decrypted with whichever key their id names, so a key can be rotated
+// by adding its successor and making that active while old messages stay
+// readable for as long as the old key is kept
+pub struct KeyRing<T: Unsigned16To64> {
+    keys: BTreeMap<u16, Eax<T>>,
+    active: Option<u16>,
+}
+
+impl<T: Unsigned16To64> KeyRing<T> {
+    pub fn new() -> Self {
+        KeyRing {
+            keys: BTreeMap::new(),
+            active: None,
+        }
+    }
+
+    // the id new messages are encrypted under
+    pub fn active_id(&self) -> Option<u16> {
+        self.active
+    }
+
+    pub fn key_ids(&self) -> impl Iterator<Item = u16> + '_ {
+        self.keys.keys().copied()
+    }
+
+    // makes the key under `id` the one new messages are encrypted with
+    pub fn set_active(&mut self, id: u16) -> Result<(), Rc5Error> {
+        if !self.keys.contains_key(&id) {
+            return Err(Rc5Error::UnknownKeyId);
+        }
+        self.active = Some(id);
+        Ok(())
+    }
+
+    // the key id a message is stamped with, e.g. to find out which keys
+    // are still in use before removing one
+    pub fn key_id(message: &[u8]) -> Option<u16> {
+        let id = message.get(..KEY_ID_LEN)?;
+        Some(u16::from_le_bytes([id[0], id[1]]))
+    }
+
+    // forgets the key under `id`, after which its messages can't be
+    // decrypted. Removing the active key leaves none active
+    pub fn remove(&mut self, id: u16) -> bool {
+        if self.active == Some(id) {
+            self.active = None;
+        }
+        self.keys.remove(&id).is_some()
+    }
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> KeyRing<T> {
+    // adds or replaces the key under `id`. The first key added becomes
+    // the active one
+    pub fn insert(&mut self, id: u16, cipher: &RC5<T>) -> Result<(), Rc5Error> {
+        self.keys.insert(id, Eax::new(cipher)?);
+        self.active.get_or_insert(id);
+        Ok(())
+    }
+
+    pub fn encrypt<R: CryptoRng>(&self, rng: R, plaintext: &[u8]) -> Result<Vec<u8>, Rc5Error> {
+        let id = self.active.ok_or(Rc5Error::UnknownKeyId)?;
+        let eax = &self.keys[&id];
+        let nonce = Nonce::<T>::generate(rng);
+
+        let mut message = Vec::with_capacity(KEY_ID_LEN + 2 * eax.tag_len() + plaintext.len());
+        message.extend_from_slice(&id.to_le_bytes());
+        message.extend_from_slice(nonce.as_ref());
+        message.extend_from_slice(&eax.encrypt(nonce.as_ref(), &id.to_le_bytes(), plaintext));
+        Ok(message)
+    }
+}
+
+impl<T: Unsigned16To64 + Copy> KeyRing<T> {
+    // `UnknownKeyId` if the stamped key isn't in the ring, and
+    // `DecryptionFailed` if the message was tampered with or truncated
+    pub fn decrypt(&self, message: &[u8]) -> Result<Vec<u8>, Rc5Error> {
+        let id = Self::key_id(message).ok_or(Rc5Error::DecryptionFailed)?;
+        let eax = self.keys.get(&id).ok_or(Rc5Error::UnknownKeyId)?;
+
+        let body = &message[KEY_ID_LEN..];
+        let nonce_len = eax.tag_len();
+        if body.len() < nonce_len {
+            return Err(Rc5Error::DecryptionFailed);
+        }
+        let (nonce, ciphertext) = body.split_at(nonce_len);
+        eax.decrypt(nonce, &id.to_le_bytes(), ciphertext)
+    }
+}
+
+impl<T: Unsigned16To64> Default for KeyRing<T> {
+    fn default() -> Self {
+        KeyRing::new()
+    }
+}
+
+impl<T: Unsigned16To64> fmt::Debug for KeyRing<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyRing")
+            .field("key_ids", &self.keys.keys().collect::<Vec<_>>())
+            .field("active", &self.active)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::tests::CountingRng;
+
+    fn cipher(byte: u8) -> RC5<u32> {
+        RC5::<u32>::new(vec![byte; 16], 4, 12, 16)
+    }
+
+    #[test]
+    fn rotation_keeps_old_messages_readable() {
+        let mut ring = KeyRing::new();
+        ring.insert(1, &cipher(1)).unwrap();
+        let old = ring.encrypt(CountingRng(0), b"old message").unwrap();
+        assert_eq!(KeyRing::<u32>::key_id(&old), Some(1));
+
+        ring.insert(2, &cipher(2)).unwrap();
+        assert_eq!(ring.active_id(), Some(1));
+        ring.set_active(2).unwrap();
+        let new = ring.encrypt(CountingRng(0), b"new message").unwrap();
+        assert_eq!(KeyRing::<u32>::key_id(&new), Some(2));
+        assert_eq!(new.len(), 2 + 8 + 11 + 8);
+
+        assert_eq!(ring.decrypt(&old).unwrap(), b"old message");
+        assert_eq!(ring.decrypt(&new).unwrap(), b"new message");
+        assert_eq!(ring.key_ids().collect::<Vec<_>>(), vec![1, 2]);
+
+        assert!(ring.remove(1));
+        assert_eq!(ring.decrypt(&old), Err(Rc5Error::UnknownKeyId));
+        assert_eq!(ring.decrypt(&new).unwrap(), b"new message");
+    }
+
+    #[test]
+    fn stamped_ids_are_authenticated() {
+        let mut ring = KeyRing::new();
+        ring.insert(1, &cipher(1)).unwrap();
+        ring.insert(2, &cipher(1)).unwrap();
+        let message = ring.encrypt(CountingRng(0), b"message").unwrap();
+
+        // the same key under another id still fails
+        let mut moved = message.clone();
+        moved[0] = 2;
+        assert_eq!(ring.decrypt(&moved), Err(Rc5Error::DecryptionFailed));
+
+        let mut tampered = message.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(ring.decrypt(&tampered), Err(Rc5Error::DecryptionFailed));
+
+        for len in [0usize, 1, 2, 9, 17].iter() {
+            assert_eq!(
+                ring.decrypt(&message[..*len]),
+                Err(Rc5Error::DecryptionFailed)
+            );
+        }
+    }
+
+    #[test]
+    fn an_active_key_is_required() {
+        let mut ring = KeyRing::<u32>::new();
+        assert_eq!(
+            ring.encrypt(CountingRng(0), b"").err(),
+            Some(Rc5Error::UnknownKeyId)
+        );
+        assert_eq!(ring.set_active(3), Err(Rc5Error::UnknownKeyId));
+
+        ring.insert(3, &cipher(3)).unwrap();
+        assert!(ring.remove(3));
+        assert_eq!(ring.active_id(), None);
+        assert!(!ring.remove(3));
+
+        let bad = RC5::<u32>::new(vec![1, 2, 3], 4, 12, 16);
+        assert_eq!(ring.insert(4, &bad), Err(Rc5Error::InvalidKeyLength));
+        assert_eq!(
+            format!("{:?}", ring),
+            "KeyRing { key_ids: [], active: None }"
+        );
+    }
+}
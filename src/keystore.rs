@@ -0,0 +1,260 @@
+use std::ffi::CString;
+
+use crate::{Rc5Error, Rc5SecretKey};
+
+// named keys kept in the operating system instead of in key files. Only
+// the Linux kernel keyring is supported, on x86_64 and aarch64: each key
+// is a "user" key described as "<service>:<name>" in the user's keyring.
+// The kernel holds the bytes in memory only, readable by processes of the
+// same user, until the key is deleted or the user's last session ends, so
+// nothing reaches the disk. On every other target the store exists but
+// each call fails with `KeyStoreUnavailable`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OsKeyStore {
+    service: String,
+}
+
+impl OsKeyStore {
+    // `service` namespaces the names, e.g. one per application
+    pub fn new(service: &str) -> Self {
+        OsKeyStore {
+            service: service.to_string(),
+        }
+    }
+
+    // stores `key` under `name`, replacing any key already stored there
+    pub fn store(&self, name: &str, key: &Rc5SecretKey) -> Result<(), Rc5Error> {
+        if key.is_empty() {
+            return Err(Rc5Error::EmptyKey);
+        }
+        sys::add(&self.description(name)?, key.expose())
+    }
+
+    // `UnknownKeyId` if nothing is stored under `name`
+    pub fn load(&self, name: &str) -> Result<Rc5SecretKey, Rc5Error> {
+        sys::read(sys::search(&self.description(name)?)?)
+    }
+
+    // destroys the key stored under `name`, `UnknownKeyId` if there is none
+    pub fn delete(&self, name: &str) -> Result<(), Rc5Error> {
+        sys::invalidate(sys::search(&self.description(name)?)?)
+    }
+
+    fn description(&self, name: &str) -> Result<CString, Rc5Error> {
+        if name.is_empty() {
+            return Err(Rc5Error::UnknownKeyId);
+        }
+        CString::new(format!("{}:{}", self.service, name)).map_err(|_| Rc5Error::UnknownKeyId)
+    }
+}
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod sys {
+    use std::ffi::CStr;
+    use std::io;
+    use std::os::raw::c_long;
+    use std::ptr;
+
+    use crate::{secure, Rc5Error, Rc5SecretKey};
+
+    #[cfg(target_arch = "x86_64")]
+    const SYS_ADD_KEY: c_long = 248;
+    #[cfg(target_arch = "x86_64")]
+    const SYS_KEYCTL: c_long = 250;
+    #[cfg(target_arch = "aarch64")]
+    const SYS_ADD_KEY: c_long = 217;
+    #[cfg(target_arch = "aarch64")]
+    const SYS_KEYCTL: c_long = 219;
+
+    const KEY_SPEC_USER_KEYRING: c_long = -4;
+    const KEYCTL_SETPERM: c_long = 5;
+    const KEYCTL_SEARCH: c_long = 10;
+    const KEYCTL_READ: c_long = 11;
+    const KEYCTL_INVALIDATE: c_long = 21;
+    // everything for the possessor and the owning user, nothing for anyone
+    // else, so processes of the same user find the key without possessing it
+    const KEY_PERM_OWNER: c_long = 0x3f3f_0000;
+
+    const ENOKEY: i32 = 126;
+    const EKEYEXPIRED: i32 = 127;
+    const EKEYREVOKED: i32 = 128;
+
+    extern "C" {
+        fn syscall(number: c_long, ...) -> c_long;
+    }
+
+    pub(super) type KeyId = c_long;
+
+    pub(super) fn add(description: &CStr, payload: &[u8]) -> Result<(), Rc5Error> {
+        // SAFETY: both strings are NUL terminated and `payload` is valid
+        // for its length
+        let id = check(unsafe {
+            syscall(
+                SYS_ADD_KEY,
+                b"user\0".as_ptr(),
+                description.as_ptr(),
+                payload.as_ptr(),
+                payload.len(),
+                KEY_SPEC_USER_KEYRING,
+            )
+        })?;
+        // SAFETY: takes integers only
+        check(unsafe { syscall(SYS_KEYCTL, KEYCTL_SETPERM, id, KEY_PERM_OWNER) }).map(drop)
+    }
+
+    pub(super) fn read(id: KeyId) -> Result<Rc5SecretKey, Rc5Error> {
+        // SAFETY: a null buffer of length 0 only asks for the size
+        let mut len =
+            check(unsafe { syscall(SYS_KEYCTL, KEYCTL_READ, id, ptr::null_mut::<u8>(), 0usize) })?
+                as usize;
+        loop {
+            let mut key = vec![0u8; len];
+            // SAFETY: `key` is valid for writes of `len` bytes
+            let read =
+                check(unsafe { syscall(SYS_KEYCTL, KEYCTL_READ, id, key.as_mut_ptr(), len) });
+            match read {
+                Ok(read) if read as usize <= len => {
+                    key.truncate(read as usize);
+                    return Ok(Rc5SecretKey::new(key));
+                }
+                // the key was replaced by a longer one in between
+                Ok(read) => {
+                    secure::wipe(&mut key);
+                    len = read as usize;
+                }
+                Err(err) => {
+                    secure::wipe(&mut key);
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    pub(super) fn invalidate(id: KeyId) -> Result<(), Rc5Error> {
+        // SAFETY: takes integers only
+        check(unsafe { syscall(SYS_KEYCTL, KEYCTL_INVALIDATE, id) }).map(drop)
+    }
+
+    pub(super) fn search(description: &CStr) -> Result<KeyId, Rc5Error> {
+        // SAFETY: both strings are NUL terminated, and no keyring is
+        // given to link the key into
+        check(unsafe {
+            syscall(
+                SYS_KEYCTL,
+                KEYCTL_SEARCH,
+                KEY_SPEC_USER_KEYRING,
+                b"user\0".as_ptr(),
+                description.as_ptr(),
+                0 as c_long,
+            )
+        })
+    }
+
+    fn check(result: c_long) -> Result<c_long, Rc5Error> {
+        if result >= 0 {
+            return Ok(result);
+        }
+        match io::Error::last_os_error().raw_os_error() {
+            Some(ENOKEY) | Some(EKEYEXPIRED) | Some(EKEYREVOKED) => Err(Rc5Error::UnknownKeyId),
+            _ => Err(Rc5Error::KeyStoreUnavailable),
+        }
+    }
+}
+
+// there is no key store to reach elsewhere yet: macOS, Windows and the
+// Secret Service of other unix desktops aren't implemented
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+mod sys {
+    use std::ffi::CStr;
+
+    use crate::{Rc5Error, Rc5SecretKey};
+
+    pub(super) enum KeyId {}
+
+    pub(super) fn add(_: &CStr, _: &[u8]) -> Result<(), Rc5Error> {
+        Err(Rc5Error::KeyStoreUnavailable)
+    }
+
+    pub(super) fn read(id: KeyId) -> Result<Rc5SecretKey, Rc5Error> {
+        match id {}
+    }
+
+    pub(super) fn invalidate(id: KeyId) -> Result<(), Rc5Error> {
+        match id {}
+    }
+
+    pub(super) fn search(_: &CStr) -> Result<KeyId, Rc5Error> {
+        Err(Rc5Error::KeyStoreUnavailable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> OsKeyStore {
+        OsKeyStore::new(&format!("rc5_test-{}", std::process::id()))
+    }
+
+    #[cfg(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    #[test]
+    fn stored_keys_load_until_deleted() {
+        let store = store();
+        store.store("a", &Rc5SecretKey::new(vec![1; 16])).unwrap();
+        store.store("b", &Rc5SecretKey::new(vec![2; 32])).unwrap();
+        assert_eq!(store.load("a").unwrap().expose(), &[1; 16]);
+        assert_eq!(store.load("b").unwrap().expose(), &[2; 32]);
+
+        // storing again replaces the key
+        store.store("a", &Rc5SecretKey::new(vec![3; 255])).unwrap();
+        assert_eq!(store.load("a").unwrap().expose(), &[3; 255][..]);
+
+        // names are per service
+        let other = OsKeyStore::new(&format!("rc5_test-other-{}", std::process::id()));
+        assert_eq!(other.load("a").err(), Some(Rc5Error::UnknownKeyId));
+
+        store.delete("a").unwrap();
+        store.delete("b").unwrap();
+        assert_eq!(store.load("a").err(), Some(Rc5Error::UnknownKeyId));
+        assert_eq!(store.delete("b"), Err(Rc5Error::UnknownKeyId));
+    }
+
+    #[test]
+    fn invalid_names_and_keys_are_refused() {
+        let store = store();
+        let key = Rc5SecretKey::new(vec![1; 16]);
+        assert_eq!(store.store("", &key), Err(Rc5Error::UnknownKeyId));
+        assert_eq!(store.store("a\0b", &key), Err(Rc5Error::UnknownKeyId));
+        assert_eq!(
+            store.store("empty", &Rc5SecretKey::new(vec![])),
+            Err(Rc5Error::EmptyKey)
+        );
+        #[cfg(all(
+            target_os = "linux",
+            any(target_arch = "x86_64", target_arch = "aarch64")
+        ))]
+        assert_eq!(store.load("missing").err(), Some(Rc5Error::UnknownKeyId));
+    }
+
+    #[cfg(not(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    )))]
+    #[test]
+    fn other_targets_have_no_store() {
+        let store = store();
+        let key = Rc5SecretKey::new(vec![1; 16]);
+        assert_eq!(store.store("a", &key), Err(Rc5Error::KeyStoreUnavailable));
+        assert_eq!(store.load("a").err(), Some(Rc5Error::KeyStoreUnavailable));
+        assert_eq!(store.delete("a"), Err(Rc5Error::KeyStoreUnavailable));
+    }
+}
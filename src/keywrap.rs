@@ -0,0 +1,135 @@
+use crate::ct::ct_eq;
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{
+    secure, Cmac, EncryptionKey, MacKey, Rc5Ctr, Rc5Error, Rc5SecretKey, MAX_KEY_LEN, RC5,
+};
+
+// deterministic key wrapping in the style of SIV (RFC 5297), for storing
+// data keys encrypted under a key-encryption key. The blob is
+//
+//   tag || CTR(encryption key, counter = tag, key)
+//
+// with `tag` the CMAC of the wrapped key under a MAC key, and both keys
+// derived from the KEK with `EncryptionKey::derive` and `MacKey::derive`.
+// Unlike RFC 3394 it takes keys of any length with any block size, and
+// the tag is a whole block: 64 bits of integrity with RC5-32, 128 with
+// RC5-64. Wrapping the same key twice gives the same blob, which is fine
+// for keys since they are random and never repeat by accident
+pub fn wrap_key<T>(kek: &RC5<T>, key: &Rc5SecretKey) -> Result<Vec<u8>, Rc5Error>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    if key.is_empty() {
+        return Err(Rc5Error::EmptyKey);
+    }
+
+    let (encryption, mac) = subkeys(kek)?;
+    let mut blob = mac.tag(key.expose());
+    let tag_len = blob.len();
+
+    blob.extend_from_slice(key.expose());
+    let (tag, wrapped) = blob.split_at_mut(tag_len);
+    Rc5Ctr::new(&encryption, tag)?.apply_keystream(wrapped);
+    Ok(blob)
+}
+
+// recovers the key from a `wrap_key` blob. Any change to the blob, or a
+// different KEK, gives `DecryptionFailed`, with the decrypted bytes wiped
+// before returning
+pub fn unwrap_key<T>(kek: &RC5<T>, blob: &[u8]) -> Result<Rc5SecretKey, Rc5Error>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let (encryption, mac) = subkeys(kek)?;
+    let tag_len = mac.tag_len();
+    if blob.len() <= tag_len || blob.len() - tag_len > MAX_KEY_LEN {
+        return Err(Rc5Error::DecryptionFailed);
+    }
+
+    let (tag, wrapped) = blob.split_at(tag_len);
+    let mut key = wrapped.to_vec();
+    Rc5Ctr::new(&encryption, tag)?.apply_keystream(&mut key);
+
+    if !ct_eq(&mac.tag(&key), tag) {
+        secure::wipe(&mut key);
+        return Err(Rc5Error::DecryptionFailed);
+    }
+    Ok(Rc5SecretKey::new(key))
+}
+
+fn subkeys<T>(kek: &RC5<T>) -> Result<(EncryptionKey<T>, Cmac<T>), Rc5Error>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let encryption = EncryptionKey::derive(kek)?;
+    let mac = Cmac::new(&MacKey::derive(kek)?)?;
+    Ok((encryption, mac))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kek() -> RC5<u32> {
+        RC5::<u32>::new((0u8..16).collect::<Vec<u8>>(), 4, 12, 16)
+    }
+
+    #[test]
+    fn wrapped_keys_unwrap() {
+        for len in [1usize, 8, 16, 17, 255].iter() {
+            let key = (0..*len).map(|i| i as u8 ^ 0x5A).collect::<Vec<u8>>();
+            let blob = wrap_key(&kek(), &Rc5SecretKey::new(key.clone())).unwrap();
+
+            assert_eq!(blob.len(), 8 + len);
+            assert_ne!(&blob[8..], &key[..]);
+            assert_eq!(unwrap_key(&kek(), &blob).unwrap().expose(), &key[..]);
+        }
+
+        #[cfg(feature = "rc5-64")]
+        {
+            let kek_64 = RC5::<u64>::new(vec![7; 32], 8, 16, 32);
+            let blob = wrap_key(&kek_64, &Rc5SecretKey::new(vec![1; 16])).unwrap();
+            assert_eq!(blob.len(), 32);
+            assert_eq!(unwrap_key(&kek_64, &blob).unwrap().expose(), &[1; 16]);
+        }
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let blob = wrap_key(&kek(), &Rc5SecretKey::new(vec![0x42; 16])).unwrap();
+
+        for i in 0..blob.len() {
+            let mut tampered = blob.clone();
+            tampered[i] ^= 0x01;
+            assert_eq!(
+                unwrap_key(&kek(), &tampered).err(),
+                Some(Rc5Error::DecryptionFailed)
+            );
+        }
+
+        let other_kek = RC5::<u32>::new(vec![9; 16], 4, 12, 16);
+        assert_eq!(
+            unwrap_key(&other_kek, &blob).err(),
+            Some(Rc5Error::DecryptionFailed)
+        );
+        for len in [0usize, 8, 23].iter() {
+            assert_eq!(
+                unwrap_key(&kek(), &blob[..*len]).err(),
+                Some(Rc5Error::DecryptionFailed)
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_keys_are_refused() {
+        assert_eq!(
+            wrap_key(&kek(), &Rc5SecretKey::new(vec![])).err(),
+            Some(Rc5Error::EmptyKey)
+        );
+
+        let bad_kek = RC5::<u32>::new(vec![1, 2, 3], 4, 12, 16);
+        let key = Rc5SecretKey::new(vec![1; 16]);
+        assert!(wrap_key(&bad_kek, &key).is_err());
+        assert!(unwrap_key(&bad_kek, &[0; 24]).is_err());
+    }
+}
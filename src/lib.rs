@@ -1,227 +1,211 @@
-use std::cmp::max;
-use std::marker::PhantomData;
-use traits::{CipherMagicConstants, Rc5CipherStream, Unsigned16To64};
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
+#[cfg(feature = "std")]
+pub use analysis::{KeyExpansionTrace, MixingStep, ReducedRc5, RotationHistogram, RoundAvalanche};
+#[cfg(feature = "std")]
+pub use archive::{ArchiveEntry, ArchiveReader, ArchiveWriter};
+#[cfg(feature = "argon2")]
+pub use argon2::{Argon2Params, ARGON2_MIN_SALT_LEN};
+#[cfg(feature = "std")]
+pub use asn1::{Rc5CbcParameters, RC5_CBC_PAD_OID};
+#[cfg(feature = "std")]
+pub use benchmark::ThroughputReport;
+#[cfg(feature = "std")]
+pub use cipher::RC5;
+#[cfg(feature = "std")]
+use cipher::{KeySchedule, Redacted};
+#[cfg(feature = "compression")]
+pub use compress::{CompressWriter, DecompressReader};
+#[cfg(feature = "std")]
+pub use container::{ContainerReader, ContainerWriter, DEFAULT_CHUNK_SIZE};
+#[cfg(feature = "std")]
+pub use ctr::Rc5Ctr;
+#[cfg(feature = "std")]
+pub use detached::{
+    detached_tag, tag_path, verify_detached, verify_tag_file, write_tag_file, TAG_SUFFIX,
+};
+#[cfg(feature = "std")]
+pub use differential::{Characteristic, DifferentialSearch};
+#[cfg(feature = "std")]
+pub use eax::{try_decrypt_with_any, Eax};
+#[cfg(feature = "std")]
+pub use encoding::{decode_base64, decode_hex, encode_base64, encode_hex};
+pub use error::Rc5Error;
+#[cfg(feature = "std")]
+pub use field::{Encrypted, EncryptedBox, FieldCipher, FieldValue};
+#[cfg(feature = "std")]
+pub use file::{
+    decrypt_file, decrypt_file_mmap, decrypt_file_mmap_with_progress, decrypt_file_with_progress,
+    encrypt_file, encrypt_file_mmap, encrypt_file_mmap_with_progress, encrypt_file_with_progress,
+};
+#[cfg(feature = "std")]
+pub use header::{
+    decrypt_with_header, encrypt_with_header, CipherMode, Compression, HeaderPolicy, Kdf,
+    Rc5Header, HEADER_MAGIC, HEADER_VERSION,
+};
+pub use heapless::{table_len, HeaplessCtr, HeaplessRc5, MAX_KEY_LEN};
+#[cfg(feature = "std")]
+pub use iter::{Rc5DecryptIter, Rc5EncryptIter, Rc5IterExt};
+#[cfg(feature = "std")]
+pub use key::{EncryptionKey, MacKey, Rc5SecretKey, KCV_LEN, PBKDF2_MIN_SALT_LEN};
+#[cfg(feature = "std")]
+pub use keyring::KeyRing;
+#[cfg(feature = "os-keyring")]
+pub use keystore::OsKeyStore;
+#[cfg(feature = "std")]
+pub use keywrap::{unwrap_key, wrap_key};
+#[cfg(feature = "std")]
+pub use mac::Cmac;
+#[cfg(feature = "std")]
+pub use metadata::FileMetadata;
+#[cfg(feature = "std")]
+pub use migrate::{migrate, MigrationSource, Migrator};
+#[cfg(feature = "std")]
+pub use mode::Mode;
+#[cfg(feature = "std")]
+pub use mux::{MuxEvent, MuxReader, MuxWriter};
+#[cfg(feature = "std")]
+pub use openssl::{
+    openssl_cipher, openssl_decrypt, openssl_encrypt, OpensslCipher, OpensslDigest, OpensslKdf,
+    OPENSSL_KEY_LEN, OPENSSL_ROUNDS, OPENSSL_SALT_LEN,
+};
+#[cfg(feature = "std")]
+pub use padding::Padding;
+#[cfg(feature = "parallel")]
+pub use parallel::{par_apply_ctr, par_decrypt_ecb, par_encrypt_ecb};
+#[cfg(feature = "std")]
+pub use params::{
+    AttackCost, AttackEstimate, AttackKind, Rc5Params, Rc5Suite, SecurityAssessment,
+    SecurityWarning,
+};
+#[cfg(feature = "parallel")]
+pub use pipeline::Pipeline;
+#[cfg(feature = "std")]
+pub use pool::BufferPool;
+#[cfg(feature = "reference")]
+pub use reference::{ReferenceRc5, ReferenceRc5_32};
+#[cfg(all(feature = "os-rng", any(unix, target_os = "wasi")))]
+pub use rng::{generate_iv, generate_key, generate_nonce, OsRng};
+#[cfg(feature = "std")]
+pub use rng::{CryptoRng, Nonce};
+#[cfg(feature = "std")]
+pub use seal::{
+    open, open_file, seal, seal_file, Sealer, Secret, MAX_KDF_ITERATIONS, MAX_KDF_LANES,
+    MAX_KDF_MEMORY_KIB, MAX_KDF_PASSES,
+};
+pub use secure::wipe;
+#[cfg(feature = "std")]
+pub use stream::{DecryptChunks, EncryptChunks, StreamDecryptor, StreamEncryptor};
+#[cfg(feature = "timing")]
+pub use timing::{measure_timing, TimingReport, TimingVerdict, TIMING_LEAK_THRESHOLD};
+#[cfg(feature = "tracing")]
+pub use trace::{clear_tracer, set_tracer, TraceEvent, TraceOperation, Tracer};
+pub use traits::{Block, CipherMagicConstants, Unsigned16To64};
+#[cfg(feature = "std")]
+pub use traits::{Rc5CipherStream, Rc5Decryptor, Rc5Encryptor};
+
+#[cfg(feature = "std")]
+mod analysis;
+#[cfg(feature = "std")]
+mod archive;
+#[cfg(feature = "argon2")]
+mod argon2;
+#[cfg(feature = "std")]
+pub mod armor;
+#[cfg(feature = "std")]
+mod asn1;
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+mod avx2;
+#[cfg(feature = "std")]
+mod benchmark;
+#[cfg(feature = "std")]
+mod cipher;
+#[cfg(feature = "compression")]
+mod compress;
+#[cfg(feature = "std")]
+mod container;
+#[cfg(feature = "std")]
+mod ct;
+#[cfg(feature = "std")]
+mod ctr;
+#[cfg(feature = "std")]
+mod detached;
+#[cfg(feature = "std")]
+mod differential;
+#[cfg(feature = "std")]
+mod digest;
+#[cfg(feature = "std")]
+mod eax;
+#[cfg(feature = "std")]
+mod encoding;
+mod error;
+#[cfg(all(feature = "std", test))]
+mod exhaustive;
+#[cfg(feature = "ffi")]
+mod ffi;
+#[cfg(feature = "std")]
+mod field;
+#[cfg(feature = "std")]
+mod file;
+#[cfg(feature = "std")]
+mod header;
+mod heapless;
+#[cfg(feature = "std")]
+mod iter;
+#[cfg(all(feature = "std", test))]
+mod kat;
+#[cfg(feature = "std")]
+mod key;
+#[cfg(feature = "std")]
+mod keyring;
+#[cfg(feature = "os-keyring")]
+mod keystore;
+#[cfg(feature = "std")]
+mod keywrap;
+#[cfg(feature = "std")]
+mod mac;
+#[cfg(feature = "std")]
+mod metadata;
+#[cfg(feature = "std")]
+mod migrate;
+#[cfg(feature = "std")]
+mod mode;
+#[cfg(feature = "std")]
+mod mux;
+#[cfg(feature = "std")]
+mod openssl;
+#[cfg(feature = "std")]
+mod padding;
+#[cfg(feature = "parallel")]
+mod parallel;
+#[cfg(feature = "std")]
+mod params;
+#[cfg(feature = "parallel")]
+mod pipeline;
+#[cfg(feature = "std")]
+mod pool;
+#[cfg(all(kani, feature = "verification"))]
+mod proofs;
+#[cfg(all(feature = "std", test))]
+mod properties;
+#[cfg(any(feature = "reference", test))]
+mod reference;
+#[cfg(feature = "std")]
+mod rng;
+#[cfg(feature = "std")]
+mod seal;
+mod secure;
+#[cfg(feature = "simd")]
+mod simd;
+#[cfg(feature = "std")]
+mod stream;
+#[cfg(feature = "timing")]
+mod timing;
+#[cfg(feature = "tracing")]
+mod trace;
 mod traits;
-
-struct RC5<T: Unsigned16To64> {
-    key: Vec<u8>,
-    words: usize,
-    rounds: usize,
-    bytes: usize,
-    data: PhantomData<T>,
-}
-
-fn generate_block_cipher<T>(rc_5: &RC5<T>) -> Result<Vec<T>, &'static str>
-where
-    T: Unsigned16To64 + CipherMagicConstants + Copy,
-{
-    // by the protocol design, we are guaranteed that the length of the
-    // key block is less than 255 = 2^8 - 1
-    let mut l = if rc_5.key.is_empty() {
-        return Err("key must not be empty");
-    } else {
-        (0..(rc_5.key.len() as u8))
-            .step_by(rc_5.words)
-            .map(|i| {
-                let slice = T::copy_from_slice(&rc_5.key, i as usize, i as usize + 4);
-                T::from_le_bytes(slice)
-            })
-            .collect::<Vec<T>>()
-    };
-
-    let p_w = T::P_W; // first magic number
-    let q_w = T::Q_W; // second magic number
-
-    let s_table = 0..(2 * (rc_5.rounds + 1));
-    let mut s_table = s_table
-        .into_iter()
-        .map(|x| T::from_usize(x).wrapping_mul(q_w).wrapping_add(p_w))
-        .collect::<Vec<T>>();
-
-    let mut i = T::zero().to_usize();
-    let mut j = T::zero().to_usize();
-
-    let mut a_block = T::zero();
-    let mut b_block = T::zero();
-
-    let l_len = l.len();
-    let s_len = s_table.len();
-
-    let max_iters = max(s_len, l_len);
-
-    for _ in 0..(3 * max_iters) {
-        a_block = s_table[i]
-            .wrapping_add(a_block)
-            .wrapping_add(b_block)
-            .rotate_left(T::from_usize(3usize));
-        b_block = (l[j].wrapping_add(a_block).wrapping_add(b_block))
-            .rotate_left(a_block.wrapping_add(b_block));
-
-        s_table[i] = a_block;
-        l[j] = b_block;
-
-        i = (i + 1) % s_len;
-        j = (j + 1) % l_len;
-    }
-
-    Ok(s_table)
-}
-
-impl<T: Unsigned16To64> RC5<T> {
-    #[allow(dead_code)]
-    fn new(key: Vec<u8>, words: usize, rounds: usize, bytes: usize) -> Self {
-        RC5 {
-            key,
-            words,
-            rounds,
-            bytes,
-            data: PhantomData,
-        }
-    }
-}
-
-impl<T: Unsigned16To64 + CipherMagicConstants + Copy> Rc5CipherStream<T> for RC5<T> {
-    fn encode(&self, plaintext: &[u8]) -> Result<Vec<u8>, &'static str> {
-        if self.key.len() != self.bytes {
-            return Err("invalid encryption key length");
-        }
-
-        let mut a_block = T::copy_from_slice(plaintext, 0usize, self.words);
-        let mut a_from_le_bytes = T::from_le_bytes(a_block);
-
-        let mut b_block = T::copy_from_slice(plaintext, self.words, plaintext.len());
-        let mut b_from_le_bytes = T::from_le_bytes(b_block);
-
-        let s_table = generate_block_cipher(self)?;
-
-        // initialize encryption of blocks A and B
-        a_from_le_bytes = a_from_le_bytes.wrapping_add(s_table[0]);
-        b_from_le_bytes = b_from_le_bytes.wrapping_add(s_table[1]);
-
-        // the algorithm uses ROUNDS iterations, but it starts with a zeroth evaluation first
-        for i in 1..(self.rounds + 1) {
-            a_from_le_bytes = (a_from_le_bytes.xor(b_from_le_bytes))
-                .rotate_left(b_from_le_bytes)
-                .wrapping_add(s_table[2 * i]);
-            b_from_le_bytes = (b_from_le_bytes.xor(a_from_le_bytes))
-                .rotate_left(a_from_le_bytes)
-                .wrapping_add(s_table[2 * i + 1]);
-        }
-
-        a_block = a_from_le_bytes.to_le_bytes();
-        b_block = b_from_le_bytes.to_le_bytes();
-
-        let mut ciphertext = Vec::with_capacity(2 * std::mem::size_of::<Self>());
-        ciphertext.extend_from_slice(a_block.as_ref());
-        ciphertext.extend_from_slice(b_block.as_ref());
-
-        Ok(ciphertext)
-    }
-
-    fn decode(&self, plaintext: &[u8]) -> Result<Vec<u8>, &'static str> {
-        if self.key.len() != self.bytes {
-            return Err("invalid decryption key length");
-        }
-
-        let mut a_block = T::copy_from_slice(plaintext, 0usize, self.words);
-        let mut a_from_le_bytes = T::from_le_bytes(a_block);
-
-        let mut b_block = T::copy_from_slice(plaintext, self.words, plaintext.len());
-        let mut b_from_le_bytes = T::from_le_bytes(b_block);
-
-        let s_table = generate_block_cipher(self)?;
-
-        // the algorithm uses ROUND iterations, but it starts with a zeroth evaluation first
-        for i in (1..(self.rounds + 1)).rev() {
-            b_from_le_bytes = b_from_le_bytes
-                .wrapping_sub(s_table[2 * i + 1])
-                .rotate_right(a_from_le_bytes)
-                .xor(a_from_le_bytes);
-            a_from_le_bytes = a_from_le_bytes
-                .wrapping_sub(s_table[2 * i])
-                .rotate_right(b_from_le_bytes)
-                .xor(b_from_le_bytes);
-        }
-
-        // last iteration
-        a_from_le_bytes = a_from_le_bytes.wrapping_sub(s_table[0]);
-        b_from_le_bytes = b_from_le_bytes.wrapping_sub(s_table[1]);
-
-        a_block = a_from_le_bytes.to_le_bytes();
-        b_block = b_from_le_bytes.to_le_bytes();
-
-        let mut ciphertext = Vec::with_capacity(2 * std::mem::size_of::<T>());
-        ciphertext.extend_from_slice(a_block.as_ref());
-        ciphertext.extend_from_slice(b_block.as_ref());
-  
-        Ok(ciphertext)
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    const WORDS: usize = 4; // 4-bytes long, or 32-bit long
-    const ROUNDS: usize = 12; // 1 round total
-    const BYTES: usize = 16; // Key generation of length 10-bytes
-
-    #[test]
-    fn encode_a() {
-        let key = vec![
-            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D,
-            0x0E, 0x0F,
-        ];
-
-        let rc_5 = RC5::<u32>::new(key, WORDS, ROUNDS, BYTES);
-
-        let pt = vec![0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
-        let ct = vec![0x2D, 0xDC, 0x14, 0x9B, 0xCF, 0x08, 0x8B, 0x9E];
-        let res = rc_5.encode(&pt).unwrap();
-        assert_eq!(ct, res);
-    }
-
-    #[test]
-    fn encode_b() {
-        let key = vec![
-            0x2B, 0xD6, 0x45, 0x9F, 0x82, 0xC5, 0xB3, 0x00, 0x95, 0x2C, 0x49, 0x10, 0x48, 0x81,
-            0xFF, 0x48,
-        ];
-
-        let rc_5 = RC5::<u32>::new(key, WORDS, ROUNDS, BYTES);
-
-        let pt = vec![0xEA, 0x02, 0x47, 0x14, 0xAD, 0x5C, 0x4D, 0x84];
-        let ct = vec![0x11, 0xE4, 0x3B, 0x86, 0xD2, 0x31, 0xEA, 0x64];
-        let res = rc_5.encode(&pt).unwrap();
-        assert_eq!(ct, res);
-    }
-
-    #[test]
-    fn decode_a() {
-        let key = vec![
-            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D,
-            0x0E, 0x0F,
-        ];
-
-        let rc_5 = RC5::<u32>::new(key, WORDS, ROUNDS, BYTES);
-
-        let pt = vec![0x96, 0x95, 0x0D, 0xDA, 0x65, 0x4A, 0x3D, 0x62];
-        let ct = vec![0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
-        let res = rc_5.decode(&ct).unwrap();
-        assert!(&pt[..] == &res[..]);
-    }
-
-    #[test]
-    fn decode_b() {
-        let key = vec![
-            0x2B, 0xD6, 0x45, 0x9F, 0x82, 0xC5, 0xB3, 0x00, 0x95, 0x2C, 0x49, 0x10, 0x48, 0x81,
-            0xFF, 0x48,
-        ];
-
-        let rc_5 = RC5::<u32>::new(key, WORDS, ROUNDS, BYTES);
-
-        let pt = vec![0x63, 0x8B, 0x3A, 0x5E, 0xF7, 0x2B, 0x66, 0x3F];
-        let ct = vec![0xEA, 0x02, 0x47, 0x14, 0xAD, 0x5C, 0x4D, 0x84];
-        let res = rc_5.decode(&ct).unwrap();
-        assert!(&pt[..] == &res[..]);
-    }
-}
+#[cfg(feature = "std")]
+mod unrolled;
+#[cfg(feature = "std")]
+pub mod vectors;
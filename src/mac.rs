@@ -0,0 +1,253 @@
+use std::io::{self, Read};
+
+use crate::ct::ct_eq;
+use crate::mode::xor_in_place;
+#[cfg(feature = "tracing")]
+use crate::trace::{self, TraceEvent};
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{KeySchedule, MacKey, Rc5Error};
+
+// CMAC message authentication over RC5. It only accepts a `MacKey`, so a
+// key meant for encryption can't be used for authentication by mistake
+pub struct Cmac<T: Unsigned16To64> {
+    schedule: KeySchedule<T>,
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> Cmac<T> {
+    pub fn new(key: &MacKey<T>) -> Result<Self, Rc5Error> {
+        Ok(Cmac {
+            schedule: key.cipher().key_schedule()?,
+        })
+    }
+}
+
+impl<T: Unsigned16To64 + Copy> Cmac<T> {
+    pub fn tag_len(&self) -> usize {
+        self.schedule.block_len()
+    }
+
+    pub fn tag(&self, data: &[u8]) -> Vec<u8> {
+        cmac(&self.schedule, data)
+    }
+
+    // the tag of everything `reader` yields, for messages too large to
+    // hold in memory
+    pub fn tag_reader<R: Read>(&self, mut reader: R) -> io::Result<Vec<u8>> {
+        let mut omac = Omac::new(&self.schedule);
+        let mut buffer = [0u8; 1 << 13];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => return Ok(omac.finish()),
+                Ok(read) => omac.update(&buffer[..read]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    pub fn verify(&self, data: &[u8], tag: &[u8]) -> Result<(), Rc5Error> {
+        let verified = ct_eq(&self.tag(data), tag);
+        #[cfg(feature = "tracing")]
+        trace::emit(TraceEvent::Verification {
+            kind: "CMAC",
+            verified,
+        });
+        if !verified {
+            return Err(Rc5Error::DecryptionFailed);
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn cmac<T>(schedule: &KeySchedule<T>, data: &[u8]) -> Vec<u8>
+where
+    T: Unsigned16To64 + Copy,
+{
+    omac(schedule, None, data)
+}
+
+// OMAC with the tweak `[t]_n` prepended as a full block, as used by EAX
+pub(crate) fn omac_t<T>(schedule: &KeySchedule<T>, t: u8, data: &[u8]) -> Vec<u8>
+where
+    T: Unsigned16To64 + Copy,
+{
+    omac(schedule, Some(t), data)
+}
+
+// CMAC (OMAC1) over the RC5 block function, the tag is one block long
+fn omac<T>(schedule: &KeySchedule<T>, tweak: Option<u8>, data: &[u8]) -> Vec<u8>
+where
+    T: Unsigned16To64 + Copy,
+{
+    let mut omac = Omac::new(schedule);
+    if let Some(t) = tweak {
+        let mut block = vec![0u8; schedule.block_len()];
+        block[schedule.block_len() - 1] = t;
+        omac.update(&block);
+    }
+    omac.update(data);
+    omac.finish()
+}
+
+// OMAC computed incrementally, for messages that arrive in pieces
+struct Omac<'a, T: Unsigned16To64> {
+    schedule: &'a KeySchedule<T>,
+    mac: Vec<u8>,
+    // the last block needs the K1 or K2 treatment, so up to one block is
+    // held back until more data shows it wasn't the last
+    pending: Vec<u8>,
+}
+
+impl<'a, T: Unsigned16To64 + Copy> Omac<'a, T> {
+    fn new(schedule: &'a KeySchedule<T>) -> Self {
+        Omac {
+            schedule,
+            mac: vec![0u8; schedule.block_len()],
+            pending: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        let block_len = self.schedule.block_len();
+        self.pending.extend_from_slice(data);
+        if self.pending.len() <= block_len {
+            return;
+        }
+
+        let keep = (self.pending.len() - 1) % block_len + 1;
+        let done = self.pending.len() - keep;
+        for block in self.pending[..done].chunks_exact(block_len) {
+            xor_in_place(&mut self.mac, block);
+            self.schedule.encrypt_block(&mut self.mac);
+        }
+        self.pending.drain(..done);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let block_len = self.schedule.block_len();
+        let mut k1 = vec![0u8; block_len];
+        self.schedule.encrypt_block(&mut k1);
+        double(&mut k1);
+        let mut k2 = k1.clone();
+        double(&mut k2);
+
+        // the last block is xored with K1 when complete, and padded with
+        // 10..0 and xored with K2 otherwise
+        let mut last = self.pending;
+        if last.len() == block_len {
+            xor_in_place(&mut last, &k1);
+        } else {
+            last.push(0x80);
+            last.resize(block_len, 0);
+            xor_in_place(&mut last, &k2);
+        }
+        xor_in_place(&mut self.mac, &last);
+        self.schedule.encrypt_block(&mut self.mac);
+        self.mac
+    }
+}
+
+// multiplication by x in GF(2^n), the reduction constants are those of
+// the lexicographically first irreducible polynomials of degree n
+fn double(block: &mut [u8]) {
+    let reduction = match block.len() {
+        4 => 0x8D,
+        8 => 0x1B,
+        _ => 0x87,
+    };
+
+    let carry = block[0] >> 7;
+    for i in 0..block.len() - 1 {
+        block[i] = (block[i] << 1) | (block[i + 1] >> 7);
+    }
+    let last = block.len() - 1;
+    block[last] = (block[last] << 1) ^ (carry * reduction);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RC5;
+
+    fn schedule() -> KeySchedule<u32> {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        RC5::<u32>::new(key, 4, 12, 16).key_schedule().unwrap()
+    }
+
+    #[test]
+    fn doubling_reduces_on_carry() {
+        let mut block = [0x80, 0, 0, 0, 0, 0, 0, 0x01];
+        double(&mut block);
+        assert_eq!(block, [0, 0, 0, 0, 0, 0, 0, 0x02 ^ 0x1B]);
+
+        let mut block = [0x40, 0, 0, 0, 0, 0, 0, 0];
+        double(&mut block);
+        assert_eq!(block, [0x80, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn single_complete_block_is_encrypted_xor_k1() {
+        let schedule = schedule();
+        let data = [0x11u8; 8];
+
+        let mut k1 = vec![0u8; 8];
+        schedule.encrypt_block(&mut k1);
+        double(&mut k1);
+
+        let mut expected = data.to_vec();
+        xor_in_place(&mut expected, &k1);
+        schedule.encrypt_block(&mut expected);
+
+        assert_eq!(omac(&schedule, None, &data), expected);
+    }
+
+    #[test]
+    fn cmac_verifies_its_own_tags() {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let master = RC5::<u32>::new(key, 4, 12, 16);
+        let cmac = Cmac::new(&MacKey::derive(&master).unwrap()).unwrap();
+
+        let tag = cmac.tag(b"message");
+        assert_eq!(tag.len(), cmac.tag_len());
+        assert!(cmac.verify(b"message", &tag).is_ok());
+        assert!(cmac.verify(b"massage", &tag).is_err());
+        assert!(cmac.verify(b"message", &tag[..4]).is_err());
+    }
+
+    #[test]
+    fn streamed_tags_match_whole_ones() {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let master = RC5::<u32>::new(key, 4, 12, 16);
+        let cmac = Cmac::new(&MacKey::derive(&master).unwrap()).unwrap();
+
+        // a reader that hands out a few bytes at a time, so blocks get
+        // split across reads
+        struct Trickle<'a>(&'a [u8]);
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let count = buf.len().min(self.0.len()).min(3);
+                buf[..count].copy_from_slice(&self.0[..count]);
+                self.0 = &self.0[count..];
+                Ok(count)
+            }
+        }
+
+        let data = (0..100u8).collect::<Vec<u8>>();
+        for len in [0, 1, 7, 8, 9, 16, 17, 100].iter() {
+            let data = &data[..*len];
+            assert_eq!(cmac.tag_reader(Trickle(data)).unwrap(), cmac.tag(data));
+        }
+    }
+
+    #[test]
+    fn tags_depend_on_tweak_and_length() {
+        let schedule = schedule();
+
+        assert_ne!(omac(&schedule, None, b""), omac(&schedule, None, &[0x80]));
+        assert_ne!(omac_t(&schedule, 0, b"abc"), omac_t(&schedule, 1, b"abc"));
+        assert_ne!(
+            omac(&schedule, None, &[0u8; 8]),
+            omac(&schedule, None, &[0u8; 16])
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::Rc5Error;
+
+// What a file looked like before it was encrypted, stored encrypted in
+// its container so the name and times don't have to show on the
+// ciphertext. Encoded as entries of
+//
+//   kind u8 | length u16 | value
+//
+// little-endian, with the kinds
+//
+//   1 name:        UTF-8, a single path component
+//   2 modified:    seconds since the epoch i64 | nanoseconds u32
+//   3 permissions: unix mode bits u32
+//
+// Readers skip kinds they don't know, so later versions can add some
+const NAME: u8 = 1;
+const MODIFIED: u8 = 2;
+const PERMISSIONS: u8 = 3;
+const MAX_NAME_LEN: usize = 1 << 12;
+const PERMISSION_BITS: u32 = 0o7777;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileMetadata {
+    // the file name without any directories
+    pub name: Option<String>,
+    pub modified: Option<SystemTime>,
+    // the permission bits of a unix mode, e.g. 0o640
+    pub permissions: Option<u32>,
+}
+
+impl FileMetadata {
+    // the name, modification time and, on unix, permissions of the file
+    // at `path`
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        #[cfg(unix)]
+        let permissions = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(metadata.permissions().mode() & PERMISSION_BITS)
+        };
+        #[cfg(not(unix))]
+        let permissions = None;
+
+        Ok(FileMetadata {
+            name: path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(str::to_string),
+            modified: metadata.modified().ok(),
+            permissions,
+        })
+    }
+
+    // gives the file at `path` the recorded modification time and
+    // permissions. The name is left to the caller, which knows where the
+    // file belongs. Permissions are ignored off unix
+    pub fn apply(&self, path: &Path) -> io::Result<()> {
+        if let Some(modified) = self.modified {
+            File::options()
+                .write(true)
+                .open(path)?
+                .set_modified(modified)?;
+        }
+        #[cfg(unix)]
+        if let Some(mode) = self.permissions {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(mode & PERMISSION_BITS))?;
+        }
+        Ok(())
+    }
+
+    // `InvalidEncoding` for a name that isn't a single path component
+    pub(crate) fn to_bytes(&self) -> Result<Vec<u8>, Rc5Error> {
+        let mut bytes = Vec::new();
+        let mut entry = |kind: u8, value: &[u8]| {
+            bytes.push(kind);
+            bytes.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(value);
+        };
+
+        if let Some(name) = &self.name {
+            if !is_file_name(name) {
+                return Err(Rc5Error::InvalidEncoding);
+            }
+            entry(NAME, name.as_bytes());
+        }
+        if let Some(modified) = self.modified {
+            let (seconds, nanos) = match modified.duration_since(UNIX_EPOCH) {
+                Ok(since) => (since.as_secs() as i64, since.subsec_nanos()),
+                Err(err) => {
+                    let before = err.duration();
+                    match before.subsec_nanos() {
+                        0 => (-(before.as_secs() as i64), 0),
+                        nanos => (-(before.as_secs() as i64) - 1, 1_000_000_000 - nanos),
+                    }
+                }
+            };
+            let mut value = seconds.to_le_bytes().to_vec();
+            value.extend_from_slice(&nanos.to_le_bytes());
+            entry(MODIFIED, &value);
+        }
+        if let Some(mode) = self.permissions {
+            entry(PERMISSIONS, &(mode & PERMISSION_BITS).to_le_bytes());
+        }
+        Ok(bytes)
+    }
+
+    pub(crate) fn from_bytes(mut bytes: &[u8]) -> Result<Self, Rc5Error> {
+        let mut metadata = FileMetadata::default();
+        while !bytes.is_empty() {
+            if bytes.len() < 3 {
+                return Err(Rc5Error::InvalidEncoding);
+            }
+            let kind = bytes[0];
+            let len = u16::from_le_bytes([bytes[1], bytes[2]]) as usize;
+            let value = bytes.get(3..3 + len).ok_or(Rc5Error::InvalidEncoding)?;
+            bytes = &bytes[3 + len..];
+
+            match kind {
+                NAME if metadata.name.is_none() => {
+                    let name = std::str::from_utf8(value)
+                        .ok()
+                        .filter(|name| is_file_name(name))
+                        .ok_or(Rc5Error::InvalidEncoding)?;
+                    metadata.name = Some(name.to_string());
+                }
+                MODIFIED if metadata.modified.is_none() && value.len() == 12 => {
+                    let mut seconds = [0u8; 8];
+                    seconds.copy_from_slice(&value[..8]);
+                    let seconds = i64::from_le_bytes(seconds);
+                    let nanos = u32::from_le_bytes([value[8], value[9], value[10], value[11]]);
+                    metadata.modified = Some(time(seconds, nanos)?);
+                }
+                PERMISSIONS if metadata.permissions.is_none() && value.len() == 4 => {
+                    let mode = u32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+                    metadata.permissions = Some(mode & PERMISSION_BITS);
+                }
+                // repeated or malformed known entries
+                NAME | MODIFIED | PERMISSIONS => return Err(Rc5Error::InvalidEncoding),
+                _ => {}
+            }
+        }
+        Ok(metadata)
+    }
+}
+
+// a name that can't step out of the directory it is restored into
+pub(crate) fn is_file_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\', '\0'])
+}
+
+fn time(seconds: i64, nanos: u32) -> Result<SystemTime, Rc5Error> {
+    if nanos >= 1_000_000_000 {
+        return Err(Rc5Error::InvalidEncoding);
+    }
+    let time = if seconds >= 0 {
+        UNIX_EPOCH.checked_add(Duration::new(seconds as u64, nanos))
+    } else {
+        UNIX_EPOCH
+            .checked_sub(Duration::from_secs(seconds.unsigned_abs()))
+            .and_then(|time| time.checked_add(Duration::from_nanos(nanos as u64)))
+    };
+    time.ok_or(Rc5Error::InvalidEncoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_round_trips() {
+        let full = FileMetadata {
+            name: Some("report.pdf".to_string()),
+            modified: Some(UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789)),
+            permissions: Some(0o640),
+        };
+        let before_epoch = FileMetadata {
+            modified: Some(UNIX_EPOCH - Duration::new(5, 250)),
+            ..FileMetadata::default()
+        };
+        for metadata in [full, before_epoch, FileMetadata::default()].iter() {
+            let bytes = metadata.to_bytes().unwrap();
+            assert_eq!(FileMetadata::from_bytes(&bytes).unwrap(), *metadata);
+        }
+
+        // unknown kinds are skipped
+        let mut bytes = vec![9, 2, 0, 1, 2];
+        bytes.extend_from_slice(&FileMetadata::default().to_bytes().unwrap());
+        assert_eq!(
+            FileMetadata::from_bytes(&bytes).unwrap(),
+            FileMetadata::default()
+        );
+    }
+
+    #[test]
+    fn names_stay_in_their_directory() {
+        for name in ["", ".", "..", "a/b", "..\\b", "a\0"].iter() {
+            let metadata = FileMetadata {
+                name: Some(name.to_string()),
+                ..FileMetadata::default()
+            };
+            assert_eq!(
+                metadata.to_bytes(),
+                Err(Rc5Error::InvalidEncoding),
+                "{}",
+                name
+            );
+
+            let mut bytes = vec![NAME, name.len() as u8, 0];
+            bytes.extend_from_slice(name.as_bytes());
+            assert_eq!(
+                FileMetadata::from_bytes(&bytes),
+                Err(Rc5Error::InvalidEncoding)
+            );
+        }
+
+        let malformed: [&[u8]; 4] = [
+            &[NAME, 5, 0, b'a'],
+            &[PERMISSIONS, 2, 0, 0, 0],
+            &[NAME, 1, 0, b'a', NAME, 1, 0, b'b'],
+            &[
+                MODIFIED, 12, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xCA, 0x9A, 0x3B,
+            ],
+        ];
+        for bytes in malformed.iter() {
+            assert_eq!(
+                FileMetadata::from_bytes(bytes),
+                Err(Rc5Error::InvalidEncoding)
+            );
+        }
+    }
+
+    #[test]
+    fn metadata_is_taken_from_and_applied_to_files() {
+        let dir = std::env::temp_dir().join(format!("rc5_metadata_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes.txt");
+        fs::write(&path, b"notes").unwrap();
+
+        let metadata = FileMetadata {
+            name: Some("elsewhere.txt".to_string()),
+            modified: Some(UNIX_EPOCH + Duration::from_secs(1_000_000_000)),
+            permissions: Some(0o600),
+        };
+        metadata.apply(&path).unwrap();
+        let read = FileMetadata::from_file(&path).unwrap();
+        assert_eq!(read.name.as_deref(), Some("notes.txt"));
+        assert_eq!(read.modified, metadata.modified);
+        #[cfg(unix)]
+        assert_eq!(read.permissions, Some(0o600));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
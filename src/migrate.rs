@@ -0,0 +1,685 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::rng::CryptoRng;
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{
+    CipherMode, Compression, ContainerReader, ContainerWriter, FileMetadata, HeaderPolicy, Kdf,
+    Mode, Padding, Rc5Error, Rc5Header, Rc5Params, Rc5SecretKey, StreamDecryptor,
+    DEFAULT_CHUNK_SIZE, RC5,
+};
+
+// Migration re-encrypts stored ciphertext of an older layout into the
+// current one, an `Rc5Header` for EAX followed by the container, as
+// `encrypt_with_header` writes it. The plaintext is streamed from the
+// source decryption into the new container, and never held whole
+const BUFFER_LEN: usize = 1 << 16;
+
+// what the data to migrate looks like
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MigrationSource {
+    // ECB, CBC or CTR ciphertext without a header, as `StreamEncryptor`
+    // writes it, preceded by the IV or nonce unless it is given here, as
+    // `rc5 encrypt --mode cbc` does. Nothing but the padding can be
+    // checked, so a wrong key may go unnoticed
+    Raw {
+        words: usize,
+        rounds: usize,
+        mode: CipherMode,
+        iv: Option<Vec<u8>>,
+        padding: Padding,
+    },
+    // a `ContainerWriter` container, whose metadata is carried over
+    Container {
+        words: usize,
+        rounds: usize,
+    },
+    // what `encrypt_with_header` writes, with any parameters the policy
+    // allows
+    Headed(HeaderPolicy),
+}
+
+// the choices of `migrate` for when its defaults don't fit: by default
+// the result is RC5-32/12 under the new key
+#[derive(Clone, Debug)]
+pub struct Migrator<'a> {
+    source: MigrationSource,
+    old_key: &'a Rc5SecretKey,
+    new_key: &'a Rc5SecretKey,
+    words: usize,
+    rounds: usize,
+}
+
+impl<'a> Migrator<'a> {
+    pub fn new(
+        source: MigrationSource,
+        old_key: &'a Rc5SecretKey,
+        new_key: &'a Rc5SecretKey,
+    ) -> Self {
+        Migrator {
+            source,
+            old_key,
+            new_key,
+            words: 4,
+            rounds: 12,
+        }
+    }
+
+    // bytes per word and rounds of the result, whose key length is the
+    // one of the new key
+    pub fn cipher(mut self, words: usize, rounds: usize) -> Self {
+        self.words = words;
+        self.rounds = rounds;
+        self
+    }
+
+    // decrypts `reader` and writes it re-encrypted to `writer`, returning
+    // the number of plaintext bytes. The source is verified as it is read,
+    // as far as its format allows, and a failure with `InvalidData` means
+    // it was modified, truncated or is under another key. The new
+    // ciphertext is written as the old is read, so after an error
+    // whatever reached `writer` must be discarded
+    pub fn migrate<G, R, W>(&self, mut rng: G, reader: R, mut writer: W) -> io::Result<u64>
+    where
+        G: CryptoRng,
+        R: Read,
+        W: Write,
+    {
+        let (plaintext, metadata) = plaintext(&self.source, self.old_key, reader)?;
+        let mut nonce = vec![0u8; 2 * self.words];
+        rng.fill_bytes(&mut nonce);
+        let header = Rc5Header {
+            params: Rc5Params {
+                words: self.words,
+                rounds: self.rounds,
+                bytes: self.new_key.len(),
+            },
+            mode: CipherMode::Eax,
+            kdf: Kdf::None,
+            iv: nonce,
+            key_id: None,
+            compression: Compression::None,
+            padding: Padding::Pkcs7,
+        };
+        header.write_to(&mut writer)?;
+
+        let target = Target {
+            header: &header,
+            key: self.new_key,
+            metadata,
+        };
+        match self.words {
+            #[cfg(feature = "rc5-16")]
+            2 => target.write::<u16, _>(plaintext, writer),
+            4 => target.write::<u32, _>(plaintext, writer),
+            #[cfg(feature = "rc5-64")]
+            8 => target.write::<u64, _>(plaintext, writer),
+            _ => Err(invalid_input(Rc5Error::InvalidWordSize)),
+        }
+    }
+
+    // `migrate` from file to file, which then decrypts both files again
+    // and compares them before it succeeds. The output must not exist
+    // yet, and is removed again if anything fails
+    pub fn migrate_file<G: CryptoRng>(
+        &self,
+        rng: G,
+        input: &Path,
+        output: &Path,
+    ) -> io::Result<u64> {
+        let reader = BufReader::new(File::open(input)?);
+        let mut writer = BufWriter::new(File::options().write(true).create_new(true).open(output)?);
+        let result = self
+            .migrate(rng, reader, &mut writer)
+            .and_then(|len| writer.flush().map(|()| len));
+        drop(writer);
+
+        let result = result.and_then(|len| self.verify(input, output).map(|()| len));
+        if result.is_err() {
+            let _ = fs::remove_file(output);
+        }
+        result
+    }
+
+    fn verify(&self, input: &Path, output: &Path) -> io::Result<()> {
+        let (mut old, _) = plaintext(
+            &self.source,
+            self.old_key,
+            BufReader::new(File::open(input)?),
+        )?;
+        let migrated =
+            MigrationSource::Headed(HeaderPolicy::new().allow_cipher(self.words, self.rounds));
+        let (mut new, _) = plaintext(&migrated, self.new_key, BufReader::new(File::open(output)?))?;
+
+        let (mut expected, mut actual) = (vec![0u8; BUFFER_LEN], vec![0u8; BUFFER_LEN]);
+        loop {
+            let len = fill(&mut old, &mut expected)?;
+            if fill(&mut new, &mut actual)? != len || expected[..len] != actual[..len] {
+                return Err(invalid_data(
+                    "the migrated file doesn't decrypt to the original",
+                ));
+            }
+            if len == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// re-encrypts `reader` from `source` under `old_key` into the current
+// format as RC5-32/12 under `new_key`, see `Migrator` for other ciphers
+// and for migrating files
+pub fn migrate<G, R, W>(
+    rng: G,
+    reader: R,
+    writer: W,
+    source: &MigrationSource,
+    old_key: &Rc5SecretKey,
+    new_key: &Rc5SecretKey,
+) -> io::Result<u64>
+where
+    G: CryptoRng,
+    R: Read,
+    W: Write,
+{
+    Migrator::new(source.clone(), old_key, new_key).migrate(rng, reader, writer)
+}
+
+struct Target<'a> {
+    header: &'a Rc5Header,
+    key: &'a Rc5SecretKey,
+    metadata: Option<FileMetadata>,
+}
+
+impl Target<'_> {
+    fn write<T, W>(&self, mut plaintext: Box<dyn Read + '_>, writer: W) -> io::Result<u64>
+    where
+        T: Unsigned16To64 + CipherMagicConstants + Copy,
+        W: Write,
+    {
+        let Rc5Params {
+            words,
+            rounds,
+            bytes,
+        } = self.header.params;
+        let cipher = RC5::<T>::with_key(self.key, words, rounds, bytes);
+        let nonce = &self.header.iv;
+        let mut container = match &self.metadata {
+            Some(metadata) => ContainerWriter::with_metadata(
+                writer,
+                &cipher,
+                DEFAULT_CHUNK_SIZE,
+                nonce,
+                metadata,
+            )?,
+            None => ContainerWriter::new(writer, &cipher, DEFAULT_CHUNK_SIZE, nonce)?,
+        };
+        let len = io::copy(&mut plaintext, &mut container)?;
+        container.finish()?;
+        Ok(len)
+    }
+}
+
+type Plaintext<'a> = (Box<dyn Read + 'a>, Option<FileMetadata>);
+
+// the decryption of `reader`, and the metadata the source recorded
+fn plaintext<'a, R: Read + 'a>(
+    source: &MigrationSource,
+    key: &Rc5SecretKey,
+    mut reader: R,
+) -> io::Result<Plaintext<'a>> {
+    let header = match source {
+        MigrationSource::Raw {
+            words,
+            rounds,
+            mode,
+            iv,
+            padding,
+        } => {
+            if *mode == CipherMode::Eax {
+                return Err(invalid_input("EAX is migrated from a container"));
+            }
+            let iv = match (mode, iv) {
+                (CipherMode::Ecb, _) => Vec::new(),
+                (_, Some(iv)) => iv.clone(),
+                (_, None) => {
+                    let mut iv = vec![0u8; 2 * words];
+                    reader.read_exact(&mut iv)?;
+                    iv
+                }
+            };
+            let header = Rc5Header {
+                params: Rc5Params {
+                    words: *words,
+                    rounds: *rounds,
+                    bytes: key.len(),
+                },
+                mode: *mode,
+                kdf: Kdf::None,
+                iv,
+                key_id: None,
+                compression: Compression::None,
+                padding: *padding,
+            };
+            // the checks of a header apply all the same
+            header.to_bytes().map_err(invalid_input)?;
+            header
+        }
+        MigrationSource::Container { words, rounds } => {
+            let params = Rc5Params {
+                words: *words,
+                rounds: *rounds,
+                bytes: key.len(),
+            };
+            return match words {
+                #[cfg(feature = "rc5-16")]
+                2 => container_plaintext::<u16, _>(params, key, reader),
+                4 => container_plaintext::<u32, _>(params, key, reader),
+                #[cfg(feature = "rc5-64")]
+                8 => container_plaintext::<u64, _>(params, key, reader),
+                _ => Err(invalid_input(Rc5Error::InvalidWordSize)),
+            };
+        }
+        MigrationSource::Headed(policy) => {
+            let header = Rc5Header::read_from(&mut reader)?;
+            policy.check(&header).map_err(invalid_data)?;
+            if header.kdf != Kdf::None || header.compression != Compression::None {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "the header asks for a key derivation or compression, see `open`",
+                ));
+            }
+            if header.params.bytes != key.len() {
+                return Err(invalid_input(Rc5Error::InvalidKeyLength));
+            }
+            if header.mode == CipherMode::Eax {
+                return match header.params.words {
+                    #[cfg(feature = "rc5-16")]
+                    2 => container_plaintext::<u16, _>(header.params, key, reader),
+                    4 => container_plaintext::<u32, _>(header.params, key, reader),
+                    #[cfg(feature = "rc5-64")]
+                    8 => container_plaintext::<u64, _>(header.params, key, reader),
+                    _ => Err(invalid_input(Rc5Error::InvalidWordSize)),
+                };
+            }
+            header
+        }
+    };
+
+    match header.params.words {
+        #[cfg(feature = "rc5-16")]
+        2 => stream_plaintext::<u16, _>(&header, key, reader),
+        4 => stream_plaintext::<u32, _>(&header, key, reader),
+        #[cfg(feature = "rc5-64")]
+        8 => stream_plaintext::<u64, _>(&header, key, reader),
+        _ => Err(invalid_input(Rc5Error::InvalidWordSize)),
+    }
+}
+
+fn container_plaintext<'a, T, R>(
+    params: Rc5Params,
+    key: &Rc5SecretKey,
+    reader: R,
+) -> io::Result<Plaintext<'a>>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy + 'static,
+    R: Read + 'a,
+{
+    let cipher = RC5::<T>::with_key(key, params.words, params.rounds, params.bytes);
+    let mut container = ContainerReader::new(reader, &cipher)?;
+    let metadata = container.metadata()?.cloned();
+    Ok((Box::new(container), metadata))
+}
+
+fn stream_plaintext<'a, T, R>(
+    header: &Rc5Header,
+    key: &Rc5SecretKey,
+    reader: R,
+) -> io::Result<Plaintext<'a>>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy + 'static,
+    R: Read + 'a,
+{
+    let Rc5Params {
+        words,
+        rounds,
+        bytes,
+    } = header.params;
+    let mode = match header.mode {
+        CipherMode::Ecb => Mode::Ecb,
+        CipherMode::Cbc => Mode::Cbc {
+            iv: header.iv.clone(),
+        },
+        CipherMode::Ctr => Mode::Ctr {
+            nonce: header.iv.clone(),
+        },
+        CipherMode::Eax => unreachable!("EAX is read as a container"),
+    };
+    let cipher = RC5::<T>::with_key(key, words, rounds, bytes);
+    let decryptor = StreamDecryptor::new(&cipher, mode)
+        .map_err(invalid_input)?
+        .with_padding(header.padding);
+    Ok((
+        Box::new(Decrypting {
+            reader,
+            decryptor: Some(decryptor),
+            input: vec![0u8; BUFFER_LEN],
+            output: Vec::new(),
+            position: 0,
+        }),
+        None,
+    ))
+}
+
+// the plaintext of a `StreamDecryptor` as a reader. The padding is only
+// checked at the end, where a bad one gives `InvalidData`
+struct Decrypting<T: Unsigned16To64, R> {
+    reader: R,
+    // `None` once finalized
+    decryptor: Option<StreamDecryptor<T>>,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    position: usize,
+}
+
+impl<T, R> Read for Decrypting<T, R>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.output.len() {
+            let decryptor = match &mut self.decryptor {
+                Some(decryptor) => decryptor,
+                None => return Ok(0),
+            };
+            let read = self.reader.read(&mut self.input)?;
+            self.output = match read {
+                0 => self
+                    .decryptor
+                    .take()
+                    .expect("checked above")
+                    .finalize()
+                    .map_err(invalid_data)?,
+                _ => decryptor.update(&self.input[..read]),
+            };
+            self.position = 0;
+        }
+
+        let count = buf.len().min(self.output.len() - self.position);
+        buf[..count].copy_from_slice(&self.output[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+// reads until `buf` is full or the input ends
+fn fill(reader: &mut dyn Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..])? {
+            0 => break,
+            read => len += read,
+        }
+    }
+    Ok(len)
+}
+
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn invalid_input<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "rc5-64")]
+    use crate::encrypt_with_header;
+    use crate::rng::tests::CountingRng;
+    use crate::{decrypt_with_header, StreamEncryptor};
+
+    fn old_key() -> Rc5SecretKey {
+        Rc5SecretKey::new((0u8..16).collect())
+    }
+
+    fn new_key() -> Rc5SecretKey {
+        Rc5SecretKey::new(vec![0x5A; 32])
+    }
+
+    fn data() -> Vec<u8> {
+        (0..100_000u32).map(|x| (x * 7) as u8).collect()
+    }
+
+    // the plaintext of migrated ciphertext, checking it is in the current
+    // format
+    fn migrated(ciphertext: &[u8], policy: &HeaderPolicy) -> Vec<u8> {
+        let mut plaintext = Vec::new();
+        let header = decrypt_with_header(ciphertext, &mut plaintext, &new_key(), policy).unwrap();
+        assert_eq!(header.mode, CipherMode::Eax);
+        plaintext
+    }
+
+    #[test]
+    fn raw_cbc_is_migrated_into_a_container() {
+        let cipher = RC5::<u32>::new((0u8..16).collect::<Vec<u8>>(), 4, 12, 16);
+        let iv = vec![9u8; 8];
+        for padding in [Padding::Pkcs7, Padding::Iso7816].iter() {
+            let mut encryptor = StreamEncryptor::new(&cipher, Mode::Cbc { iv: iv.clone() })
+                .unwrap()
+                .with_padding(*padding);
+            // the IV first, as the command line writes it
+            let mut ciphertext = iv.clone();
+            ciphertext.extend_from_slice(&encryptor.update(&data()));
+            ciphertext.extend_from_slice(&encryptor.finalize());
+
+            let source = MigrationSource::Raw {
+                words: 4,
+                rounds: 12,
+                mode: CipherMode::Cbc,
+                iv: None,
+                padding: *padding,
+            };
+            let mut output = Vec::new();
+            let len = migrate(
+                CountingRng(1),
+                &ciphertext[..],
+                &mut output,
+                &source,
+                &old_key(),
+                &new_key(),
+            )
+            .unwrap();
+            assert_eq!(len, 100_000);
+            assert_eq!(migrated(&output, &HeaderPolicy::new()), data());
+
+            // and with the IV given instead
+            let source = MigrationSource::Raw {
+                words: 4,
+                rounds: 12,
+                mode: CipherMode::Cbc,
+                iv: Some(iv.clone()),
+                padding: *padding,
+            };
+            let mut output = Vec::new();
+            migrate(
+                CountingRng(1),
+                &ciphertext[8..],
+                &mut output,
+                &source,
+                &old_key(),
+                &new_key(),
+            )
+            .unwrap();
+            assert_eq!(migrated(&output, &HeaderPolicy::new()), data());
+
+            // bad padding at the end is an error
+            let truncated = &ciphertext[..ciphertext.len() - 1];
+            assert!(migrate(
+                CountingRng(1),
+                truncated,
+                io::sink(),
+                &source,
+                &old_key(),
+                &new_key()
+            )
+            .is_err());
+        }
+    }
+
+    #[cfg(feature = "rc5-64")]
+    #[test]
+    fn containers_and_headed_data_change_key_and_cipher() {
+        let cipher = RC5::<u32>::new((0u8..16).collect::<Vec<u8>>(), 4, 12, 16);
+        let metadata = FileMetadata {
+            name: Some("kept.txt".to_string()),
+            ..FileMetadata::default()
+        };
+        let mut container =
+            ContainerWriter::with_metadata(Vec::new(), &cipher, 1000, &[3; 8], &metadata).unwrap();
+        container.write_all(&data()).unwrap();
+        let container = container.finish().unwrap();
+
+        let (old, new) = (old_key(), new_key());
+        let migrator = Migrator::new(
+            MigrationSource::Container {
+                words: 4,
+                rounds: 12,
+            },
+            &old,
+            &new,
+        )
+        .cipher(8, 16);
+        let mut output = Vec::new();
+        migrator
+            .migrate(CountingRng(2), &container[..], &mut output)
+            .unwrap();
+        let (header, len) = Rc5Header::from_bytes(&output).unwrap();
+        assert_eq!(
+            (
+                header.params.words,
+                header.params.rounds,
+                header.params.bytes
+            ),
+            (8, 16, 32)
+        );
+        assert_eq!(migrated(&output, &HeaderPolicy::new()), data());
+        // the metadata is carried over
+        let new_cipher = RC5::<u64>::new(vec![0x5A; 32], 8, 16, 32);
+        let mut reader = ContainerReader::new(&output[len..], &new_cipher).unwrap();
+        assert_eq!(reader.metadata().unwrap(), Some(&metadata));
+
+        // migrating again from the headed result
+        let header = Rc5Header {
+            params: Rc5Params {
+                words: 4,
+                rounds: 12,
+                bytes: 16,
+            },
+            mode: CipherMode::Ctr,
+            kdf: Kdf::None,
+            iv: vec![4; 8],
+            key_id: None,
+            compression: Compression::None,
+            padding: Padding::Pkcs7,
+        };
+        let mut headed = Vec::new();
+        encrypt_with_header(&header, &old_key(), &data()[..], &mut headed).unwrap();
+        let source = MigrationSource::Headed(HeaderPolicy::new());
+        let mut output = Vec::new();
+        migrate(
+            CountingRng(3),
+            &headed[..],
+            &mut output,
+            &source,
+            &old_key(),
+            &new_key(),
+        )
+        .unwrap();
+        assert_eq!(migrated(&output, &HeaderPolicy::new()), data());
+
+        // a modified container or the wrong key fail
+        let mut modified = container.clone();
+        modified[40] ^= 1;
+        let source = MigrationSource::Container {
+            words: 4,
+            rounds: 12,
+        };
+        for (input, key) in [
+            (&modified, old_key()),
+            (&container, Rc5SecretKey::new(vec![1; 16])),
+        ]
+        .iter()
+        {
+            let err = migrate(
+                CountingRng(1),
+                &input[..],
+                io::sink(),
+                &source,
+                key,
+                &new_key(),
+            )
+            .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn migrated_files_are_verified() {
+        let dir = std::env::temp_dir().join(format!("rc5_migrate_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (input, output) = (dir.join("old"), dir.join("new"));
+
+        let cipher = RC5::<u32>::new((0u8..16).collect::<Vec<u8>>(), 4, 12, 16);
+        let mut encryptor = StreamEncryptor::new(&cipher, Mode::Ecb).unwrap();
+        let mut ciphertext = encryptor.update(&data());
+        ciphertext.extend_from_slice(&encryptor.finalize());
+        fs::write(&input, &ciphertext).unwrap();
+
+        let source = MigrationSource::Raw {
+            words: 4,
+            rounds: 12,
+            mode: CipherMode::Ecb,
+            iv: None,
+            padding: Padding::Pkcs7,
+        };
+        let (old, new) = (old_key(), new_key());
+        let migrator = Migrator::new(source, &old, &new);
+        assert_eq!(
+            migrator
+                .migrate_file(CountingRng(1), &input, &output)
+                .unwrap(),
+            100_000
+        );
+        assert_eq!(
+            migrated(&fs::read(&output).unwrap(), &HeaderPolicy::new()),
+            data()
+        );
+        // the output is never replaced
+        assert_eq!(
+            migrator
+                .migrate_file(CountingRng(1), &input, &output)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::AlreadyExists
+        );
+
+        // a result that couldn't be checked is removed
+        fs::remove_file(&output).unwrap();
+        fs::write(&input, &ciphertext[..ciphertext.len() - 8]).unwrap();
+        assert!(migrator
+            .migrate_file(CountingRng(1), &input, &output)
+            .is_err());
+        assert!(!output.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
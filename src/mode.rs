@@ -0,0 +1,253 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::encoding::{decode_hex, encode_hex};
+pub(crate) use crate::heapless::increment_counter;
+use crate::traits::Unsigned16To64;
+use crate::{KeySchedule, Rc5Error};
+
+// block cipher mode of operation used by the multi-block APIs. ECB and CBC
+// pad the plaintext (see `Padding`), CTR turns the cipher into a keystream and
+// needs no padding
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Ecb,
+    Cbc { iv: Vec<u8> },
+    Ctr { nonce: Vec<u8> },
+}
+
+impl Mode {
+    pub fn is_padded(&self) -> bool {
+        !matches!(self, Mode::Ctr { .. })
+    }
+
+    // padded ciphertext is a positive number of whole blocks, CTR
+    // ciphertext can have any length
+    pub(crate) fn check_ciphertext_len(&self, len: u64, block_len: usize) -> Result<(), Rc5Error> {
+        if self.is_padded() && (len == 0 || !len.is_multiple_of(block_len as u64)) {
+            return Err(Rc5Error::InvalidCiphertextLength);
+        }
+        Ok(())
+    }
+
+    // the initial chaining value (IV for CBC, counter block for CTR)
+    pub(crate) fn initial_chain(&self, block_len: usize) -> Result<Vec<u8>, Rc5Error> {
+        match self {
+            Mode::Ecb => Ok(Vec::new()),
+            Mode::Cbc { iv } if iv.len() == block_len => Ok(iv.clone()),
+            Mode::Cbc { .. } => Err(Rc5Error::InvalidIvLength),
+            Mode::Ctr { nonce } if nonce.len() == block_len => Ok(nonce.clone()),
+            Mode::Ctr { .. } => Err(Rc5Error::InvalidNonceLength),
+        }
+    }
+}
+
+// "ECB", or "CBC:" and "CTR:" followed by the IV or nonce in hex, which
+// aren't secret. `FromStr` reads the names in any case, but doesn't check
+// the length of the IV, the cipher does when the mode is used
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Ecb => f.write_str("ECB"),
+            Mode::Cbc { iv } => write!(f, "CBC:{}", encode_hex(iv)),
+            Mode::Ctr { nonce } => write!(f, "CTR:{}", encode_hex(nonce)),
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = Rc5Error;
+
+    fn from_str(text: &str) -> Result<Self, Rc5Error> {
+        let (name, iv) = match text.split_once(':') {
+            Some((name, iv)) => (name, Some(decode_hex(iv)?)),
+            None => (text, None),
+        };
+        match (name.to_ascii_uppercase().as_str(), iv) {
+            ("ECB", None) => Ok(Mode::Ecb),
+            ("CBC", Some(iv)) => Ok(Mode::Cbc { iv }),
+            ("CTR", Some(nonce)) => Ok(Mode::Ctr { nonce }),
+            _ => Err(Rc5Error::InvalidEncoding),
+        }
+    }
+}
+
+// encrypts whole blocks in place, updating the chaining value
+pub(crate) fn encrypt_blocks<T>(
+    schedule: &KeySchedule<T>,
+    mode: &Mode,
+    chain: &mut [u8],
+    data: &mut [u8],
+) where
+    T: Unsigned16To64 + Copy,
+{
+    encrypt_blocks_with(schedule, mode, chain, data, &mut Vec::new())
+}
+
+// like `encrypt_blocks`, with `scratch` as working memory so that callers
+// processing many buffers can keep reusing it
+pub(crate) fn encrypt_blocks_with<T>(
+    schedule: &KeySchedule<T>,
+    mode: &Mode,
+    chain: &mut [u8],
+    data: &mut [u8],
+    scratch: &mut Vec<u8>,
+) where
+    T: Unsigned16To64 + Copy,
+{
+    match mode {
+        Mode::Ecb => schedule.encrypt_blocks(data),
+        Mode::Cbc { .. } => {
+            for block in data.chunks_exact_mut(schedule.block_len()) {
+                xor_in_place(block, chain);
+                schedule.encrypt_block(block);
+                chain.copy_from_slice(block);
+            }
+        }
+        Mode::Ctr { .. } => apply_keystream_blocks(schedule, chain, data, scratch),
+    }
+}
+
+// decrypts whole blocks in place, updating the chaining value
+pub(crate) fn decrypt_blocks<T>(
+    schedule: &KeySchedule<T>,
+    mode: &Mode,
+    chain: &mut [u8],
+    data: &mut [u8],
+) where
+    T: Unsigned16To64 + Copy,
+{
+    decrypt_blocks_with(schedule, mode, chain, data, &mut Vec::new())
+}
+
+// decrypting counterpart of `encrypt_blocks_with`
+pub(crate) fn decrypt_blocks_with<T>(
+    schedule: &KeySchedule<T>,
+    mode: &Mode,
+    chain: &mut [u8],
+    data: &mut [u8],
+    scratch: &mut Vec<u8>,
+) where
+    T: Unsigned16To64 + Copy,
+{
+    match mode {
+        Mode::Ecb => schedule.decrypt_blocks(data),
+        Mode::Cbc { .. } => {
+            for block in data.chunks_exact_mut(schedule.block_len()) {
+                scratch.clear();
+                scratch.extend_from_slice(block);
+                schedule.decrypt_block(block);
+                xor_in_place(block, chain);
+                chain.copy_from_slice(scratch);
+            }
+        }
+        Mode::Ctr { .. } => apply_keystream_blocks(schedule, chain, data, scratch),
+    }
+}
+
+// CTR over whole blocks, generating the keystream in batches so that the
+// multi-block backends can be used for it
+fn apply_keystream_blocks<T>(
+    schedule: &KeySchedule<T>,
+    counter: &mut [u8],
+    data: &mut [u8],
+    keystream: &mut Vec<u8>,
+) where
+    T: Unsigned16To64 + Copy,
+{
+    const BATCH_BLOCKS: usize = 64;
+
+    let block_len = schedule.block_len();
+    let whole = data.len() - data.len() % block_len;
+
+    for batch in data[..whole].chunks_mut(BATCH_BLOCKS * block_len) {
+        keystream.clear();
+        for _ in 0..batch.len() / block_len {
+            keystream.extend_from_slice(counter);
+            increment_counter(counter);
+        }
+
+        schedule.encrypt_blocks(keystream);
+        xor_in_place(batch, keystream);
+    }
+}
+
+// xors the keystream for the current counter into `data`, which may be a
+// trailing partial block, and advances the counter
+pub(crate) fn apply_keystream<T>(schedule: &KeySchedule<T>, counter: &mut [u8], data: &mut [u8])
+where
+    T: Unsigned16To64 + Copy,
+{
+    let mut keystream = counter.to_vec();
+    schedule.encrypt_block(&mut keystream);
+    xor_in_place(data, &keystream);
+    increment_counter(counter);
+}
+
+// advances the big-endian counter block by `blocks` positions at once
+pub(crate) fn add_to_counter(counter: &mut [u8], blocks: u64) {
+    let mut carry = blocks as u128;
+    for byte in counter.iter_mut().rev() {
+        if carry == 0 {
+            break;
+        }
+        let sum = *byte as u128 + (carry & 0xFF);
+        *byte = sum as u8;
+        carry = (carry >> 8) + (sum >> 8);
+    }
+}
+
+pub(crate) fn xor_in_place(data: &mut [u8], other: &[u8]) {
+    data.iter_mut().zip(other).for_each(|(x, y)| *x ^= y);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_round_trip_through_text() {
+        let modes = [
+            Mode::Ecb,
+            Mode::Cbc { iv: vec![0x5A; 8] },
+            Mode::Ctr {
+                nonce: vec![0, 1, 2, 0xFF],
+            },
+        ];
+        for mode in modes.iter() {
+            assert_eq!(mode.to_string().parse::<Mode>().as_ref(), Ok(mode));
+        }
+        assert_eq!(Mode::Ctr { nonce: vec![0xAB] }.to_string(), "CTR:ab");
+        assert_eq!("cbc:00FF".parse(), Ok(Mode::Cbc { iv: vec![0, 0xFF] }));
+
+        for text in ["", "CBC", "ECB:00", "CTR:0g", "EAX:00", "OFB"].iter() {
+            assert_eq!(
+                text.parse::<Mode>(),
+                Err(Rc5Error::InvalidEncoding),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn counter_carries_over_bytes() {
+        let mut counter = [0x00, 0x00, 0x00, 0xFF];
+        increment_counter(&mut counter);
+        assert_eq!(counter, [0x00, 0x00, 0x01, 0x00]);
+
+        let mut counter = [0xFF; 4];
+        increment_counter(&mut counter);
+        assert_eq!(counter, [0x00; 4]);
+    }
+
+    #[test]
+    fn adding_matches_repeated_increments() {
+        let mut added = [0x00, 0x00, 0xFF, 0xF0];
+        let mut incremented = added;
+
+        add_to_counter(&mut added, 0x1234);
+        (0..0x1234).for_each(|_| increment_counter(&mut incremented));
+        assert_eq!(added, incremented);
+    }
+}
@@ -0,0 +1,410 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+use crate::mode::increment_counter;
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{Eax, Rc5Error, RC5};
+
+// Multiplexed framing, many encrypted streams over one byte channel for
+// tunnels and proxies. Every frame is
+//
+//   stream id u32 | flags u8 | length u16 | ciphertext (length bytes) |
+//   tag (one block)
+//
+// little-endian, sealed with EAX under the key of its stream. The nonce
+// isn't sent: it is the nonce the stream was opened with, incremented by
+// the number of frames the stream had before. The associated data is
+//
+//   magic "RC5M" | stream id u32 | frame number u64 | flags u8
+//
+// so frames can't be reordered, replayed or moved to another stream.
+// Each stream ends with an empty frame carrying the FIN flag, which is
+// what tells a closed stream from a cut off one. Both ends have to agree
+// on the key and the first nonce of every stream beforehand, and a nonce
+// must never be reused with the same key, also across channels
+const MAGIC: &[u8; 4] = b"RC5M";
+const FIN: u8 = 1;
+const FRAME_HEADER_LEN: usize = 7;
+const MAX_FRAME_LEN: usize = u16::MAX as usize;
+
+// what `MuxReader::next_event` read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MuxEvent {
+    // verified plaintext of one frame, which is never empty
+    Data { stream: u32, data: Vec<u8> },
+    // the stream was closed by the writer, nothing more will arrive on it
+    Closed { stream: u32 },
+}
+
+// the state of one direction of one stream, `None` in the maps once it
+// is closed, so its id can't be used again
+struct Stream<T: Unsigned16To64> {
+    eax: Eax<T>,
+    nonce: Vec<u8>,
+    frames: u64,
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> Stream<T> {
+    fn new(cipher: &RC5<T>, nonce: &[u8]) -> io::Result<Self> {
+        let eax = Eax::new(cipher).map_err(invalid_input)?;
+        if nonce.len() != eax.tag_len() {
+            return Err(invalid_input(Rc5Error::InvalidNonceLength));
+        }
+        Ok(Stream {
+            eax,
+            nonce: nonce.to_vec(),
+            frames: 0,
+        })
+    }
+
+    // the associated data of the next frame, and on to the one after
+    fn next(&mut self, id: u32, flags: u8) -> (Vec<u8>, Vec<u8>) {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&id.to_le_bytes());
+        data.extend_from_slice(&self.frames.to_le_bytes());
+        data.push(flags);
+
+        let nonce = self.nonce.clone();
+        increment_counter(&mut self.nonce);
+        self.frames += 1;
+        (nonce, data)
+    }
+}
+
+// writes the frames of any number of streams to one writer, in the order
+// they are sent
+pub struct MuxWriter<T: Unsigned16To64, W: Write> {
+    writer: W,
+    streams: BTreeMap<u32, Option<Stream<T>>>,
+}
+
+impl<T, W> MuxWriter<T, W>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    W: Write,
+{
+    pub fn new(writer: W) -> Self {
+        MuxWriter {
+            writer,
+            streams: BTreeMap::new(),
+        }
+    }
+
+    // starts stream `id` under `cipher`, with `nonce` the one block nonce
+    // of its first frame. Ids can't be opened twice, even after a close
+    pub fn open(&mut self, id: u32, cipher: &RC5<T>, nonce: &[u8]) -> io::Result<()> {
+        if self.streams.contains_key(&id) {
+            return Err(invalid_input("the stream was opened before"));
+        }
+        self.streams.insert(id, Some(Stream::new(cipher, nonce)?));
+        Ok(())
+    }
+
+    // sends `data` on stream `id`, in frames of at most 64 KiB. Nothing is
+    // sent for empty data
+    pub fn send(&mut self, id: u32, data: &[u8]) -> io::Result<()> {
+        for frame in data.chunks(MAX_FRAME_LEN) {
+            self.write_frame(id, 0, frame)?;
+        }
+        Ok(())
+    }
+
+    // ends stream `id`, which can't be sent on afterwards
+    pub fn close(&mut self, id: u32) -> io::Result<()> {
+        self.write_frame(id, FIN, &[])?;
+        self.streams.insert(id, None);
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    // the streams that were opened and not closed yet
+    pub fn open_streams(&self) -> Vec<u32> {
+        open_streams(&self.streams)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_frame(&mut self, id: u32, flags: u8, data: &[u8]) -> io::Result<()> {
+        let stream = match self.streams.get_mut(&id) {
+            Some(Some(stream)) => stream,
+            _ => return Err(invalid_input("the stream isn't open")),
+        };
+        let (nonce, associated) = stream.next(id, flags);
+        let mut frame = data.to_vec();
+        let tag = stream
+            .eax
+            .encrypt_in_place_detached(&nonce, &associated, &mut frame);
+
+        let mut header = [0u8; FRAME_HEADER_LEN];
+        header[..4].copy_from_slice(&id.to_le_bytes());
+        header[4] = flags;
+        header[5..].copy_from_slice(&(frame.len() as u16).to_le_bytes());
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&frame)?;
+        self.writer.write_all(&tag)
+    }
+}
+
+// reads the frames of a `MuxWriter` back. Streams have to be accepted
+// with their key and nonce before their first frame arrives, frames of
+// any other stream are refused
+pub struct MuxReader<T: Unsigned16To64, R: Read> {
+    reader: R,
+    streams: BTreeMap<u32, Option<Stream<T>>>,
+    failed: bool,
+}
+
+impl<T, R> MuxReader<T, R>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    R: Read,
+{
+    pub fn new(reader: R) -> Self {
+        MuxReader {
+            reader,
+            streams: BTreeMap::new(),
+            failed: false,
+        }
+    }
+
+    // expects stream `id` under `cipher` with `nonce`, as the writer
+    // opened it
+    pub fn accept(&mut self, id: u32, cipher: &RC5<T>, nonce: &[u8]) -> io::Result<()> {
+        if self.streams.contains_key(&id) {
+            return Err(invalid_input("the stream was accepted before"));
+        }
+        self.streams.insert(id, Some(Stream::new(cipher, nonce)?));
+        Ok(())
+    }
+
+    // reads and verifies the next frame, or returns `None` where the
+    // channel ends between frames with every accepted stream closed. A
+    // channel that ends anywhere else gives `UnexpectedEof`, and a frame
+    // that doesn't verify, belongs to a stream that isn't open or is out
+    // of order `InvalidData`. After an error the channel can't be trusted
+    // any more, and every later call fails too
+    pub fn next_event(&mut self) -> io::Result<Option<MuxEvent>> {
+        if self.failed {
+            return Err(invalid_data("an earlier frame failed"));
+        }
+        self.failed = true;
+        let event = self.read_frame()?;
+        self.failed = false;
+        Ok(event)
+    }
+
+    // the streams that were accepted and not closed yet
+    pub fn open_streams(&self) -> Vec<u32> {
+        open_streams(&self.streams)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn read_frame(&mut self) -> io::Result<Option<MuxEvent>> {
+        let mut header = [0u8; FRAME_HEADER_LEN];
+        let read = read_full(&mut self.reader, &mut header)?;
+        if read == 0 && self.open_streams().is_empty() {
+            return Ok(None);
+        }
+        if read < FRAME_HEADER_LEN {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let id = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let flags = header[4];
+        let len = u16::from_le_bytes([header[5], header[6]]) as usize;
+        let stream = match self.streams.get_mut(&id) {
+            Some(Some(stream)) => stream,
+            _ => return Err(invalid_data("a frame for a stream that isn't open")),
+        };
+        // a closing frame is empty, and it takes a flag to close
+        if flags & !FIN != 0 || (flags & FIN != 0) != (len == 0) {
+            return Err(invalid_data("malformed frame"));
+        }
+
+        let mut frame = vec![0u8; len + stream.eax.tag_len()];
+        self.reader.read_exact(&mut frame)?;
+        let (nonce, associated) = stream.next(id, flags);
+        let (data, tag) = frame.split_at_mut(len);
+        stream
+            .eax
+            .decrypt_in_place_detached(&nonce, &associated, data, tag)
+            .map_err(invalid_data)?;
+
+        if flags & FIN != 0 {
+            self.streams.insert(id, None);
+            return Ok(Some(MuxEvent::Closed { stream: id }));
+        }
+        frame.truncate(len);
+        Ok(Some(MuxEvent::Data {
+            stream: id,
+            data: frame,
+        }))
+    }
+}
+
+fn open_streams<T: Unsigned16To64>(streams: &BTreeMap<u32, Option<Stream<T>>>) -> Vec<u32> {
+    streams
+        .iter()
+        .filter(|(_, stream)| stream.is_some())
+        .map(|(&id, _)| id)
+        .collect()
+}
+
+// reads until `buf` is full or the input ends
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(len)
+}
+
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn invalid_input<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher(byte: u8) -> RC5<u32> {
+        RC5::new(vec![byte; 16], 4, 12, 16)
+    }
+
+    fn nonce(byte: u8) -> [u8; 8] {
+        [byte; 8]
+    }
+
+    // streams 1 and 2 interleaved, stream 1 with a frame too long for one
+    fn channel() -> Vec<u8> {
+        let mut mux = MuxWriter::new(Vec::new());
+        mux.open(1, &cipher(1), &nonce(1)).unwrap();
+        mux.open(2, &cipher(2), &nonce(2)).unwrap();
+        mux.send(1, b"hello ").unwrap();
+        mux.send(2, &vec![7; 70_000]).unwrap();
+        mux.send(1, b"world").unwrap();
+        mux.close(2).unwrap();
+        mux.send(1, b"").unwrap();
+        mux.close(1).unwrap();
+        assert!(mux.open_streams().is_empty());
+        mux.into_inner()
+    }
+
+    fn reader(channel: &[u8]) -> MuxReader<u32, &[u8]> {
+        let mut reader = MuxReader::new(channel);
+        reader.accept(1, &cipher(1), &nonce(1)).unwrap();
+        reader.accept(2, &cipher(2), &nonce(2)).unwrap();
+        reader
+    }
+
+    fn events(channel: &[u8]) -> io::Result<Vec<MuxEvent>> {
+        let mut reader = reader(channel);
+        let mut events = Vec::new();
+        while let Some(event) = reader.next_event()? {
+            events.push(event);
+        }
+        Ok(events)
+    }
+
+    #[test]
+    fn interleaved_streams_arrive_in_order() {
+        let data = |stream: u32, data: &[u8]| MuxEvent::Data {
+            stream,
+            data: data.to_vec(),
+        };
+        assert_eq!(
+            events(&channel()).unwrap(),
+            [
+                data(1, b"hello "),
+                data(2, &[7; MAX_FRAME_LEN]),
+                data(2, &[7; 70_000 - MAX_FRAME_LEN]),
+                data(1, b"world"),
+                MuxEvent::Closed { stream: 2 },
+                MuxEvent::Closed { stream: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn streams_are_used_as_opened() {
+        let mut mux = MuxWriter::new(Vec::new());
+        assert!(mux.send(1, b"x").is_err());
+        mux.open(1, &cipher(1), &nonce(1)).unwrap();
+        assert!(mux.open(1, &cipher(1), &nonce(3)).is_err());
+        assert!(mux.open(2, &cipher(2), &[0; 4]).is_err());
+        mux.close(1).unwrap();
+        assert!(mux.send(1, b"x").is_err());
+        assert!(mux.open(1, &cipher(1), &nonce(1)).is_err());
+    }
+
+    #[test]
+    fn modified_channels_are_refused() {
+        let channel = channel();
+        // the first frame, "hello " on stream 1
+        let first_len = FRAME_HEADER_LEN + 6 + 8;
+
+        let mut modified = channel.clone();
+        modified[FRAME_HEADER_LEN] ^= 1;
+        assert_eq!(
+            events(&modified).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        // a frame moved to another stream, even with its key
+        let mut moved = channel.clone();
+        moved[0] = 2;
+        assert!(events(&moved).is_err());
+
+        // a replayed frame
+        let mut replayed = channel[..first_len].to_vec();
+        replayed.extend_from_slice(&channel);
+        assert!(events(&replayed).is_err());
+
+        // a stream nobody accepted
+        let mut partial = MuxReader::<u32, _>::new(&channel[..]);
+        partial.accept(2, &cipher(2), &nonce(2)).unwrap();
+        assert!(partial.next_event().is_err());
+        assert!(partial.next_event().is_err());
+
+        // truncation ends the channel early, but never cleanly
+        let cuts = [
+            1,
+            FRAME_HEADER_LEN,
+            first_len - 1,
+            first_len,
+            channel.len() - 1,
+        ];
+        for &len in cuts.iter() {
+            assert!(events(&channel[..len]).is_err(), "{}", len);
+        }
+        let mut truncated = reader(&channel[..first_len]);
+        truncated.next_event().unwrap();
+        assert_eq!(
+            truncated.next_event().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        assert_eq!(truncated.open_streams(), [1, 2]);
+    }
+}
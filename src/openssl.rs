@@ -0,0 +1,259 @@
+use crate::digest::{md5, pbkdf2_sha256, sha256};
+use crate::mode::Mode;
+use crate::stream::{StreamDecryptor, StreamEncryptor};
+use crate::{secure, Rc5Error, Rc5SecretKey, RC5};
+
+// compatibility with the RC5 ciphers of OpenSSL (`rc5-cbc`, also called
+// `rc5`, and `rc5-ecb`). They are RC5-32/12 with a 16 byte key, PKCS#7
+// padding and an 8 byte IV, with words stored little-endian as in RFC
+// 2040. Ciphertext made with an explicit key and IV (`openssl enc -K ..
+// -iv ..`) needs nothing from this module: `openssl_cipher` and
+// `Mode::Cbc` reproduce it. The functions below handle the password-based
+// `Salted__` format that `openssl enc -pass ..` writes
+pub const OPENSSL_ROUNDS: usize = 12;
+pub const OPENSSL_KEY_LEN: usize = 16;
+pub const OPENSSL_SALT_LEN: usize = 8;
+
+const SALTED_MAGIC: &[u8; 8] = b"Salted__";
+const IV_LEN: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpensslCipher {
+    Rc5Cbc,
+    Rc5Ecb,
+}
+
+// the digest given to `openssl enc -md`. MD5 was the default before
+// OpenSSL 1.1.0, SHA-256 since
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpensslDigest {
+    Md5,
+    Sha256,
+}
+
+// how `openssl enc` turns the password into a key and IV: the legacy
+// single-iteration `EVP_BytesToKey`, or PBKDF2-HMAC-SHA-256 with
+// `-pbkdf2` (10000 iterations unless `-iter` says otherwise)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpensslKdf {
+    BytesToKey(OpensslDigest),
+    Pbkdf2 { iterations: u32 },
+}
+
+// the RC5 parameters of OpenSSL's RC5 ciphers for a raw `key`, which
+// OpenSSL expects to be `OPENSSL_KEY_LEN` bytes long
+pub fn openssl_cipher(key: Rc5SecretKey) -> RC5<u32> {
+    let bytes = key.len();
+    RC5::from_secret_key(key, 4, OPENSSL_ROUNDS, bytes)
+}
+
+// encrypts like `openssl enc -<cipher> -pass .. -S <salt>`, returning the
+// `Salted__` header, the salt and the ciphertext. `salt` must be random
+// (see `Nonce::<u32>::generate`)
+pub fn openssl_encrypt(
+    cipher: OpensslCipher,
+    kdf: OpensslKdf,
+    password: &[u8],
+    salt: &[u8; OPENSSL_SALT_LEN],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, Rc5Error> {
+    let (rc5, mode) = derive(cipher, kdf, password, salt);
+
+    let mut output = SALTED_MAGIC.to_vec();
+    output.extend_from_slice(salt);
+    let mut encryptor = StreamEncryptor::new(&rc5, mode)?;
+    output.extend(encryptor.update(plaintext));
+    output.extend(encryptor.finalize());
+    Ok(output)
+}
+
+// decrypts the output of `openssl enc -<cipher> -pass ..`
+pub fn openssl_decrypt(
+    cipher: OpensslCipher,
+    kdf: OpensslKdf,
+    password: &[u8],
+    data: &[u8],
+) -> Result<Vec<u8>, Rc5Error> {
+    let header_len = SALTED_MAGIC.len() + OPENSSL_SALT_LEN;
+    if data.len() < header_len || &data[..SALTED_MAGIC.len()] != SALTED_MAGIC {
+        return Err(Rc5Error::DecryptionFailed);
+    }
+
+    let mut salt = [0u8; OPENSSL_SALT_LEN];
+    salt.copy_from_slice(&data[SALTED_MAGIC.len()..header_len]);
+    let (rc5, mode) = derive(cipher, kdf, password, &salt);
+
+    let mut decryptor = StreamDecryptor::new(&rc5, mode)?;
+    let mut plaintext = decryptor.update(&data[header_len..]);
+    plaintext.extend(decryptor.finalize()?);
+    Ok(plaintext)
+}
+
+fn derive(
+    cipher: OpensslCipher,
+    kdf: OpensslKdf,
+    password: &[u8],
+    salt: &[u8; OPENSSL_SALT_LEN],
+) -> (RC5<u32>, Mode) {
+    let iv_len = match cipher {
+        OpensslCipher::Rc5Cbc => IV_LEN,
+        OpensslCipher::Rc5Ecb => 0,
+    };
+
+    let mut material = vec![0u8; OPENSSL_KEY_LEN + iv_len];
+    match kdf {
+        OpensslKdf::BytesToKey(digest) => bytes_to_key(digest, password, salt, &mut material),
+        OpensslKdf::Pbkdf2 { iterations } => {
+            pbkdf2_sha256(password, salt, iterations, &mut material)
+        }
+    }
+
+    let key = Rc5SecretKey::new(material[..OPENSSL_KEY_LEN].to_vec());
+    let mode = match cipher {
+        OpensslCipher::Rc5Cbc => Mode::Cbc {
+            iv: material[OPENSSL_KEY_LEN..].to_vec(),
+        },
+        OpensslCipher::Rc5Ecb => Mode::Ecb,
+    };
+    secure::wipe(&mut material);
+
+    (openssl_cipher(key), mode)
+}
+
+// `EVP_BytesToKey` with one iteration: D_i = H(D_{i-1} || password || salt)
+// until enough bytes have been produced
+fn bytes_to_key(digest: OpensslDigest, password: &[u8], salt: &[u8], output: &mut [u8]) {
+    let mut previous = Vec::new();
+    let mut filled = 0;
+
+    while filled < output.len() {
+        let mut input = previous.clone();
+        input.extend_from_slice(password);
+        input.extend_from_slice(salt);
+        secure::wipe(&mut previous);
+        previous = match digest {
+            OpensslDigest::Md5 => md5(&input).to_vec(),
+            OpensslDigest::Sha256 => sha256(&input).to_vec(),
+        };
+        secure::wipe(&mut input);
+
+        let len = previous.len().min(output.len() - filled);
+        output[filled..filled + len].copy_from_slice(&previous[..len]);
+        filled += len;
+    }
+    secure::wipe(&mut previous);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SALT: [u8; 8] = [0, 1, 2, 3, 4, 5, 6, 7];
+
+    // key and IV as printed by `openssl enc -aes-128-cbc -P -pass
+    // pass:password -S 0001020304050607 ..`, whose derivation is the same
+    // as for RC5 up to the lengths
+    #[test]
+    fn key_derivation_matches_openssl() {
+        let mut material = [0u8; 24];
+        bytes_to_key(OpensslDigest::Md5, b"password", &SALT, &mut material);
+        assert_eq!(
+            material[..],
+            [
+                0xB0, 0x30, 0x96, 0x34, 0x5E, 0x80, 0x5D, 0x3A, 0xA4, 0x39, 0x2D, 0x2E, 0x72, 0x79,
+                0x1D, 0xFB, 0x13, 0xE1, 0x2D, 0x3F, 0x61, 0x09, 0x4A, 0x3F
+            ]
+        );
+
+        // -md sha256
+        bytes_to_key(OpensslDigest::Sha256, b"password", &SALT, &mut material);
+        assert_eq!(
+            material[..],
+            [
+                0xAB, 0x47, 0xA5, 0x51, 0xC8, 0x47, 0x88, 0x48, 0x19, 0x01, 0x9C, 0x30, 0xE7, 0xB5,
+                0x0C, 0xB3, 0xA2, 0x6D, 0xF8, 0xBE, 0x39, 0xFB, 0xBF, 0x39
+            ]
+        );
+    }
+
+    #[test]
+    fn salted_round_trip() {
+        let kdfs = [
+            OpensslKdf::BytesToKey(OpensslDigest::Md5),
+            OpensslKdf::BytesToKey(OpensslDigest::Sha256),
+            OpensslKdf::Pbkdf2 { iterations: 10 },
+        ];
+
+        for cipher in [OpensslCipher::Rc5Cbc, OpensslCipher::Rc5Ecb].iter() {
+            for kdf in kdfs.iter() {
+                let data = openssl_encrypt(*cipher, *kdf, b"password", &SALT, b"hello").unwrap();
+                assert_eq!(&data[..8], b"Salted__");
+                assert_eq!(data.len(), 24);
+
+                let plaintext = openssl_decrypt(*cipher, *kdf, b"password", &data).unwrap();
+                assert_eq!(plaintext, b"hello");
+                assert!(openssl_decrypt(*cipher, *kdf, b"wrong", &data).is_err());
+            }
+        }
+
+        assert!(openssl_decrypt(
+            OpensslCipher::Rc5Cbc,
+            OpensslKdf::Pbkdf2 { iterations: 10 },
+            b"password",
+            b"not salted data"
+        )
+        .is_err());
+    }
+
+    // runs the `openssl` binary, which in OpenSSL 3 only has RC5 with the
+    // legacy provider, and nothing at all in many builds. The test passes
+    // vacuously when the cipher is missing
+    #[cfg(feature = "openssl-tests")]
+    #[test]
+    fn matches_openssl_binary() {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let run = |args: &[&str], input: &[u8]| {
+            let mut child = Command::new("openssl")
+                .args(args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+            child.stdin.take()?.write_all(input).ok()?;
+            let output = child.wait_with_output().ok()?;
+            if output.status.success() {
+                Some(output.stdout)
+            } else {
+                None
+            }
+        };
+
+        let args = [
+            "enc",
+            "-rc5-cbc",
+            "-md",
+            "sha256",
+            "-pass",
+            "pass:password",
+            "-S",
+            "0001020304050607",
+        ];
+        let kdf = OpensslKdf::BytesToKey(OpensslDigest::Sha256);
+        let plaintext = b"data encrypted by openssl";
+
+        let expected = match run(&args, plaintext) {
+            Some(expected) => expected,
+            None => return,
+        };
+        let ciphertext =
+            openssl_encrypt(OpensslCipher::Rc5Cbc, kdf, b"password", &SALT, plaintext).unwrap();
+        assert_eq!(ciphertext, expected);
+
+        let mut decrypt_args = args.to_vec();
+        decrypt_args.push("-d");
+        assert_eq!(run(&decrypt_args, &ciphertext).unwrap(), plaintext);
+    }
+}
@@ -0,0 +1,236 @@
+use std::fmt;
+use std::hint::black_box;
+use std::str::FromStr;
+
+use crate::ct::{ct_is_zero, ct_lt};
+use crate::Rc5Error;
+
+// padding schemes for the last block of ECB and CBC, the same family as
+// the `block-padding` crate. PKCS#7 is the default, the others exist for
+// interop with systems that use them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Padding {
+    // n bytes of value n
+    #[default]
+    Pkcs7,
+    // 0x80 followed by zeros
+    Iso7816,
+    // zeros followed by one byte holding the padding length
+    AnsiX923,
+    // zeros, and none at all if the data already ends on a block boundary.
+    // Trailing zeros of the plaintext are lost, so it only suits data that
+    // can't end in one
+    Zero,
+}
+
+// the names of `Display` are what `FromStr` reads back, in any case
+impl fmt::Display for Padding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Padding::Pkcs7 => "PKCS7",
+            Padding::Iso7816 => "ISO7816",
+            Padding::AnsiX923 => "X923",
+            Padding::Zero => "ZERO",
+        })
+    }
+}
+
+impl FromStr for Padding {
+    type Err = Rc5Error;
+
+    fn from_str(text: &str) -> Result<Self, Rc5Error> {
+        match text.to_ascii_uppercase().as_str() {
+            "PKCS7" => Ok(Padding::Pkcs7),
+            "ISO7816" => Ok(Padding::Iso7816),
+            "X923" => Ok(Padding::AnsiX923),
+            "ZERO" => Ok(Padding::Zero),
+            _ => Err(Rc5Error::InvalidEncoding),
+        }
+    }
+}
+
+impl Padding {
+    pub(crate) fn tag(self) -> u8 {
+        match self {
+            Padding::Pkcs7 => 0,
+            Padding::Iso7816 => 1,
+            Padding::AnsiX923 => 2,
+            Padding::Zero => 3,
+        }
+    }
+
+    pub(crate) fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Padding::Pkcs7),
+            1 => Some(Padding::Iso7816),
+            2 => Some(Padding::AnsiX923),
+            3 => Some(Padding::Zero),
+            _ => None,
+        }
+    }
+
+    // whether the padding always adds at least one byte, in which case an
+    // empty ciphertext can't be valid
+    pub(crate) fn always_pads(self) -> bool {
+        self != Padding::Zero
+    }
+
+    pub(crate) fn pad(self, data: &mut Vec<u8>, block_len: usize) {
+        let pad_len = match self {
+            Padding::Zero => (block_len - data.len() % block_len) % block_len,
+            _ => block_len - data.len() % block_len,
+        };
+
+        match self {
+            Padding::Pkcs7 => data.resize(data.len() + pad_len, pad_len as u8),
+            Padding::Iso7816 => {
+                data.push(0x80);
+                data.resize(data.len() + pad_len - 1, 0);
+            }
+            Padding::AnsiX923 => {
+                data.resize(data.len() + pad_len - 1, 0);
+                data.push(pad_len as u8);
+            }
+            Padding::Zero => data.resize(data.len() + pad_len, 0),
+        }
+    }
+
+    // checks the whole last block whatever the padding says, and only
+    // decides at the end, so the time taken doesn't tell how the padding
+    // was malformed
+    pub(crate) fn unpad(self, data: &mut Vec<u8>, block_len: usize) -> Result<(), Rc5Error> {
+        if data.len() < block_len || block_len == 0 {
+            return Err(Rc5Error::DecryptionFailed);
+        }
+
+        let last_block = &data[data.len() - block_len..];
+        let (valid, pad_len) = match self {
+            Padding::Pkcs7 => length_suffixed(last_block, false),
+            Padding::AnsiX923 => length_suffixed(last_block, true),
+            Padding::Iso7816 => marker_prefixed(last_block),
+            Padding::Zero => (0xFF, trailing_zeros(last_block)),
+        };
+
+        if black_box(valid) != 0xFF {
+            return Err(Rc5Error::DecryptionFailed);
+        }
+
+        data.truncate(data.len() - pad_len);
+        Ok(())
+    }
+}
+
+// PKCS#7 and ANSI X9.23: the last byte gives the padding length, which
+// must be 1..=block_len, and the bytes before it are copies of it or zeros
+fn length_suffixed(last_block: &[u8], zero_filled: bool) -> (u8, usize) {
+    let block_len = last_block.len();
+    let pad_len = last_block[block_len - 1];
+
+    let mut valid = !ct_is_zero(pad_len) & !ct_lt(block_len as u8, pad_len);
+    for (i, &byte) in last_block.iter().rev().enumerate().skip(1) {
+        let in_padding = ct_lt(i as u8, pad_len);
+        let expected = if zero_filled { 0 } else { pad_len };
+        valid &= !in_padding | ct_is_zero(byte ^ expected);
+    }
+
+    (valid, pad_len as usize)
+}
+
+// ISO/IEC 7816-4: the last nonzero byte must be the 0x80 marker
+fn marker_prefixed(last_block: &[u8]) -> (u8, usize) {
+    let mut seeking = 0xFFu8;
+    let mut valid = 0u8;
+    let mut pad_len = 0usize;
+
+    for (i, &byte) in last_block.iter().rev().enumerate() {
+        let found = seeking & !ct_is_zero(byte);
+        valid |= found & ct_is_zero(byte ^ 0x80);
+        pad_len += (found & 1) as usize * (i + 1);
+        seeking &= !found;
+    }
+
+    (valid, pad_len)
+}
+
+fn trailing_zeros(last_block: &[u8]) -> usize {
+    let mut zeros = 0xFFu8;
+    let mut count = 0usize;
+
+    for &byte in last_block.iter().rev() {
+        zeros &= ct_is_zero(byte);
+        count += (zeros & 1) as usize;
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Padding; 4] = [
+        Padding::Pkcs7,
+        Padding::Iso7816,
+        Padding::AnsiX923,
+        Padding::Zero,
+    ];
+
+    #[test]
+    fn padding_round_trip() {
+        for padding in ALL.iter() {
+            for len in 0..17 {
+                let mut data = vec![0xAB; len];
+                padding.pad(&mut data, 8);
+                assert_eq!(data.len() % 8, 0);
+                if padding.always_pads() {
+                    assert!(data.len() > len);
+                }
+
+                if !data.is_empty() {
+                    padding.unpad(&mut data, 8).unwrap();
+                }
+                assert_eq!(data, vec![0xAB; len], "{:?}", padding);
+            }
+        }
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for padding in ALL.iter() {
+            assert_eq!(padding.to_string().parse::<Padding>().as_ref(), Ok(padding));
+        }
+        assert_eq!("iso7816".parse(), Ok(Padding::Iso7816));
+        assert_eq!("pkcs#7".parse::<Padding>(), Err(Rc5Error::InvalidEncoding));
+    }
+
+    #[test]
+    fn padding_bytes() {
+        let pad = |padding: Padding| {
+            let mut data = vec![0xAB; 5];
+            padding.pad(&mut data, 8);
+            data[5..].to_vec()
+        };
+
+        assert_eq!(pad(Padding::Pkcs7), [3, 3, 3]);
+        assert_eq!(pad(Padding::Iso7816), [0x80, 0, 0]);
+        assert_eq!(pad(Padding::AnsiX923), [0, 0, 3]);
+        assert_eq!(pad(Padding::Zero), [0, 0, 0]);
+    }
+
+    #[test]
+    fn unpad_rejects_malformed_padding() {
+        let pkcs7 = Padding::Pkcs7;
+        assert!(pkcs7.unpad(&mut vec![], 8).is_err());
+        assert!(pkcs7.unpad(&mut vec![1, 2, 3, 4, 5, 6, 7, 0], 8).is_err());
+        assert!(pkcs7.unpad(&mut vec![1, 2, 3, 4, 5, 6, 7, 9], 8).is_err());
+        assert!(pkcs7.unpad(&mut vec![1, 2, 3, 4, 5, 6, 3, 2], 8).is_err());
+
+        let iso = Padding::Iso7816;
+        assert!(iso.unpad(&mut vec![0; 8], 8).is_err());
+        assert!(iso.unpad(&mut vec![1, 2, 3, 4, 5, 6, 0x81, 0], 8).is_err());
+
+        let ansi = Padding::AnsiX923;
+        assert!(ansi.unpad(&mut vec![1, 2, 3, 4, 5, 6, 7, 0], 8).is_err());
+        assert!(ansi.unpad(&mut vec![1, 2, 3, 4, 5, 1, 0, 3], 8).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use std::thread;
+
+use crate::mode::{self, add_to_counter, Mode};
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{Rc5Error, RC5};
+
+// below this many bytes per worker the cost of spawning threads outweighs
+// the gain, so the buffer is processed on the calling thread instead
+const MIN_BYTES_PER_WORKER: usize = 1 << 16;
+
+// encrypts `data` in place with ECB, spread over all available cores.
+// `data` must be a multiple of the block length, padding is left to the
+// caller
+pub fn par_encrypt_ecb<T>(cipher: &RC5<T>, data: &mut [u8]) -> Result<(), Rc5Error>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy + Send + Sync,
+{
+    let schedule = cipher.key_schedule()?;
+    if !data.len().is_multiple_of(schedule.block_len()) {
+        return Err(Rc5Error::InvalidBlockLength);
+    }
+
+    for_each_split(schedule.block_len(), data, |_, part| {
+        mode::encrypt_blocks(&schedule, &Mode::Ecb, &mut [], part)
+    });
+    Ok(())
+}
+
+// decrypts `data` in place with ECB, see `par_encrypt_ecb`
+pub fn par_decrypt_ecb<T>(cipher: &RC5<T>, data: &mut [u8]) -> Result<(), Rc5Error>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy + Send + Sync,
+{
+    let schedule = cipher.key_schedule()?;
+    if !data.len().is_multiple_of(schedule.block_len()) {
+        return Err(Rc5Error::InvalidBlockLength);
+    }
+
+    for_each_split(schedule.block_len(), data, |_, part| {
+        mode::decrypt_blocks(&schedule, &Mode::Ecb, &mut [], part)
+    });
+    Ok(())
+}
+
+// xors the CTR keystream starting at the one-block counter `nonce` into
+// `data`, which may have any length. Encryption and decryption are the
+// same operation
+pub fn par_apply_ctr<T>(cipher: &RC5<T>, nonce: &[u8], data: &mut [u8]) -> Result<(), Rc5Error>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy + Send + Sync,
+{
+    let schedule = cipher.key_schedule()?;
+    let block_len = schedule.block_len();
+    if nonce.len() != block_len {
+        return Err(Rc5Error::InvalidNonceLength);
+    }
+
+    for_each_split(schedule.block_len(), data, |first_block, part| {
+        let mut counter = nonce.to_vec();
+        add_to_counter(&mut counter, first_block as u64);
+        for block in part.chunks_mut(block_len) {
+            mode::apply_keystream(&schedule, &mut counter, block);
+        }
+    });
+    Ok(())
+}
+
+// splits `data` into block-aligned parts, one per worker, and calls
+// `f(index of the part's first block, part)` for each of them
+fn for_each_split<F>(block_len: usize, data: &mut [u8], f: F)
+where
+    F: Fn(usize, &mut [u8]) + Sync,
+{
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let workers = workers.min(data.len() / MIN_BYTES_PER_WORKER).max(1);
+
+    split_across(workers, block_len, data, f)
+}
+
+fn split_across<F>(workers: usize, block_len: usize, data: &mut [u8], f: F)
+where
+    F: Fn(usize, &mut [u8]) + Sync,
+{
+    if workers <= 1 || data.is_empty() {
+        f(0, data);
+        return;
+    }
+
+    let blocks = data.len().div_ceil(block_len);
+    let part_len = blocks.div_ceil(workers) * block_len;
+
+    thread::scope(|scope| {
+        for (i, part) in data.chunks_mut(part_len).enumerate() {
+            let f = &f;
+            scope.spawn(move || f(i * part_len / block_len, part));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StreamEncryptor;
+
+    fn cipher() -> RC5<u32> {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        RC5::<u32>::new(key, 4, 12, 16)
+    }
+
+    #[test]
+    fn matches_sequential_modes() {
+        let plaintext = (0..(1 << 20) + 3).map(|x| x as u8).collect::<Vec<u8>>();
+
+        let mode = Mode::Ctr {
+            nonce: vec![0xFF; 8],
+        };
+        let mut encryptor = StreamEncryptor::new(&cipher(), mode).unwrap();
+        let mut expected = encryptor.update(&plaintext);
+        expected.extend(encryptor.finalize());
+
+        let mut data = plaintext.clone();
+        par_apply_ctr(&cipher(), &[0xFF; 8], &mut data).unwrap();
+        assert_eq!(data, expected);
+
+        let aligned = &plaintext[..1 << 20];
+        let mut encryptor = StreamEncryptor::new(&cipher(), Mode::Ecb).unwrap();
+        let expected = encryptor.update(aligned);
+
+        let mut data = aligned.to_vec();
+        par_encrypt_ecb(&cipher(), &mut data).unwrap();
+        assert_eq!(data, expected);
+
+        par_decrypt_ecb(&cipher(), &mut data).unwrap();
+        assert_eq!(data, aligned);
+    }
+
+    #[test]
+    fn parts_cover_the_buffer_in_order() {
+        let mut data = vec![0u8; 8 * 10 + 3];
+        split_across(4, 8, &mut data, |first_block, part| {
+            part.iter_mut().for_each(|x| *x = first_block as u8);
+        });
+
+        let starts = data.chunks(8).map(|block| block[0]).collect::<Vec<u8>>();
+        assert_eq!(starts, vec![0, 0, 0, 3, 3, 3, 6, 6, 6, 9, 9]);
+    }
+
+    #[test]
+    fn ecb_rejects_unaligned_input() {
+        assert!(par_encrypt_ecb(&cipher(), &mut [0u8; 12]).is_err());
+    }
+}
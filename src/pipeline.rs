@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::mode::{self, add_to_counter, Mode};
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{KeySchedule, RC5};
+
+const DEFAULT_CHUNK_LEN: usize = 1 << 20;
+
+// CTR encryption of readers that can't be memory-mapped (pipes, sockets):
+// a reader thread cuts the input into chunks, `workers` threads apply the
+// keystream to them and the calling thread writes them out in order. The
+// output is identical to a sequential CTR encryption with the same nonce,
+// and encryption and decryption are the same operation
+pub struct Pipeline<T: Unsigned16To64> {
+    schedule: KeySchedule<T>,
+    chunk_len: usize,
+    workers: usize,
+}
+
+impl<T> Pipeline<T>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy + Send + Sync,
+{
+    pub fn new(cipher: &RC5<T>) -> io::Result<Self> {
+        let schedule = cipher
+            .key_schedule()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+        Ok(Pipeline {
+            schedule,
+            chunk_len: DEFAULT_CHUNK_LEN,
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+        })
+    }
+
+    // rounded up to a whole number of blocks
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        let block_len = self.schedule.block_len();
+        self.chunk_len = chunk_size.max(1).div_ceil(block_len) * block_len;
+        self
+    }
+
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    // xors the CTR keystream starting at the one-block counter `nonce` into
+    // everything `reader` yields, writing the result to `writer`. Returns
+    // the number of bytes written
+    pub fn apply_ctr<R, W>(&self, reader: R, mut writer: W, nonce: &[u8]) -> io::Result<u64>
+    where
+        R: Read + Send,
+        W: Write,
+    {
+        if nonce.len() != self.schedule.block_len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid nonce length",
+            ));
+        }
+
+        // the bounded channels keep at most a couple of chunks per worker
+        // in flight, however far the reader gets ahead of the writer
+        let (chunks_tx, chunks_rx) = mpsc::sync_channel::<(u64, Vec<u8>)>(self.workers);
+        let (done_tx, done_rx) = mpsc::sync_channel::<(u64, Vec<u8>)>(self.workers);
+        let chunks_rx = Arc::new(Mutex::new(chunks_rx));
+
+        thread::scope(|scope| {
+            let reading = scope.spawn(move || read_chunks(reader, self.chunk_len, chunks_tx));
+
+            for _ in 0..self.workers {
+                let chunks_rx = Arc::clone(&chunks_rx);
+                let done_tx = done_tx.clone();
+                scope.spawn(move || self.work(nonce, &chunks_rx, |chunk| done_tx.send(chunk)));
+            }
+            drop(chunks_rx);
+            drop(done_tx);
+
+            // if writing fails, dropping the receiving end makes the workers
+            // and then the reader stop
+            let written = write_in_order(&mut writer, done_rx);
+            let read = reading
+                .join()
+                .unwrap_or_else(|err| std::panic::resume_unwind(err));
+
+            let written = written?;
+            read?;
+            writer.flush()?;
+            Ok(written)
+        })
+    }
+
+    fn work<F, E>(&self, nonce: &[u8], chunks: &Mutex<Receiver<(u64, Vec<u8>)>>, mut send: F)
+    where
+        F: FnMut((u64, Vec<u8>)) -> Result<(), E>,
+    {
+        let mode = Mode::Ctr {
+            nonce: nonce.to_vec(),
+        };
+        let block_len = self.schedule.block_len();
+        let mut scratch = Vec::new();
+
+        loop {
+            // the lock is released as soon as a chunk has been received
+            let next = chunks.lock().unwrap_or_else(|err| err.into_inner()).recv();
+            let (index, mut chunk) = match next {
+                Ok(next) => next,
+                Err(_) => return,
+            };
+
+            let mut counter = nonce.to_vec();
+            add_to_counter(&mut counter, index * (self.chunk_len / block_len) as u64);
+
+            let whole = chunk.len() - chunk.len() % block_len;
+            let (blocks, tail) = chunk.split_at_mut(whole);
+            mode::encrypt_blocks_with(&self.schedule, &mode, &mut counter, blocks, &mut scratch);
+            if !tail.is_empty() {
+                mode::apply_keystream(&self.schedule, &mut counter, tail);
+            }
+
+            if send((index, chunk)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn read_chunks<R: Read>(
+    mut reader: R,
+    chunk_len: usize,
+    chunks: mpsc::SyncSender<(u64, Vec<u8>)>,
+) -> io::Result<()> {
+    for index in 0.. {
+        let mut chunk = Vec::with_capacity(chunk_len);
+        reader
+            .by_ref()
+            .take(chunk_len as u64)
+            .read_to_end(&mut chunk)?;
+
+        // only the last chunk can be short
+        let last = chunk.len() < chunk_len;
+        if !chunk.is_empty() && chunks.send((index, chunk)).is_err() {
+            return Ok(());
+        }
+        if last {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// chunks can finish out of order, the early ones wait until all the
+// chunks before them have been written
+fn write_in_order<W: Write>(writer: &mut W, done: Receiver<(u64, Vec<u8>)>) -> io::Result<u64> {
+    let mut pending = BTreeMap::new();
+    let mut next = 0u64;
+    let mut written = 0u64;
+
+    for (index, chunk) in done {
+        pending.insert(index, chunk);
+
+        while let Some(chunk) = pending.remove(&next) {
+            writer.write_all(&chunk)?;
+            written += chunk.len() as u64;
+            next += 1;
+        }
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StreamEncryptor;
+
+    fn cipher() -> RC5<u32> {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        RC5::<u32>::new(key, 4, 12, 16)
+    }
+
+    #[test]
+    fn matches_sequential_ctr() {
+        let nonce = [0xFE; 8];
+        let plaintext = (0..10_000).map(|x| x as u8).collect::<Vec<u8>>();
+
+        let mode = Mode::Ctr {
+            nonce: nonce.to_vec(),
+        };
+        let mut encryptor = StreamEncryptor::new(&cipher(), mode).unwrap();
+        let mut expected = encryptor.update(&plaintext);
+        expected.extend(encryptor.finalize());
+
+        for &(chunk_size, workers) in [(20, 3), (64, 1), (1 << 20, 4)].iter() {
+            let pipeline = Pipeline::new(&cipher())
+                .unwrap()
+                .chunk_size(chunk_size)
+                .workers(workers);
+
+            let mut ciphertext = Vec::new();
+            let written = pipeline
+                .apply_ctr(&plaintext[..], &mut ciphertext, &nonce)
+                .unwrap();
+            assert_eq!(written, plaintext.len() as u64);
+            assert_eq!(ciphertext, expected);
+
+            let mut decrypted = Vec::new();
+            pipeline
+                .apply_ctr(&ciphertext[..], &mut decrypted, &nonce)
+                .unwrap();
+            assert_eq!(decrypted, plaintext);
+        }
+    }
+
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_errors_stop_the_pipeline() {
+        let pipeline = Pipeline::new(&cipher()).unwrap().chunk_size(8).workers(2);
+        let input = vec![0u8; 1 << 16];
+
+        let err = pipeline
+            .apply_ctr(&input[..], FailingWriter, &[0u8; 8])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "disk full");
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::{Arc, Mutex};
+
+// a shared free list of byte buffers. Streams created with a pool take
+// their output and scratch buffers from it instead of allocating, and
+// callers hand output buffers back with `put` once they are done with
+// them. Cloning a pool yields another handle to the same free list
+#[derive(Clone)]
+pub struct BufferPool {
+    buffers: Arc<Mutex<Vec<Vec<u8>>>>,
+    max_buffers: usize,
+}
+
+impl BufferPool {
+    // at most `max_buffers` idle buffers are kept, any more are dropped
+    pub fn new(max_buffers: usize) -> Self {
+        BufferPool {
+            buffers: Arc::new(Mutex::new(Vec::with_capacity(max_buffers))),
+            max_buffers,
+        }
+    }
+
+    // an empty buffer, reusing the allocation of a returned one if any
+    pub fn take(&self) -> Vec<u8> {
+        self.lock().pop().unwrap_or_default()
+    }
+
+    pub fn put(&self, mut buffer: Vec<u8>) {
+        buffer.clear();
+
+        let mut buffers = self.lock();
+        if buffers.len() < self.max_buffers {
+            buffers.push(buffer);
+        }
+    }
+
+    // number of idle buffers
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Vec<u8>>> {
+        // the free list stays consistent even if a holder panicked
+        self.buffers.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn returned_buffers_are_reused() {
+        let pool = BufferPool::new(2);
+
+        let mut buffer = pool.take();
+        buffer.extend_from_slice(&[1u8; 100]);
+        let capacity = buffer.capacity();
+        pool.put(buffer);
+        assert_eq!(pool.len(), 1);
+
+        let buffer = pool.take();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.capacity(), capacity);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn idle_buffers_are_capped() {
+        let pool = BufferPool::new(2);
+        (0..5).for_each(|_| pool.put(Vec::with_capacity(8)));
+        assert_eq!(pool.len(), 2);
+    }
+}
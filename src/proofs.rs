@@ -0,0 +1,109 @@
+// Kani harnesses, run with `cargo kani --features verification`. For every
+// word size they show that key expansion stays inside L and S for each
+// key length 1..=255 and round count 0..=255, and that encrypting or
+// decrypting a block of the right length never panics, whatever the key,
+// rounds and block. The mixing loop runs 3 * max(t, c) <= 1536 times,
+// which sets the unwind bound
+use crate::heapless::{self, table_len, MAX_KEY_LEN};
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+
+const MAX_ROUNDS: usize = 255;
+const MAX_TABLE_LEN: usize = table_len(MAX_ROUNDS);
+
+fn any_key_len() -> usize {
+    let len: usize = kani::any();
+    kani::assume((1..=MAX_KEY_LEN).contains(&len));
+    len
+}
+
+fn any_rounds() -> usize {
+    let rounds: usize = kani::any();
+    kani::assume(rounds <= MAX_ROUNDS);
+    rounds
+}
+
+// the same steps as the allocating and heapless key schedules, with L
+// and S cut from arrays big enough for any parameters
+fn expand_any_key<T>() -> ([T; MAX_TABLE_LEN], usize)
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let key: [u8; MAX_KEY_LEN] = kani::any();
+    let b = any_key_len();
+    let r = any_rounds();
+
+    let mut l = T::key_words();
+    let l = &mut l.as_mut()[..b.div_ceil(core::mem::size_of::<T>())];
+    heapless::load_key(&key[..b], l);
+
+    let mut s = [T::zero(); MAX_TABLE_LEN];
+    heapless::mix_key(l, &mut s[..table_len(r)]);
+    (s, r)
+}
+
+fn no_panic_on_blocks<T>()
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy + kani::Arbitrary,
+{
+    let (s, r) = expand_any_key::<T>();
+    let mut block: T::Block = T::Block::default();
+    for word in block.as_mut().chunks_mut(core::mem::size_of::<T>()) {
+        word.copy_from_slice(kani::any::<T>().to_le_bytes().as_ref());
+    }
+
+    heapless::encrypt_block(&s[..table_len(r)], r, block.as_mut());
+    heapless::decrypt_block(&s[..table_len(r)], r, block.as_mut());
+}
+
+#[kani::proof]
+#[kani::unwind(1537)]
+fn rc5_16_key_expansion_is_in_bounds() {
+    expand_any_key::<u16>();
+}
+
+#[kani::proof]
+#[kani::unwind(1537)]
+fn rc5_32_key_expansion_is_in_bounds() {
+    expand_any_key::<u32>();
+}
+
+#[kani::proof]
+#[kani::unwind(1537)]
+fn rc5_64_key_expansion_is_in_bounds() {
+    expand_any_key::<u64>();
+}
+
+#[kani::proof]
+#[kani::unwind(1537)]
+fn rc5_16_blocks_never_panic() {
+    no_panic_on_blocks::<u16>();
+}
+
+#[kani::proof]
+#[kani::unwind(1537)]
+fn rc5_32_blocks_never_panic() {
+    no_panic_on_blocks::<u32>();
+}
+
+#[kani::proof]
+#[kani::unwind(1537)]
+fn rc5_64_blocks_never_panic() {
+    no_panic_on_blocks::<u64>();
+}
+
+// the public entry points on top: a correctly sized block always
+// encodes and decodes once the key was accepted
+#[cfg(feature = "std")]
+#[kani::proof]
+#[kani::unwind(1537)]
+fn encode_and_decode_accept_whole_blocks() {
+    use crate::{Rc5Decryptor, Rc5Encryptor, RC5};
+
+    let key: [u8; MAX_KEY_LEN] = kani::any();
+    let b = any_key_len();
+    let cipher = RC5::<u32>::new(&key[..b], 4, any_rounds(), b);
+    let block: [u8; 8] = kani::any();
+
+    let ciphertext = cipher.encode(&block).unwrap();
+    assert_eq!(cipher.decode(&ciphertext).unwrap(), block);
+}
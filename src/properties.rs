@@ -0,0 +1,382 @@
+// property tests: random keys, parameters and messages for every word
+// size, checked against invariants instead of fixed answers. Each case
+// draws from its own seed, and a failure names it, so it can be replayed
+// alone with `RC5_PROPERTY_SEED=<seed> cargo test properties`. Without
+// the variable the seeds are fixed, which keeps CI deterministic
+use crate::reference::ReferenceRc5;
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{
+    table_len, HeaplessRc5, Mode, Padding, Rc5Decryptor, Rc5Encryptor, Rc5Error, StreamDecryptor,
+    StreamEncryptor, MAX_KEY_LEN, RC5,
+};
+
+const CASES: u64 = 64;
+
+// SplitMix64, good enough to spread cases over the parameter space
+struct Gen(u64);
+
+impl Gen {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // uniform in `low..=high`, up to a negligible bias
+    fn range(&mut self, low: usize, high: usize) -> usize {
+        low + (self.next() % (high - low + 1) as u64) as usize
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+
+    // mostly short keys and few rounds, which is where the edge cases
+    // are, with the occasional maximum
+    fn key(&mut self) -> Vec<u8> {
+        let len = if self.range(0, 7) == 0 {
+            MAX_KEY_LEN
+        } else {
+            self.range(1, 40)
+        };
+        self.bytes(len)
+    }
+
+    fn rounds(&mut self) -> usize {
+        if self.range(0, 15) == 0 {
+            255
+        } else {
+            self.range(0, 24)
+        }
+    }
+
+    // the lengths of consecutive pieces covering `len` bytes
+    fn split(&mut self, len: usize) -> Vec<usize> {
+        let mut pieces = Vec::new();
+        let mut left = len;
+        while left > 0 {
+            let piece = self.range(1, left.min(40));
+            pieces.push(piece);
+            left -= piece;
+        }
+        pieces
+    }
+}
+
+fn check<F: FnMut(&mut Gen) -> Result<(), String>>(name: &str, mut property: F) {
+    let seeds = match std::env::var("RC5_PROPERTY_SEED") {
+        Ok(seed) => {
+            let seed = seed.parse().expect("RC5_PROPERTY_SEED must be a number");
+            seed..seed + 1
+        }
+        Err(_) => 0..CASES,
+    };
+    for seed in seeds {
+        if let Err(failure) = property(&mut Gen(seed)) {
+            panic!("{} fails for RC5_PROPERTY_SEED={}: {}", name, seed, failure);
+        }
+    }
+}
+
+fn cipher<T>(gen: &mut Gen) -> (RC5<T>, String)
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let key = gen.key();
+    let rounds = gen.rounds();
+    let name = format!(
+        "RC5-{}/{}/{} key {:02x?}",
+        8 * core::mem::size_of::<T>(),
+        rounds,
+        key.len(),
+        key
+    );
+    let len = key.len();
+    (RC5::new(key, core::mem::size_of::<T>(), rounds, len), name)
+}
+
+fn block_round_trip<T>(gen: &mut Gen) -> Result<(), String>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let (cipher, name) = cipher::<T>(gen);
+    let block_len = 2 * core::mem::size_of::<T>();
+    let block = gen.bytes(block_len);
+
+    let encoded = cipher
+        .encode(&block)
+        .map_err(|err| format!("{}: {}", name, err))?;
+    if cipher.decode(&encoded) != Ok(block.clone()) {
+        return Err(format!("{}: block {:02x?} doesn't round trip", name, block));
+    }
+
+    let wrong_len = gen.range(0, 3 * block_len);
+    let wrong = gen.bytes(wrong_len);
+    if wrong.len() != block_len && cipher.encode(&wrong) != Err(Rc5Error::InvalidBlockLength) {
+        return Err(format!("{}: accepted a {} byte block", name, wrong.len()));
+    }
+    Ok(())
+}
+
+fn padding(gen: &mut Gen) -> Padding {
+    [
+        Padding::Pkcs7,
+        Padding::Iso7816,
+        Padding::AnsiX923,
+        Padding::Zero,
+    ][gen.range(0, 3)]
+}
+
+fn mode(gen: &mut Gen, block_len: usize) -> Mode {
+    match gen.range(0, 2) {
+        0 => Mode::Ecb,
+        1 => Mode::Cbc {
+            iv: gen.bytes(block_len),
+        },
+        _ => Mode::Ctr {
+            nonce: gen.bytes(block_len),
+        },
+    }
+}
+
+// streaming in random pieces gives the same result as one call, the
+// ciphertext has the length the mode promises, and decryption inverts it
+fn mode_round_trip<T>(gen: &mut Gen) -> Result<(), String>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let (cipher, name) = cipher::<T>(gen);
+    let block_len = 2 * core::mem::size_of::<T>();
+    let mode = mode(gen, block_len);
+    let padding = padding(gen);
+    let len = gen.range(0, 200);
+    let mut plaintext = gen.bytes(len);
+    if padding == Padding::Zero {
+        // zero padding can't tell trailing zeros from padding
+        if let Some(last) = plaintext.last_mut() {
+            *last |= 1;
+        }
+    }
+    let name = format!(
+        "{} {:?} {:?}, {} bytes",
+        name,
+        mode,
+        padding,
+        plaintext.len()
+    );
+    let fail = |what: &str| Err(format!("{}: {}", name, what));
+
+    let encryptor = || {
+        StreamEncryptor::new(&cipher, mode.clone())
+            .map(|encryptor| encryptor.with_padding(padding))
+            .map_err(|err| format!("{}: {}", name, err))
+    };
+    let mut whole = encryptor()?;
+    let mut ciphertext = whole.update(&plaintext);
+    ciphertext.extend(whole.finalize());
+
+    let mut pieces = encryptor()?;
+    let mut streamed = Vec::new();
+    let mut offset = 0;
+    for len in gen.split(plaintext.len()) {
+        streamed.extend(pieces.update(&plaintext[offset..offset + len]));
+        offset += len;
+    }
+    streamed.extend(pieces.finalize());
+    if streamed != ciphertext {
+        return fail("chunking changed the ciphertext");
+    }
+
+    let expected_len = match (&mode, padding) {
+        (Mode::Ctr { .. }, _) => plaintext.len(),
+        (_, Padding::Zero) => plaintext.len().div_ceil(block_len) * block_len,
+        _ => (plaintext.len() / block_len + 1) * block_len,
+    };
+    if ciphertext.len() != expected_len {
+        return fail(&format!("{} bytes of ciphertext", ciphertext.len()));
+    }
+
+    let mut decryptor = StreamDecryptor::new(&cipher, mode.clone())
+        .map(|decryptor| decryptor.with_padding(padding))
+        .map_err(|err| format!("{}: {}", name, err))?;
+    let mut decrypted = Vec::new();
+    let mut offset = 0;
+    for len in gen.split(ciphertext.len()) {
+        decrypted.extend(decryptor.update(&ciphertext[offset..offset + len]));
+        offset += len;
+    }
+    match decryptor.finalize() {
+        Ok(rest) => decrypted.extend(rest),
+        Err(err) => return fail(&err.to_string()),
+    }
+    if decrypted != plaintext {
+        return fail("decryption doesn't invert encryption");
+    }
+
+    // CTR is its own inverse
+    if let Mode::Ctr { .. } = mode {
+        let mut again = encryptor()?;
+        let mut twice = again.update(&ciphertext);
+        twice.extend(again.finalize());
+        if twice != plaintext {
+            return fail("applying CTR twice isn't the identity");
+        }
+    }
+    Ok(())
+}
+
+// a CBC plaintext change reaches every later ciphertext block
+fn cbc_propagates<T>(gen: &mut Gen) -> Result<(), String>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let (cipher, name) = cipher::<T>(gen);
+    let block_len = 2 * core::mem::size_of::<T>();
+    let mode = Mode::Cbc {
+        iv: gen.bytes(block_len),
+    };
+    let blocks = gen.range(1, 8);
+    let plaintext = gen.bytes(blocks * block_len);
+    let mut changed = plaintext.clone();
+    let position = gen.range(0, changed.len() - 1);
+    changed[position] ^= 1 << gen.range(0, 7);
+
+    let encrypt = |plaintext: &[u8]| {
+        let mut encryptor = StreamEncryptor::new(&cipher, mode.clone()).unwrap();
+        let mut ciphertext = encryptor.update(plaintext);
+        ciphertext.extend(encryptor.finalize());
+        ciphertext
+    };
+    let (original, changed) = (encrypt(&plaintext), encrypt(&changed));
+
+    let first = position / block_len;
+    for (index, (a, b)) in original
+        .chunks(block_len)
+        .zip(changed.chunks(block_len))
+        .enumerate()
+    {
+        if (index < first) != (a == b) {
+            return Err(format!(
+                "{}: block {} after a change in block {}",
+                name, index, first
+            ));
+        }
+    }
+    Ok(())
+}
+
+// the allocation-free cipher agrees with the allocating one
+fn heapless_agrees<T, const TABLE_LEN: usize>(gen: &mut Gen) -> Result<(), String>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let key = gen.key();
+    let rounds = TABLE_LEN / 2 - 1;
+    let block_len = 2 * core::mem::size_of::<T>();
+    let blocks = gen.range(0, 6);
+    let mut data = gen.bytes(blocks * block_len);
+
+    let len = key.len();
+    let cipher = RC5::<T>::new(key.clone(), core::mem::size_of::<T>(), rounds, len);
+    let expected = data
+        .chunks(block_len)
+        .flat_map(|block| cipher.encode(block).unwrap())
+        .collect::<Vec<u8>>();
+
+    let heapless = HeaplessRc5::<T, TABLE_LEN>::new(&key).map_err(|err| err.to_string())?;
+    heapless
+        .encrypt_blocks(&mut data)
+        .map_err(|err| err.to_string())?;
+    if data != expected {
+        return Err(format!("rounds {} key {:02x?}", rounds, key));
+    }
+    Ok(())
+}
+
+// the optimized cipher agrees with the spec-literal one in both
+// directions, on blocks it produced as well as arbitrary ones
+fn matches_reference<T>(gen: &mut Gen) -> Result<(), String>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let key = gen.key();
+    let rounds = gen.rounds();
+    let word_bytes = core::mem::size_of::<T>();
+    let block = gen.bytes(2 * word_bytes);
+
+    let len = key.len();
+    let cipher = RC5::<T>::new(key.clone(), word_bytes, rounds, len);
+    let reference = ReferenceRc5::new(8 * word_bytes as u32, rounds, &key);
+    let name = format!("RC5-{}/{}/{} key {:02x?}", 8 * word_bytes, rounds, len, key);
+
+    let encoded = cipher
+        .encode(&block)
+        .map_err(|err| format!("{}: {}", name, err))?;
+    if encoded != reference.encrypt(&block) {
+        return Err(format!("{}: encrypting {:02x?} differs", name, block));
+    }
+    if cipher.decode(&block) != Ok(reference.decrypt(&block)) {
+        return Err(format!("{}: decrypting {:02x?} differs", name, block));
+    }
+    Ok(())
+}
+
+#[test]
+fn blocks_round_trip() {
+    #[cfg(feature = "rc5-16")]
+    check("RC5-16 blocks", block_round_trip::<u16>);
+    check("RC5-32 blocks", block_round_trip::<u32>);
+    #[cfg(feature = "rc5-64")]
+    check("RC5-64 blocks", block_round_trip::<u64>);
+}
+
+#[test]
+fn blocks_match_the_reference() {
+    #[cfg(feature = "rc5-16")]
+    check("RC5-16 reference", matches_reference::<u16>);
+    check("RC5-32 reference", matches_reference::<u32>);
+    #[cfg(feature = "rc5-64")]
+    check("RC5-64 reference", matches_reference::<u64>);
+}
+
+#[test]
+fn modes_round_trip() {
+    #[cfg(feature = "rc5-16")]
+    check("RC5-16 modes", mode_round_trip::<u16>);
+    check("RC5-32 modes", mode_round_trip::<u32>);
+    #[cfg(feature = "rc5-64")]
+    check("RC5-64 modes", mode_round_trip::<u64>);
+}
+
+#[test]
+fn cbc_changes_propagate() {
+    #[cfg(feature = "rc5-16")]
+    check("RC5-16 CBC", cbc_propagates::<u16>);
+    check("RC5-32 CBC", cbc_propagates::<u32>);
+    #[cfg(feature = "rc5-64")]
+    check("RC5-64 CBC", cbc_propagates::<u64>);
+}
+
+#[test]
+fn heapless_matches_the_allocating_cipher() {
+    #[cfg(feature = "rc5-16")]
+    check(
+        "heapless RC5-16/12",
+        heapless_agrees::<u16, { table_len(12) }>,
+    );
+    check(
+        "heapless RC5-32/0",
+        heapless_agrees::<u32, { table_len(0) }>,
+    );
+    check(
+        "heapless RC5-32/20",
+        heapless_agrees::<u32, { table_len(20) }>,
+    );
+    #[cfg(feature = "rc5-64")]
+    check(
+        "heapless RC5-64/24",
+        heapless_agrees::<u64, { table_len(24) }>,
+    );
+}
@@ -0,0 +1,373 @@
+// a deliberately plain RC5 following the pseudocode in Rivest's paper
+// step by step, with the word size chosen at run time. Every word lives
+// in a u64 and arithmetic is reduced mod 2^w by hand, so nothing is
+// shared with the generic, unrolled or vectorised code it checks. It is
+// slow and panics on bad parameters, and is only meant for differential
+// tests and fuzzing. `ReferenceRc5_32` is the same for 32-bit words only
+
+pub struct ReferenceRc5 {
+    w: u32,
+    r: usize,
+    s: Vec<u64>,
+}
+
+impl ReferenceRc5 {
+    // `word_bits` is 16, 32 or 64, and the key may be empty, as the
+    // paper allows
+    pub fn new(word_bits: u32, rounds: usize, key: &[u8]) -> Self {
+        let (p, q) = match word_bits {
+            16 => (0xb7e1, 0x9e37),
+            32 => (0xb7e1_5163, 0x9e37_79b9),
+            64 => (0xb7e1_5162_8aed_2a6b, 0x9e37_79b9_7f4a_7c15),
+            _ => panic!("RC5 words are 16, 32 or 64 bits"),
+        };
+        assert!(rounds <= 255 && key.len() <= 255);
+
+        let mut cipher = ReferenceRc5 {
+            w: word_bits,
+            r: rounds,
+            s: Vec::new(),
+        };
+
+        // converting the secret key from bytes to words
+        let b = key.len();
+        let u = (word_bits / 8) as usize;
+        let c = core::cmp::max(1, b.div_ceil(u));
+        let mut l = vec![0u64; c];
+        for i in (0..b).rev() {
+            l[i / u] = cipher.add(cipher.rotl(l[i / u], 8), key[i] as u64);
+        }
+
+        // initializing the array S
+        let t = 2 * (rounds + 1);
+        cipher.s = vec![0u64; t];
+        cipher.s[0] = p;
+        for i in 1..t {
+            cipher.s[i] = cipher.add(cipher.s[i - 1], q);
+        }
+
+        // mixing in the secret key
+        let (mut i, mut j) = (0, 0);
+        let (mut a, mut b) = (0u64, 0u64);
+        for _ in 0..3 * core::cmp::max(t, c) {
+            a = cipher.rotl(cipher.add(cipher.add(cipher.s[i], a), b), 3);
+            cipher.s[i] = a;
+            b = cipher.rotl(cipher.add(cipher.add(l[j], a), b), cipher.add(a, b));
+            l[j] = b;
+            i = (i + 1) % t;
+            j = (j + 1) % c;
+        }
+
+        cipher
+    }
+
+    pub fn block_len(&self) -> usize {
+        2 * (self.w / 8) as usize
+    }
+
+    pub fn encrypt(&self, block: &[u8]) -> Vec<u8> {
+        let (mut a, mut b) = self.words(block);
+        a = self.add(a, self.s[0]);
+        b = self.add(b, self.s[1]);
+        for i in 1..=self.r {
+            a = self.add(self.rotl(a ^ b, b), self.s[2 * i]);
+            b = self.add(self.rotl(b ^ a, a), self.s[2 * i + 1]);
+        }
+        self.bytes(a, b)
+    }
+
+    pub fn decrypt(&self, block: &[u8]) -> Vec<u8> {
+        let (mut a, mut b) = self.words(block);
+        for i in (1..=self.r).rev() {
+            b = self.rotr(self.sub(b, self.s[2 * i + 1]), a) ^ a;
+            a = self.rotr(self.sub(a, self.s[2 * i]), b) ^ b;
+        }
+        b = self.sub(b, self.s[1]);
+        a = self.sub(a, self.s[0]);
+        self.bytes(a, b)
+    }
+
+    fn mask(&self) -> u64 {
+        u64::MAX >> (64 - self.w)
+    }
+
+    fn add(&self, x: u64, y: u64) -> u64 {
+        x.wrapping_add(y) & self.mask()
+    }
+
+    fn sub(&self, x: u64, y: u64) -> u64 {
+        x.wrapping_sub(y) & self.mask()
+    }
+
+    // only the low lg(w) bits of `y` count
+    fn rotl(&self, x: u64, y: u64) -> u64 {
+        let y = (y % self.w as u64) as u32;
+        if y == 0 {
+            return x;
+        }
+        ((x << y) | (x >> (self.w - y))) & self.mask()
+    }
+
+    fn rotr(&self, x: u64, y: u64) -> u64 {
+        let y = (y % self.w as u64) as u32;
+        if y == 0 {
+            return x;
+        }
+        ((x >> y) | (x << (self.w - y))) & self.mask()
+    }
+
+    // the two words of a block, each stored little endian
+    fn words(&self, block: &[u8]) -> (u64, u64) {
+        assert_eq!(block.len(), self.block_len());
+        let word = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .rev()
+                .fold(0u64, |word, &byte| (word << 8) | byte as u64)
+        };
+        let (a, b) = block.split_at(block.len() / 2);
+        (word(a), word(b))
+    }
+
+    fn bytes(&self, a: u64, b: u64) -> Vec<u8> {
+        let u = (self.w / 8) as usize;
+        let mut block = Vec::with_capacity(2 * u);
+        block.extend_from_slice(&a.to_le_bytes()[..u]);
+        block.extend_from_slice(&b.to_le_bytes()[..u]);
+        block
+    }
+}
+
+// RC5-32 as in section 4 of Rivest's paper, with its variable names and
+// nothing generic, so each line can be held against the pseudocode. The
+// anchor the generic, unrolled, SIMD and heapless RC5-32 paths are
+// reviewed and tested against
+const P32: u32 = 0xb7e1_5163;
+const Q32: u32 = 0x9e37_79b9;
+
+pub struct ReferenceRc5_32 {
+    r: usize,
+    s: Vec<u32>,
+}
+
+impl ReferenceRc5_32 {
+    pub fn new(rounds: usize, key: &[u8]) -> Self {
+        assert!(rounds <= 255 && key.len() <= 255);
+        let r = rounds;
+        let k = key;
+
+        // b bytes of key, u = w/8 bytes per word, c words of key, and t
+        // words in the expanded key table
+        let b = k.len();
+        let u = 4;
+        let c = core::cmp::max(1, b.div_ceil(u));
+        let t = 2 * (r + 1);
+
+        // for i = b-1 downto 0 do L[i/u] = (L[i/u] <<< 8) + K[i]
+        let mut l = vec![0u32; c];
+        for i in (0..b).rev() {
+            l[i / u] = l[i / u].rotate_left(8).wrapping_add(k[i] as u32);
+        }
+
+        // S[0] = P32; for i = 1 to t-1 do S[i] = S[i-1] + Q32
+        let mut s = vec![0u32; t];
+        s[0] = P32;
+        for i in 1..t {
+            s[i] = s[i - 1].wrapping_add(Q32);
+        }
+
+        // i = j = 0; A = B = 0; do 3 * max(t, c) times:
+        //     A = S[i] = (S[i] + A + B) <<< 3
+        //     B = L[j] = (L[j] + A + B) <<< (A + B)
+        //     i = (i + 1) mod t; j = (j + 1) mod c
+        let (mut i, mut j) = (0, 0);
+        let (mut a, mut b) = (0u32, 0u32);
+        for _ in 0..3 * core::cmp::max(t, c) {
+            s[i] = s[i].wrapping_add(a).wrapping_add(b).rotate_left(3);
+            a = s[i];
+            l[j] = l[j]
+                .wrapping_add(a)
+                .wrapping_add(b)
+                .rotate_left(a.wrapping_add(b));
+            b = l[j];
+            i = (i + 1) % t;
+            j = (j + 1) % c;
+        }
+
+        ReferenceRc5_32 { r, s }
+    }
+
+    // A = A + S[0]; B = B + S[1]; for i = 1 to r do
+    //     A = ((A ^ B) <<< B) + S[2i]
+    //     B = ((B ^ A) <<< A) + S[2i+1]
+    pub fn encrypt_words(&self, [a, b]: [u32; 2]) -> [u32; 2] {
+        let s = &self.s;
+        let mut a = a.wrapping_add(s[0]);
+        let mut b = b.wrapping_add(s[1]);
+        for i in 1..=self.r {
+            a = (a ^ b).rotate_left(b).wrapping_add(s[2 * i]);
+            b = (b ^ a).rotate_left(a).wrapping_add(s[2 * i + 1]);
+        }
+        [a, b]
+    }
+
+    // for i = r downto 1 do
+    //     B = ((B - S[2i+1]) >>> A) ^ A
+    //     A = ((A - S[2i]) >>> B) ^ B
+    // B = B - S[1]; A = A - S[0]
+    pub fn decrypt_words(&self, [a, b]: [u32; 2]) -> [u32; 2] {
+        let s = &self.s;
+        let (mut a, mut b) = (a, b);
+        for i in (1..=self.r).rev() {
+            b = b.wrapping_sub(s[2 * i + 1]).rotate_right(a) ^ a;
+            a = a.wrapping_sub(s[2 * i]).rotate_right(b) ^ b;
+        }
+        b = b.wrapping_sub(s[1]);
+        a = a.wrapping_sub(s[0]);
+        [a, b]
+    }
+
+    // a block is the two words little endian, A first
+    pub fn encrypt(&self, block: [u8; 8]) -> [u8; 8] {
+        Self::to_bytes(self.encrypt_words(Self::to_words(block)))
+    }
+
+    pub fn decrypt(&self, block: [u8; 8]) -> [u8; 8] {
+        Self::to_bytes(self.decrypt_words(Self::to_words(block)))
+    }
+
+    fn to_words(block: [u8; 8]) -> [u32; 2] {
+        [
+            u32::from_le_bytes([block[0], block[1], block[2], block[3]]),
+            u32::from_le_bytes([block[4], block[5], block[6], block[7]]),
+        ]
+    }
+
+    fn to_bytes([a, b]: [u32; 2]) -> [u8; 8] {
+        let (a, b) = (a.to_le_bytes(), b.to_le_bytes());
+        [a[0], a[1], a[2], a[3], b[0], b[1], b[2], b[3]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn block_of(chunk: &[u8]) -> [u8; 8] {
+        let mut block = [0u8; 8];
+        block.copy_from_slice(chunk);
+        block
+    }
+
+    // the RC5-32/12/16 examples from the paper, each ciphertext being the
+    // next plaintext
+    const PAPER: [(&str, &str, &str); 5] = [
+        (
+            "00000000000000000000000000000000",
+            "0000000000000000",
+            "21a5dbee154b8f6d",
+        ),
+        (
+            "915f4619be41b2516355a50110a9ce91",
+            "21a5dbee154b8f6d",
+            "f7c013ac5b2b8952",
+        ),
+        (
+            "783348e75aeb0f2fd7b169bb8dc16787",
+            "f7c013ac5b2b8952",
+            "2f42b3b70369fc92",
+        ),
+        (
+            "dc49db1375a5584f6485b413b5f12baf",
+            "2f42b3b70369fc92",
+            "65c178b284d197cc",
+        ),
+        (
+            "5269f149d41ba0152497574d7f153125",
+            "65c178b284d197cc",
+            "eb44e415da319824",
+        ),
+    ];
+
+    #[test]
+    fn matches_the_published_rc5_32_vectors() {
+        for &(key, pt, ct) in PAPER.iter() {
+            let (key, pt, ct) = (hex(key), block_of(&hex(pt)), block_of(&hex(ct)));
+
+            let cipher = ReferenceRc5_32::new(12, &key);
+            assert_eq!(cipher.encrypt(pt), ct);
+            assert_eq!(cipher.decrypt(ct), pt);
+
+            let cipher = ReferenceRc5::new(32, 12, &key);
+            assert_eq!(cipher.encrypt(&pt), ct);
+            assert_eq!(cipher.decrypt(&ct), pt);
+        }
+    }
+
+    // the unrolled path only runs for 12 rounds, and the multi-block ECB
+    // path hands whole groups of blocks to AVX2 or SIMD where available
+    #[cfg(feature = "std")]
+    #[test]
+    fn rc5_32_paths_match_the_reference() {
+        use crate::{
+            table_len, HeaplessRc5, Mode, Padding, Rc5Decryptor, Rc5Encryptor, StreamEncryptor, RC5,
+        };
+
+        let plaintext = (0..100 * 8).map(|i| (i * 7 + 3) as u8).collect::<Vec<u8>>();
+        for &(rounds, key_len) in [(0, 1), (1, 5), (12, 16), (12, 3), (20, 32), (255, 255)].iter() {
+            let key = (0..key_len)
+                .map(|i| (i * 13 + rounds) as u8)
+                .collect::<Vec<u8>>();
+            let reference = ReferenceRc5_32::new(rounds, &key);
+            let expected = plaintext
+                .chunks(8)
+                .flat_map(|chunk| reference.encrypt(block_of(chunk)).to_vec())
+                .collect::<Vec<u8>>();
+
+            let cipher = RC5::<u32>::new(key.clone(), 4, rounds, key_len);
+            for (chunk, ct) in plaintext.chunks(8).zip(expected.chunks(8)) {
+                assert_eq!(cipher.encode(chunk).unwrap(), ct);
+                assert_eq!(cipher.decode(ct).unwrap(), chunk);
+            }
+
+            let mut encryptor = StreamEncryptor::new(&cipher, Mode::Ecb)
+                .unwrap()
+                .with_padding(Padding::Zero);
+            let mut ciphertext = encryptor.update(&plaintext);
+            ciphertext.extend(encryptor.finalize());
+            assert_eq!(ciphertext, expected);
+
+            let mut data = plaintext.clone();
+            match rounds {
+                12 => HeaplessRc5::<u32, { table_len(12) }>::new(&key)
+                    .unwrap()
+                    .encrypt_blocks(&mut data)
+                    .unwrap(),
+                20 => HeaplessRc5::<u32, { table_len(20) }>::new(&key)
+                    .unwrap()
+                    .encrypt_blocks(&mut data)
+                    .unwrap(),
+                _ => continue,
+            }
+            assert_eq!(data, expected);
+        }
+    }
+
+    #[test]
+    fn handles_empty_keys_and_zero_rounds() {
+        for &w in [16u32, 32, 64].iter() {
+            for &rounds in [0usize, 1, 255].iter() {
+                let cipher = ReferenceRc5::new(w, rounds, &[]);
+                let block = (0..cipher.block_len() as u8).collect::<Vec<u8>>();
+                assert_eq!(cipher.decrypt(&cipher.encrypt(&block)), block);
+            }
+        }
+    }
+}
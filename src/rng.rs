@@ -0,0 +1,190 @@
+use std::marker::PhantomData;
+
+use crate::traits::{Block, Unsigned16To64};
+#[cfg(all(feature = "os-rng", any(unix, target_os = "wasi")))]
+use crate::{Rc5Error, Rc5SecretKey, MAX_KEY_LEN};
+
+// a source of cryptographically secure random bytes, for generating keys
+// and IVs. Shaped after `rand_core::RngCore::fill_bytes` (plus the
+// `CryptoRng` marker), so any such generator is a one-line impl away
+pub trait CryptoRng {
+    fn fill_bytes(&mut self, dest: &mut [u8]);
+}
+
+impl<R: CryptoRng + ?Sized> CryptoRng for &mut R {
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        (**self).fill_bytes(dest)
+    }
+}
+
+// the operating system's generator: /dev/urandom on unix, and the
+// `random_get` call behind wasi-libc's `getentropy` on WASI, which has no
+// such file. Like `rand_core::OsRng` it panics if the generator can't be
+// read, `try_fill_bytes` and the `generate_*` functions report that as
+// `RngUnavailable` instead
+#[cfg(all(feature = "os-rng", any(unix, target_os = "wasi")))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OsRng;
+
+#[cfg(all(feature = "os-rng", unix))]
+impl OsRng {
+    pub fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Rc5Error> {
+        use std::io::Read;
+
+        std::fs::File::open("/dev/urandom")
+            .and_then(|mut urandom| urandom.read_exact(dest))
+            .map_err(|_| Rc5Error::RngUnavailable)
+    }
+}
+
+#[cfg(all(feature = "os-rng", target_os = "wasi"))]
+impl OsRng {
+    pub fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Rc5Error> {
+        use std::os::raw::{c_int, c_void};
+
+        extern "C" {
+            fn getentropy(buffer: *mut c_void, len: usize) -> c_int;
+        }
+
+        // getentropy fills at most 256 bytes per call
+        for chunk in dest.chunks_mut(256) {
+            // SAFETY: `chunk` is a live buffer of `chunk.len()` writable bytes
+            let status = unsafe { getentropy(chunk.as_mut_ptr() as *mut c_void, chunk.len()) };
+            if status != 0 {
+                return Err(Rc5Error::RngUnavailable);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "os-rng", any(unix, target_os = "wasi")))]
+impl CryptoRng for OsRng {
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.try_fill_bytes(dest)
+            .expect("failed to read from the operating system's random generator");
+    }
+}
+
+// a fresh key of `len` bytes from `OsRng`, 16 is the usual choice
+#[cfg(all(feature = "os-rng", any(unix, target_os = "wasi")))]
+pub fn generate_key(len: usize) -> Result<Rc5SecretKey, Rc5Error> {
+    if !(1..=MAX_KEY_LEN).contains(&len) {
+        return Err(Rc5Error::InvalidKeyLength);
+    }
+
+    let mut bytes = vec![0u8; len];
+    OsRng.try_fill_bytes(&mut bytes)?;
+    Ok(Rc5SecretKey::new(bytes))
+}
+
+// a CBC IV from `OsRng`, one block of the RC5 variant with words of type
+// `T`
+#[cfg(all(feature = "os-rng", any(unix, target_os = "wasi")))]
+pub fn generate_iv<T: Unsigned16To64>() -> Result<Nonce<T>, Rc5Error> {
+    Nonce::try_generate()
+}
+
+// a random CTR nonce from `OsRng`, see `Nonce` for how many messages one
+// key can take with random nonces
+#[cfg(all(feature = "os-rng", any(unix, target_os = "wasi")))]
+pub fn generate_nonce<T: Unsigned16To64>() -> Result<Nonce<T>, Rc5Error> {
+    Nonce::try_generate()
+}
+
+// a random block-sized value, usable as a CBC IV or a CTR nonce for the
+// RC5 variant with words of type `T`. A CTR nonce must never be reused
+// with the same key, a random one is safe for far fewer messages than a
+// counter would be (see `Rc5Params::security_assessment`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nonce<T: Unsigned16To64> {
+    bytes: Block<T>,
+    word: PhantomData<T>,
+}
+
+impl<T: Unsigned16To64> Nonce<T> {
+    pub fn generate<R: CryptoRng>(mut rng: R) -> Self {
+        let mut bytes = Block::<T>::default();
+        rng.fill_bytes(bytes.as_mut());
+        Nonce {
+            bytes,
+            word: PhantomData,
+        }
+    }
+
+    #[cfg(all(feature = "os-rng", any(unix, target_os = "wasi")))]
+    fn try_generate() -> Result<Self, Rc5Error> {
+        let mut bytes = Block::<T>::default();
+        OsRng.try_fill_bytes(bytes.as_mut())?;
+        Ok(Nonce {
+            bytes,
+            word: PhantomData,
+        })
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.bytes.as_ref().to_vec()
+    }
+}
+
+impl<T: Unsigned16To64> AsRef<[u8]> for Nonce<T> {
+    fn as_ref(&self) -> &[u8] {
+        self.bytes.as_ref()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // deterministic stand-in for a real generator
+    pub(crate) struct CountingRng(pub(crate) u8);
+
+    impl CryptoRng for CountingRng {
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            for byte in dest.iter_mut() {
+                *byte = self.0;
+                self.0 = self.0.wrapping_add(1);
+            }
+        }
+    }
+
+    #[test]
+    fn nonces_are_one_block_long() {
+        let mut rng = CountingRng(0);
+        let first = Nonce::<u32>::generate(&mut rng);
+        let second = Nonce::<u32>::generate(&mut rng);
+
+        assert_eq!(first.as_ref(), &[0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(second.as_ref(), &[8, 9, 10, 11, 12, 13, 14, 15]);
+        #[cfg(feature = "rc5-64")]
+        assert_eq!(Nonce::<u64>::generate(&mut rng).to_vec().len(), 16);
+    }
+
+    #[cfg(all(feature = "os-rng", feature = "rc5-64", any(unix, target_os = "wasi")))]
+    #[test]
+    fn os_rng_fills_buffers() {
+        let first = Nonce::<u64>::generate(OsRng);
+        let second = Nonce::<u64>::generate(OsRng);
+        assert_ne!(first, second);
+    }
+
+    #[cfg(all(
+        feature = "os-rng",
+        feature = "rc5-16",
+        feature = "rc5-64",
+        any(unix, target_os = "wasi")
+    ))]
+    #[test]
+    fn generated_values_fit_the_parameters() {
+        assert_eq!(generate_key(16).unwrap().len(), 16);
+        assert_eq!(generate_key(255).unwrap().len(), 255);
+        assert_eq!(generate_key(0).err(), Some(Rc5Error::InvalidKeyLength));
+        assert_eq!(generate_key(256).err(), Some(Rc5Error::InvalidKeyLength));
+
+        assert_eq!(generate_iv::<u16>().unwrap().as_ref().len(), 4);
+        assert_eq!(generate_iv::<u32>().unwrap().as_ref().len(), 8);
+        assert_eq!(generate_nonce::<u64>().unwrap().as_ref().len(), 16);
+        assert_ne!(generate_nonce::<u64>().unwrap(), generate_nonce().unwrap());
+    }
+}
@@ -0,0 +1,638 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::rng::{CryptoRng, Nonce};
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+#[cfg(feature = "argon2")]
+use crate::Argon2Params;
+use crate::{
+    CipherMode, Compression, ContainerReader, ContainerWriter, HeaderPolicy, Kdf, Padding,
+    Rc5Error, Rc5Header, Rc5Params, Rc5SecretKey, DEFAULT_CHUNK_SIZE, RC5,
+};
+#[cfg(feature = "compression")]
+use crate::{CompressWriter, DecompressReader};
+
+// Sealed file format:
+//
+//   `Rc5Header` | `ContainerWriter` output
+//
+// The header says how the key was derived and holds the nonce of the
+// first chunk, the container that follows is RC5-32/12 EAX in chunks.
+// The chunks are encrypted under a subkey of the secret for the exact
+// header bytes, so changing anything in the header makes the first chunk
+// fail to authenticate like a wrong key would.
+const WORDS: usize = 4;
+const ROUNDS: usize = 12;
+// the length of keys derived from passphrases
+const PASSPHRASE_KEY_LEN: usize = 16;
+const SALT_LEN: usize = 16;
+// what `Sealer` uses for passphrases without the `argon2` feature, the
+// OWASP recommendation for PBKDF2-HMAC-SHA-256
+#[cfg(not(feature = "argon2"))]
+const PBKDF2_ITERATIONS: u32 = 600_000;
+// headers asking for more work than this are refused rather than
+// attempted: 4 GiB of Argon2id memory, about 28 times the recommended
+// PBKDF2 iterations, and as many Argon2id passes and lanes as anyone uses.
+// `Kdf::derive_key` holds other formats to the same limits
+pub const MAX_KDF_MEMORY_KIB: u32 = 1 << 22;
+pub const MAX_KDF_ITERATIONS: u32 = 1 << 24;
+pub const MAX_KDF_PASSES: u32 = 64;
+pub const MAX_KDF_LANES: u32 = 255;
+const SUBKEY_INFO: &[u8] = b"rc5 sealed file";
+
+// what a file is sealed with: a key, or a passphrase a key is derived
+// from with a random salt recorded in the header
+#[derive(Clone, Copy)]
+pub enum Secret<'a> {
+    Key(&'a Rc5SecretKey),
+    Passphrase(&'a [u8]),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PassphraseKdf {
+    Pbkdf2(u32),
+    #[cfg(feature = "argon2")]
+    Argon2id(Argon2Params),
+}
+
+// the choices of `seal` for when its defaults don't fit: by default
+// chunks are `DEFAULT_CHUNK_SIZE` long and passphrases go through
+// Argon2id with `Argon2Params::default()`, or PBKDF2 with 600000
+// iterations without the `argon2` feature, and nothing is compressed.
+// `open` reads all of this from the header, so it needs no options
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sealer {
+    chunk_size: u32,
+    kdf: PassphraseKdf,
+    compression: Compression,
+}
+
+impl Sealer {
+    pub fn new() -> Self {
+        Sealer {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            #[cfg(feature = "argon2")]
+            kdf: PassphraseKdf::Argon2id(Argon2Params::default()),
+            #[cfg(not(feature = "argon2"))]
+            kdf: PassphraseKdf::Pbkdf2(PBKDF2_ITERATIONS),
+            compression: Compression::None,
+        }
+    }
+
+    // at least 1
+    pub fn chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    // derives keys from passphrases with PBKDF2-HMAC-SHA-256, for when
+    // Argon2id's memory use is too much
+    pub fn pbkdf2(mut self, iterations: u32) -> Self {
+        self.kdf = PassphraseKdf::Pbkdf2(iterations);
+        self
+    }
+
+    #[cfg(feature = "argon2")]
+    pub fn argon2(mut self, params: Argon2Params) -> Self {
+        self.kdf = PassphraseKdf::Argon2id(params);
+        self
+    }
+
+    // compresses the plaintext with LZ4 before it is encrypted. The size
+    // of the sealed file then tells how well the plaintext compressed, so
+    // this must stay off for plaintext that mixes secrets with anything
+    // an attacker chooses, see `CompressWriter`
+    #[cfg(feature = "compression")]
+    pub fn compress(mut self) -> Self {
+        self.compression = Compression::Lz4;
+        self
+    }
+
+    // encrypts everything `reader` yields into `writer` and returns the
+    // number of plaintext bytes
+    pub fn seal<G, R, W>(
+        &self,
+        mut rng: G,
+        mut reader: R,
+        mut writer: W,
+        secret: Secret<'_>,
+    ) -> io::Result<u64>
+    where
+        G: CryptoRng,
+        R: Read,
+        W: Write,
+    {
+        let (derived, kdf) = match secret {
+            Secret::Key(_) => (None, Kdf::None),
+            Secret::Passphrase(passphrase) => {
+                let mut salt = vec![0u8; SALT_LEN];
+                rng.fill_bytes(&mut salt);
+                let kdf = match self.kdf {
+                    PassphraseKdf::Pbkdf2(iterations) => Kdf::Pbkdf2 { iterations, salt },
+                    #[cfg(feature = "argon2")]
+                    PassphraseKdf::Argon2id(params) => Kdf::Argon2id {
+                        memory_kib: params.memory_kib,
+                        iterations: params.iterations,
+                        parallelism: params.parallelism,
+                        salt,
+                    },
+                };
+                (Some(passphrase_key(passphrase, &kdf, invalid_input)?), kdf)
+            }
+        };
+        let master = master(secret, &derived);
+
+        let header = Rc5Header {
+            params: Rc5Params {
+                words: WORDS,
+                rounds: ROUNDS,
+                bytes: master.len(),
+            },
+            mode: CipherMode::Eax,
+            kdf,
+            iv: Nonce::<u32>::generate(&mut rng).to_vec(),
+            key_id: None,
+            compression: self.compression,
+            padding: Padding::Pkcs7,
+        };
+        let header_bytes = header.to_bytes().map_err(invalid_input)?;
+        let cipher = subkey_cipher::<u32>(master, &header, &header_bytes)?;
+
+        writer.write_all(&header_bytes)?;
+        let mut container = ContainerWriter::new(writer, &cipher, self.chunk_size, &header.iv)?;
+        let len = match self.compression {
+            Compression::None => io::copy(&mut reader, &mut container)?,
+            #[cfg(feature = "compression")]
+            Compression::Lz4 => {
+                let mut compressor = CompressWriter::new(&mut container);
+                let len = io::copy(&mut reader, &mut compressor)?;
+                compressor.finish()?;
+                len
+            }
+            #[cfg(not(feature = "compression"))]
+            Compression::Lz4 => unreachable!("only `Sealer::compress` asks for it"),
+        };
+        container.finish()?;
+        Ok(len)
+    }
+}
+
+impl Default for Sealer {
+    fn default() -> Self {
+        Sealer::new()
+    }
+}
+
+// encrypts `reader` into `writer` into an authenticated file that
+// records everything `open` needs besides the secret. See `Sealer` for
+// the defaults
+pub fn seal<G, R, W>(rng: G, reader: R, writer: W, secret: Secret<'_>) -> io::Result<u64>
+where
+    G: CryptoRng,
+    R: Read,
+    W: Write,
+{
+    Sealer::new().seal(rng, reader, writer, secret)
+}
+
+// decrypts a sealed file from `reader` into `writer` and returns the
+// number of plaintext bytes. The plaintext is written a chunk at a time
+// as each chunk verifies, so after an error whatever reached `writer`
+// must be discarded: `InvalidData` means the file was modified, truncated
+// or sealed with another secret, and `InvalidInput` that it was sealed
+// with a key instead of a passphrase or the other way around
+pub fn open<R, W>(mut reader: R, mut writer: W, secret: Secret<'_>) -> io::Result<u64>
+where
+    R: Read,
+    W: Write,
+{
+    let header = Rc5Header::read_from(&mut reader)?;
+    if header.mode != CipherMode::Eax {
+        return Err(invalid_data("not a sealed file"));
+    }
+    // `Sealer` only writes RC5-32/12, but opens the other parameters of
+    // the default policy too, and nothing weaker
+    HeaderPolicy::new().check(&header).map_err(invalid_data)?;
+    #[cfg(not(feature = "compression"))]
+    if header.compression != Compression::None {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the file is compressed, which this build can't undo",
+        ));
+    }
+    let header_bytes = header.to_bytes().map_err(invalid_data)?;
+
+    let derived = match (secret, &header.kdf) {
+        (Secret::Key(_), Kdf::None) => None,
+        (Secret::Passphrase(passphrase), kdf) if *kdf != Kdf::None => {
+            Some(passphrase_key(passphrase, kdf, invalid_data)?)
+        }
+        (Secret::Key(_), _) => return Err(invalid_input("the file was sealed with a passphrase")),
+        (Secret::Passphrase(_), _) => return Err(invalid_input("the file was sealed with a key")),
+    };
+    let master = master(secret, &derived);
+
+    match header.params.words {
+        #[cfg(feature = "rc5-16")]
+        2 => open_with::<u16, _, _>(master, &header, &header_bytes, reader, &mut writer),
+        4 => open_with::<u32, _, _>(master, &header, &header_bytes, reader, &mut writer),
+        #[cfg(feature = "rc5-64")]
+        8 => open_with::<u64, _, _>(master, &header, &header_bytes, reader, &mut writer),
+        _ => Err(invalid_input(Rc5Error::InvalidWordSize)),
+    }
+}
+
+// `seal` from file to file. The output must not exist yet
+pub fn seal_file<G: CryptoRng>(
+    rng: G,
+    input: &Path,
+    output: &Path,
+    secret: Secret<'_>,
+) -> io::Result<u64> {
+    let reader = BufReader::new(File::open(input)?);
+    let writer = BufWriter::new(File::options().write(true).create_new(true).open(output)?);
+    with_cleanup(output, |writer| seal(rng, reader, writer, secret), writer)
+}
+
+// `open` from file to file. The output must not exist yet, and is removed
+// again if the input fails to open, so no unverified plaintext is left
+pub fn open_file(input: &Path, output: &Path, secret: Secret<'_>) -> io::Result<u64> {
+    let reader = BufReader::new(File::open(input)?);
+    let writer = BufWriter::new(File::options().write(true).create_new(true).open(output)?);
+    with_cleanup(output, |writer| open(reader, writer, secret), writer)
+}
+
+fn with_cleanup<F>(path: &Path, run: F, mut writer: BufWriter<File>) -> io::Result<u64>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<u64>,
+{
+    let result = run(&mut writer).and_then(|len| writer.flush().map(|()| len));
+    if result.is_err() {
+        drop(writer);
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+fn open_with<T, R, W>(
+    master: &Rc5SecretKey,
+    header: &Rc5Header,
+    header_bytes: &[u8],
+    reader: R,
+    writer: &mut W,
+) -> io::Result<u64>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    R: Read,
+    W: Write,
+{
+    let cipher = subkey_cipher::<T>(master, header, header_bytes)?;
+    let mut container = ContainerReader::new(reader, &cipher)?;
+    match header.compression {
+        Compression::None => io::copy(&mut container, writer),
+        #[cfg(feature = "compression")]
+        Compression::Lz4 => io::copy(&mut DecompressReader::new(container), writer),
+        #[cfg(not(feature = "compression"))]
+        Compression::Lz4 => unreachable!("refused by `open`"),
+    }
+}
+
+fn subkey_cipher<T>(
+    master: &Rc5SecretKey,
+    header: &Rc5Header,
+    header_bytes: &[u8],
+) -> io::Result<RC5<T>>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let mut info = SUBKEY_INFO.to_vec();
+    info.extend_from_slice(header_bytes);
+    let Rc5Params {
+        words,
+        rounds,
+        bytes,
+    } = header.params;
+    let key = master
+        .derive_subkey(&[], &info, bytes)
+        .map_err(invalid_input)?;
+    Ok(RC5::from_secret_key(key, words, rounds, bytes))
+}
+
+// the secret's key, or the one derived from its passphrase
+fn master<'a>(secret: Secret<'a>, derived: &'a Option<Rc5SecretKey>) -> &'a Rc5SecretKey {
+    match (secret, derived) {
+        (Secret::Key(key), _) => key,
+        (Secret::Passphrase(_), Some(derived)) => derived,
+        (Secret::Passphrase(_), None) => unreachable!(),
+    }
+}
+
+impl Kdf {
+    // the key `open` would derive from `passphrase`, for formats besides
+    // sealed files whose `Rc5Header` records the derivation. Costs beyond
+    // the `MAX_KDF_*` limits give `InvalidData`, and `Kdf::None`, which
+    // derives nothing, `InvalidInput`
+    pub fn derive_key(&self, passphrase: &[u8]) -> io::Result<Rc5SecretKey> {
+        if *self == Kdf::None {
+            return Err(invalid_input(Rc5Error::InvalidKdfParameters));
+        }
+        passphrase_key(passphrase, self, invalid_data)
+    }
+}
+
+// `error` reports bad parameters, which are the caller's when sealing and
+// the file's when opening
+fn passphrase_key(
+    passphrase: &[u8],
+    kdf: &Kdf,
+    error: fn(Rc5Error) -> io::Error,
+) -> io::Result<Rc5SecretKey> {
+    match kdf {
+        Kdf::None => unreachable!(),
+        Kdf::Pbkdf2 { iterations, salt } => {
+            if *iterations > MAX_KDF_ITERATIONS {
+                return Err(error(Rc5Error::InvalidKdfParameters));
+            }
+            Rc5SecretKey::from_password_pbkdf2(passphrase, salt, *iterations, PASSPHRASE_KEY_LEN)
+                .map_err(error)
+        }
+        #[cfg(feature = "argon2")]
+        Kdf::Argon2id {
+            memory_kib,
+            iterations,
+            parallelism,
+            salt,
+        } => {
+            if *memory_kib > MAX_KDF_MEMORY_KIB
+                || *iterations > MAX_KDF_PASSES
+                || *parallelism > MAX_KDF_LANES
+            {
+                return Err(error(Rc5Error::InvalidKdfParameters));
+            }
+            let params = Argon2Params {
+                memory_kib: *memory_kib,
+                iterations: *iterations,
+                parallelism: *parallelism,
+            };
+            params
+                .derive_key(passphrase, salt, PASSPHRASE_KEY_LEN)
+                .map_err(error)
+        }
+        #[cfg(not(feature = "argon2"))]
+        Kdf::Argon2id { .. } => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the file needs Argon2id, which this build lacks",
+        )),
+    }
+}
+
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn invalid_input<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::tests::CountingRng;
+
+    fn key() -> Rc5SecretKey {
+        Rc5SecretKey::new((0u8..16).collect())
+    }
+
+    fn plaintext(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7) as u8).collect()
+    }
+
+    // cheap enough for tests
+    fn sealer() -> Sealer {
+        Sealer::new().chunk_size(64).pbkdf2(10)
+    }
+
+    #[test]
+    fn sealed_data_opens() {
+        let key = key();
+        for len in [0usize, 1, 63, 64, 65, 1000].iter() {
+            for secret in [Secret::Key(&key), Secret::Passphrase(b"hunter2")].iter() {
+                let mut sealed = Vec::new();
+                let sealer = sealer();
+                let written = sealer
+                    .seal(CountingRng(1), &plaintext(*len)[..], &mut sealed, *secret)
+                    .unwrap();
+                assert_eq!(written, *len as u64);
+
+                let mut opened = Vec::new();
+                assert_eq!(
+                    open(&sealed[..], &mut opened, *secret).unwrap(),
+                    *len as u64
+                );
+                assert_eq!(opened, plaintext(*len));
+            }
+        }
+    }
+
+    #[cfg(feature = "argon2")]
+    #[test]
+    fn passphrases_can_use_argon2id() {
+        let params = Argon2Params {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let mut sealed = Vec::new();
+        let secret = Secret::Passphrase(b"correct horse");
+        Sealer::new()
+            .argon2(params)
+            .seal(CountingRng(1), &b"message"[..], &mut sealed, secret)
+            .unwrap();
+
+        let (header, _) = Rc5Header::from_bytes(&sealed).unwrap();
+        assert!(matches!(header.kdf, Kdf::Argon2id { memory_kib: 64, .. }));
+        let mut opened = Vec::new();
+        open(&sealed[..], &mut opened, secret).unwrap();
+        assert_eq!(opened, b"message");
+    }
+
+    #[test]
+    fn excessive_kdf_costs_are_refused() {
+        let secret = Secret::Passphrase(b"hunter2");
+        let mut sealed = Vec::new();
+        sealer()
+            .seal(CountingRng(1), &b"message"[..], &mut sealed, secret)
+            .unwrap();
+        let (header, len) = Rc5Header::from_bytes(&sealed).unwrap();
+        let open_with_kdf = |kdf: Kdf| {
+            let mut bytes = Rc5Header {
+                kdf,
+                ..header.clone()
+            }
+            .to_bytes()
+            .unwrap();
+            bytes.extend_from_slice(&sealed[len..]);
+            let err = open(&bytes[..], io::sink(), secret).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            err.into_inner()
+                .and_then(|inner| inner.downcast::<Rc5Error>().ok())
+                .map(|inner| *inner)
+        };
+
+        let salt = vec![1; SALT_LEN];
+        assert_eq!(
+            open_with_kdf(Kdf::Pbkdf2 {
+                iterations: MAX_KDF_ITERATIONS + 1,
+                salt: salt.clone(),
+            }),
+            Some(Rc5Error::InvalidKdfParameters)
+        );
+        #[cfg(feature = "argon2")]
+        for &(memory_kib, iterations, parallelism) in [
+            (MAX_KDF_MEMORY_KIB + 1, 1, 1),
+            (64, MAX_KDF_PASSES + 1, 1),
+            (1 << 12, 1, MAX_KDF_LANES + 1),
+        ]
+        .iter()
+        {
+            let kdf = Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+                salt: salt.clone(),
+            };
+            assert_eq!(open_with_kdf(kdf), Some(Rc5Error::InvalidKdfParameters));
+        }
+    }
+
+    #[test]
+    fn kdf_keys_are_the_ones_open_derives() {
+        let salt = vec![1; SALT_LEN];
+        let kdf = Kdf::Pbkdf2 {
+            iterations: 10,
+            salt: salt.clone(),
+        };
+        let expected =
+            Rc5SecretKey::from_password_pbkdf2(b"hunter2", &salt, 10, PASSPHRASE_KEY_LEN).unwrap();
+        assert_eq!(
+            kdf.derive_key(b"hunter2").unwrap().expose(),
+            expected.expose()
+        );
+
+        let kind = |kdf: Kdf| kdf.derive_key(b"hunter2").unwrap_err().kind();
+        assert_eq!(kind(Kdf::None), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            kind(Kdf::Pbkdf2 {
+                iterations: MAX_KDF_ITERATIONS + 1,
+                salt,
+            }),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compression_is_opt_in_and_recorded() {
+        let key = key();
+        let text = b"compressible, compressible, compressible. ".repeat(500);
+        let seal = |sealer: Sealer| {
+            let mut sealed = Vec::new();
+            sealer
+                .seal(CountingRng(1), &text[..], &mut sealed, Secret::Key(&key))
+                .unwrap();
+            sealed
+        };
+        let plain = seal(Sealer::new());
+        let compressed = seal(Sealer::new().compress());
+        assert!(compressed.len() < plain.len() / 10);
+
+        let (header, _) = Rc5Header::from_bytes(&plain).unwrap();
+        assert_eq!(header.compression, Compression::None);
+        let (header, _) = Rc5Header::from_bytes(&compressed).unwrap();
+        assert_eq!(header.compression, Compression::Lz4);
+
+        let mut opened = Vec::new();
+        let len = open(&compressed[..], &mut opened, Secret::Key(&key)).unwrap();
+        assert_eq!((len, opened), (text.len() as u64, text));
+    }
+
+    #[test]
+    fn the_wrong_secret_or_tampering_fails() {
+        let key = key();
+        let mut sealed = Vec::new();
+        sealer()
+            .seal(
+                CountingRng(1),
+                &plaintext(100)[..],
+                &mut sealed,
+                Secret::Key(&key),
+            )
+            .unwrap();
+        let open_err = |sealed: &[u8], secret| open(sealed, io::sink(), secret).unwrap_err().kind();
+
+        let other = Rc5SecretKey::new(vec![9; 16]);
+        assert_eq!(
+            open_err(&sealed, Secret::Key(&other)),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            open_err(&sealed, Secret::Passphrase(b"x")),
+            io::ErrorKind::InvalidInput
+        );
+
+        // every byte is covered, the header through the subkey
+        for i in 0..sealed.len() {
+            let mut tampered = sealed.clone();
+            tampered[i] ^= 0x01;
+            assert_ne!(
+                open(&tampered[..], io::sink(), Secret::Key(&key)).ok(),
+                Some(100),
+                "{}",
+                i
+            );
+        }
+        assert!(open(&sealed[..sealed.len() - 1], io::sink(), Secret::Key(&key)).is_err());
+
+        let mut sealed = Vec::new();
+        let secret = Secret::Passphrase(b"right");
+        sealer()
+            .seal(CountingRng(1), &b"message"[..], &mut sealed, secret)
+            .unwrap();
+        assert_eq!(
+            open_err(&sealed, Secret::Passphrase(b"wrong")),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            open_err(&sealed, Secret::Key(&key)),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn files_are_sealed_and_opened() {
+        let dir = std::env::temp_dir().join(format!("rc5-seal-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (plain, sealed, opened) = (dir.join("plain"), dir.join("sealed"), dir.join("opened"));
+        fs::write(&plain, plaintext(5000)).unwrap();
+        let key = key();
+
+        seal_file(CountingRng(1), &plain, &sealed, Secret::Key(&key)).unwrap();
+        assert!(seal_file(CountingRng(1), &plain, &sealed, Secret::Key(&key)).is_err());
+        open_file(&sealed, &opened, Secret::Key(&key)).unwrap();
+        assert_eq!(fs::read(&opened).unwrap(), plaintext(5000));
+
+        // a failed open leaves no output behind
+        fs::remove_file(&opened).unwrap();
+        let other = Rc5SecretKey::new(vec![9; 16]);
+        assert!(open_file(&sealed, &opened, Secret::Key(&other)).is_err());
+        assert!(!opened.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,76 @@
+// handling of memory holding key material: it is wiped when released and,
+// with the `secure-memory` feature on unix, locked into RAM so that it is
+// never written to swap. Locking is best effort, it fails silently when
+// the RLIMIT_MEMLOCK limit is reached. mlock works on whole pages, so
+// unlocking a buffer also unlocks whatever else shares its pages
+
+use crate::traits::sealed::Word;
+
+// locks the memory of `data`
+#[cfg(any(feature = "std", test))]
+pub(crate) fn lock<T>(data: &[T]) {
+    #[cfg(all(feature = "secure-memory", unix))]
+    sys::lock(data.as_ptr() as *const u8, core::mem::size_of_val(data));
+    #[cfg(not(all(feature = "secure-memory", unix)))]
+    let _ = data;
+}
+
+// overwrites `data` with zeros and unlocks it
+pub(crate) fn release<T: Word>(data: &mut [T]) {
+    let len = core::mem::size_of_val(data);
+    // SAFETY: the bytes of `data` are valid and exclusively borrowed, and
+    // `Word` types have no padding and take any byte pattern as a value
+    let bytes = unsafe { core::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, len) };
+    wipe(bytes);
+
+    #[cfg(all(feature = "secure-memory", unix))]
+    sys::unlock(bytes.as_ptr(), len);
+}
+
+// overwrites `bytes` with zeros in a way the optimizer can't drop as a
+// dead store
+pub fn wipe(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        // SAFETY: `byte` is a valid, exclusive reference
+        unsafe { core::ptr::write_volatile(byte, 0) };
+    }
+    core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+}
+
+#[cfg(all(feature = "secure-memory", unix))]
+mod sys {
+    use core::ffi::{c_int, c_void};
+
+    extern "C" {
+        fn mlock(addr: *const c_void, len: usize) -> c_int;
+        fn munlock(addr: *const c_void, len: usize) -> c_int;
+    }
+
+    pub(super) fn lock(ptr: *const u8, len: usize) {
+        if len > 0 {
+            // SAFETY: the range is a live allocation, mlock only changes
+            // its paging behaviour
+            unsafe { mlock(ptr as *const c_void, len) };
+        }
+    }
+
+    pub(super) fn unlock(ptr: *const u8, len: usize) {
+        if len > 0 {
+            // SAFETY: see `lock`
+            unsafe { munlock(ptr as *const c_void, len) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn released_memory_is_zeroed() {
+        let mut words = vec![0xDEAD_BEEFu32; 26];
+        lock(&words);
+        release(&mut words);
+        assert!(words.iter().all(|&word| word == 0));
+    }
+}
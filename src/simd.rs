@@ -0,0 +1,124 @@
+use std::simd::Simd;
+
+// number of RC5-32 blocks processed per iteration, one per lane
+pub(crate) const LANES: usize = 8;
+const CHUNK_LEN: usize = 8 * LANES;
+
+type Lanes = Simd<u32, LANES>;
+
+// data-dependent rotations differ per lane, so they are emulated with a
+// pair of per-lane shifts. Masking the right shift keeps a rotation by
+// zero well defined
+fn rotate_left(x: Lanes, amount: Lanes) -> Lanes {
+    let mask = Lanes::splat(31);
+    let amount = amount & mask;
+    (x << amount) | (x >> ((Lanes::splat(32) - amount) & mask))
+}
+
+fn rotate_right(x: Lanes, amount: Lanes) -> Lanes {
+    let mask = Lanes::splat(31);
+    let amount = amount & mask;
+    (x >> amount) | (x << ((Lanes::splat(32) - amount) & mask))
+}
+
+// splits LANES consecutive 8-byte blocks into their A and B words
+fn load(chunk: &[u8]) -> (Lanes, Lanes) {
+    let mut a = [0u32; LANES];
+    let mut b = [0u32; LANES];
+    for (i, block) in chunk.chunks_exact(8).enumerate() {
+        a[i] = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+        b[i] = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    }
+    (Lanes::from_array(a), Lanes::from_array(b))
+}
+
+fn store(chunk: &mut [u8], a: Lanes, b: Lanes) {
+    let (a, b) = (a.to_array(), b.to_array());
+    for (i, block) in chunk.chunks_exact_mut(8).enumerate() {
+        block[..4].copy_from_slice(&a[i].to_le_bytes());
+        block[4..].copy_from_slice(&b[i].to_le_bytes());
+    }
+}
+
+// encrypts as many whole groups of LANES blocks as `data` holds and returns
+// the number of bytes processed, the remainder is left to the scalar path
+pub(crate) fn encrypt_blocks(s_table: &[u32], rounds: usize, data: &mut [u8]) -> usize {
+    let mut processed = 0;
+
+    for chunk in data.chunks_exact_mut(CHUNK_LEN) {
+        let (mut a, mut b) = load(chunk);
+
+        a += Lanes::splat(s_table[0]);
+        b += Lanes::splat(s_table[1]);
+        for i in 1..(rounds + 1) {
+            a = rotate_left(a ^ b, b) + Lanes::splat(s_table[2 * i]);
+            b = rotate_left(b ^ a, a) + Lanes::splat(s_table[2 * i + 1]);
+        }
+
+        store(chunk, a, b);
+        processed += CHUNK_LEN;
+    }
+
+    processed
+}
+
+// decrypting counterpart of `encrypt_blocks`
+pub(crate) fn decrypt_blocks(s_table: &[u32], rounds: usize, data: &mut [u8]) -> usize {
+    let mut processed = 0;
+
+    for chunk in data.chunks_exact_mut(CHUNK_LEN) {
+        let (mut a, mut b) = load(chunk);
+
+        for i in (1..(rounds + 1)).rev() {
+            b = rotate_right(b - Lanes::splat(s_table[2 * i + 1]), a) ^ a;
+            a = rotate_right(a - Lanes::splat(s_table[2 * i]), b) ^ b;
+        }
+        a -= Lanes::splat(s_table[0]);
+        b -= Lanes::splat(s_table[1]);
+
+        store(chunk, a, b);
+        processed += CHUNK_LEN;
+    }
+
+    processed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RC5;
+
+    #[test]
+    fn rotations_match_scalar() {
+        let x = Lanes::splat(0x8000_0001);
+        let amounts = Lanes::from_array([0, 1, 4, 31, 32, 33, 63, 16]);
+
+        let left = rotate_left(x, amounts).to_array();
+        let right = rotate_right(x, amounts).to_array();
+        for (i, &amount) in amounts.to_array().iter().enumerate() {
+            assert_eq!(left[i], 0x8000_0001u32.rotate_left(amount));
+            assert_eq!(right[i], 0x8000_0001u32.rotate_right(amount));
+        }
+    }
+
+    #[test]
+    fn matches_scalar_backend() {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        let schedule = RC5::<u32>::new(key, 4, 12, 16).key_schedule().unwrap();
+        let s_table = schedule.u32_table().unwrap();
+
+        let plaintext = (0..CHUNK_LEN * 3 + 8).map(|x| x as u8).collect::<Vec<u8>>();
+
+        let mut expected = plaintext.clone();
+        expected
+            .chunks_exact_mut(8)
+            .for_each(|block| schedule.encrypt_block(block));
+
+        let mut data = plaintext.clone();
+        assert_eq!(encrypt_blocks(s_table, 12, &mut data), CHUNK_LEN * 3);
+        assert_eq!(data[..CHUNK_LEN * 3], expected[..CHUNK_LEN * 3]);
+
+        decrypt_blocks(s_table, 12, &mut data);
+        assert_eq!(data, plaintext);
+    }
+}
@@ -0,0 +1,726 @@
+use crate::mode::{self, Mode};
+use crate::padding::Padding;
+#[cfg(feature = "tracing")]
+use crate::trace::{self, TraceEvent, TraceOperation};
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{secure, BufferPool, KeySchedule, Rc5Error, RC5};
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"RC5S";
+// version 1 snapshots predate `Padding` and were always PKCS#7
+const SNAPSHOT_VERSION: u8 = 2;
+const ENCRYPTING: u8 = 0;
+const DECRYPTING: u8 = 1;
+
+// incremental encryptor accepting input of arbitrary length. Input is
+// buffered up to block boundaries, so only whole blocks are emitted by
+// `update` and the remainder (plus padding) is emitted by `finalize`
+pub struct StreamEncryptor<T: Unsigned16To64> {
+    state: StreamState<T>,
+}
+
+// incremental counterpart of `StreamEncryptor`. For padded modes the last
+// block is always held back, since it can only be unpadded in `finalize`
+pub struct StreamDecryptor<T: Unsigned16To64> {
+    state: StreamState<T>,
+}
+
+struct StreamState<T: Unsigned16To64> {
+    schedule: KeySchedule<T>,
+    mode: Mode,
+    padding: Padding,
+    chain: Vec<u8>,
+    buffer: Vec<u8>,
+    processed: u64,
+    // working memory of the mode, kept across calls
+    scratch: Vec<u8>,
+    pool: Option<BufferPool>,
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> StreamState<T> {
+    fn new(cipher: &RC5<T>, mode: Mode) -> Result<Self, Rc5Error> {
+        let schedule = cipher.key_schedule()?;
+        let chain = mode.initial_chain(schedule.block_len())?;
+
+        Ok(StreamState {
+            schedule,
+            mode,
+            padding: Padding::default(),
+            chain,
+            buffer: Vec::new(),
+            processed: 0,
+            scratch: Vec::new(),
+            pool: None,
+        })
+    }
+
+    fn with_pool(mut self, pool: BufferPool) -> Self {
+        self.scratch = pool.take();
+        self.pool = Some(pool);
+        self
+    }
+
+    fn with_padding(mut self, padding: Padding) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    fn output_buffer(&self) -> Vec<u8> {
+        match &self.pool {
+            Some(pool) => pool.take(),
+            None => Vec::new(),
+        }
+    }
+
+    // moves the buffered bytes and `input` into an output buffer, keeps the
+    // bytes past the first `ready(total)` ones buffered, and returns the rest
+    fn gather(&mut self, input: &[u8], ready: impl FnOnce(usize) -> usize) -> Vec<u8> {
+        self.processed += input.len() as u64;
+
+        let mut output = self.output_buffer();
+        output.extend_from_slice(&self.buffer);
+        output.extend_from_slice(input);
+
+        let ready = ready(output.len());
+        self.buffer.clear();
+        self.buffer.extend_from_slice(&output[ready..]);
+        output.truncate(ready);
+        output
+    }
+
+    // the snapshot never contains key material, but it does contain the
+    // buffered (not yet processed) input, which may be plaintext
+    fn snapshot(&self, direction: u8) -> Vec<u8> {
+        let mode_tag = match self.mode {
+            Mode::Ecb => 0,
+            Mode::Cbc { .. } => 1,
+            Mode::Ctr { .. } => 2,
+        };
+
+        let mut snapshot = Vec::with_capacity(19 + self.chain.len() + self.buffer.len());
+        snapshot.extend_from_slice(SNAPSHOT_MAGIC);
+        snapshot.push(SNAPSHOT_VERSION);
+        snapshot.push(direction);
+        snapshot.push(mode_tag);
+        snapshot.push(self.schedule.block_len() as u8);
+        snapshot.extend_from_slice(&self.processed.to_le_bytes());
+        snapshot.push(self.chain.len() as u8);
+        snapshot.extend_from_slice(&self.chain);
+        snapshot.push(self.buffer.len() as u8);
+        snapshot.extend_from_slice(&self.buffer);
+        snapshot.push(self.padding.tag());
+        snapshot
+    }
+
+    fn resume(cipher: &RC5<T>, direction: u8, snapshot: &[u8]) -> Result<Self, Rc5Error> {
+        let schedule = cipher.key_schedule()?;
+        let block_len = schedule.block_len();
+
+        if snapshot.len() < 17 || &snapshot[..4] != SNAPSHOT_MAGIC {
+            return Err(Rc5Error::InvalidSnapshot);
+        }
+        if snapshot[5] != direction {
+            return Err(Rc5Error::InvalidSnapshot);
+        }
+        if snapshot[7] as usize != block_len {
+            return Err(Rc5Error::InvalidSnapshot);
+        }
+
+        let mut processed = [0u8; 8];
+        processed.copy_from_slice(&snapshot[8..16]);
+
+        let (chain, rest) = split_length_prefixed(&snapshot[16..])?;
+        let (buffer, rest) = split_length_prefixed(rest)?;
+        if buffer.len() > block_len {
+            return Err(Rc5Error::InvalidSnapshot);
+        }
+        let padding = match (snapshot[4], rest) {
+            (1, []) => Padding::Pkcs7,
+            (SNAPSHOT_VERSION, &[tag]) => {
+                Padding::from_tag(tag).ok_or(Rc5Error::InvalidSnapshot)?
+            }
+            _ => return Err(Rc5Error::InvalidSnapshot),
+        };
+
+        // the chaining value picks up where the snapshot left off
+        let mode = match snapshot[6] {
+            0 => Mode::Ecb,
+            1 => Mode::Cbc { iv: chain.to_vec() },
+            2 => Mode::Ctr {
+                nonce: chain.to_vec(),
+            },
+            _ => return Err(Rc5Error::InvalidSnapshot),
+        };
+        let chain = mode.initial_chain(block_len)?;
+
+        Ok(StreamState {
+            schedule,
+            mode,
+            padding,
+            chain,
+            buffer: buffer.to_vec(),
+            processed: u64::from_le_bytes(processed),
+            scratch: Vec::new(),
+            pool: None,
+        })
+    }
+}
+
+impl<T: Unsigned16To64> Drop for StreamState<T> {
+    fn drop(&mut self) {
+        if let Some(pool) = &self.pool {
+            pool.put(std::mem::take(&mut self.scratch));
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn traced(operation: TraceOperation, input_len: usize, output_len: usize, started: Instant) {
+    trace::emit(TraceEvent::Buffer {
+        operation,
+        input_len,
+        output_len,
+        elapsed: started.elapsed(),
+    });
+}
+
+fn split_length_prefixed(bytes: &[u8]) -> Result<(&[u8], &[u8]), Rc5Error> {
+    match bytes.split_first() {
+        Some((&len, rest)) if rest.len() >= len as usize => Ok(rest.split_at(len as usize)),
+        _ => Err(Rc5Error::InvalidSnapshot),
+    }
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> StreamEncryptor<T> {
+    pub fn new(cipher: &RC5<T>, mode: Mode) -> Result<Self, Rc5Error> {
+        Ok(StreamEncryptor {
+            state: StreamState::new(cipher, mode)?,
+        })
+    }
+
+    // continues an encryption from the output of `snapshot`, the cipher
+    // must be constructed with the same key and parameters as the original
+    pub fn resume(cipher: &RC5<T>, snapshot: &[u8]) -> Result<Self, Rc5Error> {
+        Ok(StreamEncryptor {
+            state: StreamState::resume(cipher, ENCRYPTING, snapshot)?,
+        })
+    }
+
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.state.snapshot(ENCRYPTING)
+    }
+
+    // number of input bytes passed to `update` so far
+    pub fn bytes_processed(&self) -> u64 {
+        self.state.processed
+    }
+
+    // takes the output buffers from `pool`, they can be handed back with
+    // `BufferPool::put` once consumed
+    pub fn with_pool(self, pool: BufferPool) -> Self {
+        StreamEncryptor {
+            state: self.state.with_pool(pool),
+        }
+    }
+
+    // pads the last block with `padding` instead of PKCS#7, has no effect
+    // in CTR mode
+    pub fn with_padding(self, padding: Padding) -> Self {
+        StreamEncryptor {
+            state: self.state.with_padding(padding),
+        }
+    }
+
+    pub fn update(&mut self, input: &[u8]) -> Vec<u8> {
+        #[cfg(feature = "tracing")]
+        let started = Instant::now();
+        let state = &mut self.state;
+        let block_len = state.schedule.block_len();
+
+        // only the trailing partial block stays buffered
+        let mut output = state.gather(input, |len| len - len % block_len);
+        mode::encrypt_blocks_with(
+            &state.schedule,
+            &state.mode,
+            &mut state.chain,
+            &mut output,
+            &mut state.scratch,
+        );
+        #[cfg(feature = "tracing")]
+        traced(TraceOperation::Encrypt, input.len(), output.len(), started);
+        output
+    }
+
+    pub fn finalize(self) -> Vec<u8> {
+        #[cfg(feature = "tracing")]
+        let started = Instant::now();
+        let mut state = self.state;
+        let mut output = state.gather(&[], |len| len);
+
+        if state.mode.is_padded() {
+            state.padding.pad(&mut output, state.schedule.block_len());
+            mode::encrypt_blocks_with(
+                &state.schedule,
+                &state.mode,
+                &mut state.chain,
+                &mut output,
+                &mut state.scratch,
+            );
+        } else if !output.is_empty() {
+            mode::apply_keystream(&state.schedule, &mut state.chain, &mut output);
+        }
+
+        #[cfg(feature = "tracing")]
+        traced(TraceOperation::Encrypt, 0, output.len(), started);
+        output
+    }
+
+    // encrypts all of `text` at once, e.g. a value for a config file
+    pub fn encrypt_str(mut self, text: &str) -> Vec<u8> {
+        let mut output = self.update(text.as_bytes());
+        output.extend_from_slice(&self.finalize());
+        output
+    }
+
+    // adapts an iterator of plaintext chunks into an iterator of ciphertext
+    // chunks, the last item being the output of `finalize`. This is a
+    // synchronous adapter only: no `futures::Stream` one is provided, since
+    // the crate takes no dependencies and `futures-core` cannot be added
+    // here. Async bodies can drive `update` and `finalize` from their own
+    // `poll_next` instead
+    pub fn encrypt_chunks<I>(self, chunks: I) -> EncryptChunks<T, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        EncryptChunks {
+            chunks: chunks.into_iter(),
+            encryptor: Some(self),
+        }
+    }
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> StreamDecryptor<T> {
+    pub fn new(cipher: &RC5<T>, mode: Mode) -> Result<Self, Rc5Error> {
+        Ok(StreamDecryptor {
+            state: StreamState::new(cipher, mode)?,
+        })
+    }
+
+    // continues a decryption from the output of `snapshot`, see
+    // `StreamEncryptor::resume`
+    pub fn resume(cipher: &RC5<T>, snapshot: &[u8]) -> Result<Self, Rc5Error> {
+        Ok(StreamDecryptor {
+            state: StreamState::resume(cipher, DECRYPTING, snapshot)?,
+        })
+    }
+
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.state.snapshot(DECRYPTING)
+    }
+
+    // number of input bytes passed to `update` so far
+    pub fn bytes_processed(&self) -> u64 {
+        self.state.processed
+    }
+
+    // see `StreamEncryptor::with_pool`
+    pub fn with_pool(self, pool: BufferPool) -> Self {
+        StreamDecryptor {
+            state: self.state.with_pool(pool),
+        }
+    }
+
+    // see `StreamEncryptor::with_padding`
+    pub fn with_padding(self, padding: Padding) -> Self {
+        StreamDecryptor {
+            state: self.state.with_padding(padding),
+        }
+    }
+
+    pub fn update(&mut self, input: &[u8]) -> Vec<u8> {
+        #[cfg(feature = "tracing")]
+        let started = Instant::now();
+        let state = &mut self.state;
+        let block_len = state.schedule.block_len();
+        let padded = state.mode.is_padded();
+
+        let mut output = state.gather(input, |len| {
+            if padded {
+                len.saturating_sub(1) / block_len * block_len
+            } else {
+                len / block_len * block_len
+            }
+        });
+        mode::decrypt_blocks_with(
+            &state.schedule,
+            &state.mode,
+            &mut state.chain,
+            &mut output,
+            &mut state.scratch,
+        );
+        #[cfg(feature = "tracing")]
+        traced(TraceOperation::Decrypt, input.len(), output.len(), started);
+        output
+    }
+
+    pub fn finalize(self) -> Result<Vec<u8>, Rc5Error> {
+        #[cfg(feature = "tracing")]
+        let started = Instant::now();
+        let mut state = self.state;
+        let block_len = state.schedule.block_len();
+        let mut output = state.gather(&[], |len| len);
+
+        // zero padding turns empty plaintext into empty ciphertext
+        let padded = state.mode.is_padded();
+        if padded && (state.padding.always_pads() || !output.is_empty()) {
+            // a truncated last block is decrypted and unpadded all the
+            // same, so that it fails the same way as bad padding
+            let complete = output.len() == block_len;
+            output.resize(block_len, 0);
+            mode::decrypt_blocks_with(
+                &state.schedule,
+                &state.mode,
+                &mut state.chain,
+                &mut output,
+                &mut state.scratch,
+            );
+            let unpadded = state.padding.unpad(&mut output, block_len);
+            if !complete || unpadded.is_err() {
+                return Err(Rc5Error::DecryptionFailed);
+            }
+        } else if !padded && !output.is_empty() {
+            mode::apply_keystream(&state.schedule, &mut state.chain, &mut output);
+        }
+
+        #[cfg(feature = "tracing")]
+        traced(TraceOperation::Decrypt, 0, output.len(), started);
+        Ok(output)
+    }
+
+    // the inverse of `StreamEncryptor::encrypt_str`. Text that decrypts
+    // but isn't UTF-8, like after a wrong key in CTR mode, gives
+    // `InvalidUtf8` and is wiped
+    pub fn decrypt_to_string(mut self, ciphertext: &[u8]) -> Result<String, Rc5Error> {
+        let mut output = self.update(ciphertext);
+        match self.finalize() {
+            Ok(last) => output.extend_from_slice(&last),
+            Err(err) => {
+                secure::wipe(&mut output);
+                return Err(err);
+            }
+        }
+        String::from_utf8(output).map_err(|err| {
+            secure::wipe(&mut err.into_bytes());
+            Rc5Error::InvalidUtf8
+        })
+    }
+
+    // adapts an iterator of ciphertext chunks into an iterator of plaintext
+    // chunks, the last item being the output of `finalize`
+    pub fn decrypt_chunks<I>(self, chunks: I) -> DecryptChunks<T, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        DecryptChunks {
+            chunks: chunks.into_iter(),
+            decryptor: Some(self),
+        }
+    }
+}
+
+pub struct EncryptChunks<T: Unsigned16To64, I> {
+    chunks: I,
+    encryptor: Option<StreamEncryptor<T>>,
+}
+
+impl<T, I> Iterator for EncryptChunks<T, I>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let encryptor = self.encryptor.as_mut()?;
+
+        match self.chunks.next() {
+            Some(chunk) => Some(encryptor.update(chunk.as_ref())),
+            None => self.encryptor.take().map(StreamEncryptor::finalize),
+        }
+    }
+}
+
+pub struct DecryptChunks<T: Unsigned16To64, I> {
+    chunks: I,
+    decryptor: Option<StreamDecryptor<T>>,
+}
+
+impl<T, I> Iterator for DecryptChunks<T, I>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    type Item = Result<Vec<u8>, Rc5Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let decryptor = self.decryptor.as_mut()?;
+
+        match self.chunks.next() {
+            Some(chunk) => Some(Ok(decryptor.update(chunk.as_ref()))),
+            None => self.decryptor.take().map(StreamDecryptor::finalize),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const WORDS: usize = 4;
+    const ROUNDS: usize = 12;
+    const BYTES: usize = 16;
+
+    fn cipher() -> RC5<u32> {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        RC5::<u32>::new(key, WORDS, ROUNDS, BYTES)
+    }
+
+    fn modes() -> Vec<Mode> {
+        vec![
+            Mode::Ecb,
+            Mode::Cbc { iv: vec![0x5A; 8] },
+            Mode::Ctr {
+                nonce: vec![0xA5; 8],
+            },
+        ]
+    }
+
+    #[test]
+    fn chunk_boundaries_do_not_change_output() {
+        let plaintext = (0..100u8).collect::<Vec<u8>>();
+
+        for mode in modes() {
+            let mut encryptor = StreamEncryptor::new(&cipher(), mode.clone()).unwrap();
+            let mut whole = encryptor.update(&plaintext);
+            whole.extend(encryptor.finalize());
+
+            let encryptor = StreamEncryptor::new(&cipher(), mode).unwrap();
+            let split = encryptor
+                .encrypt_chunks(plaintext.chunks(7))
+                .flatten()
+                .collect::<Vec<u8>>();
+
+            assert_eq!(whole, split);
+        }
+    }
+
+    #[test]
+    fn round_trip_across_chunks() {
+        let plaintext = (0..61u8).collect::<Vec<u8>>();
+
+        for mode in modes() {
+            let encryptor = StreamEncryptor::new(&cipher(), mode.clone()).unwrap();
+            let ciphertext = encryptor
+                .encrypt_chunks(plaintext.chunks(5))
+                .flatten()
+                .collect::<Vec<u8>>();
+
+            if mode.is_padded() {
+                assert_eq!(ciphertext.len(), 64);
+            } else {
+                assert_eq!(ciphertext.len(), 61);
+            }
+
+            let decryptor = StreamDecryptor::new(&cipher(), mode).unwrap();
+            let decrypted = decryptor
+                .decrypt_chunks(ciphertext.chunks(3))
+                .collect::<Result<Vec<Vec<u8>>, _>>()
+                .unwrap()
+                .concat();
+
+            assert_eq!(plaintext, decrypted);
+        }
+    }
+
+    #[test]
+    fn ecb_blocks_match_single_block_encode() {
+        use crate::Rc5Encryptor;
+
+        let mut encryptor = StreamEncryptor::new(&cipher(), Mode::Ecb).unwrap();
+        let ciphertext = encryptor.update(&[0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77]);
+
+        let expected = cipher()
+            .encode(&[0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77])
+            .unwrap();
+        assert_eq!(ciphertext, expected);
+    }
+
+    #[test]
+    fn resumed_stream_matches_uninterrupted_one() {
+        let plaintext = (0..77u8).collect::<Vec<u8>>();
+
+        for mode in modes() {
+            let mut encryptor = StreamEncryptor::new(&cipher(), mode.clone()).unwrap();
+            let mut expected = encryptor.update(&plaintext);
+            expected.extend(encryptor.finalize());
+
+            let mut encryptor = StreamEncryptor::new(&cipher(), mode.clone()).unwrap();
+            let mut ciphertext = encryptor.update(&plaintext[..30]);
+            let snapshot = encryptor.snapshot();
+            drop(encryptor);
+
+            let mut encryptor = StreamEncryptor::resume(&cipher(), &snapshot).unwrap();
+            assert_eq!(encryptor.bytes_processed(), 30);
+            ciphertext.extend(encryptor.update(&plaintext[30..]));
+            ciphertext.extend(encryptor.finalize());
+            assert_eq!(ciphertext, expected);
+
+            let mut decryptor = StreamDecryptor::new(&cipher(), mode).unwrap();
+            let mut decrypted = decryptor.update(&ciphertext[..41]);
+            let snapshot = decryptor.snapshot();
+
+            let mut decryptor = StreamDecryptor::resume(&cipher(), &snapshot).unwrap();
+            decrypted.extend(decryptor.update(&ciphertext[41..]));
+            decrypted.extend(decryptor.finalize().unwrap());
+            assert_eq!(decrypted, plaintext);
+        }
+    }
+
+    #[test]
+    fn snapshot_direction_and_format_are_checked() {
+        let encryptor = StreamEncryptor::new(&cipher(), Mode::Ecb).unwrap();
+        let snapshot = encryptor.snapshot();
+
+        assert!(StreamDecryptor::resume(&cipher(), &snapshot).is_err());
+        assert!(StreamEncryptor::resume(&cipher(), &snapshot[..10]).is_err());
+        assert!(StreamEncryptor::resume(&cipher(), b"not a snapshot").is_err());
+    }
+
+    #[test]
+    fn every_padding_round_trips_and_survives_a_snapshot() {
+        let paddings = [
+            Padding::Pkcs7,
+            Padding::Iso7816,
+            Padding::AnsiX923,
+            Padding::Zero,
+        ];
+
+        for padding in paddings.iter() {
+            for len in [0usize, 5, 16, 21].iter() {
+                let plaintext = vec![0x42; *len];
+                let mut encryptor = StreamEncryptor::new(&cipher(), Mode::Ecb)
+                    .unwrap()
+                    .with_padding(*padding);
+                let mut ciphertext = encryptor.update(&plaintext);
+                ciphertext.extend(encryptor.finalize());
+
+                let mut decryptor = StreamDecryptor::new(&cipher(), Mode::Ecb)
+                    .unwrap()
+                    .with_padding(*padding);
+                let mut decrypted = decryptor.update(&ciphertext);
+                let decryptor = StreamDecryptor::resume(&cipher(), &decryptor.snapshot()).unwrap();
+                decrypted.extend(decryptor.finalize().unwrap());
+                assert_eq!(decrypted, plaintext, "{:?}", padding);
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_ciphertext_is_rejected() {
+        let mut decryptor = StreamDecryptor::new(&cipher(), Mode::Ecb).unwrap();
+        decryptor.update(&[0u8; 12]);
+        assert!(decryptor.finalize().is_err());
+    }
+
+    #[test]
+    fn pooled_streams_match_unpooled_ones() {
+        let plaintext = (0..200u8).collect::<Vec<u8>>();
+        let pool = BufferPool::new(4);
+
+        for mode in modes() {
+            let encryptor = StreamEncryptor::new(&cipher(), mode.clone()).unwrap();
+            let expected = encryptor
+                .encrypt_chunks(plaintext.chunks(9))
+                .flatten()
+                .collect::<Vec<u8>>();
+
+            let mut encryptor = StreamEncryptor::new(&cipher(), mode.clone())
+                .unwrap()
+                .with_pool(pool.clone());
+            let mut ciphertext = Vec::new();
+            for chunk in plaintext.chunks(9) {
+                let output = encryptor.update(chunk);
+                ciphertext.extend_from_slice(&output);
+                pool.put(output);
+            }
+            ciphertext.extend(encryptor.finalize());
+            assert_eq!(ciphertext, expected);
+
+            let mut decryptor = StreamDecryptor::new(&cipher(), mode)
+                .unwrap()
+                .with_pool(pool.clone());
+            let mut decrypted = decryptor.update(&ciphertext);
+            decrypted.extend(decryptor.finalize().unwrap());
+            assert_eq!(decrypted, plaintext);
+        }
+
+        // the scratch buffers of the finished streams went back to the pool
+        assert!(!pool.is_empty());
+    }
+
+    #[test]
+    fn decryption_failures_are_indistinguishable() {
+        let mode = Mode::Cbc { iv: vec![0x5A; 8] };
+        let mut encryptor = StreamEncryptor::new(&cipher(), mode.clone()).unwrap();
+        let mut ciphertext = encryptor.update(b"attack at dawn");
+        ciphertext.extend(encryptor.finalize());
+
+        let mut bad_padding = ciphertext.clone();
+        bad_padding[7] ^= 0x01;
+        let truncated = &ciphertext[..13];
+
+        for input in [&bad_padding[..], truncated, &[]].iter() {
+            let mut decryptor = StreamDecryptor::new(&cipher(), mode.clone()).unwrap();
+            decryptor.update(input);
+            assert_eq!(decryptor.finalize(), Err(Rc5Error::DecryptionFailed));
+        }
+    }
+
+    #[test]
+    fn strings_round_trip_and_are_checked() {
+        for mode in modes() {
+            for text in ["", "db-password", "grüße, 世界"].iter() {
+                let encryptor = StreamEncryptor::new(&cipher(), mode.clone()).unwrap();
+                let ciphertext = encryptor.encrypt_str(text);
+                let decryptor = StreamDecryptor::new(&cipher(), mode.clone()).unwrap();
+                assert_eq!(decryptor.decrypt_to_string(&ciphertext).unwrap(), *text);
+            }
+        }
+
+        // CTR has no padding to fail, bytes that aren't UTF-8 still do
+        let mode = Mode::Ctr {
+            nonce: vec![0xA5; 8],
+        };
+        let mut encryptor = StreamEncryptor::new(&cipher(), mode.clone()).unwrap();
+        let mut ciphertext = encryptor.update(&[b'a', 0xFF, b'b']);
+        ciphertext.extend(encryptor.finalize());
+        let decryptor = StreamDecryptor::new(&cipher(), mode).unwrap();
+        assert_eq!(
+            decryptor.decrypt_to_string(&ciphertext),
+            Err(Rc5Error::InvalidUtf8)
+        );
+
+        let mode = Mode::Cbc { iv: vec![0x5A; 8] };
+        let encryptor = StreamEncryptor::new(&cipher(), mode.clone()).unwrap();
+        let ciphertext = encryptor.encrypt_str("attack at dawn");
+        let decryptor = StreamDecryptor::new(&cipher(), mode).unwrap();
+        assert_eq!(
+            decryptor.decrypt_to_string(&ciphertext[..15]),
+            Err(Rc5Error::DecryptionFailed)
+        );
+    }
+}
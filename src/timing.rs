@@ -0,0 +1,426 @@
+use std::hint::black_box;
+use std::time::Instant;
+
+use crate::mode::Mode;
+use crate::padding::Padding;
+use crate::stream::StreamDecryptor;
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::vectors::SplitMix64;
+use crate::{Cmac, Eax, KeySchedule, Rc5Error, RC5};
+
+// |t| above which the two classes are taken to differ, the threshold
+// dudect uses. Under the null hypothesis |t| stays well below it even for
+// millions of measurements
+pub const TIMING_LEAK_THRESHOLD: f64 = 4.5;
+
+// the outcome of a Welch's t-test between the running times of two
+// classes of inputs, as measured by `measure_timing`. A `NoLeakDetected`
+// only means that this many samples on this machine didn't show one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimingVerdict {
+    NoLeakDetected,
+    Leak,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimingReport {
+    // measurements kept for class 0 and class 1, after the warm-up
+    pub samples: [usize; 2],
+    // mean running time of each class, in nanoseconds
+    pub mean_ns: [f64; 2],
+    // the t statistic of largest magnitude over the uncropped measurements
+    // and those cropped at a few percentiles, which drops the long tail
+    // that interrupts and cache misses add to both classes
+    pub t: f64,
+    pub verdict: TimingVerdict,
+}
+
+// pooled percentiles the measurements are cropped at, besides not at all
+const CROP_PERCENTILES: [f64; 5] = [0.5, 0.75, 0.9, 0.95, 0.99];
+
+// times `op` on `samples` inputs, each from `prepare(class)` with the
+// class 0 or 1 picked at random by SplitMix64 started at `seed`. Inputs
+// are all prepared before the first measurement so that only `op` is on
+// the clock, and the classes are interleaved so that drift in the
+// machine's speed affects both alike. The first tenth of the
+// measurements warms up caches and branch predictors and is dropped
+pub fn measure_timing<I, P, F>(samples: usize, seed: u64, mut prepare: P, op: F) -> TimingReport
+where
+    P: FnMut(usize) -> I,
+    F: FnMut(&I),
+{
+    let inputs = classes(samples, seed)
+        .map(|class| (class, prepare(class)))
+        .collect::<Vec<_>>();
+    measure(&inputs, op)
+}
+
+// the last plaintext blocks `padding_timing` and
+// `malformed_padding_timing` decrypt to
+#[derive(Clone, Copy)]
+enum PaddedBlock {
+    Valid,
+    // the byte giving the padding length, or the ISO 7816 marker, is off
+    BadLastByte,
+    // only the padding byte farthest from the end is off, so the padding
+    // looks right until the last byte checked
+    BadFirstByte,
+}
+
+impl<T: Unsigned16To64 + CipherMagicConstants + Copy> RC5<T> {
+    // compares finishing an ECB decryption whose last block has valid
+    // `padding` (class 0) against one whose padding is malformed (class
+    // 1). Only a valid block is then unpadded and returned, which tends to
+    // show up as a small difference given enough samples; that the padding
+    // was valid is in the result anyway. `Padding::Zero` can't be
+    // malformed, so it is refused with `InvalidEncoding`
+    pub fn padding_timing(
+        &self,
+        padding: Padding,
+        samples: usize,
+        seed: u64,
+    ) -> Result<TimingReport, Rc5Error> {
+        self.padded_block_timing(
+            padding,
+            [PaddedBlock::Valid, PaddedBlock::BadLastByte],
+            samples,
+            seed,
+        )
+    }
+
+    // compares two ways of malformed padding, one caught at the last byte
+    // of the block (class 0) and one only at the first padding byte (class
+    // 1), which `Padding::unpad` is written to take equally long on
+    pub fn malformed_padding_timing(
+        &self,
+        padding: Padding,
+        samples: usize,
+        seed: u64,
+    ) -> Result<TimingReport, Rc5Error> {
+        self.padded_block_timing(
+            padding,
+            [PaddedBlock::BadLastByte, PaddedBlock::BadFirstByte],
+            samples,
+            seed,
+        )
+    }
+
+    fn padded_block_timing(
+        &self,
+        padding: Padding,
+        kinds: [PaddedBlock; 2],
+        samples: usize,
+        seed: u64,
+    ) -> Result<TimingReport, Rc5Error> {
+        let schedule = self.schedule()?;
+        if padding == Padding::Zero {
+            return Err(Rc5Error::InvalidEncoding);
+        }
+
+        let block_len = schedule.block_len();
+        let mut random = SplitMix64(seed);
+        let inputs = classes(samples, seed)
+            .map(|class| {
+                let mut block = padded_block(padding, kinds[class], block_len, &mut random);
+                schedule.encrypt_block(&mut block);
+                (class, block)
+            })
+            .collect::<Vec<_>>();
+
+        Ok(measure(&inputs, |ciphertext| {
+            if let Ok(mut decryptor) = StreamDecryptor::new(self, Mode::Ecb) {
+                decryptor = decryptor.with_padding(padding);
+                decryptor.update(ciphertext);
+                black_box(decryptor.finalize()).ok();
+            }
+        }))
+    }
+
+    // compares expanding a key and decrypting one block under the all-zero
+    // key (class 0) against random keys (class 1), both with this cipher's
+    // parameters, so that key-dependent timing in the key schedule or the
+    // data-dependent rotations would show up
+    pub fn key_timing(&self, samples: usize, seed: u64) -> Result<TimingReport, Rc5Error> {
+        let block_len = self.schedule()?.block_len();
+
+        let mut random = SplitMix64(seed);
+        let mut inputs = |class| {
+            let key = if class == 0 {
+                vec![0u8; self.bytes]
+            } else {
+                random.bytes(self.bytes)
+            };
+            Ok((key, random.bytes(block_len)))
+        };
+
+        let prepared = prepare_all(samples, seed, &mut inputs)?;
+        Ok(measure(&prepared, |(key, block)| {
+            if let Ok(schedule) = KeySchedule::<T>::new(key, self.words, self.rounds, self.bytes) {
+                let mut block = block.clone();
+                schedule.decrypt_block(&mut block);
+                black_box(block);
+            }
+        }))
+    }
+}
+
+impl<T: Unsigned16To64 + Copy> Cmac<T> {
+    // compares verifying `message_len` random bytes against their tag
+    // (class 0) and against the tag with its first byte changed (class 1),
+    // where an early-exit comparison would return soonest
+    pub fn tag_timing(&self, message_len: usize, samples: usize, seed: u64) -> TimingReport {
+        let mut random = SplitMix64(seed);
+        let inputs = classes(samples, seed)
+            .map(|class| {
+                let message = random.bytes(message_len);
+                let mut tag = self.tag(&message);
+                tag[0] ^= class as u8;
+                (class, (message, tag))
+            })
+            .collect::<Vec<_>>();
+
+        measure(&inputs, |(message, tag)| {
+            black_box(self.verify(message, tag)).ok();
+        })
+    }
+}
+
+impl<T: Unsigned16To64 + Copy> Eax<T> {
+    // compares decrypting a message whose tag has its first byte changed
+    // (class 0) against one whose last byte is changed (class 1). A valid
+    // tag isn't one of the classes: only then is the message decrypted,
+    // which takes longer, but whether the tag was valid is public anyway
+    pub fn tag_timing(&self, message_len: usize, samples: usize, seed: u64) -> TimingReport {
+        let tag_len = self.tag_len();
+        let mut random = SplitMix64(seed);
+        let inputs = classes(samples, seed)
+            .map(|class| {
+                let nonce = random.bytes(tag_len);
+                let mut ciphertext = self.encrypt(&nonce, b"", &random.bytes(message_len));
+                let changed = ciphertext.len() - tag_len + class * (tag_len - 1);
+                ciphertext[changed] ^= 1;
+                (class, (nonce, ciphertext))
+            })
+            .collect::<Vec<_>>();
+
+        measure(&inputs, |(nonce, ciphertext)| {
+            black_box(self.decrypt(nonce, b"", ciphertext)).ok();
+        })
+    }
+}
+
+// random data padded with `padding` to one block, then spoilt as `kind`
+// says. At least two bytes are padding, so that the first of them isn't
+// the last byte of the block
+fn padded_block(
+    padding: Padding,
+    kind: PaddedBlock,
+    block_len: usize,
+    random: &mut SplitMix64,
+) -> Vec<u8> {
+    let data_len = random.next() as usize % (block_len - 1);
+    let mut block = random.bytes(data_len);
+    padding.pad(&mut block, block_len);
+
+    match kind {
+        PaddedBlock::Valid => {}
+        // a zero length, or with ISO 7816 a nonzero byte after the marker
+        PaddedBlock::BadLastByte => {
+            block[block_len - 1] = (padding == Padding::Iso7816) as u8;
+        }
+        PaddedBlock::BadFirstByte => block[data_len] ^= 1,
+    }
+    block
+}
+
+// the class of each measurement for `seed`. The harnesses draw their
+// inputs from SplitMix64 at `seed` too, so the classes come from another
+// starting point to stay independent of them
+fn classes(samples: usize, seed: u64) -> impl Iterator<Item = usize> {
+    let mut random = SplitMix64(!seed);
+    (0..samples).map(move |_| (random.next() & 1) as usize)
+}
+
+// the harnesses above need fallible preparation, so they build the
+// inputs here and then call `measure` themselves
+fn prepare_all<I, P>(
+    samples: usize,
+    seed: u64,
+    prepare: &mut P,
+) -> Result<Vec<(usize, I)>, Rc5Error>
+where
+    P: FnMut(usize) -> Result<I, Rc5Error>,
+{
+    classes(samples, seed)
+        .map(|class| Ok((class, prepare(class)?)))
+        .collect()
+}
+
+fn measure<I, F: FnMut(&I)>(inputs: &[(usize, I)], mut op: F) -> TimingReport {
+    let warm_up = inputs.len() / 10;
+    let mut times = [Vec::new(), Vec::new()];
+    for (index, (class, input)) in inputs.iter().enumerate() {
+        let start = Instant::now();
+        op(black_box(input));
+        let elapsed = start.elapsed().as_nanos() as f64;
+
+        if index >= warm_up {
+            times[*class].push(elapsed);
+        }
+    }
+
+    let mut pooled = times.concat();
+    pooled.sort_by(|a, b| a.total_cmp(b));
+
+    let mut t = welch_t(&times[0], &times[1]);
+    for percentile in CROP_PERCENTILES.iter() {
+        if let Some(&limit) = pooled.get((percentile * pooled.len() as f64) as usize) {
+            let cropped_t = welch_t(&crop(&times[0], limit), &crop(&times[1], limit));
+            if cropped_t.abs() > t.abs() {
+                t = cropped_t;
+            }
+        }
+    }
+
+    TimingReport {
+        samples: [times[0].len(), times[1].len()],
+        mean_ns: [mean(&times[0]), mean(&times[1])],
+        t,
+        verdict: if t.abs() > TIMING_LEAK_THRESHOLD {
+            TimingVerdict::Leak
+        } else {
+            TimingVerdict::NoLeakDetected
+        },
+    }
+}
+
+fn crop(times: &[f64], limit: f64) -> Vec<f64> {
+    times
+        .iter()
+        .copied()
+        .filter(|time| *time <= limit)
+        .collect()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+// Welch's t statistic for the difference of the two means, 0 when either
+// side has too few values to estimate its variance
+fn welch_t(a: &[f64], b: &[f64]) -> f64 {
+    if a.len() < 2 || b.len() < 2 {
+        return 0.0;
+    }
+
+    let variance = |values: &[f64], mean: f64| {
+        values.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (values.len() - 1) as f64
+    };
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let error =
+        (variance(a, mean_a) / a.len() as f64 + variance(b, mean_b) / b.len() as f64).sqrt();
+
+    if error == 0.0 {
+        return 0.0;
+    }
+    (mean_a - mean_b) / error
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MacKey;
+
+    fn cipher() -> RC5<u32> {
+        let key = (0u8..16).collect::<Vec<u8>>();
+        RC5::<u32>::new(key, 4, 12, 16)
+    }
+
+    #[test]
+    fn welch_t_statistic() {
+        assert_eq!(welch_t(&[1.0, 2.0, 3.0], &[1.0, 2.0, 3.0]), 0.0);
+        assert_eq!(welch_t(&[1.0], &[2.0, 3.0]), 0.0);
+
+        // means 2 and 5, variances 1 and 1, three values each
+        let t = welch_t(&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]);
+        assert!((t + 3.0 / (2.0f64 / 3.0).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn detects_a_data_dependent_loop() {
+        let report = measure_timing(
+            2000,
+            1,
+            |class| if class == 0 { 10 } else { 10_000 },
+            |iterations| {
+                (0..*iterations).for_each(|i| {
+                    black_box(i);
+                })
+            },
+        );
+
+        assert_eq!(report.samples.iter().sum::<usize>(), 1800);
+        assert!(report.mean_ns[1] > report.mean_ns[0]);
+        assert_eq!(report.verdict, TimingVerdict::Leak);
+    }
+
+    #[test]
+    fn padded_blocks_are_spoilt_as_asked() {
+        let mut random = SplitMix64(8);
+        for padding in [Padding::Pkcs7, Padding::Iso7816, Padding::AnsiX923].iter() {
+            for block_len in [2, 4, 8, 16].iter() {
+                for _ in 0..100 {
+                    for kind in [PaddedBlock::BadLastByte, PaddedBlock::BadFirstByte].iter() {
+                        let mut block = padded_block(*padding, *kind, *block_len, &mut random);
+                        assert!(padding.unpad(&mut block, *block_len).is_err());
+                    }
+
+                    let mut block =
+                        padded_block(*padding, PaddedBlock::Valid, *block_len, &mut random);
+                    assert!(padding.unpad(&mut block, *block_len).is_ok());
+                }
+            }
+        }
+    }
+
+    // only the bookkeeping is checked, a verdict from a shared test
+    // machine says little either way
+    #[test]
+    fn harnesses_measure_both_classes() {
+        let reports = [
+            cipher().padding_timing(Padding::Pkcs7, 1000, 2).unwrap(),
+            cipher().padding_timing(Padding::Iso7816, 1000, 3).unwrap(),
+            cipher()
+                .malformed_padding_timing(Padding::AnsiX923, 1000, 3)
+                .unwrap(),
+            cipher().key_timing(1000, 4).unwrap(),
+            Cmac::new(&MacKey::derive(&cipher()).unwrap())
+                .unwrap()
+                .tag_timing(32, 1000, 5),
+            Eax::new(&cipher()).unwrap().tag_timing(32, 1000, 6),
+        ];
+
+        for report in reports.iter() {
+            assert_eq!(report.samples.iter().sum::<usize>(), 900);
+            assert!(report.samples.iter().all(|&samples| samples > 300));
+            assert!(report.t.is_finite());
+        }
+    }
+
+    #[test]
+    fn invalid_keys_are_reported() {
+        let rc_5 = RC5::<u32>::new(vec![1, 2, 3], 4, 12, 16);
+        assert_eq!(
+            rc_5.padding_timing(Padding::Pkcs7, 10, 0),
+            Err(Rc5Error::InvalidKeyLength)
+        );
+        assert_eq!(rc_5.key_timing(10, 0), Err(Rc5Error::InvalidKeyLength));
+        assert_eq!(
+            cipher().padding_timing(Padding::Zero, 10, 0),
+            Err(Rc5Error::InvalidEncoding)
+        );
+    }
+}
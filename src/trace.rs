@@ -0,0 +1,167 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+
+// Instrumentation for the `tracing` feature: key expansions, buffers
+// through the stream API, container chunks and tag checks are reported to
+// one process-wide `Tracer`, so that a service can put them next to the
+// rest of its telemetry. Timed work is reported once it is done, with how
+// long it took, which is what a span around it would have recorded.
+//
+// Events carry sizes, parameters, durations and outcomes, never key bytes,
+// plaintext or tags. Without a tracer installed, reporting is one atomic
+// load
+static TRACER: RwLock<Option<Box<dyn Tracer>>> = RwLock::new(None);
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceOperation {
+    Encrypt,
+    Decrypt,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceEvent {
+    // a key was expanded for RC5-w/r/b, e.g. by `RC5::new`
+    KeyExpansion {
+        word_bits: usize,
+        rounds: usize,
+        key_len: usize,
+        elapsed: Duration,
+    },
+    // one call to `update` or `finalize` of a stream encryptor or decryptor
+    Buffer {
+        operation: TraceOperation,
+        input_len: usize,
+        output_len: usize,
+        elapsed: Duration,
+    },
+    // a container chunk was sealed, or read and verified. `index` counts
+    // the metadata chunk too, if there is one
+    Chunk {
+        operation: TraceOperation,
+        index: u64,
+        len: usize,
+        last: bool,
+    },
+    // the outcome of checking a tag: "EAX" for the authenticated modes and
+    // the containers on top of them, "CMAC" or "detached"
+    Verification {
+        kind: &'static str,
+        verified: bool,
+    },
+}
+
+// receives every event, on the thread that produced it
+pub trait Tracer: Send + Sync {
+    fn event(&self, event: &TraceEvent);
+}
+
+impl<F: Fn(&TraceEvent) + Send + Sync> Tracer for F {
+    fn event(&self, event: &TraceEvent) {
+        self(event)
+    }
+}
+
+// reports all further events to `tracer`, in place of any earlier one
+pub fn set_tracer<R: Tracer + 'static>(tracer: R) {
+    *TRACER.write().unwrap_or_else(|err| err.into_inner()) = Some(Box::new(tracer));
+    INSTALLED.store(true, Ordering::Release);
+}
+
+// stops reporting events
+pub fn clear_tracer() {
+    INSTALLED.store(false, Ordering::Release);
+    *TRACER.write().unwrap_or_else(|err| err.into_inner()) = None;
+}
+
+pub(crate) fn emit(event: TraceEvent) {
+    if !INSTALLED.load(Ordering::Acquire) {
+        return;
+    }
+    if let Some(tracer) = &*TRACER.read().unwrap_or_else(|err| err.into_inner()) {
+        tracer.event(&event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContainerReader, ContainerWriter, Mode, StreamEncryptor, RC5};
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    #[test]
+    fn work_is_reported_without_key_material() {
+        // other tests run on other threads at the same time
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        let test_thread = thread::current().id();
+        set_tracer(move |event: &TraceEvent| {
+            if thread::current().id() == test_thread {
+                recorded.lock().unwrap().push(event.clone());
+            }
+        });
+
+        let cipher = RC5::<u32>::new(vec![0x5A; 16], 4, 12, 16);
+        let mut encryptor = StreamEncryptor::new(&cipher, Mode::Ecb).unwrap();
+        encryptor.update(&[1; 20]);
+        encryptor.finalize();
+
+        let mut writer = ContainerWriter::new(Vec::new(), &cipher, 16, &[0; 8]).unwrap();
+        writer.write_all(&[2; 20]).unwrap();
+        let mut container = writer.finish().unwrap();
+        let last = container.len() - 1;
+        container[last] ^= 1;
+        let mut reader = ContainerReader::new(&container[..], &cipher).unwrap();
+        reader.next_chunk().unwrap();
+        assert!(reader.next_chunk().is_err());
+        clear_tracer();
+
+        let seen = events.lock().unwrap().clone();
+        assert!(matches!(
+            seen[0],
+            TraceEvent::KeyExpansion {
+                word_bits: 32,
+                rounds: 12,
+                key_len: 16,
+                ..
+            }
+        ));
+        let summary = seen[1..]
+            .iter()
+            .map(|event| match event {
+                TraceEvent::KeyExpansion { .. } => "key".to_string(),
+                TraceEvent::Buffer {
+                    input_len,
+                    output_len,
+                    ..
+                } => format!("buffer {} {}", input_len, output_len),
+                TraceEvent::Chunk {
+                    operation,
+                    index,
+                    len,
+                    last,
+                } => format!("{:?} {} {} {}", operation, index, len, last),
+                TraceEvent::Verification { kind, verified } => format!("{} {}", kind, verified),
+            })
+            .collect::<Vec<String>>();
+        assert_eq!(
+            summary,
+            [
+                "buffer 20 16",
+                "buffer 0 8",
+                "Encrypt 0 16 false",
+                "Encrypt 1 4 true",
+                "EAX true",
+                "Decrypt 0 16 false",
+                "EAX false",
+            ]
+        );
+
+        // nothing is reported once the tracer is gone
+        RC5::<u32>::new(vec![0x5A; 16], 4, 12, 16);
+        assert_eq!(events.lock().unwrap().len(), seen.len());
+    }
+}
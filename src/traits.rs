@@ -1,30 +1,62 @@
-use std::convert::TryInto;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use crate::Rc5Error;
+use crate::MAX_KEY_LEN;
+
+// the word traits are sealed, so `Unsigned16To64` and
+// `CipherMagicConstants` can be named but not implemented outside the
+// crate
+pub(crate) mod sealed {
+    // implemented only for the unsigned integers and arrays of them, whose
+    // bytes are all initialised and for which any byte pattern, all zeros
+    // included, is a valid value. `secure::release` and the RC5-32 fast
+    // paths, which borrow a table of 4-byte words as `u32`, rely on it
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe trait Word: Copy {}
+
+    unsafe impl Word for u8 {}
+    unsafe impl Word for u16 {}
+    unsafe impl Word for u32 {}
+    unsafe impl Word for u64 {}
+    unsafe impl<T: Word, const N: usize> Word for [T; N] {}
+}
 
 // represents either one of the types `u16`, `u32` or `u64`
-pub trait Unsigned16To64 {
+pub trait Unsigned16To64: sealed::Word {
     type Bytes: Clone + Copy + AsRef<[u8]> + AsMut<[u8]>;
+    // one cipher block, two words long
+    type Block: Clone + Copy + Default + Eq + fmt::Debug + AsRef<[u8]> + AsMut<[u8]>;
+    // the key schedule's L for the longest key, `MAX_KEY_LEN` bytes
+    // rounded up to whole words, so it stays small on the stack
+    type KeyWords: Copy + AsRef<[Self]> + AsMut<[Self]>;
     fn copy_from_slice(plaintext: &[u8], start: usize, end: usize) -> Self::Bytes;
     fn from_le_bytes(bytes: Self::Bytes) -> Self;
     fn to_le_bytes(self) -> Self::Bytes;
     fn wrapping_add(self, other: Self) -> Self;
     fn wrapping_sub(self, other: Self) -> Self;
     fn wrapping_mul(self, other: Self) -> Self;
+    // rotate by `other` mod w, so any word is a valid amount
     fn rotate_left(self, other: Self) -> Self;
     fn rotate_right(self, other: Self) -> Self;
     fn xor(self, other: Self) -> Self;
     fn zero() -> Self;
+    // an all-zero `KeyWords`
+    fn key_words() -> Self::KeyWords;
     fn from_str_radix(val: &str, base: u32) -> Self;
+    // `val` mod 2^w, whatever the width of `usize` on the target
     fn from_usize(val: usize) -> Self;
-    fn to_usize(&self) -> usize;
 }
 
 macro_rules! impl_unsigned_16_to_64 {
     ($($ty:ty),*) => { $(
         impl Unsigned16To64 for $ty {
-            type Bytes = [u8; std::mem::size_of::<Self>()];
+            type Bytes = [u8; core::mem::size_of::<Self>()];
+            type Block = [u8; 2 * core::mem::size_of::<Self>()];
+            type KeyWords = [Self; MAX_KEY_LEN.div_ceil(core::mem::size_of::<Self>())];
 
             fn copy_from_slice(plaintext: &[u8], start: usize, end: usize) -> Self::Bytes {
-                let mut output = [0u8; std::mem::size_of::<Self>()];
+                let mut output = [0u8; core::mem::size_of::<Self>()];
                 output.copy_from_slice(plaintext[start..end].as_ref());
                 output
             }
@@ -49,12 +81,14 @@ macro_rules! impl_unsigned_16_to_64 {
                 self.wrapping_mul(other)
             }
 
+            // only the low lg(w) bits of the amount are relevant. w is a
+            // power of two, so truncating the amount to u32 keeps them
             fn rotate_left(self, other: Self) -> Self {
-                self.rotate_left(other.try_into().unwrap())
+                self.rotate_left(other as u32 % Self::BITS)
             }
 
             fn rotate_right(self, other: Self) -> Self {
-                self.rotate_right(other.try_into().unwrap())
+                self.rotate_right(other as u32 % Self::BITS)
             }
 
             fn xor(self, other: Self) -> Self {
@@ -65,27 +99,49 @@ macro_rules! impl_unsigned_16_to_64 {
                 Self::MIN
             }
 
+            fn key_words() -> Self::KeyWords {
+                [Self::MIN; MAX_KEY_LEN.div_ceil(core::mem::size_of::<Self>())]
+            }
+
             fn from_str_radix(val: &str, base: u32) -> Self {
                 Self::from_str_radix(val, base).unwrap()
             }
 
+            // wraps: a `usize` wider than the word keeps only its low w bits,
+            // which is what the key schedule's arithmetic mod 2^w wants, and
+            // a narrower one is widened
             fn from_usize(val: usize) -> Self {
                 val as Self
             }
-
-            fn to_usize(&self) -> usize {
-                *self as usize
-            }
         }
     )* }
 }
-impl_unsigned_16_to_64!(u16, u32, u64);
+impl_unsigned_16_to_64!(u32);
+#[cfg(feature = "rc5-16")]
+impl_unsigned_16_to_64!(u16);
+#[cfg(feature = "rc5-64")]
+impl_unsigned_16_to_64!(u64);
+// RC5-8, whose 16-bit blocks the exhaustive tests can enumerate. It is far
+// too small to be a cipher, so the word type only exists in tests
+#[cfg(test)]
+impl_unsigned_16_to_64!(u8);
+
+// a block of the RC5 variant with words of type `T`, e.g. `[u8; 8]` for
+// RC5-32
+pub type Block<T> = <T as Unsigned16To64>::Block;
 
-pub trait CipherMagicConstants {
+pub trait CipherMagicConstants: sealed::Word {
     const P_W: Self;
     const Q_W: Self;
 }
 
+#[cfg(test)]
+impl CipherMagicConstants for u8 {
+    const P_W: Self = 0xb7; // first magic number
+    const Q_W: Self = 0x9f; // second magic number
+}
+
+#[cfg(feature = "rc5-16")]
 impl CipherMagicConstants for u16 {
     const P_W: Self = 0xb7e1; // first magic number
     const Q_W: Self = 0x9e37; // second magic number
@@ -96,12 +152,164 @@ impl CipherMagicConstants for u32 {
     const Q_W: Self = 0x9e3779b9; // second magic number
 }
 
+#[cfg(feature = "rc5-64")]
 impl CipherMagicConstants for u64 {
-    const P_W: Self = 0xb7e151628aed2a6b; // fist magic number
-    const Q_W: Self = 0x9e3779b97f47c15; // second magic number
+    const P_W: Self = 0xb7e151628aed2a6b; // first magic number
+    const Q_W: Self = 0x9e3779b97f4a7c15; // second magic number
+}
+
+// the encrypting half of a block cipher, for code that only ever
+// encrypts, e.g. CTR or CMAC. How the key got expanded isn't part of it.
+// Implementors only provide the fixed-size block, whose length the type
+// checks, and get the slice variants on top of it
+#[cfg(feature = "std")]
+pub trait Rc5Encryptor<T: Unsigned16To64> {
+    fn encrypt_block_in_place(&self, block: &mut Block<T>) -> Result<(), Rc5Error>;
+
+    fn encrypt_block(&self, block: &Block<T>) -> Result<Block<T>, Rc5Error> {
+        let mut output = *block;
+        self.encrypt_block_in_place(&mut output)?;
+        Ok(output)
+    }
+
+    // single-block variant writing into the front of a caller-provided
+    // buffer, returning the number of bytes written. It never allocates
+    fn encode_into(&self, input: &[u8], out: &mut [u8]) -> Result<usize, Rc5Error> {
+        let mut block = block_from::<T>(input, out)?;
+        self.encrypt_block_in_place(&mut block)?;
+        Ok(block_to(&block, out))
+    }
+
+    fn encode(&self, plaintext: &[u8]) -> Result<Vec<u8>, Rc5Error> {
+        let mut ciphertext = vec![0u8; plaintext.len()];
+        let written = self.encode_into(plaintext, &mut ciphertext)?;
+        ciphertext.truncate(written);
+        Ok(ciphertext)
+    }
+}
+
+// the decrypting half, the inverse of `Rc5Encryptor`
+#[cfg(feature = "std")]
+pub trait Rc5Decryptor<T: Unsigned16To64> {
+    fn decrypt_block_in_place(&self, block: &mut Block<T>) -> Result<(), Rc5Error>;
+
+    fn decrypt_block(&self, block: &Block<T>) -> Result<Block<T>, Rc5Error> {
+        let mut output = *block;
+        self.decrypt_block_in_place(&mut output)?;
+        Ok(output)
+    }
+
+    fn decode_into(&self, input: &[u8], out: &mut [u8]) -> Result<usize, Rc5Error> {
+        let mut block = block_from::<T>(input, out)?;
+        self.decrypt_block_in_place(&mut block)?;
+        Ok(block_to(&block, out))
+    }
+
+    fn decode(&self, ciphertext: &[u8]) -> Result<Vec<u8>, Rc5Error> {
+        let mut plaintext = vec![0u8; ciphertext.len()];
+        let written = self.decode_into(ciphertext, &mut plaintext)?;
+        plaintext.truncate(written);
+        Ok(plaintext)
+    }
+}
+
+// `input` as a block, if it is exactly one block long and `out` has room
+// for one
+#[cfg(feature = "std")]
+fn block_from<T: Unsigned16To64>(input: &[u8], out: &[u8]) -> Result<Block<T>, Rc5Error> {
+    let mut block = Block::<T>::default();
+    if input.len() != block.as_ref().len() {
+        return Err(Rc5Error::InvalidBlockLength);
+    }
+    if out.len() < input.len() {
+        return Err(Rc5Error::BufferTooSmall);
+    }
+    block.as_mut().copy_from_slice(input);
+    Ok(block)
 }
 
-pub trait Rc5CipherStream<T: Unsigned16To64> {
-    fn encode(&self, plaintext: &[u8]) -> Result<Vec<u8>, &'static str>;
-    fn decode(&self, plaintext: &[u8]) -> Result<Vec<u8>, &'static str>;
+#[cfg(feature = "std")]
+fn block_to<B: AsRef<[u8]>>(block: &B, out: &mut [u8]) -> usize {
+    let block = block.as_ref();
+    out[..block.len()].copy_from_slice(block);
+    block.len()
+}
+
+// both halves, for bounds that need to go either way. Implemented for
+// everything that implements the two
+#[cfg(feature = "std")]
+pub trait Rc5CipherStream<T: Unsigned16To64>: Rc5Encryptor<T> + Rc5Decryptor<T> {}
+
+#[cfg(feature = "std")]
+impl<T: Unsigned16To64, C: Rc5Encryptor<T> + Rc5Decryptor<T> + ?Sized> Rc5CipherStream<T> for C {}
+
+#[cfg(all(test, feature = "rc5-16", feature = "rc5-64"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_amounts_are_reduced_mod_w() {
+        let word = 0x8000_0000_0000_0001u64;
+        let amount = (1u64 << 32) + 66;
+        assert_eq!(
+            Unsigned16To64::rotate_left(word, amount),
+            0x0000_0000_0000_0006
+        );
+        assert_eq!(
+            Unsigned16To64::rotate_right(word, amount),
+            0x6000_0000_0000_0000
+        );
+        assert_eq!(Unsigned16To64::rotate_left(0x8001u16, 0xFFF1), 0x0003);
+        assert_eq!(Unsigned16To64::rotate_left(0x8001u16, 16), 0x8001);
+    }
+
+    #[test]
+    fn from_usize_wraps_to_the_word() {
+        assert_eq!(<u16 as Unsigned16To64>::from_usize(0x1_0005), 5);
+        assert_eq!(
+            <u64 as Unsigned16To64>::from_usize(usize::MAX),
+            usize::MAX as u64
+        );
+    }
+
+    // adds one to every byte, enough to see what the provided methods do
+    #[cfg(feature = "std")]
+    struct Increment;
+
+    #[cfg(feature = "std")]
+    impl Rc5Encryptor<u16> for Increment {
+        fn encrypt_block_in_place(&self, block: &mut Block<u16>) -> Result<(), Rc5Error> {
+            block
+                .iter_mut()
+                .for_each(|byte| *byte = byte.wrapping_add(1));
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl Rc5Decryptor<u16> for Increment {
+        fn decrypt_block_in_place(&self, block: &mut Block<u16>) -> Result<(), Rc5Error> {
+            block
+                .iter_mut()
+                .for_each(|byte| *byte = byte.wrapping_sub(1));
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn slice_variants_are_layered_on_the_block() {
+        assert_eq!(Increment.encrypt_block(&[0, 1, 2, 0xFF]), Ok([1, 2, 3, 0]));
+        assert_eq!(Increment.encode(&[0, 1, 2, 0xFF]), Ok(vec![1, 2, 3, 0]));
+        assert_eq!(Increment.decode(&[1, 2, 3, 0]), Ok(vec![0, 1, 2, 0xFF]));
+
+        let mut out = [9u8; 6];
+        assert_eq!(Increment.decode_into(&[1, 1, 1, 1], &mut out), Ok(4));
+        assert_eq!(out, [0, 0, 0, 0, 9, 9]);
+        assert_eq!(
+            Increment.encode_into(&[0; 4], &mut [0; 3]),
+            Err(Rc5Error::BufferTooSmall)
+        );
+        assert_eq!(Increment.encode(&[0; 8]), Err(Rc5Error::InvalidBlockLength));
+    }
 }
@@ -0,0 +1,675 @@
+// test vector files, laid out like Wycheproof files: `testGroups` share
+// the word size (`wordBits`), `rounds` and `mode` ("block" for a single
+// block, or "ECB", "CBC", "CTR"), and each of their `tests` gives a hex
+// `key`, `iv` (CBC and CTR), `msg` and `ct` with an expected `result`. A
+// valid vector must encrypt and decrypt to exactly its counterpart, an
+// invalid one must do neither. `VectorFile` reads and writes them,
+// `generate` makes a reproducible set and `run` replays one, so the sets
+// in `vectors/` and anyone else's are maintained as files, not code
+use std::collections::BTreeMap;
+
+use crate::traits::{CipherMagicConstants, Unsigned16To64};
+use crate::{
+    Mode, Rc5Decryptor, Rc5Encryptor, Rc5Error, StreamDecryptor, StreamEncryptor, MAX_KEY_LEN, RC5,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VectorFile {
+    pub algorithm: String,
+    pub notes: String,
+    pub groups: Vec<VectorGroup>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VectorGroup {
+    pub word_bits: u32,
+    pub rounds: usize,
+    pub mode: VectorMode,
+    pub tests: Vec<Vector>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VectorMode {
+    // `msg` is a single block, encrypted without any mode
+    Block,
+    // PKCS#7 padded
+    Ecb,
+    Cbc,
+    // `iv` is the initial counter
+    Ctr,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Vector {
+    pub id: i64,
+    pub comment: String,
+    pub key: Vec<u8>,
+    // present exactly for CBC and CTR
+    pub iv: Option<Vec<u8>>,
+    pub msg: Vec<u8>,
+    pub ct: Vec<u8>,
+    pub valid: bool,
+}
+
+impl VectorMode {
+    fn name(self) -> &'static str {
+        match self {
+            VectorMode::Block => "block",
+            VectorMode::Ecb => "ECB",
+            VectorMode::Cbc => "CBC",
+            VectorMode::Ctr => "CTR",
+        }
+    }
+}
+
+impl VectorFile {
+    // fails with `InvalidEncoding` for anything but a well-formed file
+    // with supported word sizes and round counts. Fields it doesn't know
+    // are ignored
+    pub fn parse(text: &str) -> Result<Self, Rc5Error> {
+        let file = Json::parse(text)?;
+        let groups = file
+            .array("testGroups")?
+            .iter()
+            .map(VectorGroup::from_json)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(VectorFile {
+            algorithm: file.str("algorithm")?.to_string(),
+            notes: file.str("notes").unwrap_or_default().to_string(),
+            groups,
+        })
+    }
+
+    // the layout of the files in `vectors/`, one vector per line, which
+    // `parse` reads back unchanged
+    pub fn to_json(&self) -> String {
+        let groups = self
+            .groups
+            .iter()
+            .map(VectorGroup::to_json)
+            .collect::<Vec<_>>()
+            .join(",\n");
+        format!(
+            "{{\n  \"algorithm\": {},\n  \"notes\": {},\n  \"testGroups\": [\n{}\n  ]\n}}\n",
+            quote(&self.algorithm),
+            quote(&self.notes),
+            groups
+        )
+    }
+}
+
+impl VectorGroup {
+    fn from_json(group: &Json) -> Result<Self, Rc5Error> {
+        let word_bits = match group.int("wordBits")? {
+            bits @ (16 | 32 | 64) => bits as u32,
+            _ => return Err(Rc5Error::InvalidEncoding),
+        };
+        let rounds = match group.int("rounds")? {
+            rounds @ 0..=255 => rounds as usize,
+            _ => return Err(Rc5Error::InvalidEncoding),
+        };
+        let mode = match group.str("mode")? {
+            "block" => VectorMode::Block,
+            "ECB" => VectorMode::Ecb,
+            "CBC" => VectorMode::Cbc,
+            "CTR" => VectorMode::Ctr,
+            _ => return Err(Rc5Error::InvalidEncoding),
+        };
+        let needs_iv = mode == VectorMode::Cbc || mode == VectorMode::Ctr;
+
+        let tests = group
+            .array("tests")?
+            .iter()
+            .map(|test| {
+                Ok(Vector {
+                    id: test.int("tcId")?,
+                    comment: test.str("comment").unwrap_or_default().to_string(),
+                    key: test.hex("key")?,
+                    iv: if needs_iv {
+                        Some(test.hex("iv")?)
+                    } else {
+                        None
+                    },
+                    msg: test.hex("msg")?,
+                    ct: test.hex("ct")?,
+                    valid: match test.str("result")? {
+                        "valid" => true,
+                        "invalid" => false,
+                        _ => return Err(Rc5Error::InvalidEncoding),
+                    },
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(VectorGroup {
+            word_bits,
+            rounds,
+            mode,
+            tests,
+        })
+    }
+
+    fn to_json(&self) -> String {
+        let tests = self
+            .tests
+            .iter()
+            .map(|test| {
+                let iv = match &test.iv {
+                    Some(iv) => format!(" \"iv\": \"{}\",", to_hex(iv)),
+                    None => String::new(),
+                };
+                format!(
+                    "        {{ \"tcId\": {}, \"comment\": {}, \"key\": \"{}\",{} \"msg\": \"{}\", \"ct\": \"{}\", \"result\": \"{}\" }}",
+                    test.id,
+                    quote(&test.comment),
+                    to_hex(&test.key),
+                    iv,
+                    to_hex(&test.msg),
+                    to_hex(&test.ct),
+                    if test.valid { "valid" } else { "invalid" }
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",\n");
+        format!(
+            "    {{\n      \"wordBits\": {},\n      \"rounds\": {},\n      \"mode\": \"{}\",\n      \"tests\": [\n{}\n      ]\n    }}",
+            self.word_bits,
+            self.rounds,
+            self.mode.name(),
+            tests
+        )
+    }
+}
+
+// `count` valid single-block vectors for RC5-`word_bits`/`rounds` with
+// `key_bytes` byte keys. Keys and blocks are drawn in turn from the
+// little-endian outputs of SplitMix64 started at `seed`, so the same
+// arguments always give the same file
+pub fn generate(
+    word_bits: u32,
+    rounds: usize,
+    key_bytes: usize,
+    count: usize,
+    seed: u64,
+) -> Result<VectorFile, Rc5Error> {
+    if key_bytes == 0 {
+        return Err(Rc5Error::EmptyKey);
+    }
+    if key_bytes > MAX_KEY_LEN {
+        return Err(Rc5Error::InvalidKeyLength);
+    }
+    let tests = match word_bits {
+        #[cfg(feature = "rc5-16")]
+        16 => generate_tests::<u16>(rounds, key_bytes, count, seed),
+        32 => generate_tests::<u32>(rounds, key_bytes, count, seed),
+        #[cfg(feature = "rc5-64")]
+        64 => generate_tests::<u64>(rounds, key_bytes, count, seed),
+        _ => return Err(Rc5Error::InvalidWordSize),
+    }?;
+
+    Ok(VectorFile {
+        algorithm: "RC5".to_string(),
+        notes: format!(
+            "RC5-{}/{}/{} vectors for seed {}: each key, then each msg, is taken from the little-endian SplitMix64 outputs for that seed",
+            word_bits, rounds, key_bytes, seed
+        ),
+        groups: vec![VectorGroup {
+            word_bits,
+            rounds,
+            mode: VectorMode::Block,
+            tests,
+        }],
+    })
+}
+
+fn generate_tests<T>(
+    rounds: usize,
+    key_bytes: usize,
+    count: usize,
+    seed: u64,
+) -> Result<Vec<Vector>, Rc5Error>
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let words = core::mem::size_of::<T>();
+    let comment = format!("RC5-{}/{}/{}", 8 * words, rounds, key_bytes);
+    let mut random = SplitMix64(seed);
+
+    (1..=count)
+        .map(|id| {
+            let key = random.bytes(key_bytes);
+            let msg = random.bytes(2 * words);
+            let ct = RC5::<T>::new(key.clone(), words, rounds, key_bytes).encode(&msg)?;
+            Ok(Vector {
+                id: id as i64,
+                comment: comment.clone(),
+                key,
+                iv: None,
+                msg,
+                ct,
+                valid: true,
+            })
+        })
+        .collect()
+}
+
+// replays every vector of `file`, returning those whose outcome differs
+// from their `result`
+pub fn run(file: &VectorFile) -> Vec<&Vector> {
+    file.groups
+        .iter()
+        .flat_map(|group| {
+            group.tests.iter().filter(move |test| {
+                let matched = match group.word_bits {
+                    #[cfg(feature = "rc5-16")]
+                    16 => matches::<u16>(group, test),
+                    32 => matches::<u32>(group, test),
+                    #[cfg(feature = "rc5-64")]
+                    64 => matches::<u64>(group, test),
+                    // a word size that isn't compiled in can't be checked
+                    _ => !test.valid,
+                };
+                matched != test.valid
+            })
+        })
+        .collect()
+}
+
+// whether `ct` is what this crate makes of `msg`, in both directions
+fn matches<T>(group: &VectorGroup, test: &Vector) -> bool
+where
+    T: Unsigned16To64 + CipherMagicConstants + Copy,
+{
+    let words = group.word_bits as usize / 8;
+    let bytes = test.key.len();
+    let cipher = RC5::<T>::new(test.key.clone(), words, group.rounds, bytes);
+    let iv = || test.iv.clone().unwrap_or_default();
+
+    let mode = match group.mode {
+        VectorMode::Block => {
+            let encrypted = cipher.encode(&test.msg);
+            let decrypted = cipher.decode(&test.ct);
+            return encrypted.as_ref() == Ok(&test.ct) && decrypted.as_ref() == Ok(&test.msg);
+        }
+        VectorMode::Ecb => Mode::Ecb,
+        VectorMode::Cbc => Mode::Cbc { iv: iv() },
+        VectorMode::Ctr => Mode::Ctr { nonce: iv() },
+    };
+
+    let encrypted = StreamEncryptor::new(&cipher, mode.clone()).map(|mut encryptor| {
+        let mut output = encryptor.update(&test.msg);
+        output.extend(encryptor.finalize());
+        output
+    });
+    let decrypted = StreamDecryptor::new(&cipher, mode).and_then(|mut decryptor| {
+        let mut output = decryptor.update(&test.ct);
+        output.extend(decryptor.finalize()?);
+        Ok::<_, Rc5Error>(output)
+    });
+
+    encrypted.as_ref() == Ok(&test.ct) && decrypted.as_ref() == Ok(&test.msg)
+}
+
+// SplitMix64 (Steele, Lea and Flood), which any implementation can
+// reproduce in a few lines
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // `len` bytes from fresh outputs, each little-endian
+    pub(crate) fn bytes(&mut self, len: usize) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(len + 8);
+        while bytes.len() < len {
+            bytes.extend_from_slice(&self.next().to_le_bytes());
+        }
+        bytes.truncate(len);
+        bytes
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// a JSON string with the escapes the parser understands
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// the subset of JSON the vector files use: no escapes beyond the simple
+// ones, numbers as integers
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+impl Json {
+    pub(crate) fn parse(text: &str) -> Result<Json, Rc5Error> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            position: 0,
+        };
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.position != parser.bytes.len() {
+            return Err(Rc5Error::InvalidEncoding);
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.get(key),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn str(&self, key: &str) -> Result<&str, Rc5Error> {
+        match self.get(key) {
+            Some(Json::String(value)) => Ok(value),
+            _ => Err(Rc5Error::InvalidEncoding),
+        }
+    }
+
+    pub(crate) fn int(&self, key: &str) -> Result<i64, Rc5Error> {
+        match self.get(key) {
+            Some(Json::Number(value)) => Ok(*value),
+            _ => Err(Rc5Error::InvalidEncoding),
+        }
+    }
+
+    pub(crate) fn array(&self, key: &str) -> Result<&[Json], Rc5Error> {
+        match self.get(key) {
+            Some(Json::Array(values)) => Ok(values),
+            _ => Err(Rc5Error::InvalidEncoding),
+        }
+    }
+
+    pub(crate) fn hex(&self, key: &str) -> Result<Vec<u8>, Rc5Error> {
+        let text = self.str(key)?;
+        if !text.len().is_multiple_of(2) || !text.is_ascii() {
+            return Err(Rc5Error::InvalidEncoding);
+        }
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| Rc5Error::InvalidEncoding)
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.position) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), Rc5Error> {
+        if self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+            Ok(())
+        } else {
+            Err(Rc5Error::InvalidEncoding)
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, Rc5Error> {
+        self.whitespace();
+        match self.bytes.get(self.position) {
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => self.array(),
+            Some(b'{') => self.object(),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => Err(Rc5Error::InvalidEncoding),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, Rc5Error> {
+        let start = self.position;
+        if self.bytes[self.position] == b'-' {
+            self.position += 1;
+        }
+        while let Some(b'0'..=b'9') = self.bytes.get(self.position) {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+            .map(Json::Number)
+            .ok_or(Rc5Error::InvalidEncoding)
+    }
+
+    fn string(&mut self) -> Result<String, Rc5Error> {
+        self.expect("\"")?;
+        let mut value = Vec::new();
+
+        loop {
+            let byte = *self
+                .bytes
+                .get(self.position)
+                .ok_or(Rc5Error::InvalidEncoding)?;
+            self.position += 1;
+
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.bytes.get(self.position) {
+                        Some(b'"') => b'"',
+                        Some(b'\\') => b'\\',
+                        Some(b'/') => b'/',
+                        Some(b'n') => b'\n',
+                        Some(b't') => b'\t',
+                        _ => return Err(Rc5Error::InvalidEncoding),
+                    };
+                    self.position += 1;
+                    value.push(escaped);
+                }
+                _ => value.push(byte),
+            }
+        }
+
+        String::from_utf8(value).map_err(|_| Rc5Error::InvalidEncoding)
+    }
+
+    fn array(&mut self) -> Result<Json, Rc5Error> {
+        self.expect("[")?;
+        let mut values = Vec::new();
+
+        self.whitespace();
+        if self.bytes.get(self.position) == Some(&b']') {
+            self.position += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.whitespace();
+            match self.bytes.get(self.position) {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Json::Array(values));
+                }
+                _ => return Err(Rc5Error::InvalidEncoding),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, Rc5Error> {
+        self.expect("{")?;
+        let mut fields = BTreeMap::new();
+
+        self.whitespace();
+        if self.bytes.get(self.position) == Some(&b'}') {
+            self.position += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.whitespace();
+            let key = self.string()?;
+            self.whitespace();
+            self.expect(":")?;
+            fields.insert(key, self.value()?);
+            self.whitespace();
+            match self.bytes.get(self.position) {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Json::Object(fields));
+                }
+                _ => return Err(Rc5Error::InvalidEncoding),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(all(feature = "rc5-16", feature = "rc5-64"))]
+    use std::{fs, path::Path};
+
+    #[test]
+    fn parses_json() {
+        let value = Json::parse(r#" { "a": [1, -2, "x\"y"], "b": { }, "c": [], "d": null } "#);
+        let mut fields = BTreeMap::new();
+        fields.insert(
+            "a".to_string(),
+            Json::Array(vec![
+                Json::Number(1),
+                Json::Number(-2),
+                Json::String("x\"y".to_string()),
+            ]),
+        );
+        fields.insert("b".to_string(), Json::Object(BTreeMap::new()));
+        fields.insert("c".to_string(), Json::Array(Vec::new()));
+        fields.insert("d".to_string(), Json::Null);
+        assert_eq!(value, Ok(Json::Object(fields)));
+
+        assert!(Json::parse("[1, 2").is_err());
+        assert!(Json::parse("{\"a\" 1}").is_err());
+        assert!(Json::parse("true false").is_err());
+    }
+
+    // the checked-in files cover every word size
+    #[cfg(all(feature = "rc5-16", feature = "rc5-64"))]
+    #[test]
+    fn vector_files_pass() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("vectors");
+        let mut files = 0;
+
+        for entry in fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+
+            let text = fs::read_to_string(&path).unwrap();
+            let file = VectorFile::parse(&text).unwrap_or_else(|err| panic!("{:?}: {}", path, err));
+            assert_eq!(
+                file.to_json(),
+                text,
+                "{:?} isn't in the canonical layout",
+                path
+            );
+
+            let failures = run(&file);
+            assert!(failures.is_empty(), "{:?}: {:?}", path, failures);
+            files += 1;
+        }
+
+        assert!(files > 0);
+    }
+
+    #[cfg(feature = "rc5-16")]
+    #[test]
+    fn generated_vectors_replay() {
+        let file = generate(16, 16, 8, 3, 0).unwrap();
+        assert_eq!(file, generate(16, 16, 8, 3, 0).unwrap());
+        assert_ne!(file, generate(16, 16, 8, 3, 1).unwrap());
+        assert_eq!(VectorFile::parse(&file.to_json()), Ok(file.clone()));
+        assert!(run(&file).is_empty());
+
+        // the first key and block are the first two SplitMix64(0) outputs
+        let first = &file.groups[0].tests[0];
+        assert_eq!(first.key, 0xE220_A839_7B1D_CDAFu64.to_le_bytes());
+        assert_eq!(first.msg, 0x6E78_9E6A_A1B9_65F4u64.to_le_bytes()[..4]);
+
+        let mut tampered = file;
+        tampered.groups[0].tests[1].ct[0] ^= 1;
+        let failures = run(&tampered);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].id, 2);
+
+        assert_eq!(generate(48, 12, 16, 1, 0), Err(Rc5Error::InvalidWordSize));
+        assert_eq!(generate(32, 12, 0, 1, 0), Err(Rc5Error::EmptyKey));
+        assert_eq!(generate(32, 12, 256, 1, 0), Err(Rc5Error::InvalidKeyLength));
+    }
+
+    #[cfg(not(feature = "rc5-64"))]
+    #[test]
+    fn word_sizes_left_out_of_the_build_are_refused() {
+        assert_eq!(generate(64, 12, 16, 1, 0), Err(Rc5Error::InvalidWordSize));
+
+        // their vectors can't be checked either way, so all are reported
+        let mut file = generate(32, 12, 16, 2, 0).unwrap();
+        file.groups[0].word_bits = 64;
+        file.groups[0].tests[1].valid = false;
+        assert_eq!(run(&file).len(), 2);
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let file = generate(32, 12, 16, 1, 0).unwrap().to_json();
+        let malformed = [
+            file.replace("\"wordBits\": 32", "\"wordBits\": 8"),
+            file.replace("\"rounds\": 12", "\"rounds\": 256"),
+            file.replace("\"mode\": \"block\"", "\"mode\": \"OFB\""),
+            file.replace("\"result\": \"valid\"", "\"result\": \"maybe\""),
+            file.replace("\"mode\": \"block\"", "\"mode\": \"CBC\""),
+            file.replace("\"msg\": \"", "\"msg\": \"0"),
+            file[..file.len() - 3].to_string(),
+        ];
+        for text in malformed.iter() {
+            assert_eq!(
+                VectorFile::parse(text),
+                Err(Rc5Error::InvalidEncoding),
+                "{}",
+                text
+            );
+        }
+    }
+}